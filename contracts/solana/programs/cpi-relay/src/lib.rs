@@ -0,0 +1,65 @@
+#![allow(unexpected_cfgs)]
+
+// Test helper: relays an instruction to another program through CPI, nested
+// `depth` extra levels by calling itself first.
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program::invoke;
+use anchor_lang::InstructionData;
+
+declare_id!("535xfr42BQqYG8MUkMRKRg98gk4BwzecPEhLWXk49i5E");
+
+#[program]
+pub mod cpi_relay {
+    use super::*;
+
+    /// Calls the first remaining account, a program, with `data` and the rest
+    /// of the remaining accounts.
+    pub fn forward<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Forward<'info>>,
+        depth: u8,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        let (target, accounts) = ctx
+            .remaining_accounts
+            .split_first()
+            .ok_or(ErrorCode::AccountNotEnoughKeys)?;
+        let ix = if depth == 0 {
+            Instruction {
+                program_id: target.key(),
+                accounts: accounts.iter().map(account_meta).collect(),
+                data,
+            }
+        } else {
+            let mut metas = vec![AccountMeta::new_readonly(crate::ID, false)];
+            metas.extend(ctx.remaining_accounts.iter().map(account_meta));
+            Instruction {
+                program_id: crate::ID,
+                accounts: metas,
+                data: crate::instruction::Forward {
+                    depth: depth - 1,
+                    data,
+                }
+                .data(),
+            }
+        };
+
+        let mut infos = vec![ctx.accounts.relay.to_account_info()];
+        infos.extend_from_slice(ctx.remaining_accounts);
+        invoke(&ix, &infos)?;
+        Ok(())
+    }
+}
+
+fn account_meta(info: &AccountInfo) -> AccountMeta {
+    AccountMeta {
+        pubkey: info.key(),
+        is_signer: info.is_signer,
+        is_writable: info.is_writable,
+    }
+}
+
+#[derive(Accounts)]
+pub struct Forward<'info> {
+    pub relay: Program<'info, crate::program::CpiRelay>,
+}
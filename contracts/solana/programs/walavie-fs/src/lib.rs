@@ -2,25 +2,190 @@
 
 // lib.rs
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::{get_stack_height, TRANSACTION_LEVEL_STACK_HEIGHT};
+use anchor_lang::solana_program::sysvar::instructions::{
+    load_current_index_checked, load_instruction_at_checked,
+};
+use anchor_lang::solana_program::{
+    ed25519_program,
+    hash::{hash, hashv},
+};
+use anchor_lang::InstructionData;
+use std::borrow::Cow;
 use std::collections::BTreeSet; // BTreeSet is still used and generally fine
+use unicode_normalization::UnicodeNormalization;
 
 declare_id!("9NhNPHXjiCoZ9Hi5ch26x1yQJUq3u2weNoMeViwu7r2r"); // Replace with your program ID
 
 // Max length for strings to manage account space, adjust as needed
 const MAX_STRING_LEN: usize = 64;
+const MAX_FS_NAME_LEN: usize = 32; // Filesystem names are a root seed, which holds 32 bytes at most
 const MAX_TAGS: usize = 5;
+const MAX_PATH_LEN: usize = MAX_STRING_LEN * 5;
+const SPL_TOKEN_PROGRAM_ID: Pubkey = pubkey!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+const SPL_TOKEN_2022_PROGRAM_ID: Pubkey = pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLXLt3kLfnUDePm7sN");
+const METAPLEX_METADATA_PROGRAM_ID: Pubkey = pubkey!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+const METAPLEX_METADATA_V1_KEY: u8 = 4; // Leading tag of metadata accounts, among the program's others
+const ROOT_DIR_OBJECT_ID: u64 = 0; // Object ids handed out by obj_id_counter start at 1
+const MAX_SYMLINK_HOPS: usize = 8;
+const MAX_XATTRS: usize = 8; // Per file or directory
+const MAX_XATTR_VALUE_LEN: usize = 256;
+const MAX_DESCRIPTION_LEN: usize = 256;
+const MAX_CO_AUTHORITIES: usize = 8;
+const MAX_CPI_CALLERS: usize = 8; // Programs allowed to write through CPI
+const MAX_GROUP_MEMBERS: usize = 32;
+const MAX_GROUP_NAME_LEN: usize = 32; // Used as a PDA seed
+const MODE_BITS: u16 = 0o777; // Owner, group and other rwx
+const MODE_GROUP_WRITE: u16 = 0o020;
+const MODE_OTHER_WRITE: u16 = 0o002;
+// The root's mode, as it has no arena entry. New dirs inherit their parent's, so
+// this is also the default for every dir.
+const DEFAULT_DIR_MODE: u16 = 0o755;
+// Ceilings for FsLimits; a filesystem may configure lower ones at initialization
+const DEFAULT_FS_LIMITS: FsLimits = FsLimits {
+    max_tags: MAX_TAGS as u8,
+    max_string_len: MAX_STRING_LEN as u16,
+    max_path_depth: (MAX_PATH_LEN / 2) as u16, // One byte per name plus its separator
+    max_children_per_dir: u32::MAX,
+};
+
+// Reported by describe() so generic clients can adapt to this deployment
+const LAYOUT_VERSION: u16 = 1;
+const FEATURES: &[&str] = &[
+    "canonical_paths",
+    "move",
+    "copy_dir",
+    "add_dir_recursive",
+    "batch_files",
+    "search",
+    "symlinks",
+    "journals",
+    "chunked_files",
+    "default_tags",
+    "hard_links",
+    "trash",
+    "sealed_files",
+    "file_locks",
+    "cas_guards",
+    "dir_versions",
+    "resumable_delete",
+    "access_times",
+    "checksums",
+    "content_types",
+    "xattrs",
+    "descriptions",
+    "previews",
+    "storage_classes",
+    "pins",
+    "object_kinds",
+    "raw_blob_ids",
+    "sui_object_ids",
+    "encryption_metadata",
+    "schema_versions",
+    "fs_metadata",
+    "named_filesystems",
+    "fs_limits",
+    "tag_dictionary",
+    "blob_status",
+    "access_counts",
+    "dir_aggregates",
+    "file_owners",
+    "file_sales",
+    "authority_transfer",
+    "co_authorities",
+    "freeze",
+    "cpi_whitelist",
+    "program_config",
+    "writer_grants",
+    "read_grants",
+    "shares",
+    "read_capabilities",
+    "mode_bits",
+    "groups",
+    "mounts",
+    "access_policies",
+    "permits",
+    "session_keys",
+    "snapshots",
+    "snapshot_diff",
+    "published_clones",
+    "collation",
+    "renewal_policy",
+    "space_warnings",
+    "retag",
+    "rename_tag",
+    "reap_orphans",
+    "share_keys",
+    "audit_log",
+    "blob_delete_queue",
+];
 
 // Estimated space for PDAs (you'll need to manage realloc for production)
-const WALRUSFS_ROOT_PDA_SPACE: usize = 8 + 8 + 8 + 32 + 1; // current_epoch + obj_id_counter + authority + bump
-const CHILDREN_PDA_SPACE: usize = 1024 * 1; // For RootChildrenFiles/Dirs Pda (now Vec<KeyValueStringU64>)
-const ARENA_PDA_SPACE: usize = 1024 * 1; // For File/Dir Arena Pda (now Vec<KeyValueU64Object>)
-                                         // --- KeyValue Struct Definitions ---
+const FS_METADATA_SPACE: usize =
+    (4 + MAX_STRING_LEN) * (1 + MAX_TAGS) + 4 + 4 + MAX_DESCRIPTION_LEN; // name + tags + description
+const WALRUSFS_ROOT_PDA_SPACE: usize = 8
+    + 2
+    + 8
+    + 8
+    + 32
+    + 1
+    + 1
+    + 3
+    + 1
+    + FS_METADATA_SPACE
+    + 9
+    + 32
+    + 33
+    + 4
+    + 32 * MAX_CO_AUTHORITIES
+    + 1
+    + 1
+    + 4
+    + 32 * MAX_CPI_CALLERS
+    + 4
+    + MAX_FS_NAME_LEN; // schema_version + current_epoch + obj_id_counter + authority + bump + default_renewal_policy + space_warning_pcts + collation + fs metadata + limits + seed_key + pending_authority + co_authorities + frozen + cpi_callers + seed_name
+const DEFAULT_SPACE_WARNING_PCTS: [u8; 3] = [80, 90, 95];
+const CHILDREN_PDA_SPACE: usize = 1024; // For RootChildrenFiles/Dirs Pda (now Vec<KeyValueStringU64>)
+const ARENA_PDA_SPACE: usize = 1024; // For File/Dir Arena Pda (now Vec<KeyValueU64Object>)
+const SHARE_KEYS_PDA_SPACE: usize = 1024; // For per-file ShareKeysPda (Vec<WrappedKeyEntry>)
+const MAX_WRAPPED_KEY_LEN: usize = 128;
+const MAX_ENCRYPTION_SCHEME_LEN: usize = 32;
+const MAX_ENCRYPTION_NONCE_LEN: usize = 32;
+const PENDING_BLOB_DELETES_PDA_SPACE: usize = 1024; // Vec<[u8; 32]> of blob ids awaiting Sui-side deletion
+const NO_BLOB_ID: [u8; 32] = [0; 32]; // Symlinks, journals, chunked files and directories
+const SCHEMA_VERSION: u16 = 1; // Bump together with a migration step whenever a PDA layout changes
+const MAX_DICTIONARY_TAGS: usize = 128; // Distinct tag names per filesystem
+const TAG_DICTIONARY_PDA_SPACE: usize = 8 + 2 + 4 + MAX_DICTIONARY_TAGS * (4 + MAX_STRING_LEN) + 1; // schema_version + tags + bump
+const DESCRIPTION_PDA_SPACE: usize = 8 + 2 + 8 + 4 + MAX_DESCRIPTION_LEN + 1; // discriminator + schema_version + object_id + description + bump
+const TRASH_PDA_SPACE: usize = 1024; // Vec<TrashEntry> of detached files and directories
+const DELETION_CURSOR_PDA_SPACE: usize = 1024; // Path plus the stack of dir ids still to remove
+const SNAPSHOT_INDEX_PDA_SPACE: usize = 1024; // Vec<SnapshotSummary>
+const SNAPSHOT_PDA_SPACE: usize = 1024 * 10; // Largest size creatable in one instruction
+const MAX_SNAPSHOT_NAME_LEN: usize = 32; // Used as a PDA seed
+const PUBLISHED_CLONE_PDA_SPACE: usize = 1024 * 10; // Largest size creatable in one instruction
+const WRITER_GRANT_PDA_SPACE: usize = 8 + 2 + 32 + 4 + MAX_PATH_LEN + 8 + 1; // schema_version + delegate + path_prefix + expiry_slot + bump
+const READ_GRANT_PDA_SPACE: usize = 8 + 2 + 32 + 4 + MAX_PATH_LEN + 8 + 1; // schema_version + grantee + path_prefix + expiry_slot + bump
+const READ_CAPABILITY_PDA_SPACE: usize = 8 + 2 + 4 + MAX_PATH_LEN + 8 + 5 + 4 + 32 + 32 + 1; // schema_version + path + expiry_slot + max_uses + uses + gateway + created_by + bump
+const SHARE_GRANT_PDA_SPACE: usize = 8 + 2 + 4 + MAX_PATH_LEN + 1 + 32 + 8 + 32 + 1; // schema_version + path + grantee + expiry_slot + created_by + bump
+const PROGRAM_CONFIG_PDA_SPACE: usize = 8 + 2 + 32 + 1 + 8 + 32 + 1; // schema_version + admin + paused + fs_creation_fee_lamports + fee_recipient + bump
+const SESSION_KEY_PDA_SPACE: usize = 8 + 2 + 32 + 4 + MAX_PATH_LEN + 8 + 1; // schema_version + owner + path_prefix + expiry_slot + bump
+const PERMIT_NONCE_PDA_SPACE: usize = 8 + 2 + 32 + 8 + 1; // schema_version + signer + next_nonce + bump
+const GROUP_PDA_SPACE: usize = 8 + 2 + 4 + MAX_GROUP_NAME_LEN + 32 + 4 + 32 * MAX_GROUP_MEMBERS + 1; // schema_version + name + admin + members + bump
+const AUDIT_LOG_PDA_SPACE: usize = 8 + 2 + 8 + 32 + 4 + MAX_STRING_LEN + 1; // schema_version + sequence + last_hash + last_log_blob_id + bump
+
+// --- KeyValue Struct Definitions ---
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
 pub struct KeyValueStringU64 {
     pub key: String,
     pub value: u64,
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct KeyValueStringString {
+    pub key: String,
+    pub value: String,
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 pub struct KeyValueU64FileObject {
     pub key: u64,
@@ -33,42 +198,517 @@ pub struct KeyValueU64DirObject {
     pub value: DirObjectAnchor,
 }
 
+// A file content key wrapped (encrypted) to one grantee's public key.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct WrappedKeyEntry {
+    pub grantee: Pubkey,
+    pub wrapped_key: Vec<u8>,
+    pub key_epoch: u32, // Wraps older than ShareKeysPda.key_epoch are stale
+}
+
 // --- PDA Struct Definitions (Modified) ---
+// Every PDA starts with schema_version so migrations can tell layouts apart.
 #[account]
 pub struct WalrusfsRootPda {
+    pub schema_version: u16,
     pub current_epoch: u64,
     pub obj_id_counter: u64,
     pub authority: Pubkey,
     pub bump: u8,
+    pub default_renewal_policy: RenewalPolicy,
+    pub space_warning_pcts: [u8; 3], // Ascending usage thresholds for SpaceLowEvent
+    pub collation: Collation,
+    pub name: String, // Shown by explorers instead of the owner's pubkey
+    pub description: String,
+    pub tags: Vec<String>,
+    pub limits: FsLimits,
+    pub seed_key: Pubkey, // The root's address derives from it; authority transfers leave it alone
+    pub pending_authority: Option<Pubkey>, // Proposed new authority, until it accepts
+    pub co_authorities: Vec<Pubkey>, // May sign everything the authority can except transfers
+    pub frozen: bool,     // Rejects changes to entries; see set_frozen
+    pub cpi_callers: Option<Vec<Pubkey>>, // Programs that may write through CPI; None for any
+    pub seed_name: String, // Seeds the address along with seed_key; empty for a wallet's default filesystem
+}
+
+// Program-wide settings, one per deployment. Every instruction that writes
+// checks `paused`, the kill switch for incident response. Until the config is
+// created its address holds no data, which reads as unpaused with no fee.
+#[account]
+pub struct ProgramConfigPda {
+    pub schema_version: u16,
+    pub admin: Pubkey,
+    pub paused: bool,
+    pub fs_creation_fee_lamports: u64, // Charged by initialize_walrusfs
+    pub fee_recipient: Pubkey,
+    pub bump: u8,
+}
+
+// Per-filesystem limits on what may be created. They can only be lowered after
+// initialization, so entries that already exist always fit.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FsLimits {
+    pub max_tags: u8,
+    pub max_string_len: u16, // Applies to names and tags
+    pub max_path_depth: u16,
+    pub max_children_per_dir: u32, // Files and subdirectories are counted separately
 }
 
 #[account]
 pub struct ChildrenFilesPda {
+    pub schema_version: u16,
     pub data: Vec<KeyValueStringU64>, // Changed from BTreeMap
+    pub version: u64,                 // Bumped whenever the root's file list changes
     pub bump: u8,
 }
 
 #[account]
 pub struct ChildrenDirectoriesPda {
+    pub schema_version: u16,
     pub data: Vec<KeyValueStringU64>, // Changed from BTreeMap
+    pub version: u64,                 // Bumped whenever the root's directory list changes
     pub bump: u8,
 }
 
 #[account]
 pub struct FileArenaPda {
+    pub schema_version: u16,
     pub data: Vec<KeyValueU64FileObject>, // Changed from BTreeMap
     pub bump: u8,
 }
 
 #[account]
 pub struct DirArenaPda {
+    pub schema_version: u16,
     pub data: Vec<KeyValueU64DirObject>, // Changed from BTreeMap
     pub bump: u8,
 }
 
+// Free-form description of one file or directory, kept out of the arenas to
+// save space. Left behind when the object is deleted; ids are never reused.
+#[account]
+pub struct DescriptionPda {
+    pub schema_version: u16,
+    pub object_id: u64,
+    pub description: String,
+    pub bump: u8,
+}
+
+// Per-file envelope encryption keys, one wrap per grantee.
+#[account]
+pub struct ShareKeysPda {
+    pub schema_version: u16,
+    pub file_id: u64,
+    pub key_epoch: u32,
+    pub wraps: Vec<WrappedKeyEntry>,
+    pub bump: u8,
+}
+
+// Lets a delegate such as a CI bot write at or below path_prefix without holding
+// the authority key. Seeded by the root and the delegate, so each delegate has at
+// most one grant per filesystem.
+#[account]
+pub struct WriterGrantPda {
+    pub schema_version: u16,
+    pub delegate: Pubkey,
+    pub path_prefix: String,
+    pub expiry_slot: u64, // The grant stops working at this slot
+    pub bump: u8,
+}
+
+// Records that a grantee may read at or below path_prefix. Chain data is public,
+// so nothing here is enforced on-chain; HTTP gateways and other programs consult
+// it, directly or through can_read. One grant per grantee, like writer grants.
+#[account]
+pub struct ReadGrantPda {
+    pub schema_version: u16,
+    pub grantee: Pubkey,
+    pub path_prefix: String,
+    pub expiry_slot: u64,
+    pub bump: u8,
+}
+
+// A share link for a path, named by a share_key chosen by its creator. Its
+// address is public on-chain like any account's, so a share without a grantee
+// is readable by anyone. Like read grants, gateways and other programs enforce
+// it, directly or through check_share.
+#[account]
+pub struct ShareGrantPda {
+    pub schema_version: u16,
+    pub path: String,            // Shared along with everything below it
+    pub grantee: Option<Pubkey>, // None for anyone holding the link
+    pub expiry_slot: u64,
+    pub created_by: Pubkey,
+    pub bump: u8,
+}
+
+// An ephemeral key, such as one held by a game client, that writes as `owner`
+// within path_prefix until expiry_slot without prompting the owner's wallet.
+// Seeded by the root and the session key itself.
+#[account]
+pub struct SessionKeyPda {
+    pub schema_version: u16,
+    pub owner: Pubkey,
+    pub path_prefix: String,
+    pub expiry_slot: u64,
+    pub bump: u8,
+}
+
+// Replay protection for permits signed by `signer`: each permit must carry
+// next_nonce, which the write that uses it then increments.
+#[account]
+pub struct PermitNoncePda {
+    pub schema_version: u16,
+    pub signer: Pubkey,
+    pub next_nonce: u64,
+    pub bump: u8,
+}
+
+// A short-lived right to read a path through one gateway, named by a
+// capability_key like a share. The gateway calls consume_capability for each
+// download it serves, which counts it and, with max_uses, stops the capability
+// once they run out.
+#[account]
+pub struct ReadCapabilityPda {
+    pub schema_version: u16,
+    pub path: String, // Readable along with everything below it
+    pub expiry_slot: u64,
+    pub max_uses: Option<u32>, // None for unlimited
+    pub uses: u32,
+    pub gateway: Pubkey, // The only signer that may consume it
+    pub created_by: Pubkey,
+    pub bump: u8,
+}
+
+// Named set of members who may write the files and dirs chgrp'd to it, where
+// their mode has the group-write bit. Seeded by the fs root and the name.
+#[account]
+pub struct GroupPda {
+    pub schema_version: u16,
+    pub name: String,
+    pub admin: Pubkey, // Manages members, as the fs authorities can
+    pub members: Vec<Pubkey>,
+    pub bump: u8,
+}
+
+// Deletable Walrus blobs no longer referenced by any file, waiting for the
+// keeper to delete them on Sui and call confirm_blob_deleted.
+#[account]
+pub struct PendingBlobDeletesPda {
+    pub schema_version: u16,
+    pub blob_ids: Vec<[u8; 32]>,
+    pub bump: u8,
+    // After bump so queues created before snapshots pinned blobs read it as
+    // empty from their zero padding.
+    pub pinned_blobs: Vec<PinnedBlob>,
+}
+
+// A blob listed by at least one snapshot, held back from the delete queue
+// until the last snapshot listing it is deleted.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct PinnedBlob {
+    pub blob_id: [u8; 32],
+    pub holders: u32,
+    pub release_on_unpin: bool, // The last live file using it was deletable
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct TrashEntry {
+    pub original_path: String,
+    pub is_dir: bool,
+    pub object_id: u64, // Still in its arena, detached from any parent
+    pub deleted_ts: u64,
+}
+
+// Entries soft-deleted with to_trash, kept until restore or empty_trash.
+#[account]
+pub struct TrashPda {
+    pub schema_version: u16,
+    pub entries: Vec<TrashEntry>,
+    pub bump: u8,
+}
+
+// A recursive delete spread over several transactions. The subtree is detached
+// when it starts, so ids left on the stack are unreachable from the root;
+// reap_orphans leaves them to delete_dir_continue.
+#[account]
+pub struct DeletionCursorPda {
+    pub schema_version: u16,
+    pub path: String,
+    pub pending_dir_ids: Vec<u64>, // Empty when no delete is in progress
+    pub dirs_removed: u64,
+    pub files_removed: u64,
+    pub bump: u8,
+}
+
+// Metadata of one entry as it was when the snapshot was taken. Blob data is
+// shared with the live tree rather than copied.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct SnapshotEntry {
+    pub path: String, // Relative to the snapshot root, with a leading '/'
+    pub object_id: u64,
+    pub is_dir: bool,
+    pub walrus_blob_id: [u8; 32],
+    pub size: u64,
+    pub modify_ts: u64,
+}
+
+#[account]
+pub struct SnapshotPda {
+    pub schema_version: u16,
+    pub name: String,
+    pub path: String,
+    pub create_ts: u64,
+    pub entries: Vec<SnapshotEntry>,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct SnapshotSummary {
+    pub name: String,
+    pub path: String,
+    pub create_ts: u64,
+    pub entry_count: u32,
+}
+
+// A frozen copy of a snapshot that gateways and domains can point at while the
+// working tree keeps changing. Nothing writes to it after publish_clone, and it
+// outlives the snapshot it was copied from.
+#[account]
+pub struct PublishedClonePda {
+    pub schema_version: u16,
+    pub source_root: Pubkey,
+    pub snapshot_name: String,
+    pub path: String,
+    pub snapshot_ts: u64,
+    pub publish_ts: u64,
+    pub entries: Vec<SnapshotEntry>,
+    pub bump: u8,
+}
+
+// Names of every snapshot, since snapshot PDAs can't be enumerated on-chain.
+#[account]
+pub struct SnapshotIndexPda {
+    pub schema_version: u16,
+    pub snapshots: Vec<SnapshotSummary>,
+    pub bump: u8,
+}
+
+// Every tag name used in the filesystem, stored once. Objects refer to tags by
+// their index here; names are never removed, so ids stay valid.
+#[account]
+pub struct TagDictionaryPda {
+    pub schema_version: u16,
+    pub tags: Vec<String>, // Indexed by tag id
+    pub bump: u8,
+}
+
+// Head of the hash-chained audit log whose segments are stored on Walrus.
+#[account]
+pub struct AuditLogPda {
+    pub schema_version: u16,
+    pub sequence: u64,
+    pub last_hash: [u8; 32],
+    pub last_log_blob_id: String,
+    pub bump: u8,
+}
+
 // --- Data Structs (DirObjectAnchor Modified) ---
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ListSortBy {
+    #[default]
+    Name, // By the filesystem's collation sort key
+    CreateTs,
+    Size,
+    DirsFirst,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ListKindFilter {
+    #[default]
+    Both,
+    FilesOnly,
+    DirsOnly,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Default)]
+pub struct ListFilter {
+    pub kind: ListKindFilter,
+    pub tag: Option<String>, // Entries must carry this tag
+    pub pinned_only: bool,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryKind {
+    None,
+    File,
+    Dir,
+}
+
+// How entry names are turned into sort keys; independent of any locale.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Collation {
+    #[default]
+    Binary,
+    CaseFolded,
+    NumericAware, // Case-folded, digit runs compared by numeric value
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RenewalPolicy {
+    Never, // Let the blob expire
+    #[default]
+    Manual,
+    AutoWhileFunded,
+}
+
+// Hint for renewal cranks and cost reporting; the program itself treats all
+// classes the same.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StorageClass {
+    #[default]
+    Standard,
+    Archive,   // Rarely read, renewed in long increments
+    Ephemeral, // Scratch data, expected to lapse
+}
+
+// What a file entry holds. Directories are told apart by `is_dir` and carry no kind.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ObjectKind {
+    RegularFile,
+    Symlink,     // symlink_target is set
+    ChunkedFile, // chunks is set
+    Journal,     // journal_segments is set
+}
+
+// Where a file's blob is in its Walrus lifecycle. Entries start out pending whenever
+// they get a new blob, then move forward only; see BlobStatus::can_become.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlobStatus {
+    PendingCertification, // Uploaded, but Walrus has not certified it yet
+    Certified,
+    Expired,
+    Superseded, // Replaced by a newer blob held elsewhere
+}
+
+impl BlobStatus {
+    fn can_become(self, next: BlobStatus) -> bool {
+        matches!(
+            (self, next),
+            (
+                BlobStatus::PendingCertification,
+                BlobStatus::Certified | BlobStatus::Expired | BlobStatus::Superseded
+            ) | (
+                BlobStatus::Certified,
+                BlobStatus::Expired | BlobStatus::Superseded
+            )
+        )
+    }
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 pub struct FileObjectAnchor {
+    pub create_ts: u64,
+    pub modify_ts: u64,
+    pub tags: Vec<u16>, // Ids in the TagDictionaryPda
+    pub size: u64,
+    pub walrus_blob_id: [u8; 32],
+    pub walrus_epoch_till: u64,
+    pub deletable: bool, // Blob was stored as deletable on Walrus
+    pub renewal_policy: RenewalPolicy,
+    pub sort_key: Vec<u8>,
+    pub symlink_target: Option<String>, // Some for symlinks, which carry no blob
+    pub journal_segments: Option<Vec<JournalSegment>>, // Some for append-only journals
+    pub chunks: Option<Vec<FileChunk>>, // Some for files split across several blobs
+    pub link_count: u32,                // Directory entries referencing this object
+    pub sealed: bool,                   // Rejects update, rename, move and delete
+    pub seal_permanent: bool,           // Sealed with no way to unseal
+    pub lock: Option<FileLock>,         // Advisory lease, see lock_file
+    pub access_ts: Option<u64>,         // Set only by record_access
+    pub access_count: u64,              // Likewise; a download counter
+    pub sha256: Option<[u8; 32]>,       // Digest of the blob contents, as committed by the uploader
+    pub content_type: String,           // MIME type for gateways; empty when unknown
+    pub preview_blob_id: Option<String>, // Small thumbnail blob for listings
+    pub sui_object_id: Option<[u8; 32]>, // Sui object of the Walrus blob, for extend and delete
+    pub encryption: Option<EncryptionInfo>, // None for plaintext blobs
+    pub storage_class: StorageClass,
+    pub xattrs: Vec<KeyValueStringString>, // User metadata, see set_xattr
+    pub has_description: bool,             // Text lives in the object's DescriptionPda
+    pub pinned: bool,                      // Never reaped; listed first by list_expiring
+    pub kind: ObjectKind,
+    pub status: BlobStatus,
+    pub owner: Pubkey, // May sign changes to this file besides the fs authority
+    pub mode: u16,     // Permission bits, see chmod
+    pub group: Option<Pubkey>, // GroupPda whose members get the group bits, see chgrp
+    pub access_policy: Option<AccessPolicy>, // Token gate for readers, see check_access
+    pub sale: Option<SaleListing>, // Set while listed, see list_file_for_sale
+}
+
+// A file's standing offer to sell it to whoever pays price_lamports to its owner.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct SaleListing {
+    pub price_lamports: u64,
+    pub was_sealed: bool, // Listing seals the file; ending the sale restores this
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct JournalSegment {
+    pub walrus_blob_id: [u8; 32],
+    pub size: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct FileChunk {
+    pub walrus_blob_id: [u8; 32],
+    pub offset: u64, // Byte offset of this chunk within the file
+    pub length: u64,
+}
+
+// How a client-side encrypted blob was sealed, so any compliant client can open it.
+// The program stores these as given and never interprets them.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct EncryptionInfo {
+    pub scheme: String, // e.g. "aes-256-gcm"
+    pub nonce: Vec<u8>,
+    pub wrapped_key: Vec<u8>, // Content key wrapped for the owner; see share_keys for grantees
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct FileLock {
+    pub holder: Pubkey,
+    pub expires_slot: u64,
+}
+
+// Layouts of filesystems created before schema_version, read only by
+// migrate_filesystem. Tags are names and blob ids are in their base64url text form.
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct BaselineRootPda {
+    pub current_epoch: u64,
+    pub obj_id_counter: u64,
+    pub authority: Pubkey,
+    pub bump: u8,
+}
+
+// Both root children PDAs
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct BaselineChildrenPda {
+    pub data: Vec<KeyValueStringU64>,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct BaselineFileArenaPda {
+    pub data: Vec<BaselineKeyValueU64FileObject>,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct BaselineKeyValueU64FileObject {
+    pub key: u64,
+    pub value: BaselineFileObjectAnchor,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct BaselineFileObjectAnchor {
     pub create_ts: u64,
     pub tags: Vec<String>,
     pub size: u64,
@@ -76,12 +716,63 @@ pub struct FileObjectAnchor {
     pub walrus_epoch_till: u64,
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct BaselineDirArenaPda {
+    pub data: Vec<BaselineKeyValueU64DirObject>,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct BaselineKeyValueU64DirObject {
+    pub key: u64,
+    pub value: BaselineDirObjectAnchor,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct BaselineDirObjectAnchor {
+    pub create_ts: u64,
+    pub tags: Vec<String>,
+    pub children_files: Vec<KeyValueStringU64>,
+    pub children_directories: Vec<KeyValueStringU64>,
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 pub struct DirObjectAnchor {
     pub create_ts: u64,
-    pub tags: Vec<String>,
+    pub modify_ts: u64,         // Last change to this dir's children
+    pub tags: Vec<u16>,         // Ids in the TagDictionaryPda
+    pub default_tags: Vec<u16>, // Merged into files created anywhere below this dir
+    pub sort_key: Vec<u8>,
+    pub version: u64, // Bumped on every change to this dir's children
+    pub xattrs: Vec<KeyValueStringString>, // User metadata, see set_xattr
+    pub has_description: bool, // Text lives in the object's DescriptionPda
+    pub pinned: bool, // Never reaped automatically
     pub children_files: Vec<KeyValueStringU64>, // Changed
     pub children_directories: Vec<KeyValueStringU64>, // Changed
+    pub child_file_count: u32, // Cached children_files.len(), kept by bump_dir_version
+    pub child_dir_count: u32, // Likewise for children_directories
+    pub subtree_bytes: u64, // File sizes below, once per link; see adjust_subtree_bytes
+    pub mode: u16,    // Permission bits, see chmod
+    pub group: Option<Pubkey>, // Likewise; inherited by new children
+    pub mount: Option<MountPoint>, // Set on empty dirs standing in for another filesystem
+    pub access_policy: Option<AccessPolicy>, // Applies to everything below as well
+}
+
+// A path in another filesystem, named by its owner and name like
+// ReadUserFileSystem does.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct MountPoint {
+    pub owner: Pubkey,
+    pub fs_name: String,
+    pub path: String,
+}
+
+// Who may read an entry, for gateways and dApps to enforce. check_access verifies
+// it against token accounts the reader holds.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub enum AccessPolicy {
+    MintHolder { mint: Pubkey, min_amount: u64 }, // Fungible or not, summed over the reader's accounts
+    CollectionMember { collection: Pubkey }, // Holds an NFT in this verified Metaplex collection
 }
 
 // --- Helper Functions for Vec<KeyValue...> operations ---
@@ -90,13 +781,25 @@ fn get_from_vec_str_key<'a>(vec: &'a [KeyValueStringU64], key: &str) -> Option<&
     vec.iter().find(|kv| kv.key == key).map(|kv| &kv.value)
 }
 
-fn get_mut_from_vec_str_key<'a>(
-    vec: &'a mut [KeyValueStringU64],
-    key: &'a str,
-) -> Option<&'a mut u64> {
-    vec.iter_mut()
-        .find(|kv| kv.key == key)
-        .map(|kv| &mut kv.value)
+// Adds or replaces a child entry, enforcing the filesystem's name length and
+// children-per-directory limits. Replacing an existing name never counts as growth.
+fn insert_child(
+    children: &mut Vec<KeyValueStringU64>,
+    name: String,
+    id: u64,
+    limits: &FsLimits,
+) -> Result<()> {
+    require!(
+        name.len() <= limits.max_string_len as usize,
+        WalrusFsError::StringTooLong
+    );
+    require!(
+        children.len() < limits.max_children_per_dir as usize
+            || get_from_vec_str_key(children, &name).is_some(),
+        WalrusFsError::DirectoryFull
+    );
+    insert_into_vec_str_key(children, name, id);
+    Ok(())
 }
 
 fn insert_into_vec_str_key(
@@ -115,11 +818,9 @@ fn insert_into_vec_str_key(
 }
 
 fn remove_from_vec_str_key(vec: &mut Vec<KeyValueStringU64>, key: &str) -> Option<u64> {
-    if let Some(index) = vec.iter().position(|kv| kv.key == key) {
-        Some(vec.remove(index).value)
-    } else {
-        None
-    }
+    vec.iter()
+        .position(|kv| kv.key == key)
+        .map(|index| vec.remove(index).value)
 }
 
 fn contains_key_in_vec_str(vec: &[KeyValueStringU64], key: &str) -> bool {
@@ -127,16 +828,19 @@ fn contains_key_in_vec_str(vec: &[KeyValueStringU64], key: &str) -> bool {
 }
 
 // For Vec<KeyValueU64FileObject> (File Arena)
-fn get_from_file_arena<'a>(
-    arena: &'a [KeyValueU64FileObject],
-    id: u64,
-) -> Option<&'a FileObjectAnchor> {
+fn get_from_file_arena(arena: &[KeyValueU64FileObject], id: u64) -> Option<&FileObjectAnchor> {
     arena.iter().find(|kv| kv.key == id).map(|kv| &kv.value)
 }
 
-// fn get_mut_from_file_arena(arena: &mut [KeyValueU64FileObject], id: &u64) -> Option<&mut FileObjectAnchor> {
-//     arena.iter_mut().find(|kv| kv.key == *id).map(|kv| &mut kv.value)
-// }
+fn get_mut_from_file_arena(
+    arena: &mut [KeyValueU64FileObject],
+    id: u64,
+) -> Option<&mut FileObjectAnchor> {
+    arena
+        .iter_mut()
+        .find(|kv| kv.key == id)
+        .map(|kv| &mut kv.value)
+}
 
 fn insert_into_file_arena(
     arena: &mut Vec<KeyValueU64FileObject>,
@@ -159,25 +863,21 @@ fn remove_from_file_arena(
     arena: &mut Vec<KeyValueU64FileObject>,
     id: &u64,
 ) -> Option<FileObjectAnchor> {
-    if let Some(index) = arena.iter().position(|kv| kv.key == *id) {
-        Some(arena.remove(index).value)
-    } else {
-        None
-    }
+    arena
+        .iter()
+        .position(|kv| kv.key == *id)
+        .map(|index| arena.remove(index).value)
 }
 
 // For Vec<KeyValueU64DirObject> (Dir Arena)
-fn get_from_dir_arena<'a>(
-    arena: &'a [KeyValueU64DirObject],
-    id: u64,
-) -> Option<&'a DirObjectAnchor> {
+fn get_from_dir_arena(arena: &[KeyValueU64DirObject], id: u64) -> Option<&DirObjectAnchor> {
     arena.iter().find(|kv| kv.key == id).map(|kv| &kv.value)
 }
 
-fn get_mut_from_dir_arena<'a>(
-    arena: &'a mut [KeyValueU64DirObject],
+fn get_mut_from_dir_arena(
+    arena: &mut [KeyValueU64DirObject],
     id: u64,
-) -> Option<&'a mut DirObjectAnchor> {
+) -> Option<&mut DirObjectAnchor> {
     arena
         .iter_mut()
         .find(|kv| kv.key == id)
@@ -205,79 +905,837 @@ fn remove_from_dir_arena(
     arena: &mut Vec<KeyValueU64DirObject>,
     id: &u64,
 ) -> Option<DirObjectAnchor> {
-    if let Some(index) = arena.iter().position(|kv| kv.key == *id) {
-        Some(arena.remove(index).value)
-    } else {
-        None
-    }
+    arena
+        .iter()
+        .position(|kv| kv.key == *id)
+        .map(|index| arena.remove(index).value)
 }
 
 #[program]
 pub mod walrusfs_anchor {
     use super::*;
 
+    /// `name` tells apart the filesystems one wallet creates, up to 32 bytes; the
+    /// empty name is the wallet's default filesystem. It only seeds the address;
+    /// the display name starts empty for set_fs_metadata to fill in. `limits` of
+    /// None uses the program-wide maximums.
     #[inline(never)]
-    pub fn initialize_walrusfs(ctx: Context<InitializeWalrusfs>) -> Result<()> {
+    pub fn initialize_walrusfs(
+        ctx: Context<InitializeWalrusfs>,
+        name: String,
+        limits: Option<FsLimits>,
+    ) -> Result<()> {
+        require!(name.len() <= MAX_FS_NAME_LEN, WalrusFsError::FsNameTooLong);
+        let limits = limits.unwrap_or(DEFAULT_FS_LIMITS);
+        validate_fs_limits(&limits, &DEFAULT_FS_LIMITS)?;
+
         let root = &mut ctx.accounts.walrusfs_root;
         root.current_epoch = 0;
         root.obj_id_counter = 0;
         root.authority = *ctx.accounts.payer.key;
+        root.seed_key = *ctx.accounts.payer.key;
+        root.pending_authority = None;
+        root.co_authorities = Vec::new();
+        root.frozen = false;
+        root.cpi_callers = None;
+        root.seed_name = name;
+        root.schema_version = SCHEMA_VERSION;
         root.bump = ctx.bumps.walrusfs_root;
+        root.default_renewal_policy = RenewalPolicy::default();
+        root.space_warning_pcts = DEFAULT_SPACE_WARNING_PCTS;
+        root.collation = Collation::default();
+        root.name = String::new();
+        root.description = String::new();
+        root.tags = Vec::new();
+        root.limits = limits;
 
         let root_children_files = &mut ctx.accounts.root_children_files;
         root_children_files.data = Vec::new(); // Changed
+        root_children_files.version = 0;
+        root_children_files.schema_version = SCHEMA_VERSION;
         root_children_files.bump = ctx.bumps.root_children_files;
 
         let root_children_directories = &mut ctx.accounts.root_children_directories;
         root_children_directories.data = Vec::new(); // Changed
+        root_children_directories.version = 0;
+        root_children_directories.schema_version = SCHEMA_VERSION;
         root_children_directories.bump = ctx.bumps.root_children_directories;
 
         let file_arena = &mut ctx.accounts.file_arena;
         file_arena.data = Vec::new(); // Changed
+        file_arena.schema_version = SCHEMA_VERSION;
         file_arena.bump = ctx.bumps.file_arena;
 
         let dir_arena = &mut ctx.accounts.dir_arena;
         dir_arena.data = Vec::new(); // Changed
+        dir_arena.schema_version = SCHEMA_VERSION;
         dir_arena.bump = ctx.bumps.dir_arena;
 
+        let tag_dictionary = &mut ctx.accounts.tag_dictionary;
+        tag_dictionary.tags = Vec::new();
+        tag_dictionary.schema_version = SCHEMA_VERSION;
+        tag_dictionary.bump = ctx.bumps.tag_dictionary;
+
+        let fee = match load_program_config(&ctx.accounts.program_config)? {
+            Some(config) => {
+                require_keys_eq!(
+                    ctx.accounts.fee_recipient.key(),
+                    config.fee_recipient,
+                    WalrusFsError::InvalidFeeRecipient
+                );
+                config.fs_creation_fee_lamports
+            }
+            None => 0,
+        };
+        if fee > 0 {
+            anchor_lang::system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.payer.to_account_info(),
+                        to: ctx.accounts.fee_recipient.to_account_info(),
+                    },
+                ),
+                fee,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Creates the program config, once per deployment. Only the program's
+    /// upgrade authority may, and it names the `admin` who manages it after.
+    pub fn initialize_program_config(
+        ctx: Context<InitializeProgramConfig>,
+        admin: Pubkey,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.program_config;
+        config.schema_version = SCHEMA_VERSION;
+        config.admin = admin;
+        config.paused = false;
+        config.fs_creation_fee_lamports = 0;
+        config.fee_recipient = admin;
+        config.bump = ctx.bumps.program_config;
+        Ok(())
+    }
+
+    /// Pauses or resumes every instruction that writes, across all filesystems.
+    pub fn set_paused(ctx: Context<UpdateProgramConfig>, paused: bool) -> Result<()> {
+        ctx.accounts.program_config.paused = paused;
+
+        emit!(PausedChangedEvent {
+            paused,
+            admin: ctx.accounts.admin.key(),
+        });
+        Ok(())
+    }
+
+    pub fn set_fees(
+        ctx: Context<UpdateProgramConfig>,
+        fs_creation_fee_lamports: u64,
+        fee_recipient: Pubkey,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.program_config;
+        config.fs_creation_fee_lamports = fs_creation_fee_lamports;
+        config.fee_recipient = fee_recipient;
+
+        emit!(FeesUpdatedEvent {
+            fs_creation_fee_lamports,
+            fee_recipient,
+        });
         Ok(())
     }
 
     pub fn update_epoch(ctx: Context<UpdateEpoch>, current_epoch: u64) -> Result<()> {
-        require_keys_eq!(
-            ctx.accounts.walrusfs_root.authority,
-            ctx.accounts.authority.key(),
+        require!(
+            is_fs_writer(&ctx.accounts.walrusfs_root, ctx.accounts.authority.key),
             WalrusFsError::Unauthorized
         );
         ctx.accounts.walrusfs_root.current_epoch = current_epoch;
         Ok(())
     }
 
-    pub fn add_file(
-        ctx: Context<AddFile>,
-        path: String,
+    pub fn set_default_renewal_policy(
+        ctx: Context<UpdateRootSettings>,
+        policy: RenewalPolicy,
+    ) -> Result<()> {
+        require!(
+            is_fs_writer(&ctx.accounts.walrusfs_root, ctx.accounts.authority.key),
+            WalrusFsError::Unauthorized
+        );
+        ctx.accounts.walrusfs_root.default_renewal_policy = policy;
+        Ok(())
+    }
+
+    pub fn set_space_warning_thresholds(
+        ctx: Context<UpdateRootSettings>,
+        pcts: [u8; 3],
+    ) -> Result<()> {
+        require!(
+            pcts[0] <= pcts[1] && pcts[1] <= pcts[2] && pcts[2] <= 100,
+            WalrusFsError::InvalidThresholds
+        );
+        ctx.accounts.walrusfs_root.space_warning_pcts = pcts;
+        Ok(())
+    }
+
+    pub fn set_fs_metadata(
+        ctx: Context<UpdateRootSettings>,
+        name: String,
+        description: String,
         tags: Vec<String>,
-        size: u64,
-        walrus_blob_id: String,
-        end_epoch: u64,
-        overwrite: bool,
     ) -> Result<()> {
-        validate_path(&path)?;
-        validate_tags(&tags)?;
-        validate_string_len(&walrus_blob_id, "walrus_blob_id")?;
+        validate_string_len(&name, "name")?;
+        require!(
+            description.len() <= MAX_DESCRIPTION_LEN,
+            WalrusFsError::DescriptionTooLong
+        );
+        validate_tags(&tags, &ctx.accounts.walrusfs_root.limits)?;
 
-        let clock = Clock::get()?;
         let root = &mut ctx.accounts.walrusfs_root;
-        let file_arena_data = &mut ctx.accounts.file_arena.data;
-        let dir_arena_data_mut = &mut ctx.accounts.dir_arena.data;
-        let root_children_files_data = &mut ctx.accounts.root_children_files.data;
-        let root_children_dirs_data_ro = &ctx.accounts.root_children_directories.data;
+        root.name = name.clone();
+        root.description = description;
+        root.tags = tags.clone();
 
-        let (parent_dir_id, file_name) = internal_resolve_parent_id_and_name(
-            &path,
-            root_children_dirs_data_ro,
-            dir_arena_data_mut,
-        )?;
+        emit!(FsMetadataUpdatedEvent { name, tags });
+        Ok(())
+    }
+
+    /// Lowers this filesystem's limits. Raising any of them is rejected, since
+    /// existing entries were only checked against the limits in force at the time.
+    /// Only the authority may change them.
+    pub fn set_fs_limits(ctx: Context<UpdateRootSettings>, limits: FsLimits) -> Result<()> {
+        require_keys_eq!(
+            ctx.accounts.walrusfs_root.authority,
+            ctx.accounts.authority.key(),
+            WalrusFsError::Unauthorized
+        );
+        validate_fs_limits(&limits, &ctx.accounts.walrusfs_root.limits)?;
+        ctx.accounts.walrusfs_root.limits = limits;
+
+        emit!(FsLimitsUpdatedEvent { limits });
+        Ok(())
+    }
+
+    /// Makes the filesystem read-only, or writable again, for maintenance,
+    /// audits or archiving. While frozen every instruction that changes entries
+    /// fails with FilesystemFrozen; settings, grants and migrations still work.
+    /// Only the authority may freeze or unfreeze.
+    pub fn set_frozen(ctx: Context<UpdateRootSettings>, frozen: bool) -> Result<()> {
+        require_keys_eq!(
+            ctx.accounts.walrusfs_root.authority,
+            ctx.accounts.authority.key(),
+            WalrusFsError::Unauthorized
+        );
+        ctx.accounts.walrusfs_root.frozen = frozen;
+
+        emit!(FrozenChangedEvent {
+            frozen,
+            changed_by: ctx.accounts.authority.key(),
+        });
+        Ok(())
+    }
+
+    /// Limits which programs may call this filesystem's write instructions
+    /// through CPI to `callers`; None lifts the limit and an empty list allows
+    /// none. A listed program must call in directly from the transaction's top
+    /// level; nested CPIs are refused. Transactions calling the program directly
+    /// are unaffected. Only the authority may change the list.
+    pub fn set_cpi_callers(
+        ctx: Context<UpdateRootSettings>,
+        callers: Option<Vec<Pubkey>>,
+    ) -> Result<()> {
+        require_keys_eq!(
+            ctx.accounts.walrusfs_root.authority,
+            ctx.accounts.authority.key(),
+            WalrusFsError::Unauthorized
+        );
+        if let Some(callers) = &callers {
+            require!(
+                callers.len() <= MAX_CPI_CALLERS,
+                WalrusFsError::TooManyCpiCallers
+            );
+        }
+        ctx.accounts.walrusfs_root.cpi_callers = callers.clone();
+
+        emit!(CpiCallersChangedEvent { callers });
+        Ok(())
+    }
+
+    /// First step of handing the filesystem to another key, such as a rotated key or
+    /// a multisig. `new_authority` takes over once it signs accept_authority_transfer;
+    /// proposing again replaces it and None withdraws it. The root keeps its address,
+    /// so readers still find it by seed_key. Co-authorities cannot propose.
+    pub fn propose_authority_transfer(
+        ctx: Context<UpdateRootSettings>,
+        new_authority: Option<Pubkey>,
+    ) -> Result<()> {
+        let root = &mut ctx.accounts.walrusfs_root;
+        require_keys_eq!(
+            root.authority,
+            ctx.accounts.authority.key(),
+            WalrusFsError::Unauthorized
+        );
+        root.pending_authority = new_authority;
+
+        emit!(AuthorityTransferProposedEvent {
+            authority: root.authority,
+            pending_authority: new_authority,
+        });
+        Ok(())
+    }
+
+    pub fn accept_authority_transfer(ctx: Context<AcceptAuthorityTransfer>) -> Result<()> {
+        let root = &mut ctx.accounts.walrusfs_root;
+        require!(
+            root.pending_authority == Some(ctx.accounts.new_authority.key()),
+            WalrusFsError::NotPendingAuthority
+        );
+        let previous = root.authority;
+        root.authority = ctx.accounts.new_authority.key();
+        root.pending_authority = None;
+        let authority = root.authority;
+        root.co_authorities.retain(|k| *k != authority);
+
+        emit!(AuthorityTransferredEvent {
+            previous,
+            authority: root.authority,
+        });
+        Ok(())
+    }
+
+    /// Lets another key sign writes to this filesystem alongside the authority.
+    /// Only the authority may add one, so co-authorities can't appoint each other.
+    pub fn add_co_authority(ctx: Context<UpdateRootSettings>, co_authority: Pubkey) -> Result<()> {
+        let root = &mut ctx.accounts.walrusfs_root;
+        require_keys_eq!(
+            root.authority,
+            ctx.accounts.authority.key(),
+            WalrusFsError::Unauthorized
+        );
+        require!(
+            co_authority != root.authority && !root.co_authorities.contains(&co_authority),
+            WalrusFsError::AlreadyCoAuthority
+        );
+        require!(
+            root.co_authorities.len() < MAX_CO_AUTHORITIES,
+            WalrusFsError::TooManyCoAuthorities
+        );
+        root.co_authorities.push(co_authority);
+
+        emit!(CoAuthorityAddedEvent {
+            co_authority,
+            added_by: ctx.accounts.authority.key(),
+        });
+        Ok(())
+    }
+
+    /// Only the authority may remove co-authorities. The authority itself cannot be
+    /// removed; transfer it instead.
+    pub fn remove_co_authority(
+        ctx: Context<UpdateRootSettings>,
+        co_authority: Pubkey,
+    ) -> Result<()> {
+        let root = &mut ctx.accounts.walrusfs_root;
+        require_keys_eq!(
+            root.authority,
+            ctx.accounts.authority.key(),
+            WalrusFsError::Unauthorized
+        );
+        let index = root
+            .co_authorities
+            .iter()
+            .position(|k| *k == co_authority)
+            .ok_or(WalrusFsError::NotCoAuthority)?;
+        root.co_authorities.remove(index);
+
+        emit!(CoAuthorityRemovedEvent {
+            co_authority,
+            removed_by: ctx.accounts.authority.key(),
+        });
+        Ok(())
+    }
+
+    /// Lets `delegate` add and change files and directories, and delete files, at
+    /// or below `path_prefix` until `expiry_slot`. The delegate passes the grant as
+    /// writer_grant, and may rename or move files only where the grant covers both
+    /// paths. Deleting, renaming and moving directories, and root settings, stay
+    /// with the authorities.
+    pub fn grant_writer(
+        ctx: Context<GrantWriter>,
+        delegate: Pubkey,
+        path_prefix: String,
+        expiry_slot: u64,
+    ) -> Result<()> {
+        let path_prefix = canonicalize_path(&path_prefix)?;
+        require!(
+            expiry_slot > Clock::get()?.slot,
+            WalrusFsError::WriterGrantExpired
+        );
+
+        let grant = &mut ctx.accounts.writer_grant;
+        grant.schema_version = SCHEMA_VERSION;
+        grant.delegate = delegate;
+        grant.path_prefix = path_prefix.clone();
+        grant.expiry_slot = expiry_slot;
+        grant.bump = ctx.bumps.writer_grant;
+
+        emit!(WriterGrantedEvent {
+            delegate,
+            path_prefix,
+            expiry_slot,
+            granted_by: ctx.accounts.authority.key(),
+        });
+        Ok(())
+    }
+
+    /// Closes a delegate's grant, returning its rent to the signer.
+    pub fn revoke_writer(ctx: Context<RevokeWriter>, delegate: Pubkey) -> Result<()> {
+        emit!(WriterRevokedEvent {
+            delegate,
+            revoked_by: ctx.accounts.authority.key(),
+        });
+        Ok(())
+    }
+
+    /// Records that `grantee` may read at or below `path_prefix` until
+    /// `expiry_slot`. Revoke and grant again to change either.
+    pub fn grant_reader(
+        ctx: Context<GrantReader>,
+        grantee: Pubkey,
+        path_prefix: String,
+        expiry_slot: u64,
+    ) -> Result<()> {
+        let path_prefix = canonicalize_path(&path_prefix)?;
+        require!(
+            expiry_slot > Clock::get()?.slot,
+            WalrusFsError::ReadGrantExpired
+        );
+
+        let grant = &mut ctx.accounts.read_grant;
+        grant.schema_version = SCHEMA_VERSION;
+        grant.grantee = grantee;
+        grant.path_prefix = path_prefix.clone();
+        grant.expiry_slot = expiry_slot;
+        grant.bump = ctx.bumps.read_grant;
+
+        emit!(ReaderGrantedEvent {
+            grantee,
+            path_prefix,
+            expiry_slot,
+            granted_by: ctx.accounts.authority.key(),
+        });
+        Ok(())
+    }
+
+    pub fn revoke_reader(ctx: Context<RevokeReader>, grantee: Pubkey) -> Result<()> {
+        emit!(ReaderRevokedEvent {
+            grantee,
+            revoked_by: ctx.accounts.authority.key(),
+        });
+        Ok(())
+    }
+
+    /// Whether `grantee` may read `path`: authorities always may, anyone else
+    /// needs an unexpired read grant covering it. Pass the grantee's read_grant
+    /// if it exists.
+    pub fn can_read(ctx: Context<CanRead>, grantee: Pubkey, path: String) -> Result<bool> {
+        let path = canonicalize_path(&path)?;
+        if is_fs_writer(&ctx.accounts.walrusfs_root, &grantee) {
+            return Ok(true);
+        }
+        let current_slot = Clock::get()?.slot;
+        Ok(ctx.accounts.read_grant.as_ref().is_some_and(|grant| {
+            grant.expiry_slot > current_slot && path_within(&path, &grant.path_prefix)
+        }))
+    }
+
+    /// Shares `path` with `grantee`, or with anyone given the link when None,
+    /// until `expiry_slot`. `share_key` only names the share: its address is
+    /// public, so restrict it with `grantee` rather than keeping the key secret.
+    pub fn create_share(
+        ctx: Context<CreateShare>,
+        share_key: Pubkey,
+        path: String,
+        grantee: Option<Pubkey>,
+        expiry_slot: u64,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+        require!(
+            expiry_slot > Clock::get()?.slot,
+            WalrusFsError::ShareExpired
+        );
+
+        let share = &mut ctx.accounts.share_grant;
+        share.schema_version = SCHEMA_VERSION;
+        share.path = path.clone();
+        share.grantee = grantee;
+        share.expiry_slot = expiry_slot;
+        share.created_by = ctx.accounts.authority.key();
+        share.bump = ctx.bumps.share_grant;
+
+        emit!(ShareCreatedEvent {
+            share_key,
+            path,
+            grantee,
+            expiry_slot,
+            created_by: ctx.accounts.authority.key(),
+        });
+        Ok(())
+    }
+
+    /// Closes a share before it expires, returning its rent to the signer.
+    pub fn revoke_share(ctx: Context<RevokeShare>, share_key: Pubkey) -> Result<()> {
+        emit!(ShareRevokedEvent {
+            share_key,
+            revoked_by: ctx.accounts.authority.key(),
+        });
+        Ok(())
+    }
+
+    /// Whether the share named by `share_key` lets `wallet` read `path` now.
+    pub fn check_share(
+        ctx: Context<CheckShare>,
+        _share_key: Pubkey,
+        wallet: Pubkey,
+        path: String,
+    ) -> Result<bool> {
+        let path = canonicalize_path(&path)?;
+        let share = &ctx.accounts.share_grant;
+        Ok(share.expiry_slot > Clock::get()?.slot
+            && share.grantee.iter().all(|g| *g == wallet)
+            && path_within(&path, &share.path))
+    }
+
+    /// Mints a capability for `gateway` to serve reads of `path` until
+    /// `expiry_slot`, at most `max_uses` times when set. Like share keys,
+    /// `capability_key` only names it; the gateway binding is what limits use.
+    pub fn mint_capability(
+        ctx: Context<MintCapability>,
+        capability_key: Pubkey,
+        path: String,
+        gateway: Pubkey,
+        expiry_slot: u64,
+        max_uses: Option<u32>,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+        require!(
+            expiry_slot > Clock::get()?.slot,
+            WalrusFsError::CapabilityExpired
+        );
+        require!(
+            max_uses.iter().all(|n| *n > 0),
+            WalrusFsError::CapabilityExhausted
+        );
+
+        let capability = &mut ctx.accounts.capability;
+        capability.schema_version = SCHEMA_VERSION;
+        capability.path = path.clone();
+        capability.expiry_slot = expiry_slot;
+        capability.max_uses = max_uses;
+        capability.uses = 0;
+        capability.gateway = gateway;
+        capability.created_by = ctx.accounts.authority.key();
+        capability.bump = ctx.bumps.capability;
+
+        emit!(CapabilityMintedEvent {
+            capability_key,
+            path,
+            gateway,
+            expiry_slot,
+            max_uses,
+        });
+        Ok(())
+    }
+
+    /// Records one read of `path` under the capability, failing once it has
+    /// expired or used up its max_uses. Only the capability's gateway may call it.
+    pub fn consume_capability(
+        ctx: Context<ConsumeCapability>,
+        capability_key: Pubkey,
+        path: String,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+        let capability = &mut ctx.accounts.capability;
+        require_keys_eq!(
+            ctx.accounts.consumer.key(),
+            capability.gateway,
+            WalrusFsError::Unauthorized
+        );
+        require!(
+            capability.expiry_slot > Clock::get()?.slot,
+            WalrusFsError::CapabilityExpired
+        );
+        require!(
+            capability.max_uses.iter().all(|n| capability.uses < *n),
+            WalrusFsError::CapabilityExhausted
+        );
+        require!(
+            path_within(&path, &capability.path),
+            WalrusFsError::OutsideCapability
+        );
+        capability.uses += 1;
+
+        emit!(CapabilityConsumedEvent {
+            capability_key,
+            path,
+            consumer: ctx.accounts.consumer.key(),
+            uses: capability.uses,
+        });
+        Ok(())
+    }
+
+    /// Closes a capability early, returning its rent to the signer.
+    pub fn revoke_capability(ctx: Context<RevokeCapability>, capability_key: Pubkey) -> Result<()> {
+        emit!(CapabilityRevokedEvent {
+            capability_key,
+            revoked_by: ctx.accounts.authority.key(),
+        });
+        Ok(())
+    }
+
+    /// Lets `session_pubkey` write as the signer at or below `scope`, a path
+    /// prefix, until `expiry_slot`. The session gets no rights the signer lacks.
+    pub fn create_session_key(
+        ctx: Context<CreateSessionKey>,
+        session_pubkey: Pubkey,
+        scope: String,
+        expiry_slot: u64,
+    ) -> Result<()> {
+        let scope = canonicalize_path(&scope)?;
+        require!(
+            expiry_slot > Clock::get()?.slot,
+            WalrusFsError::SessionKeyExpired
+        );
+
+        let session = &mut ctx.accounts.session_key;
+        session.schema_version = SCHEMA_VERSION;
+        session.owner = ctx.accounts.owner.key();
+        session.path_prefix = scope.clone();
+        session.expiry_slot = expiry_slot;
+        session.bump = ctx.bumps.session_key;
+
+        emit!(SessionKeyCreatedEvent {
+            session_key: session_pubkey,
+            owner: ctx.accounts.owner.key(),
+            path_prefix: scope,
+            expiry_slot,
+        });
+        Ok(())
+    }
+
+    /// Ends a session before it expires, returning its rent to its owner.
+    pub fn revoke_session_key(
+        ctx: Context<RevokeSessionKey>,
+        session_pubkey: Pubkey,
+    ) -> Result<()> {
+        emit!(SessionKeyRevokedEvent {
+            session_key: session_pubkey,
+            owner: ctx.accounts.owner.key(),
+        });
+        Ok(())
+    }
+
+    /// Starts the permit nonce of `signer` at zero. Anyone may pay for it,
+    /// usually the relayer submitting that signer's first permit.
+    pub fn init_permit_nonce(ctx: Context<InitPermitNonce>, signer: Pubkey) -> Result<()> {
+        let permit_nonce = &mut ctx.accounts.permit_nonce;
+        permit_nonce.schema_version = SCHEMA_VERSION;
+        permit_nonce.signer = signer;
+        permit_nonce.next_nonce = 0;
+        permit_nonce.bump = ctx.bumps.permit_nonce;
+        Ok(())
+    }
+
+    /// Creates an empty group for chgrp to assign files and dirs to. `admin` may
+    /// add and remove members without being an fs authority.
+    pub fn create_group(ctx: Context<CreateGroup>, name: String, admin: Pubkey) -> Result<()> {
+        require!(
+            !name.is_empty() && name.len() <= MAX_GROUP_NAME_LEN,
+            WalrusFsError::InvalidGroupName
+        );
+
+        let group = &mut ctx.accounts.group;
+        group.schema_version = SCHEMA_VERSION;
+        group.name = name.clone();
+        group.admin = admin;
+        group.members = Vec::new();
+        group.bump = ctx.bumps.group;
+
+        emit!(GroupCreatedEvent {
+            name,
+            admin,
+            created_by: ctx.accounts.authority.key(),
+        });
+        Ok(())
+    }
+
+    pub fn add_group_member(ctx: Context<ManageGroup>, member: Pubkey) -> Result<()> {
+        let group = &mut ctx.accounts.group;
+        require!(
+            !group.members.contains(&member),
+            WalrusFsError::AlreadyGroupMember
+        );
+        require!(
+            group.members.len() < MAX_GROUP_MEMBERS,
+            WalrusFsError::TooManyGroupMembers
+        );
+        group.members.push(member);
+
+        emit!(GroupMemberAddedEvent {
+            name: group.name.clone(),
+            member,
+            added_by: ctx.accounts.authority.key(),
+        });
+        Ok(())
+    }
+
+    pub fn remove_group_member(ctx: Context<ManageGroup>, member: Pubkey) -> Result<()> {
+        let group = &mut ctx.accounts.group;
+        let index = group
+            .members
+            .iter()
+            .position(|k| *k == member)
+            .ok_or(WalrusFsError::NotGroupMember)?;
+        group.members.remove(index);
+
+        emit!(GroupMemberRemovedEvent {
+            name: group.name.clone(),
+            member,
+            removed_by: ctx.accounts.authority.key(),
+        });
+        Ok(())
+    }
+
+    pub fn set_collation(ctx: Context<SetCollation>, collation: Collation) -> Result<()> {
+        let file_arena_data = &mut ctx.accounts.file_arena.data;
+        let dir_arena_data = &mut ctx.accounts.dir_arena.data;
+        let root_children_files_data = &ctx.accounts.root_children_files.data;
+        let root_children_dirs_data = &ctx.accounts.root_children_directories.data;
+
+        let entries = internal_walk_subtree(
+            None,
+            "/",
+            root_children_files_data,
+            root_children_dirs_data,
+            dir_arena_data,
+        )?;
+        for entry in entries {
+            let name = entry.path.rsplit('/').next().unwrap_or_default();
+            let sort_key = compute_sort_key(name, collation);
+            if entry.is_dir {
+                get_mut_from_dir_arena(dir_arena_data, entry.id)
+                    .ok_or(WalrusFsError::ArenaMismatchError)?
+                    .sort_key = sort_key;
+            } else {
+                get_mut_from_file_arena(file_arena_data, entry.id)
+                    .ok_or(WalrusFsError::ArenaMismatchError)?
+                    .sort_key = sort_key;
+            }
+        }
+
+        ctx.accounts.walrusfs_root.collation = collation;
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_file(
+        ctx: Context<AddFile>,
+        path: String,
+        tags: Vec<String>,
+        size: u64,
+        walrus_blob_id: [u8; 32],
+        end_epoch: u64,
+        sha256: Option<[u8; 32]>,
+        content_type: String,
+        preview_blob_id: Option<String>,
+        storage_class: StorageClass,
+        sui_object_id: Option<[u8; 32]>,
+        encryption: Option<EncryptionInfo>,
+        overwrite: bool,
+        expect_unlocked: bool,
+        expected_version: Option<u64>,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+        let limits = ctx.accounts.walrusfs_root.limits;
+        require_path_depth(path_depth(&path), &limits)?;
+        validate_tags(&tags, &limits)?;
+        validate_string_len(&content_type, "content_type")?;
+        validate_encryption(&encryption)?;
+        let encrypted = encryption.is_some();
+        if let Some(preview_blob_id) = &preview_blob_id {
+            validate_string_len(preview_blob_id, "preview_blob_id")?;
+        }
+        let root_version = root_dir_version(
+            &ctx.accounts.root_children_files,
+            &ctx.accounts.root_children_directories,
+        );
+
+        let thresholds = ctx.accounts.walrusfs_root.space_warning_pcts;
+        let file_arena_pct = space_used_pct(&ctx.accounts.file_arena)?;
+        let dir_arena_pct = space_used_pct(&ctx.accounts.dir_arena)?;
+        let root_files_pct = space_used_pct(&ctx.accounts.root_children_files)?;
+
+        let clock = Clock::get()?;
+        let root = &mut ctx.accounts.walrusfs_root;
+        let file_arena_data = &mut ctx.accounts.file_arena.data;
+        let dir_arena_data_mut = &mut ctx.accounts.dir_arena.data;
+        let root_children_files_data = &mut ctx.accounts.root_children_files.data;
+        let root_children_dirs_data_ro = &ctx.accounts.root_children_directories.data;
+
+        let (parent_dir_id, file_name) = internal_resolve_parent_id_and_name(
+            &path,
+            root_children_dirs_data_ro,
+            dir_arena_data_mut,
+        )?;
+        require_dir_version(
+            parent_dir_id,
+            root_version,
+            dir_arena_data_mut,
+            expected_version,
+        )?;
+        let (parent_mode, parent_group) = dir_access(parent_dir_id, dir_arena_data_mut)?;
+        // Overwriting changes the existing file, so it is checked like any other
+        // change to that file
+        let replaced = internal_resolve_file_id(
+            &path,
+            root_children_files_data,
+            root_children_dirs_data_ro,
+            dir_arena_data_mut,
+        )
+        .ok()
+        .filter(|_| overwrite)
+        .and_then(|id| get_from_file_arena(file_arena_data, id));
+        match replaced {
+            Some(f) => require_file_writer(
+                root,
+                &ctx.accounts.writer_grant,
+                &ctx.accounts.group,
+                &ctx.accounts.session_key,
+                f,
+                ctx.accounts.authority.key,
+                &path,
+            )?,
+            None => require_writable(
+                root,
+                &ctx.accounts.writer_grant,
+                &ctx.accounts.group,
+                &ctx.accounts.session_key,
+                ctx.accounts.authority.key,
+                &path,
+                parent_mode,
+                parent_group,
+            )?,
+        }
+        let tag_dictionary = &mut ctx.accounts.tag_dictionary.tags;
+        let tags = merge_default_tags(
+            intern_tags(tag_dictionary, &tags)?,
+            &internal_inherited_default_tags(
+                parent_dir_path(&path),
+                root_children_dirs_data_ro,
+                dir_arena_data_mut,
+            )?,
+            &limits,
+        );
 
         let children_files_map: &mut Vec<KeyValueStringU64> = match parent_dir_id {
             Some(id) => {
@@ -288,6 +1746,13 @@ pub mod walrusfs_anchor {
             None => root_children_files_data,
         };
 
+        let mut replaced_bytes = 0;
+        let mut removed_files = Vec::new();
+        // A new file belongs to the fs authority under its parent's access; a
+        // replacement keeps the one it replaces, token gate included
+        let (mut owner, mut mode, mut group) =
+            (root.authority, file_mode_under(parent_mode), parent_group);
+        let mut access_policy = None;
         if let Some(existing_file_id) = get_from_vec_str_key(children_files_map, &file_name) {
             if !overwrite {
                 let f = get_from_file_arena(file_arena_data, *existing_file_id)
@@ -295,15 +1760,26 @@ pub mod walrusfs_anchor {
                 emit!(FileAlreadyExistsEvent {
                     path: path.clone(),
                     create_ts: f.create_ts,
-                    tags: f.tags.clone(),
+                    tags: tag_names(tag_dictionary, &f.tags),
                     size: f.size,
-                    walrus_blob_id: f.walrus_blob_id.clone(),
+                    walrus_blob_id: f.walrus_blob_id,
                     walrus_epoch_till: f.walrus_epoch_till,
                 });
                 return err!(WalrusFsError::FileAlreadyExists);
             } else {
-                // Remove from arena, id will be removed from children_files_map by insert_into_vec_str_key later
-                remove_from_file_arena(file_arena_data, &existing_file_id);
+                require_unsealed(file_arena_data, *existing_file_id)?;
+                let existing = get_from_file_arena(file_arena_data, *existing_file_id)
+                    .ok_or(WalrusFsError::ArenaMismatchError)?;
+                require_unlocked(
+                    existing,
+                    expect_unlocked,
+                    acting_signer(&ctx.accounts.session_key, ctx.accounts.authority.key, &path)?,
+                )?;
+                replaced_bytes = existing.size;
+                (owner, mode, group) = (existing.owner, existing.mode, existing.group);
+                access_policy = existing.access_policy.clone();
+                // Unlink from arena, id will be replaced in children_files_map by insert_into_vec_str_key later
+                removed_files.extend(internal_unlink_file(file_arena_data, *existing_file_id)?);
                 // Also explicitly remove from children_files_map before re-inserting if overwrite means true replacement.
                 // However, insert_into_vec_str_key will update the value, which is what we want for the ID.
                 // The key (file_name) remains, value (ID) changes.
@@ -316,57 +1792,490 @@ pub mod walrusfs_anchor {
 
         let new_file = FileObjectAnchor {
             create_ts: now,
+            modify_ts: now,
             tags: tags.clone(),
             size,
-            walrus_blob_id: walrus_blob_id.clone(),
+            walrus_blob_id,
             walrus_epoch_till: end_epoch,
+            deletable: false,
+            renewal_policy: root.default_renewal_policy,
+            sort_key: compute_sort_key(&file_name, root.collation),
+            symlink_target: None,
+            journal_segments: None,
+            chunks: None,
+            link_count: 1,
+            sealed: false,
+            seal_permanent: false,
+            lock: None,
+            access_ts: None,
+            access_count: 0,
+            sha256,
+            content_type,
+            preview_blob_id,
+            sui_object_id,
+            encryption,
+            storage_class,
+            xattrs: Vec::new(),
+            has_description: false,
+            pinned: false,
+            kind: ObjectKind::RegularFile,
+            status: BlobStatus::PendingCertification,
+            owner,
+            mode,
+            group,
+            access_policy,
+            sale: None,
         };
         insert_into_file_arena(file_arena_data, new_file_id, new_file);
-        insert_into_vec_str_key(children_files_map, file_name.clone(), new_file_id);
+        insert_child(children_files_map, file_name.clone(), new_file_id, &limits)?;
+        bump_dir_version(
+            parent_dir_id,
+            &mut ctx.accounts.root_children_files.version,
+            dir_arena_data_mut,
+        )?;
+        adjust_subtree_bytes(
+            parent_dir_path(&path),
+            size,
+            replaced_bytes,
+            root_children_dirs_data_ro,
+            dir_arena_data_mut,
+        )?;
+        // After the insert, so overwriting with the same blob keeps it
+        internal_enqueue_unreferenced_blobs(
+            removed_files,
+            file_arena_data,
+            ctx.accounts
+                .pending_blob_deletes
+                .as_deref_mut()
+                .map(|q| &mut **q),
+        )?;
 
         emit!(FileAddedEvent {
             path,
             create_ts: now,
-            tags,
+            tags: tag_names(tag_dictionary, &tags),
             size,
             walrus_blob_id,
             walrus_epoch_till: end_epoch,
+            encrypted,
         });
 
+        emit_space_low_if_crossed(&ctx.accounts.file_arena, file_arena_pct, &thresholds)?;
+        emit_space_low_if_crossed(&ctx.accounts.dir_arena, dir_arena_pct, &thresholds)?;
+        emit_space_low_if_crossed(
+            &ctx.accounts.root_children_files,
+            root_files_pct,
+            &thresholds,
+        )?;
         Ok(())
     }
 
-    pub fn add_dir(ctx: Context<AddDir>, path: String, tags: Vec<String>) -> Result<()> {
-        let clean_path = remove_trailing_slash(&path);
-        validate_path(&clean_path)?;
-        validate_tags(&tags)?;
+    /// Adds a symlink entry pointing at `target`, which need not exist yet.
+    pub fn add_symlink(
+        ctx: Context<AddFile>,
+        path: String,
+        target: String,
+        tags: Vec<String>,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+        let target = canonicalize_path(&target)?;
+        let limits = ctx.accounts.walrusfs_root.limits;
+        require_path_depth(path_depth(&path), &limits)?;
+        validate_tags(&tags, &limits)?;
+        require!(path != target, WalrusFsError::SymlinkLoop);
+
+        let thresholds = ctx.accounts.walrusfs_root.space_warning_pcts;
+        let file_arena_pct = space_used_pct(&ctx.accounts.file_arena)?;
 
         let clock = Clock::get()?;
         let root = &mut ctx.accounts.walrusfs_root;
+        let file_arena_data = &mut ctx.accounts.file_arena.data;
         let dir_arena_data = &mut ctx.accounts.dir_arena.data;
-        let root_children_dirs_data = &mut ctx.accounts.root_children_directories.data;
+        let root_children_files_data = &mut ctx.accounts.root_children_files.data;
+        let root_children_dirs_data_ro = &ctx.accounts.root_children_directories.data;
 
-        let (parent_dir_id, dir_name) = internal_resolve_parent_id_and_name(
-            &clean_path,
-            root_children_dirs_data,
+        let (parent_dir_id, link_name) =
+            internal_resolve_parent_id_and_name(&path, root_children_dirs_data_ro, dir_arena_data)?;
+        let (parent_mode, parent_group) = dir_access(parent_dir_id, dir_arena_data)?;
+        require_writable(
+            root,
+            &ctx.accounts.writer_grant,
+            &ctx.accounts.group,
+            &ctx.accounts.session_key,
+            ctx.accounts.authority.key,
+            &path,
+            parent_mode,
+            parent_group,
+        )?;
+        let tag_dictionary = &mut ctx.accounts.tag_dictionary.tags;
+        let tags = merge_default_tags(
+            intern_tags(tag_dictionary, &tags)?,
+            &internal_inherited_default_tags(
+                parent_dir_path(&path),
+                root_children_dirs_data_ro,
+                dir_arena_data,
+            )?,
+            &limits,
+        );
+        let children_files_map =
+            internal_children_files_mut(parent_dir_id, root_children_files_data, dir_arena_data)?;
+        require!(
+            !contains_key_in_vec_str(children_files_map, &link_name),
+            WalrusFsError::FileAlreadyExists
+        );
+
+        root.obj_id_counter += 1;
+        let new_file_id = root.obj_id_counter;
+        let now = clock.unix_timestamp as u64 * 1000;
+        let link = FileObjectAnchor {
+            create_ts: now,
+            modify_ts: now,
+            tags,
+            size: 0,
+            walrus_blob_id: NO_BLOB_ID,
+            walrus_epoch_till: 0,
+            deletable: false,
+            renewal_policy: RenewalPolicy::Never,
+            sort_key: compute_sort_key(&link_name, root.collation),
+            symlink_target: Some(target.clone()),
+            journal_segments: None,
+            chunks: None,
+            link_count: 1,
+            sealed: false,
+            seal_permanent: false,
+            lock: None,
+            access_ts: None,
+            access_count: 0,
+            sha256: None,
+            content_type: String::new(),
+            preview_blob_id: None,
+            sui_object_id: None,
+            encryption: None,
+            storage_class: StorageClass::default(),
+            xattrs: Vec::new(),
+            has_description: false,
+            pinned: false,
+            kind: ObjectKind::Symlink,
+            status: BlobStatus::Certified, // No blob of its own to wait for
+            owner: root.authority,
+            mode: file_mode_under(parent_mode),
+            group: parent_group,
+            access_policy: None,
+            sale: None,
+        };
+        insert_into_file_arena(file_arena_data, new_file_id, link);
+        insert_child(children_files_map, link_name, new_file_id, &limits)?;
+        bump_dir_version(
+            parent_dir_id,
+            &mut ctx.accounts.root_children_files.version,
             dir_arena_data,
         )?;
 
-        let existing: Option<u64>;
-        let children_dirs_map: &mut Vec<KeyValueStringU64> = match parent_dir_id {
-            Some(id) => {
-                let parent_dir = get_mut_from_dir_arena(dir_arena_data, id)
-                    .ok_or(WalrusFsError::ArenaMismatchError)?;
-                &mut parent_dir.children_directories
-            }
-            None => root_children_dirs_data,
+        emit!(SymlinkAddedEvent { path, target });
+
+        emit_space_low_if_crossed(&ctx.accounts.file_arena, file_arena_pct, &thresholds)?;
+        Ok(())
+    }
+
+    /// Adds an empty append-only journal; content is added with append_segment.
+    pub fn add_journal(ctx: Context<AddFile>, path: String, tags: Vec<String>) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+        let limits = ctx.accounts.walrusfs_root.limits;
+        require_path_depth(path_depth(&path), &limits)?;
+        validate_tags(&tags, &limits)?;
+
+        let thresholds = ctx.accounts.walrusfs_root.space_warning_pcts;
+        let file_arena_pct = space_used_pct(&ctx.accounts.file_arena)?;
+
+        let clock = Clock::get()?;
+        let root = &mut ctx.accounts.walrusfs_root;
+        let file_arena_data = &mut ctx.accounts.file_arena.data;
+        let dir_arena_data = &mut ctx.accounts.dir_arena.data;
+        let root_children_files_data = &mut ctx.accounts.root_children_files.data;
+        let root_children_dirs_data_ro = &ctx.accounts.root_children_directories.data;
+
+        let (parent_dir_id, journal_name) =
+            internal_resolve_parent_id_and_name(&path, root_children_dirs_data_ro, dir_arena_data)?;
+        let (parent_mode, parent_group) = dir_access(parent_dir_id, dir_arena_data)?;
+        require_writable(
+            root,
+            &ctx.accounts.writer_grant,
+            &ctx.accounts.group,
+            &ctx.accounts.session_key,
+            ctx.accounts.authority.key,
+            &path,
+            parent_mode,
+            parent_group,
+        )?;
+        let tag_dictionary = &mut ctx.accounts.tag_dictionary.tags;
+        let tags = merge_default_tags(
+            intern_tags(tag_dictionary, &tags)?,
+            &internal_inherited_default_tags(
+                parent_dir_path(&path),
+                root_children_dirs_data_ro,
+                dir_arena_data,
+            )?,
+            &limits,
+        );
+        let children_files_map =
+            internal_children_files_mut(parent_dir_id, root_children_files_data, dir_arena_data)?;
+        require!(
+            !contains_key_in_vec_str(children_files_map, &journal_name),
+            WalrusFsError::FileAlreadyExists
+        );
+
+        root.obj_id_counter += 1;
+        let new_file_id = root.obj_id_counter;
+        let now = clock.unix_timestamp as u64 * 1000;
+        let journal = FileObjectAnchor {
+            create_ts: now,
+            modify_ts: now,
+            tags,
+            size: 0,
+            walrus_blob_id: NO_BLOB_ID,
+            walrus_epoch_till: 0,
+            deletable: false,
+            renewal_policy: RenewalPolicy::Never,
+            sort_key: compute_sort_key(&journal_name, root.collation),
+            symlink_target: None,
+            journal_segments: Some(Vec::new()),
+            chunks: None,
+            link_count: 1,
+            sealed: false,
+            seal_permanent: false,
+            lock: None,
+            access_ts: None,
+            access_count: 0,
+            sha256: None,
+            content_type: String::new(),
+            preview_blob_id: None,
+            sui_object_id: None,
+            encryption: None,
+            storage_class: StorageClass::default(),
+            xattrs: Vec::new(),
+            has_description: false,
+            pinned: false,
+            kind: ObjectKind::Journal,
+            status: BlobStatus::Certified, // No blob of its own to wait for
+            owner: root.authority,
+            mode: file_mode_under(parent_mode),
+            group: parent_group,
+            access_policy: None,
+            sale: None,
         };
+        insert_into_file_arena(file_arena_data, new_file_id, journal);
+        insert_child(children_files_map, journal_name, new_file_id, &limits)?;
+        bump_dir_version(
+            parent_dir_id,
+            &mut ctx.accounts.root_children_files.version,
+            dir_arena_data,
+        )?;
+
+        emit!(JournalAddedEvent { path });
+
+        emit_space_low_if_crossed(&ctx.accounts.file_arena, file_arena_pct, &thresholds)?;
+        Ok(())
+    }
+
+    /// Adds an empty chunked file; chunks are attached in order with add_chunk.
+    pub fn add_chunked_file(
+        ctx: Context<AddFile>,
+        path: String,
+        tags: Vec<String>,
+        end_epoch: u64,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+        let limits = ctx.accounts.walrusfs_root.limits;
+        require_path_depth(path_depth(&path), &limits)?;
+        validate_tags(&tags, &limits)?;
+
+        let thresholds = ctx.accounts.walrusfs_root.space_warning_pcts;
+        let file_arena_pct = space_used_pct(&ctx.accounts.file_arena)?;
+
+        let clock = Clock::get()?;
+        let root = &mut ctx.accounts.walrusfs_root;
+        let file_arena_data = &mut ctx.accounts.file_arena.data;
+        let dir_arena_data = &mut ctx.accounts.dir_arena.data;
+        let root_children_files_data = &mut ctx.accounts.root_children_files.data;
+        let root_children_dirs_data_ro = &ctx.accounts.root_children_directories.data;
 
-        existing = get_from_vec_str_key(children_dirs_map, &dir_name).copied();
+        let (parent_dir_id, file_name) =
+            internal_resolve_parent_id_and_name(&path, root_children_dirs_data_ro, dir_arena_data)?;
+        let (parent_mode, parent_group) = dir_access(parent_dir_id, dir_arena_data)?;
+        require_writable(
+            root,
+            &ctx.accounts.writer_grant,
+            &ctx.accounts.group,
+            &ctx.accounts.session_key,
+            ctx.accounts.authority.key,
+            &path,
+            parent_mode,
+            parent_group,
+        )?;
+        let tag_dictionary = &mut ctx.accounts.tag_dictionary.tags;
+        let tags = merge_default_tags(
+            intern_tags(tag_dictionary, &tags)?,
+            &internal_inherited_default_tags(
+                parent_dir_path(&path),
+                root_children_dirs_data_ro,
+                dir_arena_data,
+            )?,
+            &limits,
+        );
+        let children_files_map =
+            internal_children_files_mut(parent_dir_id, root_children_files_data, dir_arena_data)?;
+        require!(
+            !contains_key_in_vec_str(children_files_map, &file_name),
+            WalrusFsError::FileAlreadyExists
+        );
 
         root.obj_id_counter += 1;
-        let new_dir_id = root.obj_id_counter;
-        insert_into_vec_str_key(children_dirs_map, dir_name.clone(), new_dir_id);
+        let new_file_id = root.obj_id_counter;
+        let now = clock.unix_timestamp as u64 * 1000;
+        let chunked_file = FileObjectAnchor {
+            create_ts: now,
+            modify_ts: now,
+            tags: tags.clone(),
+            size: 0,
+            walrus_blob_id: NO_BLOB_ID,
+            walrus_epoch_till: end_epoch,
+            deletable: false,
+            renewal_policy: root.default_renewal_policy,
+            sort_key: compute_sort_key(&file_name, root.collation),
+            symlink_target: None,
+            journal_segments: None,
+            chunks: Some(Vec::new()),
+            link_count: 1,
+            sealed: false,
+            seal_permanent: false,
+            lock: None,
+            access_ts: None,
+            access_count: 0,
+            sha256: None,
+            content_type: String::new(),
+            preview_blob_id: None,
+            sui_object_id: None,
+            encryption: None,
+            storage_class: StorageClass::default(),
+            xattrs: Vec::new(),
+            has_description: false,
+            pinned: false,
+            kind: ObjectKind::ChunkedFile,
+            status: BlobStatus::PendingCertification,
+            owner: root.authority,
+            mode: file_mode_under(parent_mode),
+            group: parent_group,
+            access_policy: None,
+            sale: None,
+        };
+        insert_into_file_arena(file_arena_data, new_file_id, chunked_file);
+        insert_child(children_files_map, file_name, new_file_id, &limits)?;
+        bump_dir_version(
+            parent_dir_id,
+            &mut ctx.accounts.root_children_files.version,
+            dir_arena_data,
+        )?;
+
+        emit!(FileAddedEvent {
+            path,
+            create_ts: now,
+            tags: tag_names(tag_dictionary, &tags),
+            size: 0,
+            walrus_blob_id: NO_BLOB_ID,
+            walrus_epoch_till: end_epoch,
+            encrypted: false, // Chunks carry no encryption metadata of their own
+        });
+
+        emit_space_low_if_crossed(&ctx.accounts.file_arena, file_arena_pct, &thresholds)?;
+        Ok(())
+    }
+
+    /// Adds several files under one existing parent directory. Entries whose name
+    /// already exists are skipped rather than failing the whole batch.
+    pub fn batch_add_files(
+        ctx: Context<AddFile>,
+        parent_path: String,
+        entries: Vec<AddFileEntry>,
+    ) -> Result<()> {
+        let signer = ctx.accounts.authority.key();
+        internal_batch_add_files(ctx.accounts, &signer, parent_path, entries)
+    }
+
+    /// batch_add_files on behalf of `permit.signer`, who signed the permit off
+    /// chain; the transaction signer only relays it and pays the fees.
+    pub fn batch_add_files_with_permit(
+        ctx: Context<AddFile>,
+        permit: Permit,
+        parent_path: String,
+        entries: Vec<AddFileEntry>,
+    ) -> Result<()> {
+        let instruction_data = crate::instruction::BatchAddFiles {
+            parent_path: parent_path.clone(),
+            entries: entries.clone(),
+        }
+        .data();
+        use_permit(
+            &permit,
+            &instruction_data,
+            &ctx.accounts.walrusfs_root.key(),
+            ctx.accounts.authority.key,
+            &mut ctx.accounts.permit_nonce,
+            &ctx.accounts.instructions,
+        )?;
+        internal_batch_add_files(ctx.accounts, &permit.signer, parent_path, entries)
+    }
+
+    pub fn add_dir(
+        ctx: Context<AddDir>,
+        path: String,
+        tags: Vec<String>,
+        if_not_exists: bool,
+        expected_version: Option<u64>,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+        let limits = ctx.accounts.walrusfs_root.limits;
+        require_path_depth(path_depth(&path), &limits)?;
+        validate_tags(&tags, &limits)?;
+        let root_version = root_dir_version(
+            &ctx.accounts.root_children_files,
+            &ctx.accounts.root_children_directories,
+        );
+
+        let thresholds = ctx.accounts.walrusfs_root.space_warning_pcts;
+        let dir_arena_pct = space_used_pct(&ctx.accounts.dir_arena)?;
+        let root_dirs_pct = space_used_pct(&ctx.accounts.root_children_directories)?;
+
+        let clock = Clock::get()?;
+        let root = &mut ctx.accounts.walrusfs_root;
+        let dir_arena_data = &mut ctx.accounts.dir_arena.data;
+        let root_children_dirs_data = &mut ctx.accounts.root_children_directories.data;
+
+        let (parent_dir_id, dir_name) =
+            internal_resolve_parent_id_and_name(&path, root_children_dirs_data, dir_arena_data)?;
+        require_dir_version(
+            parent_dir_id,
+            root_version,
+            dir_arena_data,
+            expected_version,
+        )?;
+
+        let (parent_mode, parent_group) = dir_access(parent_dir_id, dir_arena_data)?;
+        require_writable(
+            root,
+            &ctx.accounts.writer_grant,
+            &ctx.accounts.group,
+            &ctx.accounts.session_key,
+            ctx.accounts.authority.key,
+            &path,
+            parent_mode,
+            parent_group,
+        )?;
+
+        let existing = get_from_vec_str_key(
+            internal_children_dirs(parent_dir_id, root_children_dirs_data, dir_arena_data)?,
+            &dir_name,
+        )
+        .copied();
 
         if let Some(existing_dir_id) = existing {
             let d = get_from_dir_arena(dir_arena_data, existing_dir_id)
@@ -374,150 +2283,381 @@ pub mod walrusfs_anchor {
             emit!(DirAlreadyExistsEvent {
                 path: path.clone(),
                 create_ts: d.create_ts,
-                tags: d.tags.clone(),
+                tags: tag_names(&ctx.accounts.tag_dictionary.tags, &d.tags),
             });
+            if if_not_exists {
+                return Ok(());
+            }
             return err!(WalrusFsError::DirectoryAlreadyExists);
         }
 
+        root.obj_id_counter += 1;
+        let new_dir_id = root.obj_id_counter;
+        let children_dirs_map =
+            internal_children_dirs_mut(parent_dir_id, root_children_dirs_data, dir_arena_data)?;
+        insert_child(children_dirs_map, dir_name.clone(), new_dir_id, &limits)?;
+
         let now = clock.unix_timestamp as u64 * 1000;
         let new_dir = DirObjectAnchor {
             create_ts: now,
-            tags: tags.clone(),
+            modify_ts: now,
+            tags: intern_tags(&mut ctx.accounts.tag_dictionary.tags, &tags)?,
+            default_tags: Vec::new(),
+            sort_key: compute_sort_key(&dir_name, root.collation),
+            version: 0,
+            xattrs: Vec::new(),
+            has_description: false,
+            pinned: false,
             children_files: Vec::new(),       // Changed
             children_directories: Vec::new(), // Changed
+            child_file_count: 0,
+            child_dir_count: 0,
+            subtree_bytes: 0,
+            mode: parent_mode, // Inherited, so a writable dir stays writable below
+            group: parent_group,
+            mount: None,
+            access_policy: None,
         };
         insert_into_dir_arena(dir_arena_data, new_dir_id, new_dir);
+        bump_dir_version(
+            parent_dir_id,
+            &mut ctx.accounts.root_children_directories.version,
+            dir_arena_data,
+        )?;
 
         emit!(DirAddedEvent {
             path,
             create_ts: now,
             tags
         });
+
+        emit_space_low_if_crossed(&ctx.accounts.dir_arena, dir_arena_pct, &thresholds)?;
+        emit_space_low_if_crossed(
+            &ctx.accounts.root_children_directories,
+            root_dirs_pct,
+            &thresholds,
+        )?;
         Ok(())
     }
 
-    pub fn list_dir(ctx: Context<ListDir>, path: String) -> Result<Vec<DirListObjectAnchor>> {
-        let path_with_slash = ensure_trailing_slash(&path);
-        validate_path(&path_with_slash)?;
-
-        let file_arena_data = &ctx.accounts.file_arena.data;
-        let dir_arena_data = &ctx.accounts.dir_arena.data;
-        let root_children_files_data = &ctx.accounts.root_children_files.data;
-        let root_children_dirs_data = &ctx.accounts.root_children_directories.data;
+    /// Creates every missing directory along `path`, like `mkdir -p`. `tags` are
+    /// applied to the final directory only; intermediate directories are untagged.
+    /// Returns the ids of the directories created, outermost first.
+    pub fn add_dir_recursive(
+        ctx: Context<AddDir>,
+        path: String,
+        tags: Vec<String>,
+    ) -> Result<Vec<u64>> {
+        let path = canonicalize_path(&path)?;
+        let limits = ctx.accounts.walrusfs_root.limits;
+        require_path_depth(path_depth(&path), &limits)?;
+        validate_tags(&tags, &limits)?;
 
-        let (target_dir_files_vec, target_dir_dirs_vec) = internal_get_dir_children_refs(
-            &path_with_slash,
-            root_children_files_data,
-            root_children_dirs_data,
-            dir_arena_data,
-        )?;
+        let thresholds = ctx.accounts.walrusfs_root.space_warning_pcts;
+        let dir_arena_pct = space_used_pct(&ctx.accounts.dir_arena)?;
+        let root_dirs_pct = space_used_pct(&ctx.accounts.root_children_directories)?;
 
-        let mut results = Vec::new();
+        let clock = Clock::get()?;
+        let now = clock.unix_timestamp as u64 * 1000;
+        let root = &mut ctx.accounts.walrusfs_root;
+        let dir_arena_data = &mut ctx.accounts.dir_arena.data;
+        let root_children_dirs_data = &mut ctx.accounts.root_children_directories.data;
 
-        for kv_pair in target_dir_dirs_vec.iter() {
-            // Iterate over Vec<KeyValueStringU64>
-            let d = get_from_dir_arena(dir_arena_data, kv_pair.value)
-                .ok_or(WalrusFsError::ArenaMismatchError)?;
-            results.push(DirListObjectAnchor {
-                name: kv_pair.key.clone(),
-                create_ts: d.create_ts,
-                is_dir: true,
-                tags: d.tags.clone(),
-                size: 0,
-                walrus_blob_id: String::new(),
-                walrus_epoch_till: 0,
-            });
+        let components = path
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<&str>>();
+        if components.is_empty() {
+            return err!(WalrusFsError::InvalidPathOperationOnRoot);
         }
 
-        for kv_pair in target_dir_files_vec.iter() {
-            // Iterate over Vec<KeyValueStringU64>
-            let f = get_from_file_arena(file_arena_data, kv_pair.value)
-                .ok_or(WalrusFsError::ArenaMismatchError)?;
-            results.push(DirListObjectAnchor {
-                name: kv_pair.key.clone(),
-                create_ts: f.create_ts,
-                is_dir: false,
-                tags: f.tags.clone(),
-                size: f.size,
-                walrus_blob_id: f.walrus_blob_id.clone(),
-                walrus_epoch_till: f.walrus_epoch_till,
+        let mut created_ids = Vec::new();
+        let mut touched_parents = Vec::new();
+        let mut current_parent_id: Option<u64> = None;
+        let mut current_path = String::new();
+        for (i, component) in components.iter().enumerate() {
+            validate_string_len(component, "name")?;
+            current_path = join_path(&current_path, component);
+
+            let (parent_mode, parent_group) = dir_access(current_parent_id, dir_arena_data)?;
+            let children_dirs_vec = internal_children_dirs_mut(
+                current_parent_id,
+                root_children_dirs_data,
+                dir_arena_data,
+            )?;
+            if let Some(existing_id) = get_from_vec_str_key(children_dirs_vec, component) {
+                current_parent_id = Some(*existing_id);
+                continue;
+            }
+            // Delegates may only create the components that fall under their grant
+            require_writable(
+                root,
+                &ctx.accounts.writer_grant,
+                &ctx.accounts.group,
+                &ctx.accounts.session_key,
+                ctx.accounts.authority.key,
+                &current_path,
+                parent_mode,
+                parent_group,
+            )?;
+
+            root.obj_id_counter += 1;
+            let new_dir_id = root.obj_id_counter;
+            insert_child(
+                children_dirs_vec,
+                component.to_string(),
+                new_dir_id,
+                &limits,
+            )?;
+            touched_parents.push(current_parent_id);
+
+            let dir_tags = if i == components.len() - 1 {
+                tags.clone()
+            } else {
+                Vec::new()
+            };
+            let new_dir = DirObjectAnchor {
+                create_ts: now,
+                modify_ts: now,
+                tags: intern_tags(&mut ctx.accounts.tag_dictionary.tags, &dir_tags)?,
+                default_tags: Vec::new(),
+                sort_key: compute_sort_key(component, root.collation),
+                version: 0,
+                xattrs: Vec::new(),
+                has_description: false,
+                pinned: false,
+                children_files: Vec::new(),
+                children_directories: Vec::new(),
+                child_file_count: 0,
+                child_dir_count: 0,
+                subtree_bytes: 0,
+                mode: parent_mode,
+                group: parent_group,
+                mount: None,
+                access_policy: None,
+            };
+            insert_into_dir_arena(dir_arena_data, new_dir_id, new_dir);
+
+            emit!(DirAddedEvent {
+                path: current_path.clone(),
+                create_ts: now,
+                tags: dir_tags
             });
+            created_ids.push(new_dir_id);
+            current_parent_id = Some(new_dir_id);
         }
-        Ok(results)
+        for parent_id in touched_parents {
+            bump_dir_version(
+                parent_id,
+                &mut ctx.accounts.root_children_directories.version,
+                dir_arena_data,
+            )?;
+        }
+
+        emit_space_low_if_crossed(&ctx.accounts.dir_arena, dir_arena_pct, &thresholds)?;
+        emit_space_low_if_crossed(
+            &ctx.accounts.root_children_directories,
+            root_dirs_pct,
+            &thresholds,
+        )?;
+        Ok(created_ids)
     }
 
-    pub fn stat(ctx: Context<Stat>, path: String) -> Result<DirListObjectAnchor> {
-        let clean_path = remove_trailing_slash(&path);
-        validate_path(&clean_path)?;
+    /// Lists one page of a directory. `limit` of 0 returns everything from `offset` on.
+    /// With `dereference`, a symlink as the final component is followed to its target.
+    #[allow(clippy::too_many_arguments)]
+    pub fn list_dir(
+        ctx: Context<ListDir>,
+        path: String,
+        offset: u32,
+        limit: u32,
+        sort_by: ListSortBy,
+        descending: bool,
+        filter: ListFilter,
+        dereference: bool,
+    ) -> Result<DirListPageAnchor> {
+        let path = canonicalize_path(&path)?;
+        let local = FsView {
+            root_children_files: &ctx.accounts.root_children_files,
+            root_children_directories: &ctx.accounts.root_children_directories,
+            file_arena: &ctx.accounts.file_arena.data,
+            dir_arena: &ctx.accounts.dir_arena.data,
+            tag_dictionary: &ctx.accounts.tag_dictionary.tags,
+            collation: ctx.accounts.walrusfs_root.collation,
+        };
 
-        let file_arena_data = &ctx.accounts.file_arena.data;
-        let dir_arena_data = &ctx.accounts.dir_arena.data;
-        let root_children_files_data = &ctx.accounts.root_children_files.data;
-        let root_children_dirs_data = &ctx.accounts.root_children_directories.data;
+        let path = if dereference {
+            internal_follow_symlinks(path, &local)?
+        } else {
+            path
+        };
+        let (entries, version) = match internal_find_mount(
+            &path,
+            true,
+            &local.root_children_directories.data,
+            local.dir_arena,
+        )? {
+            Some((mount, mounted_path)) => {
+                let mounted = load_mounted_fs(&mount, ctx.remaining_accounts)?;
+                let view = mounted.view();
+                let mounted_path = if dereference {
+                    internal_follow_symlinks(mounted_path, &view)?
+                } else {
+                    mounted_path
+                };
+                internal_list_dir(&mounted_path, &view, sort_by, descending, &filter)?
+            }
+            None => internal_list_dir(&path, &local, sort_by, descending, &filter)?,
+        };
+        Ok(paginate_listing(entries, offset, limit, version))
+    }
 
-        let (parent_dir_id, item_name) = internal_resolve_parent_id_and_name(
-            &clean_path,
-            root_children_dirs_data,
-            dir_arena_data,
-        )?;
+    /// With `dereference`, a symlink as the final component is followed to its target.
+    pub fn stat(
+        ctx: Context<Stat>,
+        path: String,
+        dereference: bool,
+    ) -> Result<DirListObjectAnchor> {
+        let path = canonicalize_path(&path)?;
+        let local = FsView {
+            root_children_files: &ctx.accounts.root_children_files,
+            root_children_directories: &ctx.accounts.root_children_directories,
+            file_arena: &ctx.accounts.file_arena.data,
+            dir_arena: &ctx.accounts.dir_arena.data,
+            tag_dictionary: &ctx.accounts.tag_dictionary.tags,
+            collation: ctx.accounts.walrusfs_root.collation,
+        };
 
-        let (parent_files_vec, parent_dirs_vec) = match parent_dir_id {
-            Some(id) => {
-                let parent_dir = get_from_dir_arena(dir_arena_data, id)
-                    .ok_or(WalrusFsError::ArenaMismatchError)?;
-                (&parent_dir.children_files, &parent_dir.children_directories)
-            },
-            None => {
-                (root_children_files_data, root_children_dirs_data)
-            },
+        let path = if dereference {
+            internal_follow_symlinks(path, &local)?
+        } else {
+            path
         };
+        // Entries below a mount point come from the mounted filesystem, without
+        // descriptions, since its sidecars are keyed by its own root
+        if let Some((mount, mounted_path)) = internal_find_mount(
+            &path,
+            false,
+            &local.root_children_directories.data,
+            local.dir_arena,
+        )? {
+            let mounted = load_mounted_fs(&mount, ctx.remaining_accounts)?;
+            let view = mounted.view();
+            let mounted_path = if dereference {
+                internal_follow_symlinks(mounted_path, &view)?
+            } else {
+                mounted_path
+            };
+            return Ok(internal_stat(&mounted_path, &view)?.1);
+        }
 
-        if let Some(file_id_ref) = get_from_vec_str_key(parent_files_vec, &item_name) {
-            let f = get_from_file_arena(file_arena_data, *file_id_ref)
-                .ok_or(WalrusFsError::ArenaMismatchError)?;
-            Ok(DirListObjectAnchor {
-                name: item_name,
-                create_ts: f.create_ts,
-                is_dir: false,
-                tags: f.tags.clone(),
-                size: f.size,
-                walrus_blob_id: f.walrus_blob_id.clone(),
-                walrus_epoch_till: f.walrus_epoch_till,
-            })
-        } else if let Some(dir_id_ref) = get_from_vec_str_key(parent_dirs_vec, &item_name) {
-            let d = get_from_dir_arena(dir_arena_data, *dir_id_ref)
-                .ok_or(WalrusFsError::ArenaMismatchError)?;
-            Ok(DirListObjectAnchor {
-                name: item_name,
-                create_ts: d.create_ts,
-                is_dir: true,
-                tags: d.tags.clone(),
-                size: 0,
-                walrus_blob_id: String::new(),
-                walrus_epoch_till: 0,
-            })
+        let (object_id, mut entry) = internal_stat(&path, &local)?;
+        if let Some(sidecar) = &ctx.accounts.description {
+            if sidecar.object_id == object_id && entry.has_description {
+                entry.description = Some(sidecar.description.clone());
+            }
+        }
+        Ok(entry)
+    }
+
+    /// Stats an entry by object id without walking a path. Entries do not record
+    /// their own name, so `name` is left empty.
+    pub fn stat_by_id(
+        ctx: Context<ReadUserFileSystem>,
+        id: u64,
+        is_dir: bool,
+    ) -> Result<DirListObjectAnchor> {
+        if is_dir {
+            let d = get_from_dir_arena(&ctx.accounts.dir_arena.data, id)
+                .ok_or(WalrusFsError::PathNotFound)?;
+            Ok(dir_list_entry(
+                String::new(),
+                d,
+                &ctx.accounts.tag_dictionary.tags,
+            ))
         } else {
-            err!(WalrusFsError::PathNotFound)
+            let f = get_from_file_arena(&ctx.accounts.file_arena.data, id)
+                .ok_or(WalrusFsError::PathNotFound)?;
+            Ok(file_list_entry(
+                String::new(),
+                f,
+                &ctx.accounts.tag_dictionary.tags,
+            ))
         }
     }
 
-    pub fn rename_file(ctx: Context<RenameFile>, from_path: String, to_path: String) -> Result<()> {
-        let clean_from_path = remove_trailing_slash(&from_path);
-        let clean_to_path = remove_trailing_slash(&to_path);
-        validate_path(&clean_from_path)?;
-        validate_path(&clean_to_path)?;
+    /// Lists a directory by object id; ROOT_DIR_OBJECT_ID lists the root.
+    pub fn list_dir_by_id(
+        ctx: Context<ReadUserFileSystem>,
+        dir_id: u64,
+        offset: u32,
+        limit: u32,
+        sort_by: ListSortBy,
+        descending: bool,
+        filter: ListFilter,
+    ) -> Result<DirListPageAnchor> {
+        let file_arena_data = &ctx.accounts.file_arena.data;
+        let dir_arena_data = &ctx.accounts.dir_arena.data;
+
+        let (children_files, children_dirs, version) = if dir_id == ROOT_DIR_OBJECT_ID {
+            (
+                &ctx.accounts.root_children_files.data,
+                &ctx.accounts.root_children_directories.data,
+                root_dir_version(
+                    &ctx.accounts.root_children_files,
+                    &ctx.accounts.root_children_directories,
+                ),
+            )
+        } else {
+            let d =
+                get_from_dir_arena(dir_arena_data, dir_id).ok_or(WalrusFsError::PathNotFound)?;
+            (&d.children_files, &d.children_directories, d.version)
+        };
+
+        let entries = internal_list_children(
+            children_files,
+            children_dirs,
+            file_arena_data,
+            dir_arena_data,
+            sort_by,
+            descending,
+            &filter,
+            ctx.accounts.walrusfs_root.collation,
+            &ctx.accounts.tag_dictionary.tags,
+        )?;
+        Ok(paginate_listing(entries, offset, limit, version))
+    }
+
+    pub fn rename_file(
+        ctx: Context<RenameFile>,
+        from_path: String,
+        to_path: String,
+        expect_unlocked: bool,
+        expected_object_id: Option<u64>,
+        expected_blob_id: Option<[u8; 32]>,
+        expected_version: Option<u64>,
+    ) -> Result<()> {
+        let from_path = canonicalize_path(&from_path)?;
+        let to_path = canonicalize_path(&to_path)?;
+        let limits = ctx.accounts.walrusfs_root.limits;
+        require_path_depth(path_depth(&to_path), &limits)?;
+        let collation = ctx.accounts.walrusfs_root.collation;
+        let root_version = root_dir_version(
+            &ctx.accounts.root_children_files,
+            &ctx.accounts.root_children_directories,
+        );
 
         let dir_arena_data = &mut ctx.accounts.dir_arena.data;
         let root_children_files_data = &mut ctx.accounts.root_children_files.data;
         let root_children_dirs_data_for_read = &ctx.accounts.root_children_directories.data;
 
         let (from_parent_id, from_name) = internal_resolve_parent_id_and_name(
-            &clean_from_path,
+            &from_path,
             root_children_dirs_data_for_read,
             dir_arena_data,
         )?;
         let (to_parent_id, to_name) = internal_resolve_parent_id_and_name(
-            &clean_to_path,
+            &to_path,
             root_children_dirs_data_for_read,
             dir_arena_data,
         )?;
@@ -526,6 +2666,12 @@ pub mod walrusfs_anchor {
             from_parent_id == to_parent_id,
             WalrusFsError::RenamePathMismatch
         );
+        require_dir_version(
+            from_parent_id,
+            root_version,
+            dir_arena_data,
+            expected_version,
+        )?;
 
         let children_files_vec: &mut Vec<KeyValueStringU64> = match from_parent_id {
             Some(id) => {
@@ -546,46 +2692,279 @@ pub mod walrusfs_anchor {
         );
 
         let file_id = remove_from_vec_str_key(children_files_vec, &from_name).unwrap(); // Should exist due to check
-        insert_into_vec_str_key(children_files_vec, to_name, file_id);
+        insert_child(children_files_vec, to_name.clone(), file_id, &limits)?;
+        bump_dir_version(
+            from_parent_id,
+            &mut ctx.accounts.root_children_files.version,
+            dir_arena_data,
+        )?;
+
+        let now = Clock::get()?.unix_timestamp as u64 * 1000;
+        let f = get_mut_from_file_arena(&mut ctx.accounts.file_arena.data, file_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        // The file must stay writable by the signer under its new path too
+        for path in [&from_path, &to_path] {
+            require_file_writer(
+                &ctx.accounts.walrusfs_root,
+                &ctx.accounts.writer_grant,
+                &ctx.accounts.group,
+                &ctx.accounts.session_key,
+                f,
+                ctx.accounts.authority.key,
+                path,
+            )?;
+        }
+        require!(!f.sealed, WalrusFsError::FileSealed);
+        require_unlocked(
+            f,
+            expect_unlocked,
+            acting_signer(
+                &ctx.accounts.session_key,
+                ctx.accounts.authority.key,
+                &from_path,
+            )?,
+        )?;
+        require_expected(file_id, f, expected_object_id, &expected_blob_id)?;
+        f.sort_key = compute_sort_key(&to_name, collation);
+        f.modify_ts = now;
 
         Ok(())
     }
 
-    pub fn rename_dir(ctx: Context<RenameDir>, from_path: String, to_path: String) -> Result<()> {
-        let clean_from_path = remove_trailing_slash(&from_path);
-        let clean_to_path = remove_trailing_slash(&to_path);
-        validate_path(&clean_from_path)?;
-        validate_path(&clean_to_path)?;
+    /// Adds another directory entry for an existing file object (a hard link).
+    /// Sealed files and files listed for sale can't gain new names.
+    pub fn link(ctx: Context<LinkFile>, existing_path: String, new_path: String) -> Result<()> {
+        let existing_path = canonicalize_path(&existing_path)?;
+        let new_path = canonicalize_path(&new_path)?;
+        let limits = ctx.accounts.walrusfs_root.limits;
+        require_path_depth(path_depth(&new_path), &limits)?;
 
+        let file_arena_data = &mut ctx.accounts.file_arena.data;
         let dir_arena_data = &mut ctx.accounts.dir_arena.data;
-        let root_children_dirs_data = &mut ctx.accounts.root_children_directories.data;
+        let root_children_files_data = &mut ctx.accounts.root_children_files.data;
+        let root_children_dirs_data_ro = &ctx.accounts.root_children_directories.data;
 
-        let (from_parent_id, from_name) = internal_resolve_parent_id_and_name(
-            &clean_from_path,
-            root_children_dirs_data,
+        let file_id = internal_resolve_file_id(
+            &existing_path,
+            root_children_files_data,
+            root_children_dirs_data_ro,
             dir_arena_data,
         )?;
-        let (to_parent_id, to_name) = internal_resolve_parent_id_and_name(
-            &clean_to_path,
-            root_children_dirs_data,
+        require_unsealed(file_arena_data, file_id)?;
+        let f = get_from_file_arena(file_arena_data, file_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        for path in [&existing_path, &new_path] {
+            require_file_writer(
+                &ctx.accounts.walrusfs_root,
+                &ctx.accounts.writer_grant,
+                &ctx.accounts.group,
+                &ctx.accounts.session_key,
+                f,
+                ctx.accounts.authority.key,
+                path,
+            )?;
+        }
+        require!(f.sale.is_none(), WalrusFsError::FileListedForSale);
+        let (new_parent_id, new_name) = internal_resolve_parent_id_and_name(
+            &new_path,
+            root_children_dirs_data_ro,
             dir_arena_data,
         )?;
 
+        let children_files_map =
+            internal_children_files_mut(new_parent_id, root_children_files_data, dir_arena_data)?;
         require!(
-            from_parent_id == to_parent_id,
-            WalrusFsError::RenamePathMismatch
+            !contains_key_in_vec_str(children_files_map, &new_name),
+            WalrusFsError::FileAlreadyExists
         );
+        insert_child(children_files_map, new_name, file_id, &limits)?;
+        bump_dir_version(
+            new_parent_id,
+            &mut ctx.accounts.root_children_files.version,
+            dir_arena_data,
+        )?;
 
-        let children_dirs_vec: &mut Vec<KeyValueStringU64> = match from_parent_id {
-            Some(id) => {
-                let parent_dir = get_mut_from_dir_arena(dir_arena_data, id)
-                    .ok_or(WalrusFsError::ArenaMismatchError)?;
-                &mut parent_dir.children_directories
-            }
-            None => root_children_dirs_data,
-        };
+        let f = get_mut_from_file_arena(file_arena_data, file_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        f.link_count += 1;
+        adjust_subtree_bytes(
+            parent_dir_path(&new_path),
+            f.size,
+            0,
+            root_children_dirs_data_ro,
+            dir_arena_data,
+        )?;
 
-        require!(
+        emit!(LinkEvent {
+            existing_path,
+            new_path,
+            link_count: f.link_count,
+        });
+        Ok(())
+    }
+
+    pub fn move_file(
+        ctx: Context<MoveFile>,
+        from_path: String,
+        to_path: String,
+        expect_unlocked: bool,
+        expected_object_id: Option<u64>,
+        expected_blob_id: Option<[u8; 32]>,
+    ) -> Result<()> {
+        let from_path = canonicalize_path(&from_path)?;
+        let to_path = canonicalize_path(&to_path)?;
+        let limits = ctx.accounts.walrusfs_root.limits;
+        require_path_depth(path_depth(&to_path), &limits)?;
+        let collation = ctx.accounts.walrusfs_root.collation;
+
+        let dir_arena_data = &mut ctx.accounts.dir_arena.data;
+        let root_children_files_data = &mut ctx.accounts.root_children_files.data;
+        let root_children_dirs_data_for_read = &ctx.accounts.root_children_directories.data;
+
+        let (from_parent_id, from_name) = internal_resolve_parent_id_and_name(
+            &from_path,
+            root_children_dirs_data_for_read,
+            dir_arena_data,
+        )?;
+        let (to_parent_id, to_name) = internal_resolve_parent_id_and_name(
+            &to_path,
+            root_children_dirs_data_for_read,
+            dir_arena_data,
+        )?;
+
+        require!(
+            !contains_key_in_vec_str(
+                internal_children_files(to_parent_id, root_children_files_data, dir_arena_data)?,
+                &to_name
+            ),
+            WalrusFsError::FileAlreadyExists
+        );
+
+        // Source and destination may both live in the dir arena, so detach first
+        // and re-borrow for the insert.
+        let file_id = remove_from_vec_str_key(
+            internal_children_files_mut(from_parent_id, root_children_files_data, dir_arena_data)?,
+            &from_name,
+        )
+        .ok_or(WalrusFsError::PathNotFound)?;
+        insert_child(
+            internal_children_files_mut(to_parent_id, root_children_files_data, dir_arena_data)?,
+            to_name.clone(),
+            file_id,
+            &limits,
+        )?;
+        bump_dir_version(
+            from_parent_id,
+            &mut ctx.accounts.root_children_files.version,
+            dir_arena_data,
+        )?;
+        bump_dir_version(
+            to_parent_id,
+            &mut ctx.accounts.root_children_files.version,
+            dir_arena_data,
+        )?;
+
+        let now = Clock::get()?.unix_timestamp as u64 * 1000;
+        let f = get_mut_from_file_arena(&mut ctx.accounts.file_arena.data, file_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        // The file must stay writable by the signer under its new path too
+        for path in [&from_path, &to_path] {
+            require_file_writer(
+                &ctx.accounts.walrusfs_root,
+                &ctx.accounts.writer_grant,
+                &ctx.accounts.group,
+                &ctx.accounts.session_key,
+                f,
+                ctx.accounts.authority.key,
+                path,
+            )?;
+        }
+        require!(!f.sealed, WalrusFsError::FileSealed);
+        require_unlocked(
+            f,
+            expect_unlocked,
+            acting_signer(
+                &ctx.accounts.session_key,
+                ctx.accounts.authority.key,
+                &from_path,
+            )?,
+        )?;
+        require_expected(file_id, f, expected_object_id, &expected_blob_id)?;
+        f.sort_key = compute_sort_key(&to_name, collation);
+        f.modify_ts = now;
+        if from_parent_id != to_parent_id {
+            adjust_subtree_bytes(
+                parent_dir_path(&from_path),
+                0,
+                f.size,
+                root_children_dirs_data_for_read,
+                dir_arena_data,
+            )?;
+            adjust_subtree_bytes(
+                parent_dir_path(&to_path),
+                f.size,
+                0,
+                root_children_dirs_data_for_read,
+                dir_arena_data,
+            )?;
+        }
+
+        emit!(MoveEvent {
+            from_path,
+            to_path,
+            is_dir: false,
+        });
+        Ok(())
+    }
+
+    pub fn rename_dir(
+        ctx: Context<RenameDir>,
+        from_path: String,
+        to_path: String,
+        expected_version: Option<u64>,
+    ) -> Result<()> {
+        let from_path = canonicalize_path(&from_path)?;
+        let to_path = canonicalize_path(&to_path)?;
+        let limits = ctx.accounts.walrusfs_root.limits;
+        require_path_depth(path_depth(&to_path), &limits)?;
+        let collation = ctx.accounts.walrusfs_root.collation;
+        let root_version = root_dir_version(
+            &ctx.accounts.root_children_files,
+            &ctx.accounts.root_children_directories,
+        );
+
+        let dir_arena_data = &mut ctx.accounts.dir_arena.data;
+        let root_children_dirs_data = &mut ctx.accounts.root_children_directories.data;
+
+        let (from_parent_id, from_name) = internal_resolve_parent_id_and_name(
+            &from_path,
+            root_children_dirs_data,
+            dir_arena_data,
+        )?;
+        let (to_parent_id, to_name) =
+            internal_resolve_parent_id_and_name(&to_path, root_children_dirs_data, dir_arena_data)?;
+
+        require!(
+            from_parent_id == to_parent_id,
+            WalrusFsError::RenamePathMismatch
+        );
+        require_dir_version(
+            from_parent_id,
+            root_version,
+            dir_arena_data,
+            expected_version,
+        )?;
+
+        let children_dirs_vec: &mut Vec<KeyValueStringU64> = match from_parent_id {
+            Some(id) => {
+                let parent_dir = get_mut_from_dir_arena(dir_arena_data, id)
+                    .ok_or(WalrusFsError::ArenaMismatchError)?;
+                &mut parent_dir.children_directories
+            }
+            None => root_children_dirs_data,
+        };
+
+        require!(
             contains_key_in_vec_str(children_dirs_vec, &from_name),
             WalrusFsError::PathNotFound
         );
@@ -595,23 +2974,302 @@ pub mod walrusfs_anchor {
         );
 
         let dir_id = remove_from_vec_str_key(children_dirs_vec, &from_name).unwrap();
-        insert_into_vec_str_key(children_dirs_vec, to_name, dir_id);
+        insert_child(children_dirs_vec, to_name.clone(), dir_id, &limits)?;
+        bump_dir_version(
+            from_parent_id,
+            &mut ctx.accounts.root_children_directories.version,
+            dir_arena_data,
+        )?;
+
+        let d = get_mut_from_dir_arena(dir_arena_data, dir_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        d.sort_key = compute_sort_key(&to_name, collation);
+        Ok(())
+    }
+
+    pub fn move_dir(ctx: Context<MoveDir>, from_path: String, to_path: String) -> Result<()> {
+        let from_path = canonicalize_path(&from_path)?;
+        let to_path = canonicalize_path(&to_path)?;
+        let limits = ctx.accounts.walrusfs_root.limits;
+        require_path_depth(path_depth(&to_path), &limits)?;
+        let collation = ctx.accounts.walrusfs_root.collation;
+
+        let dir_arena_data = &mut ctx.accounts.dir_arena.data;
+        let root_children_directories = &mut **ctx.accounts.root_children_directories;
+        let root_children_dirs_data = &mut root_children_directories.data;
+
+        let (from_parent_id, from_name) = internal_resolve_parent_id_and_name(
+            &from_path,
+            root_children_dirs_data,
+            dir_arena_data,
+        )?;
+        let (to_parent_id, to_name) =
+            internal_resolve_parent_id_and_name(&to_path, root_children_dirs_data, dir_arena_data)?;
+
+        let dir_id = *get_from_vec_str_key(
+            internal_children_dirs(from_parent_id, root_children_dirs_data, dir_arena_data)?,
+            &from_name,
+        )
+        .ok_or(WalrusFsError::PathNotFound)?;
+        require!(
+            !contains_key_in_vec_str(
+                internal_children_dirs(to_parent_id, root_children_dirs_data, dir_arena_data)?,
+                &to_name
+            ),
+            WalrusFsError::DirectoryAlreadyExists
+        );
+
+        // The destination parent must not be the moved directory or anything below it,
+        // otherwise the subtree would be detached from the root.
+        if let Some(to_parent) = to_parent_id {
+            let (_, subtree_dir_ids) = internal_recursive_get_dir_obj_ids(dir_id, dir_arena_data)?;
+            require!(
+                to_parent != dir_id && !subtree_dir_ids.contains(&to_parent),
+                WalrusFsError::MoveIntoOwnSubtree
+            );
+        }
+
+        remove_from_vec_str_key(
+            internal_children_dirs_mut(from_parent_id, root_children_dirs_data, dir_arena_data)?,
+            &from_name,
+        );
+        insert_child(
+            internal_children_dirs_mut(to_parent_id, root_children_dirs_data, dir_arena_data)?,
+            to_name.clone(),
+            dir_id,
+            &limits,
+        )?;
+        bump_dir_version(
+            from_parent_id,
+            &mut root_children_directories.version,
+            dir_arena_data,
+        )?;
+        bump_dir_version(
+            to_parent_id,
+            &mut root_children_directories.version,
+            dir_arena_data,
+        )?;
+
+        let d = get_mut_from_dir_arena(dir_arena_data, dir_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        d.sort_key = compute_sort_key(&to_name, collation);
+        let moved_bytes = d.subtree_bytes;
+        if from_parent_id != to_parent_id {
+            adjust_subtree_bytes(
+                parent_dir_path(&from_path),
+                0,
+                moved_bytes,
+                root_children_dirs_data,
+                dir_arena_data,
+            )?;
+            adjust_subtree_bytes(
+                parent_dir_path(&to_path),
+                moved_bytes,
+                0,
+                root_children_dirs_data,
+                dir_arena_data,
+            )?;
+        }
+
+        emit!(MoveEvent {
+            from_path,
+            to_path,
+            is_dir: true,
+        });
+        Ok(())
+    }
+
+    pub fn copy_dir(ctx: Context<CopyDir>, from_path: String, to_path: String) -> Result<()> {
+        let from_path = canonicalize_path(&from_path)?;
+        let to_path = canonicalize_path(&to_path)?;
+        let limits = ctx.accounts.walrusfs_root.limits;
+        require_path_depth(path_depth(&to_path), &limits)?;
+
+        let thresholds = ctx.accounts.walrusfs_root.space_warning_pcts;
+        let file_arena_pct = space_used_pct(&ctx.accounts.file_arena)?;
+        let dir_arena_pct = space_used_pct(&ctx.accounts.dir_arena)?;
+
+        let clock = Clock::get()?;
+        let now = clock.unix_timestamp as u64 * 1000;
+        let root = &mut ctx.accounts.walrusfs_root;
+        let file_arena_data = &mut ctx.accounts.file_arena.data;
+        let dir_arena_data = &mut ctx.accounts.dir_arena.data;
+        let root_children_directories = &mut **ctx.accounts.root_children_directories;
+        let root_children_dirs_data = &mut root_children_directories.data;
+
+        let (from_parent_id, from_name) = internal_resolve_parent_id_and_name(
+            &from_path,
+            root_children_dirs_data,
+            dir_arena_data,
+        )?;
+        let (to_parent_id, to_name) =
+            internal_resolve_parent_id_and_name(&to_path, root_children_dirs_data, dir_arena_data)?;
+
+        let src_dir_id = *get_from_vec_str_key(
+            internal_children_dirs(from_parent_id, root_children_dirs_data, dir_arena_data)?,
+            &from_name,
+        )
+        .ok_or(WalrusFsError::PathNotFound)?;
+        require!(
+            !contains_key_in_vec_str(
+                internal_children_dirs(to_parent_id, root_children_dirs_data, dir_arena_data)?,
+                &to_name
+            ),
+            WalrusFsError::DirectoryAlreadyExists
+        );
+
+        // Build the whole copy before touching the arenas, so copying into a
+        // descendant of the source never walks the directories it creates.
+        root.obj_id_counter += 1;
+        let new_top_id = root.obj_id_counter;
+        let mut dirs_to_copy = vec![(src_dir_id, new_top_id)];
+        let mut new_dirs: Vec<(u64, DirObjectAnchor)> = Vec::new();
+        let mut new_files: Vec<(u64, FileObjectAnchor)> = Vec::new();
+
+        while let Some((src_id, new_id)) = dirs_to_copy.pop() {
+            let src_dir = get_from_dir_arena(dir_arena_data, src_id)
+                .ok_or(WalrusFsError::ArenaMismatchError)?;
+
+            let mut children_files = Vec::with_capacity(src_dir.children_files.len());
+            for kv in src_dir.children_files.iter() {
+                let src_file = get_from_file_arena(file_arena_data, kv.value)
+                    .ok_or(WalrusFsError::ArenaMismatchError)?;
+                root.obj_id_counter += 1;
+                let new_file_id = root.obj_id_counter;
+                new_files.push((
+                    new_file_id,
+                    FileObjectAnchor {
+                        create_ts: now,
+                        modify_ts: now,
+                        link_count: 1, // Copies are independent even if the source was linked
+                        sealed: false,
+                        seal_permanent: false,
+                        lock: None,
+                        access_ts: None,
+                        access_count: 0,
+                        has_description: false,
+                        owner: root.authority, // The copy is a new file
+                        sale: None,
+                        ..src_file.clone()
+                    },
+                ));
+                children_files.push(KeyValueStringU64 {
+                    key: kv.key.clone(),
+                    value: new_file_id,
+                });
+            }
+
+            let mut children_directories = Vec::with_capacity(src_dir.children_directories.len());
+            for kv in src_dir.children_directories.iter() {
+                root.obj_id_counter += 1;
+                let new_sub_id = root.obj_id_counter;
+                dirs_to_copy.push((kv.value, new_sub_id));
+                children_directories.push(KeyValueStringU64 {
+                    key: kv.key.clone(),
+                    value: new_sub_id,
+                });
+            }
+
+            let sort_key = if new_id == new_top_id {
+                compute_sort_key(&to_name, root.collation)
+            } else {
+                src_dir.sort_key.clone()
+            };
+            new_dirs.push((
+                new_id,
+                DirObjectAnchor {
+                    create_ts: now,
+                    modify_ts: now,
+                    tags: src_dir.tags.clone(),
+                    default_tags: src_dir.default_tags.clone(),
+                    sort_key,
+                    version: 0,
+                    xattrs: src_dir.xattrs.clone(),
+                    has_description: false, // The sidecar is keyed by the source id
+                    pinned: src_dir.pinned,
+                    child_file_count: children_files.len() as u32,
+                    child_dir_count: children_directories.len() as u32,
+                    subtree_bytes: src_dir.subtree_bytes, // Copies keep every size
+                    mode: src_dir.mode,
+                    group: src_dir.group,
+                    mount: src_dir.mount.clone(),
+                    access_policy: src_dir.access_policy.clone(),
+                    children_files,
+                    children_directories,
+                },
+            ));
+        }
+
+        let dirs_copied = new_dirs.len() as u64;
+        let files_copied = new_files.len() as u64;
+        for (id, file_obj) in new_files {
+            insert_into_file_arena(file_arena_data, id, file_obj);
+        }
+        for (id, dir_obj) in new_dirs {
+            insert_into_dir_arena(dir_arena_data, id, dir_obj);
+        }
+        insert_child(
+            internal_children_dirs_mut(to_parent_id, root_children_dirs_data, dir_arena_data)?,
+            to_name,
+            new_top_id,
+            &limits,
+        )?;
+        bump_dir_version(
+            to_parent_id,
+            &mut root_children_directories.version,
+            dir_arena_data,
+        )?;
+        let copied_bytes = get_from_dir_arena(dir_arena_data, new_top_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?
+            .subtree_bytes;
+        adjust_subtree_bytes(
+            parent_dir_path(&to_path),
+            copied_bytes,
+            0,
+            root_children_dirs_data,
+            dir_arena_data,
+        )?;
+
+        emit!(DirCopiedEvent {
+            from_path,
+            to_path,
+            dirs_copied,
+            files_copied,
+        });
+
+        emit_space_low_if_crossed(&ctx.accounts.file_arena, file_arena_pct, &thresholds)?;
+        emit_space_low_if_crossed(&ctx.accounts.dir_arena, dir_arena_pct, &thresholds)?;
         Ok(())
     }
 
-    pub fn delete_file(ctx: Context<DeleteFile>, path: String) -> Result<()> {
-        let clean_path = remove_trailing_slash(&path);
-        validate_path(&clean_path)?;
+    /// Deletes a file, or with `to_trash` detaches it into the trash so it can be restored.
+    pub fn delete_file(
+        ctx: Context<DeleteFile>,
+        path: String,
+        to_trash: bool,
+        expect_unlocked: bool,
+        expected_object_id: Option<u64>,
+        expected_blob_id: Option<[u8; 32]>,
+        expected_version: Option<u64>,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+        let root_version = root_dir_version(
+            &ctx.accounts.root_children_files,
+            &ctx.accounts.root_children_directories,
+        );
 
         let file_arena_data = &mut ctx.accounts.file_arena.data;
         let dir_arena_data = &mut ctx.accounts.dir_arena.data;
         let root_children_files_data = &mut ctx.accounts.root_children_files.data;
         let root_children_dirs_data_ro = &ctx.accounts.root_children_directories.data;
 
-        let (parent_dir_id, file_name) = internal_resolve_parent_id_and_name(
-            &clean_path,
-            root_children_dirs_data_ro,
+        let (parent_dir_id, file_name) =
+            internal_resolve_parent_id_and_name(&path, root_children_dirs_data_ro, dir_arena_data)?;
+        require_dir_version(
+            parent_dir_id,
+            root_version,
             dir_arena_data,
+            expected_version,
         )?;
 
         let children_files_vec: &mut Vec<KeyValueStringU64> = match parent_dir_id {
@@ -625,25 +3283,134 @@ pub mod walrusfs_anchor {
 
         let file_id = remove_from_vec_str_key(children_files_vec, &file_name)
             .ok_or(WalrusFsError::PathNotFound)?;
-        remove_from_file_arena(file_arena_data, &file_id)
-            .ok_or(WalrusFsError::ArenaMismatchError)?; // Ensure it was in arena
+        require_unsealed(file_arena_data, file_id)?;
+        let f = get_from_file_arena(file_arena_data, file_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        require_file_writer(
+            &ctx.accounts.walrusfs_root,
+            &ctx.accounts.writer_grant,
+            &ctx.accounts.group,
+            &ctx.accounts.session_key,
+            f,
+            ctx.accounts.authority.key,
+            &path,
+        )?;
+        require_unlocked(
+            f,
+            expect_unlocked,
+            acting_signer(&ctx.accounts.session_key, ctx.accounts.authority.key, &path)?,
+        )?;
+        require_expected(file_id, f, expected_object_id, &expected_blob_id)?;
+        let removed_bytes = f.size;
+        bump_dir_version(
+            parent_dir_id,
+            &mut ctx.accounts.root_children_files.version,
+            dir_arena_data,
+        )?;
+        adjust_subtree_bytes(
+            parent_dir_path(&path),
+            0,
+            removed_bytes,
+            root_children_dirs_data_ro,
+            dir_arena_data,
+        )?;
+        if to_trash {
+            let trash = ctx
+                .accounts
+                .trash
+                .as_mut()
+                .ok_or(WalrusFsError::TrashRequired)?;
+            trash.entries.push(TrashEntry {
+                original_path: path.clone(),
+                is_dir: false,
+                object_id: file_id,
+                deleted_ts: Clock::get()?.unix_timestamp as u64 * 1000,
+            });
+            emit!(TrashedEvent {
+                path,
+                is_dir: false
+            });
+            return Ok(());
+        }
+        let removed_files: Vec<FileObjectAnchor> = internal_unlink_file(file_arena_data, file_id)?
+            .into_iter()
+            .collect();
+
+        internal_enqueue_unreferenced_blobs(
+            removed_files,
+            file_arena_data,
+            ctx.accounts
+                .pending_blob_deletes
+                .as_deref_mut()
+                .map(|q| &mut **q),
+        )?;
 
         emit!(DeleteEvent { path });
         Ok(())
     }
 
-    pub fn delete_dir(ctx: Context<DeleteDir>, path: String) -> Result<()> {
-        let clean_path = remove_trailing_slash(&path);
-        validate_path(&clean_path)?;
+    /// Deletes many files in one instruction. Paths that are invalid, missing, sealed
+    /// or (with `expect_unlocked`) locked are reported as failed in the emitted event
+    /// instead of aborting the batch.
+    pub fn batch_delete_files(
+        ctx: Context<DeleteFile>,
+        paths: Vec<String>,
+        expect_unlocked: bool,
+    ) -> Result<()> {
+        let signer = ctx.accounts.authority.key();
+        internal_batch_delete_files(ctx.accounts, &signer, paths, expect_unlocked)
+    }
+
+    /// batch_delete_files on behalf of `permit.signer`, relayed like
+    /// batch_add_files_with_permit.
+    pub fn batch_delete_files_with_permit(
+        ctx: Context<DeleteFile>,
+        permit: Permit,
+        paths: Vec<String>,
+        expect_unlocked: bool,
+    ) -> Result<()> {
+        let instruction_data = crate::instruction::BatchDeleteFiles {
+            paths: paths.clone(),
+            expect_unlocked,
+        }
+        .data();
+        use_permit(
+            &permit,
+            &instruction_data,
+            &ctx.accounts.walrusfs_root.key(),
+            ctx.accounts.authority.key,
+            &mut ctx.accounts.permit_nonce,
+            &ctx.accounts.instructions,
+        )?;
+        internal_batch_delete_files(ctx.accounts, &permit.signer, paths, expect_unlocked)
+    }
+
+    /// Deletes a directory, or with `to_trash` detaches it (with its subtree) into the trash.
+    pub fn delete_dir(
+        ctx: Context<DeleteDir>,
+        path: String,
+        recursive: bool,
+        to_trash: bool,
+        expected_version: Option<u64>,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+        let root_version = root_dir_version(
+            &ctx.accounts.root_children_files,
+            &ctx.accounts.root_children_directories,
+        );
 
         let file_arena_data = &mut ctx.accounts.file_arena.data;
         let dir_arena_data = &mut ctx.accounts.dir_arena.data;
-        let root_children_dirs_data = &mut ctx.accounts.root_children_directories.data;
-
-        let (parent_dir_id, dir_name_to_delete) = internal_resolve_parent_id_and_name(
-            &clean_path,
-            root_children_dirs_data,
+        let root_children_directories = &mut **ctx.accounts.root_children_directories;
+        let root_children_dirs_data = &mut root_children_directories.data;
+
+        let (parent_dir_id, dir_name_to_delete) =
+            internal_resolve_parent_id_and_name(&path, root_children_dirs_data, dir_arena_data)?;
+        require_dir_version(
+            parent_dir_id,
+            root_version,
             dir_arena_data,
+            expected_version,
         )?;
 
         let dir_id_to_delete = {
@@ -658,530 +3425,7821 @@ pub mod walrusfs_anchor {
             remove_from_vec_str_key(children_dirs_vec, &dir_name_to_delete)
                 .ok_or(WalrusFsError::PathNotFound)?
         };
+        bump_dir_version(
+            parent_dir_id,
+            &mut root_children_directories.version,
+            dir_arena_data,
+        )?;
 
-        let (files_to_delete, dirs_to_delete_recursive) =
+        let is_empty = get_from_dir_arena(dir_arena_data, dir_id_to_delete)
+            .map(|d| d.children_files.is_empty() && d.children_directories.is_empty())
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        require!(recursive || is_empty, WalrusFsError::DirectoryNotEmpty);
+        let (subtree_file_ids, _) =
             internal_recursive_get_dir_obj_ids(dir_id_to_delete, dir_arena_data)?;
-
-        for file_id in files_to_delete {
-            remove_from_file_arena(file_arena_data, &file_id); // .ok_or(WalrusFsError::ArenaMismatchError)?; // Optionally check, but might be gone
+        for file_id in subtree_file_ids {
+            require_unsealed(file_arena_data, file_id)?;
         }
-        for dir_id in dirs_to_delete_recursive {
-            remove_from_dir_arena(dir_arena_data, &dir_id); // .ok_or(WalrusFsError::ArenaMismatchError)?;
+        let removed_bytes = get_from_dir_arena(dir_arena_data, dir_id_to_delete)
+            .ok_or(WalrusFsError::ArenaMismatchError)?
+            .subtree_bytes;
+        adjust_subtree_bytes(
+            parent_dir_path(&path),
+            0,
+            removed_bytes,
+            root_children_dirs_data,
+            dir_arena_data,
+        )?;
+
+        if to_trash {
+            let trash = ctx
+                .accounts
+                .trash
+                .as_mut()
+                .ok_or(WalrusFsError::TrashRequired)?;
+            trash.entries.push(TrashEntry {
+                original_path: path.clone(),
+                is_dir: true,
+                object_id: dir_id_to_delete,
+                deleted_ts: Clock::get()?.unix_timestamp as u64 * 1000,
+            });
+            emit!(TrashedEvent { path, is_dir: true });
+            return Ok(());
         }
-        remove_from_dir_arena(dir_arena_data, &dir_id_to_delete); // .ok_or(WalrusFsError::ArenaMismatchError)?;
+
+        let removed_files =
+            internal_remove_dir_subtree(dir_id_to_delete, file_arena_data, dir_arena_data)?;
+
+        internal_enqueue_unreferenced_blobs(
+            removed_files,
+            file_arena_data,
+            ctx.accounts
+                .pending_blob_deletes
+                .as_deref_mut()
+                .map(|q| &mut **q),
+        )?;
 
         emit!(DeleteEvent { path });
         Ok(())
     }
 
-    pub fn get_dir_all(ctx: Context<GetDirAll>, path: String) -> Result<RecursiveDirListAnchor> {
-        let clean_path = remove_trailing_slash(&path);
-        validate_path(&clean_path)?;
+    /// Detaches a directory and records it in the deletion cursor, so a subtree too
+    /// large for one instruction can be removed with delete_dir_continue. The
+    /// directory disappears from its parent immediately.
+    pub fn delete_dir_begin(
+        ctx: Context<DeleteDirBegin>,
+        path: String,
+        expected_version: Option<u64>,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+        require!(
+            ctx.accounts.deletion_cursor.pending_dir_ids.is_empty(),
+            WalrusFsError::DeletionInProgress
+        );
+        let root_version = root_dir_version(
+            &ctx.accounts.root_children_files,
+            &ctx.accounts.root_children_directories,
+        );
 
         let file_arena_data = &ctx.accounts.file_arena.data;
-        let dir_arena_data = &ctx.accounts.dir_arena.data;
-        let root_children_dirs_data = &ctx.accounts.root_children_directories.data;
+        let dir_arena_data = &mut ctx.accounts.dir_arena.data;
+        let root_children_directories = &mut **ctx.accounts.root_children_directories;
+        let root_children_dirs_data = &mut root_children_directories.data;
+
+        let (parent_dir_id, dir_name_to_delete) =
+            internal_resolve_parent_id_and_name(&path, root_children_dirs_data, dir_arena_data)?;
+        require_dir_version(
+            parent_dir_id,
+            root_version,
+            dir_arena_data,
+            expected_version,
+        )?;
 
-        let target_dir_id = {
-            let (grandparent_dir_id, target_dir_name_from_parent) =
-                internal_resolve_parent_id_and_name(
-                    &clean_path,
-                    root_children_dirs_data,
-                    dir_arena_data,
-                )?;
+        let dir_id_to_delete = remove_from_vec_str_key(
+            internal_children_dirs_mut(parent_dir_id, root_children_dirs_data, dir_arena_data)?,
+            &dir_name_to_delete,
+        )
+        .ok_or(WalrusFsError::PathNotFound)?;
+        // The read-only walk is cheap next to the arena removals the cursor spreads out
+        let (subtree_file_ids, _) =
+            internal_recursive_get_dir_obj_ids(dir_id_to_delete, dir_arena_data)?;
+        for file_id in subtree_file_ids {
+            require_unsealed(file_arena_data, file_id)?;
+        }
+        bump_dir_version(
+            parent_dir_id,
+            &mut root_children_directories.version,
+            dir_arena_data,
+        )?;
+        let removed_bytes = get_from_dir_arena(dir_arena_data, dir_id_to_delete)
+            .ok_or(WalrusFsError::ArenaMismatchError)?
+            .subtree_bytes;
+        adjust_subtree_bytes(
+            parent_dir_path(&path),
+            0,
+            removed_bytes,
+            root_children_dirs_data,
+            dir_arena_data,
+        )?;
 
-            let grandparent_children_dirs_vec = match grandparent_dir_id {
-                Some(id) => {
-                    &get_from_dir_arena(dir_arena_data, id)
-                        .ok_or(WalrusFsError::ArenaMismatchError)?
-                        .children_directories
-                }
-                None => root_children_dirs_data,
-            };
-            get_from_vec_str_key(grandparent_children_dirs_vec, &target_dir_name_from_parent)
-                .ok_or(WalrusFsError::PathNotFound)?
-        };
+        let cursor = &mut ctx.accounts.deletion_cursor;
+        cursor.path = path.clone();
+        cursor.pending_dir_ids = vec![dir_id_to_delete];
+        cursor.dirs_removed = 0;
+        cursor.files_removed = 0;
 
-        let (file_ids, dir_ids_recursive) =
-            internal_recursive_get_dir_obj_ids(*target_dir_id, dir_arena_data)?;
+        emit!(DeleteProgressEvent {
+            path,
+            dirs_removed: 0,
+            files_removed: 0,
+            dirs_remaining: 1,
+        });
+        Ok(())
+    }
 
-        let mut files_ex = Vec::new();
-        for fid in file_ids {
-            if let Some(obj) = get_from_file_arena(file_arena_data, fid) {
-                files_ex.push(FileObjectExAnchor {
-                    id: fid,
-                    obj: obj.clone(),
-                });
-            }
+    /// Removes up to `max_dirs` directories (0 for no limit) of the delete started by
+    /// delete_dir_begin, with their files. Returns true once the subtree is gone.
+    pub fn delete_dir_continue(ctx: Context<DeleteDirContinue>, max_dirs: u32) -> Result<bool> {
+        let file_arena_data = &mut ctx.accounts.file_arena.data;
+        let dir_arena_data = &mut ctx.accounts.dir_arena.data;
+        let cursor = &mut ctx.accounts.deletion_cursor;
+        if cursor.pending_dir_ids.is_empty() {
+            return Ok(true);
         }
 
-        let mut dirs_ex = Vec::new();
-        let mut all_dir_ids_to_fetch = BTreeSet::new();
-        all_dir_ids_to_fetch.insert(*target_dir_id);
-        for did in dir_ids_recursive {
-            all_dir_ids_to_fetch.insert(did);
-        }
-
-        for did in all_dir_ids_to_fetch {
-            if let Some(d_obj) = get_from_dir_arena(dir_arena_data, did) {
-                dirs_ex.push(DirObjectExAnchor {
-                    id: did,
-                    create_ts: d_obj.create_ts,
-                    tags: d_obj.tags.clone(),
-                    children_file_names: d_obj
-                        .children_files
-                        .iter()
-                        .map(|kv| kv.key.clone())
-                        .collect(),
-                    children_file_ids: d_obj.children_files.iter().map(|kv| kv.value).collect(),
-                    children_directory_names: d_obj
-                        .children_directories
-                        .iter()
-                        .map(|kv| kv.key.clone())
-                        .collect(),
-                    children_directory_ids: d_obj
-                        .children_directories
-                        .iter()
-                        .map(|kv| kv.value)
-                        .collect(),
-                });
+        let mut removed_files = Vec::new();
+        let mut processed = 0u32;
+        while max_dirs == 0 || processed < max_dirs {
+            let dir_id = match cursor.pending_dir_ids.pop() {
+                Some(id) => id,
+                None => break,
+            };
+            processed += 1;
+            let dir = match remove_from_dir_arena(dir_arena_data, &dir_id) {
+                Some(d) => d,
+                None => continue,
+            };
+            for kv in dir.children_files {
+                // Err means the object is already gone from the arena; nothing to free
+                if let Ok(Some(f)) = internal_unlink_file(file_arena_data, kv.value) {
+                    removed_files.push(f);
+                }
+                cursor.files_removed += 1;
             }
+            cursor
+                .pending_dir_ids
+                .extend(dir.children_directories.iter().map(|kv| kv.value));
+            cursor.dirs_removed += 1;
         }
 
-        Ok(RecursiveDirListAnchor {
-            dirobj: *target_dir_id,
-            files: files_ex,
-            dirs: dirs_ex,
-        })
+        internal_enqueue_unreferenced_blobs(
+            removed_files,
+            file_arena_data,
+            ctx.accounts
+                .pending_blob_deletes
+                .as_deref_mut()
+                .map(|q| &mut **q),
+        )?;
+
+        let cursor = &ctx.accounts.deletion_cursor;
+        let done = cursor.pending_dir_ids.is_empty();
+        if done {
+            emit!(DeleteEvent {
+                path: cursor.path.clone()
+            });
+        } else {
+            emit!(DeleteProgressEvent {
+                path: cursor.path.clone(),
+                dirs_removed: cursor.dirs_removed,
+                files_removed: cursor.files_removed,
+                dirs_remaining: cursor.pending_dir_ids.len() as u32,
+            });
+        }
+        Ok(done)
     }
-}
 
-// --- Internal Helper Functions (Modified parameters, core logic adapted) ---
-fn internal_resolve_parent_id_and_name<'a>(
-    full_path: &str,
-    root_children_dirs_data: &'a Vec<KeyValueStringU64>,
-    dir_arena_data: &'a Vec<KeyValueU64DirObject>,
-) -> Result<(Option<u64>, String)> {
-    let path = remove_trailing_slash(full_path);
-    if path == "/" {
-        return err!(WalrusFsError::InvalidPathOperationOnRoot);
-    }
-
-    let mut components = path
-        .split('/')
-        .filter(|s| !s.is_empty())
-        .collect::<Vec<&str>>();
-    if components.is_empty() {
-        return err!(WalrusFsError::PathError);
-    }
-
-    let name = components.pop().unwrap().to_string();
+    /// Reattaches the most recently trashed entry with this original path. The parent
+    /// directory must still exist and the name must be free.
+    pub fn restore(ctx: Context<Restore>, path: String) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+        let limits = ctx.accounts.walrusfs_root.limits;
+        require_path_depth(path_depth(&path), &limits)?;
 
-    let mut current_parent_id: Option<u64> = None;
-    let mut current_children_dirs_vec: &Vec<KeyValueStringU64> = root_children_dirs_data;
+        let dir_arena_data = &mut ctx.accounts.dir_arena.data;
+        let root_children_files_data = &mut ctx.accounts.root_children_files.data;
+        let root_children_directories = &mut **ctx.accounts.root_children_directories;
+        let root_children_dirs_data = &mut root_children_directories.data;
+        let trash_entries = &mut ctx.accounts.trash.entries;
 
-    for component_str in components {
-        let component = component_str.to_string();
-        let found_id_ref = get_from_vec_str_key(current_children_dirs_vec, &component)
+        let index = trash_entries
+            .iter()
+            .rposition(|e| e.original_path == path)
             .ok_or(WalrusFsError::PathNotFound)?;
+        let (parent_dir_id, name) =
+            internal_resolve_parent_id_and_name(&path, root_children_dirs_data, dir_arena_data)?;
+
+        let entry = &trash_entries[index];
+        let restored_bytes = if entry.is_dir {
+            get_from_dir_arena(dir_arena_data, entry.object_id)
+                .ok_or(WalrusFsError::ArenaMismatchError)?
+                .subtree_bytes
+        } else {
+            get_from_file_arena(&ctx.accounts.file_arena.data, entry.object_id)
+                .ok_or(WalrusFsError::ArenaMismatchError)?
+                .size
+        };
+        if entry.is_dir {
+            let children_dirs_vec =
+                internal_children_dirs_mut(parent_dir_id, root_children_dirs_data, dir_arena_data)?;
+            require!(
+                !contains_key_in_vec_str(children_dirs_vec, &name),
+                WalrusFsError::DirectoryAlreadyExists
+            );
+            insert_child(children_dirs_vec, name, entry.object_id, &limits)?;
+            bump_dir_version(
+                parent_dir_id,
+                &mut root_children_directories.version,
+                dir_arena_data,
+            )?;
+        } else {
+            let children_files_vec = internal_children_files_mut(
+                parent_dir_id,
+                root_children_files_data,
+                dir_arena_data,
+            )?;
+            require!(
+                !contains_key_in_vec_str(children_files_vec, &name),
+                WalrusFsError::FileAlreadyExists
+            );
+            insert_child(children_files_vec, name, entry.object_id, &limits)?;
+            bump_dir_version(
+                parent_dir_id,
+                &mut ctx.accounts.root_children_files.version,
+                dir_arena_data,
+            )?;
+        }
+        adjust_subtree_bytes(
+            parent_dir_path(&path),
+            restored_bytes,
+            0,
+            root_children_dirs_data,
+            dir_arena_data,
+        )?;
+        trash_entries.remove(index);
 
-        current_parent_id = Some(*found_id_ref);
-        let dir_object = get_from_dir_arena(dir_arena_data, *found_id_ref)
-            .ok_or(WalrusFsError::ArenaMismatchError)?;
-        current_children_dirs_vec = &dir_object.children_directories;
-    }
-    Ok((current_parent_id, name))
-}
-
-fn internal_get_dir_children_refs<'a>(
-    path_with_trailing_slash: &str,
-    root_children_files_data: &'a Vec<KeyValueStringU64>,
-    root_children_dirs_data: &'a Vec<KeyValueStringU64>,
-    dir_arena_data: &'a Vec<KeyValueU64DirObject>,
-) -> Result<(Vec<KeyValueStringU64>, Vec<KeyValueStringU64>)> {
-    if path_with_trailing_slash == "/" {
-        return Ok((
-            root_children_files_data.clone(),
-            root_children_dirs_data.clone(),
-        ));
+        emit!(RestoredEvent { path });
+        Ok(())
     }
 
-    let components: Vec<&str> = path_with_trailing_slash
-        .trim_matches('/')
-        .split('/')
-        .filter(|s| !s.is_empty())
-        .collect();
-
-    let mut current_dir_id_opt: Option<u64> = None;
-    let mut current_children_dirs_vec_ref = root_children_dirs_data;
+    /// Permanently deletes everything in the trash, queueing blobs that lose their last reference.
+    pub fn empty_trash(ctx: Context<EmptyTrash>) -> Result<()> {
+        let file_arena_data = &mut ctx.accounts.file_arena.data;
+        let dir_arena_data = &mut ctx.accounts.dir_arena.data;
 
-    for component_str in components {
-        let component = component_str.to_string();
-        let dir_id_ref = get_from_vec_str_key(current_children_dirs_vec_ref, &component)
-            .ok_or(WalrusFsError::PathNotFound)?;
+        let entries = std::mem::take(&mut ctx.accounts.trash.entries);
+        let entry_count = entries.len() as u64;
+        let mut removed_files = Vec::new();
+        for entry in entries {
+            if entry.is_dir {
+                removed_files.extend(internal_remove_dir_subtree(
+                    entry.object_id,
+                    file_arena_data,
+                    dir_arena_data,
+                )?);
+            } else if let Some(f) = internal_unlink_file(file_arena_data, entry.object_id)? {
+                removed_files.push(f);
+            }
+        }
 
-        let dir_object = get_from_dir_arena(dir_arena_data, *dir_id_ref)
-            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        internal_enqueue_unreferenced_blobs(
+            removed_files,
+            file_arena_data,
+            ctx.accounts
+                .pending_blob_deletes
+                .as_deref_mut()
+                .map(|q| &mut **q),
+        )?;
 
-        current_children_dirs_vec_ref = &dir_object.children_directories;
-        current_dir_id_opt = Some(*dir_id_ref);
+        emit!(TrashEmptiedEvent { entry_count });
+        Ok(())
     }
 
-    let target_dir_id = current_dir_id_opt.ok_or(WalrusFsError::PathNotFound)?; // Should be Some if path is valid and not root
-    let target_dir_obj = get_from_dir_arena(dir_arena_data, target_dir_id)
-        .ok_or(WalrusFsError::ArenaMismatchError)?;
+    /// Exports a subtree one page of directories at a time, in breadth-first order.
+    /// `max_depth` of None walks the whole subtree; `limit` of 0 returns every remaining
+    /// directory. Pass `next_cursor` back as `cursor` (0 to start) to continue.
+    pub fn get_dir_all(
+        ctx: Context<GetDirAll>,
+        path: String,
+        max_depth: Option<u32>,
+        cursor: u64,
+        limit: u32,
+    ) -> Result<RecursiveDirListAnchor> {
+        let path = canonicalize_path(&path)?;
 
-    Ok((
-        target_dir_obj.children_files.clone(),
-        target_dir_obj.children_directories.clone(),
-    ))
-}
+        let file_arena_data = &ctx.accounts.file_arena.data;
+        let dir_arena_data = &ctx.accounts.dir_arena.data;
+        let root_children_dirs_data = &ctx.accounts.root_children_directories.data;
 
-fn internal_recursive_get_dir_obj_ids(
-    dir_id: u64,
-    dir_arena_data: &Vec<KeyValueU64DirObject>,
-) -> Result<(BTreeSet<u64>, BTreeSet<u64>)> {
-    let mut file_ids = BTreeSet::new();
-    let mut dir_ids_recursive = BTreeSet::new();
+        let target_dir_id = {
+            let (grandparent_dir_id, target_dir_name_from_parent) =
+                internal_resolve_parent_id_and_name(
+                    &path,
+                    root_children_dirs_data,
+                    dir_arena_data,
+                )?;
 
-    let mut dirs_to_process = vec![dir_id];
-    let mut visited_dirs = BTreeSet::new();
+            let grandparent_children_dirs_vec = match grandparent_dir_id {
+                Some(id) => {
+                    &get_from_dir_arena(dir_arena_data, id)
+                        .ok_or(WalrusFsError::ArenaMismatchError)?
+                        .children_directories
+                }
+                None => root_children_dirs_data,
+            };
+            get_from_vec_str_key(grandparent_children_dirs_vec, &target_dir_name_from_parent)
+                .ok_or(WalrusFsError::PathNotFound)?
+        };
 
-    while let Some(current_dir_id) = dirs_to_process.pop() {
-        if visited_dirs.contains(&current_dir_id) {
-            continue;
+        let ordered_dir_ids = internal_bfs_dir_ids(*target_dir_id, max_depth, dir_arena_data)?;
+        let start = if cursor == 0 {
+            0
+        } else {
+            ordered_dir_ids
+                .iter()
+                .position(|id| *id == cursor)
+                .ok_or(WalrusFsError::InvalidCursor)?
+        };
+        let end = if limit == 0 {
+            ordered_dir_ids.len()
+        } else {
+            ordered_dir_ids.len().min(start + limit as usize)
+        };
+
+        let mut files_ex = Vec::new();
+        let mut dirs_ex = Vec::new();
+        for did in ordered_dir_ids[start..end].iter() {
+            let d_obj = get_from_dir_arena(dir_arena_data, *did)
+                .ok_or(WalrusFsError::ArenaMismatchError)?;
+            for kv in d_obj.children_files.iter() {
+                if let Some(obj) = get_from_file_arena(file_arena_data, kv.value) {
+                    files_ex.push(FileObjectExAnchor {
+                        id: kv.value,
+                        obj: obj.clone(),
+                    });
+                }
+            }
+            dirs_ex.push(DirObjectExAnchor {
+                id: *did,
+                create_ts: d_obj.create_ts,
+                modify_ts: d_obj.modify_ts,
+                tags: tag_names(&ctx.accounts.tag_dictionary.tags, &d_obj.tags),
+                children_file_names: d_obj
+                    .children_files
+                    .iter()
+                    .map(|kv| kv.key.clone())
+                    .collect(),
+                children_file_ids: d_obj.children_files.iter().map(|kv| kv.value).collect(),
+                children_directory_names: d_obj
+                    .children_directories
+                    .iter()
+                    .map(|kv| kv.key.clone())
+                    .collect(),
+                children_directory_ids: d_obj
+                    .children_directories
+                    .iter()
+                    .map(|kv| kv.value)
+                    .collect(),
+            });
         }
-        visited_dirs.insert(current_dir_id);
 
-        let dir_object = get_from_dir_arena(dir_arena_data, current_dir_id)
+        Ok(RecursiveDirListAnchor {
+            dirobj: *target_dir_id,
+            files: files_ex,
+            dirs: dirs_ex,
+            next_cursor: ordered_dir_ids.get(end).copied(),
+        })
+    }
+
+    pub fn set_blob_deletable(
+        ctx: Context<UpdateFileMeta>,
+        path: String,
+        deletable: bool,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+
+        let file_id = internal_resolve_file_id(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        let f = get_mut_from_file_arena(&mut ctx.accounts.file_arena.data, file_id)
             .ok_or(WalrusFsError::ArenaMismatchError)?;
+        require_file_writer(
+            &ctx.accounts.walrusfs_root,
+            &ctx.accounts.writer_grant,
+            &ctx.accounts.group,
+            &ctx.accounts.session_key,
+            f,
+            ctx.accounts.authority.key,
+            &path,
+        )?;
+        f.deletable = deletable;
+        Ok(())
+    }
 
-        for kv_pair in dir_object.children_files.iter() {
-            file_ids.insert(kv_pair.value);
+    #[allow(clippy::too_many_arguments)]
+    pub fn update_file(
+        ctx: Context<UpdateFile>,
+        path: String,
+        walrus_blob_id: [u8; 32],
+        size: u64,
+        end_epoch: u64,
+        sha256: Option<[u8; 32]>,
+        content_type: Option<String>,
+        preview_blob_id: Option<String>,
+        sui_object_id: Option<[u8; 32]>,
+        encryption: Option<EncryptionInfo>,
+        expect_unlocked: bool,
+        expected_object_id: Option<u64>,
+        expected_blob_id: Option<[u8; 32]>,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+        if let Some(content_type) = &content_type {
+            validate_string_len(content_type, "content_type")?;
         }
+        if let Some(preview_blob_id) = &preview_blob_id {
+            validate_string_len(preview_blob_id, "preview_blob_id")?;
+        }
+        validate_encryption(&encryption)?;
+        let encrypted = encryption.is_some();
 
-        for kv_pair in dir_object.children_directories.iter() {
-            let sub_dir_id = kv_pair.value;
-            if sub_dir_id != dir_id {
-                // Avoid self-reference cycles if they could exist (though unlikely here)
-                dir_ids_recursive.insert(sub_dir_id);
-            }
-            // Only add to process if not already visited (implicitly handled by visited_dirs check at loop start)
-            if !visited_dirs.contains(&sub_dir_id) {
-                // Explicit check here can be slightly more efficient
-                dirs_to_process.push(sub_dir_id);
-            }
+        let now = Clock::get()?.unix_timestamp as u64 * 1000;
+        let file_arena_data = &mut ctx.accounts.file_arena.data;
+        let file_id = internal_resolve_file_id(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        let f = get_mut_from_file_arena(file_arena_data, file_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        require_file_writer(
+            &ctx.accounts.walrusfs_root,
+            &ctx.accounts.writer_grant,
+            &ctx.accounts.group,
+            &ctx.accounts.session_key,
+            f,
+            ctx.accounts.authority.key,
+            &path,
+        )?;
+        require!(!f.sealed, WalrusFsError::FileSealed);
+        require!(
+            f.journal_segments.is_none(),
+            WalrusFsError::JournalIsAppendOnly
+        );
+        require!(f.chunks.is_none(), WalrusFsError::ChunkedFileUpdate);
+        require_unlocked(
+            f,
+            expect_unlocked,
+            acting_signer(&ctx.accounts.session_key, ctx.accounts.authority.key, &path)?,
+        )?;
+        require_expected(file_id, f, expected_object_id, &expected_blob_id)?;
+        let previous = f.clone();
+        f.walrus_blob_id = walrus_blob_id;
+        f.size = size;
+        f.walrus_epoch_till = end_epoch;
+        f.sha256 = sha256; // None clears it, since the old digest no longer describes the blob
+        if let Some(content_type) = content_type {
+            f.content_type = content_type; // None keeps the previous type
         }
+        f.preview_blob_id = preview_blob_id; // Like sha256, describes only the blob it came with
+        f.sui_object_id = sui_object_id; // Belongs to the new blob, so None clears it
+        f.encryption = encryption;
+        f.status = BlobStatus::PendingCertification;
+        f.modify_ts = now;
+        adjust_subtree_bytes(
+            parent_dir_path(&path),
+            size,
+            previous.size,
+            &ctx.accounts.root_children_directories.data,
+            &mut ctx.accounts.dir_arena.data,
+        )?;
+
+        // The replaced blob may no longer be referenced by any entry
+        internal_enqueue_unreferenced_blobs(
+            vec![previous],
+            file_arena_data,
+            ctx.accounts
+                .pending_blob_deletes
+                .as_deref_mut()
+                .map(|q| &mut **q),
+        )?;
+
+        emit!(FileUpdatedEvent {
+            path,
+            size,
+            walrus_blob_id,
+            walrus_epoch_till: end_epoch,
+            encrypted,
+        });
+        Ok(())
     }
-    Ok((file_ids, dir_ids_recursive))
-}
-// --- Path Validation and String Utils (Unchanged) ---
-fn validate_path(path: &str) -> Result<()> {
-    if path.is_empty() || path.len() > MAX_STRING_LEN * 5 {
-        // Path can be multiple components
-        return err!(WalrusFsError::PathError);
-    }
-    if !path.starts_with('/') && !path.is_empty() {
-        return err!(WalrusFsError::PathError);
-    }
-    if path.contains("//") {
-        return err!(WalrusFsError::PathError);
-    }
-    Ok(())
-}
 
-fn validate_tags(tags: &[String]) -> Result<()> {
-    if tags.len() > MAX_TAGS {
-        return err!(WalrusFsError::TooManyTags);
+    /// Moves a file's blob along its lifecycle, e.g. to Certified once Walrus has
+    /// certified it. Only forward moves are allowed; a new blob from update_file or
+    /// add_chunk is what puts an entry back to PendingCertification.
+    pub fn set_blob_status(
+        ctx: Context<UpdateFileMeta>,
+        path: String,
+        status: BlobStatus,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+
+        let file_id = internal_resolve_file_id(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        let f = get_mut_from_file_arena(&mut ctx.accounts.file_arena.data, file_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        require_file_writer(
+            &ctx.accounts.walrusfs_root,
+            &ctx.accounts.writer_grant,
+            &ctx.accounts.group,
+            &ctx.accounts.session_key,
+            f,
+            ctx.accounts.authority.key,
+            &path,
+        )?;
+        let previous = f.status;
+        require!(
+            previous.can_become(status),
+            WalrusFsError::InvalidStatusTransition
+        );
+        f.status = status;
+
+        emit!(BlobStatusChangedEvent {
+            path,
+            previous,
+            status,
+        });
+        Ok(())
     }
-    for tag in tags {
-        validate_string_len(tag, "tag")?;
+
+    /// Hands a file to `new_owner`, who may then sign changes to it alongside the fs
+    /// authority, including renaming, moving, linking, overwriting and deleting it.
+    pub fn chown_file(ctx: Context<UpdateFileMeta>, path: String, new_owner: Pubkey) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+
+        let file_id = internal_resolve_file_id(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        let f = get_mut_from_file_arena(&mut ctx.accounts.file_arena.data, file_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        require_file_writer(
+            &ctx.accounts.walrusfs_root,
+            &ctx.accounts.writer_grant,
+            &ctx.accounts.group,
+            &ctx.accounts.session_key,
+            f,
+            ctx.accounts.authority.key,
+            &path,
+        )?;
+        require!(f.sale.is_none(), WalrusFsError::FileListedForSale);
+        let previous = f.owner;
+        f.owner = new_owner;
+
+        emit!(FileOwnerChangedEvent {
+            path,
+            previous,
+            owner: new_owner,
+        });
+        Ok(())
     }
-    Ok(())
-}
 
-fn validate_string_len(s: &str, field_name: &str) -> Result<()> {
-    if s.len() > MAX_STRING_LEN {
-        msg!(
-            "String too long for field: {}. Max: {}, Found: {}",
-            field_name,
-            MAX_STRING_LEN,
-            s.len()
+    /// Offers the file to anyone for `price_lamports`, paid to its owner by
+    /// buy_file. Only the owner may list it. The file stays sealed while listed,
+    /// so the buyer gets the blob that was offered.
+    pub fn list_file_for_sale(
+        ctx: Context<UpdateFileMeta>,
+        path: String,
+        price_lamports: u64,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+
+        let file_id = internal_resolve_file_id(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        let f = get_mut_from_file_arena(&mut ctx.accounts.file_arena.data, file_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        require_keys_eq!(
+            f.owner,
+            ctx.accounts.authority.key(),
+            WalrusFsError::Unauthorized
         );
-        return err!(WalrusFsError::StringTooLong);
-    }
-    Ok(())
-}
+        require!(f.sale.is_none(), WalrusFsError::FileListedForSale);
+        f.sale = Some(SaleListing {
+            price_lamports,
+            was_sealed: f.sealed,
+        });
+        f.sealed = true;
 
-fn remove_trailing_slash(path: &str) -> String {
-    if path != "/" && path.ends_with('/') {
-        path[..path.len() - 1].to_string()
-    } else {
-        path.to_string()
+        emit!(FileListedForSaleEvent {
+            path,
+            seller: f.owner,
+            price_lamports,
+        });
+        Ok(())
     }
-}
 
-fn ensure_trailing_slash(path: &str) -> String {
-    if path == "/" {
-        return "/".to_string();
-    } // Keep root as is
-    if !path.ends_with('/') {
-        format!("{}/", path)
-    } else {
-        path.to_string()
+    /// Withdraws a listing. Only the owner may.
+    pub fn cancel_sale(ctx: Context<UpdateFileMeta>, path: String) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+
+        let file_id = internal_resolve_file_id(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        let f = get_mut_from_file_arena(&mut ctx.accounts.file_arena.data, file_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        require_keys_eq!(
+            f.owner,
+            ctx.accounts.authority.key(),
+            WalrusFsError::Unauthorized
+        );
+        let sale = f.sale.take().ok_or(WalrusFsError::FileNotForSale)?;
+        f.sealed = sale.was_sealed;
+
+        emit!(SaleCancelledEvent { path });
+        Ok(())
+    }
+
+    /// Pays a listed file's price to its owner and makes the signer its owner.
+    /// Fails if the price is above `max_price_lamports`, so a seller cannot
+    /// relist at a higher price ahead of the buyer's transaction.
+    pub fn buy_file(ctx: Context<BuyFile>, path: String, max_price_lamports: u64) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+
+        let file_id = internal_resolve_file_id(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        let f = get_mut_from_file_arena(&mut ctx.accounts.file_arena.data, file_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        let sale = f.sale.take().ok_or(WalrusFsError::FileNotForSale)?;
+        require_keys_eq!(
+            f.owner,
+            ctx.accounts.seller.key(),
+            WalrusFsError::SellerMismatch
+        );
+        require!(
+            sale.price_lamports <= max_price_lamports,
+            WalrusFsError::PriceAboveMaximum
+        );
+        let seller = f.owner;
+        let buyer = ctx.accounts.buyer.key();
+        f.owner = buyer;
+        f.sealed = sale.was_sealed;
+
+        if sale.price_lamports > 0 {
+            anchor_lang::system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.buyer.to_account_info(),
+                        to: ctx.accounts.seller.to_account_info(),
+                    },
+                ),
+                sale.price_lamports,
+            )?;
+        }
+
+        emit!(FileSoldEvent {
+            path,
+            seller,
+            buyer,
+            price_lamports: sale.price_lamports,
+        });
+        Ok(())
+    }
+
+    pub fn extend_file_epoch(
+        ctx: Context<UpdateFileMeta>,
+        path: String,
+        new_end_epoch: u64,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+
+        let file_id = internal_resolve_file_id(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        let f = get_mut_from_file_arena(&mut ctx.accounts.file_arena.data, file_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        require_file_writer(
+            &ctx.accounts.walrusfs_root,
+            &ctx.accounts.writer_grant,
+            &ctx.accounts.group,
+            &ctx.accounts.session_key,
+            f,
+            ctx.accounts.authority.key,
+            &path,
+        )?;
+        require!(
+            new_end_epoch >= f.walrus_epoch_till,
+            WalrusFsError::EpochRegression
+        );
+        f.walrus_epoch_till = new_end_epoch;
+        f.modify_ts = Clock::get()?.unix_timestamp as u64 * 1000;
+
+        emit!(FileEpochExtendedEvent {
+            path,
+            walrus_epoch_till: new_end_epoch,
+        });
+        Ok(())
+    }
+
+    pub fn touch(ctx: Context<UpdateFileMeta>, path: String) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+
+        let file_id = internal_resolve_file_id(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        let now = Clock::get()?.unix_timestamp as u64 * 1000;
+        let f = get_mut_from_file_arena(&mut ctx.accounts.file_arena.data, file_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        require_file_writer(
+            &ctx.accounts.walrusfs_root,
+            &ctx.accounts.writer_grant,
+            &ctx.accounts.group,
+            &ctx.accounts.session_key,
+            f,
+            ctx.accounts.authority.key,
+            &path,
+        )?;
+        f.modify_ts = now;
+
+        emit!(FileTouchedEvent {
+            path,
+            modify_ts: now,
+        });
+        Ok(())
+    }
+
+    /// Stamps access_ts on a file and bumps its access_count. Reads never update
+    /// them implicitly, since views cannot write; clients call this when they fetch
+    /// the blob. Anyone may call it, so readers other than the owner are counted too.
+    pub fn record_access(ctx: Context<RecordAccess>, path: String) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+
+        let file_id = internal_resolve_file_id(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        let now = Clock::get()?.unix_timestamp as u64 * 1000;
+        let f = get_mut_from_file_arena(&mut ctx.accounts.file_arena.data, file_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        f.access_ts = Some(now);
+        f.access_count = f.access_count.saturating_add(1);
+
+        emit!(FileAccessedEvent {
+            path,
+            access_ts: now,
+            access_count: f.access_count,
+        });
+        Ok(())
+    }
+
+    pub fn update_file_tags(
+        ctx: Context<UpdateFileMeta>,
+        path: String,
+        tags: Vec<String>,
+        expect_unlocked: bool,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+        let limits = ctx.accounts.walrusfs_root.limits;
+        validate_tags(&tags, &limits)?;
+
+        let file_id = internal_resolve_file_id(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        let f = get_mut_from_file_arena(&mut ctx.accounts.file_arena.data, file_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        require_file_writer(
+            &ctx.accounts.walrusfs_root,
+            &ctx.accounts.writer_grant,
+            &ctx.accounts.group,
+            &ctx.accounts.session_key,
+            f,
+            ctx.accounts.authority.key,
+            &path,
+        )?;
+        require!(!f.sealed, WalrusFsError::FileSealed);
+        require_unlocked(
+            f,
+            expect_unlocked,
+            acting_signer(&ctx.accounts.session_key, ctx.accounts.authority.key, &path)?,
+        )?;
+        f.tags = intern_tags(&mut ctx.accounts.tag_dictionary.tags, &tags)?;
+        f.modify_ts = Clock::get()?.unix_timestamp as u64 * 1000;
+
+        emit!(TagsUpdatedEvent { path, tags });
+        Ok(())
+    }
+
+    pub fn set_renewal_policy(
+        ctx: Context<UpdateFileMeta>,
+        path: String,
+        policy: RenewalPolicy,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+
+        let file_id = internal_resolve_file_id(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        let f = get_mut_from_file_arena(&mut ctx.accounts.file_arena.data, file_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        require_file_writer(
+            &ctx.accounts.walrusfs_root,
+            &ctx.accounts.writer_grant,
+            &ctx.accounts.group,
+            &ctx.accounts.session_key,
+            f,
+            ctx.accounts.authority.key,
+            &path,
+        )?;
+        f.renewal_policy = policy;
+        Ok(())
+    }
+
+    pub fn set_storage_class(
+        ctx: Context<UpdateFileMeta>,
+        path: String,
+        storage_class: StorageClass,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+
+        let file_id = internal_resolve_file_id(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        let f = get_mut_from_file_arena(&mut ctx.accounts.file_arena.data, file_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        require_file_writer(
+            &ctx.accounts.walrusfs_root,
+            &ctx.accounts.writer_grant,
+            &ctx.accounts.group,
+            &ctx.accounts.session_key,
+            f,
+            ctx.accounts.authority.key,
+            &path,
+        )?;
+        f.storage_class = storage_class;
+        Ok(())
+    }
+
+    pub fn pin(ctx: Context<PinEntry>, path: String) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+        internal_set_pinned(&path, true, ctx.accounts)
+    }
+
+    pub fn unpin(ctx: Context<PinEntry>, path: String) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+        internal_set_pinned(&path, false, ctx.accounts)
+    }
+
+    /// Sets the owner/group/other rwx bits of a file or directory. Authorities may
+    /// chmod anything and a file's owner may chmod that file; delegates cannot.
+    pub fn chmod(ctx: Context<Chmod>, path: String, mode: u16) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+        require!(mode & !MODE_BITS == 0, WalrusFsError::InvalidMode);
+
+        let signer = ctx.accounts.authority.key;
+        let is_authority = is_fs_writer(&ctx.accounts.walrusfs_root, signer);
+        let resolved = internal_resolve_entry(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        if resolved.is_dir {
+            require!(is_authority, WalrusFsError::Unauthorized);
+            get_mut_from_dir_arena(&mut ctx.accounts.dir_arena.data, resolved.object_id)
+                .ok_or(WalrusFsError::ArenaMismatchError)?
+                .mode = mode;
+        } else {
+            let f = get_mut_from_file_arena(&mut ctx.accounts.file_arena.data, resolved.object_id)
+                .ok_or(WalrusFsError::ArenaMismatchError)?;
+            require!(
+                is_authority || *signer == f.owner,
+                WalrusFsError::Unauthorized
+            );
+            f.mode = mode;
+        }
+
+        emit!(ModeChangedEvent { path, mode });
+        Ok(())
+    }
+
+    /// Assigns a file or directory to the passed group, or to none when no group
+    /// is passed. New children inherit a directory's group. The same signers as
+    /// chmod may chgrp, but a file's owner may only pick a group they belong to.
+    pub fn chgrp(ctx: Context<Chgrp>, path: String) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+        let signer = ctx.accounts.authority.key;
+        let is_authority = is_fs_writer(&ctx.accounts.walrusfs_root, signer);
+        let group = ctx.accounts.group.as_ref().map(|g| g.key());
+        let resolved = internal_resolve_entry(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        if resolved.is_dir {
+            require!(is_authority, WalrusFsError::Unauthorized);
+            get_mut_from_dir_arena(&mut ctx.accounts.dir_arena.data, resolved.object_id)
+                .ok_or(WalrusFsError::ArenaMismatchError)?
+                .group = group;
+        } else {
+            let f = get_mut_from_file_arena(&mut ctx.accounts.file_arena.data, resolved.object_id)
+                .ok_or(WalrusFsError::ArenaMismatchError)?;
+            if !is_authority {
+                require!(*signer == f.owner, WalrusFsError::Unauthorized);
+                if let Some(g) = &ctx.accounts.group {
+                    require!(g.members.contains(signer), WalrusFsError::NotGroupMember);
+                }
+            }
+            f.group = group;
+        }
+
+        emit!(GroupChangedEvent { path, group });
+        Ok(())
+    }
+
+    /// Gates reads of a file or directory, and everything below a directory, on
+    /// `policy`, or lifts the gate when None. The same signers as chmod may set it.
+    pub fn set_access_policy(
+        ctx: Context<Chmod>,
+        path: String,
+        policy: Option<AccessPolicy>,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+        let signer = ctx.accounts.authority.key;
+        let is_authority = is_fs_writer(&ctx.accounts.walrusfs_root, signer);
+        let resolved = internal_resolve_entry(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        if resolved.is_dir {
+            require!(is_authority, WalrusFsError::Unauthorized);
+            get_mut_from_dir_arena(&mut ctx.accounts.dir_arena.data, resolved.object_id)
+                .ok_or(WalrusFsError::ArenaMismatchError)?
+                .access_policy = policy.clone();
+        } else {
+            let f = get_mut_from_file_arena(&mut ctx.accounts.file_arena.data, resolved.object_id)
+                .ok_or(WalrusFsError::ArenaMismatchError)?;
+            require!(
+                is_authority || *signer == f.owner,
+                WalrusFsError::Unauthorized
+            );
+            f.access_policy = policy.clone();
+        }
+
+        emit!(AccessPolicyChangedEvent { path, policy });
+        Ok(())
+    }
+
+    /// Whether `wallet` passes the access policies of `path` and of every
+    /// directory above it. Pass the wallet's token accounts as remaining accounts,
+    /// plus the Metaplex metadata of each NFT offered for a collection policy.
+    /// Authorities always pass.
+    pub fn check_access(
+        ctx: Context<ReadUserFileSystem>,
+        path: String,
+        wallet: Pubkey,
+    ) -> Result<bool> {
+        let path = canonicalize_path(&path)?;
+        if is_fs_writer(&ctx.accounts.walrusfs_root, &wallet) {
+            return Ok(true);
+        }
+        let policies = internal_access_policies(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.file_arena.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        if policies.is_empty() {
+            return Ok(true);
+        }
+        let holdings = wallet_holdings(&wallet, ctx.remaining_accounts)?;
+        Ok(policies
+            .iter()
+            .all(|policy| holdings_satisfy(&holdings, policy)))
+    }
+
+    /// Turns an empty directory into a mount point for `target`, a path in another
+    /// user's filesystem, or back into a plain directory when None. stat and
+    /// list_dir below the mount then read the mounted filesystem, which callers
+    /// pass as remaining accounts; see load_mounted_fs. Nothing can be written
+    /// below a mount point here.
+    pub fn set_mount(
+        ctx: Context<SetMount>,
+        path: String,
+        target: Option<MountPoint>,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+        let target = match target {
+            Some(t) => Some(MountPoint {
+                owner: t.owner,
+                fs_name: t.fs_name,
+                path: canonicalize_path(&t.path)?,
+            }),
+            None => None,
+        };
+        let dir_id = internal_resolve_dir_id(
+            &path,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?
+        .ok_or(WalrusFsError::InvalidPathOperationOnRoot)?;
+        let d = get_mut_from_dir_arena(&mut ctx.accounts.dir_arena.data, dir_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        require!(
+            d.child_file_count == 0 && d.child_dir_count == 0,
+            WalrusFsError::DirectoryNotEmpty
+        );
+        d.mount = target.clone();
+
+        emit!(MountChangedEvent { path, target });
+        Ok(())
+    }
+
+    /// Appends a blob to the end of a journal; the journal's size is the sum of its segments.
+    pub fn append_segment(
+        ctx: Context<UpdateFileMeta>,
+        path: String,
+        walrus_blob_id: [u8; 32],
+        size: u64,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+
+        let file_id = internal_resolve_file_id(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        let f = get_mut_from_file_arena(&mut ctx.accounts.file_arena.data, file_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        require_file_writer(
+            &ctx.accounts.walrusfs_root,
+            &ctx.accounts.writer_grant,
+            &ctx.accounts.group,
+            &ctx.accounts.session_key,
+            f,
+            ctx.accounts.authority.key,
+            &path,
+        )?;
+        require!(!f.sealed, WalrusFsError::FileSealed);
+        let segments = f
+            .journal_segments
+            .as_mut()
+            .ok_or(WalrusFsError::NotAJournal)?;
+        segments.push(JournalSegment {
+            walrus_blob_id,
+            size,
+        });
+        let segment_index = (segments.len() - 1) as u32;
+        f.size = f.size.saturating_add(size);
+        f.modify_ts = Clock::get()?.unix_timestamp as u64 * 1000;
+        adjust_subtree_bytes(
+            parent_dir_path(&path),
+            size,
+            0,
+            &ctx.accounts.root_children_directories.data,
+            &mut ctx.accounts.dir_arena.data,
+        )?;
+
+        emit!(SegmentAppendedEvent {
+            path,
+            segment_index,
+            walrus_blob_id,
+            size,
+        });
+        Ok(())
+    }
+
+    /// Attaches the next chunk of a chunked file. Chunks must not overlap and are kept
+    /// in offset order; the file's size is the end of its last chunk.
+    pub fn add_chunk(
+        ctx: Context<UpdateFileMeta>,
+        path: String,
+        walrus_blob_id: [u8; 32],
+        offset: u64,
+        length: u64,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+        require!(length > 0, WalrusFsError::InvalidChunk);
+        let chunk_end = offset
+            .checked_add(length)
+            .ok_or(WalrusFsError::InvalidChunk)?;
+
+        let file_id = internal_resolve_file_id(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        let f = get_mut_from_file_arena(&mut ctx.accounts.file_arena.data, file_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        require_file_writer(
+            &ctx.accounts.walrusfs_root,
+            &ctx.accounts.writer_grant,
+            &ctx.accounts.group,
+            &ctx.accounts.session_key,
+            f,
+            ctx.accounts.authority.key,
+            &path,
+        )?;
+        require!(!f.sealed, WalrusFsError::FileSealed);
+        let chunks = f.chunks.as_mut().ok_or(WalrusFsError::NotAChunkedFile)?;
+        let current_end = chunks.last().map_or(0, |c| c.offset + c.length);
+        require!(offset >= current_end, WalrusFsError::InvalidChunk);
+        chunks.push(FileChunk {
+            walrus_blob_id,
+            offset,
+            length,
+        });
+        let previous_size = f.size;
+        f.size = chunk_end;
+        f.status = BlobStatus::PendingCertification;
+        f.modify_ts = Clock::get()?.unix_timestamp as u64 * 1000;
+        adjust_subtree_bytes(
+            parent_dir_path(&path),
+            chunk_end,
+            previous_size,
+            &ctx.accounts.root_children_directories.data,
+            &mut ctx.accounts.dir_arena.data,
+        )?;
+
+        emit!(ChunkAddedEvent {
+            path,
+            walrus_blob_id,
+            offset,
+            length,
+        });
+        Ok(())
+    }
+
+    /// Sets the tags merged into every file later created below this directory.
+    /// Existing files are left as they are.
+    pub fn set_default_tags(
+        ctx: Context<SetDefaultTags>,
+        path: String,
+        default_tags: Vec<String>,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+        let limits = ctx.accounts.walrusfs_root.limits;
+        validate_tags(&default_tags, &limits)?;
+
+        let dir_arena_data = &mut ctx.accounts.dir_arena.data;
+        let dir_id = internal_resolve_dir_id(
+            &path,
+            &ctx.accounts.root_children_directories.data,
+            dir_arena_data,
+        )?
+        .ok_or(WalrusFsError::InvalidPathOperationOnRoot)?;
+        get_mut_from_dir_arena(dir_arena_data, dir_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?
+            .default_tags = intern_tags(&mut ctx.accounts.tag_dictionary.tags, &default_tags)?;
+
+        emit!(DefaultTagsUpdatedEvent { path, default_tags });
+        Ok(())
+    }
+
+    /// Sets one extended attribute on a file or directory, replacing any existing
+    /// value for `key`.
+    pub fn set_xattr(
+        ctx: Context<SetXattr>,
+        path: String,
+        key: String,
+        value: String,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+        require!(
+            !key.is_empty() && key.len() <= MAX_STRING_LEN && value.len() <= MAX_XATTR_VALUE_LEN,
+            WalrusFsError::InvalidXattr
+        );
+
+        let xattrs = internal_xattrs_mut(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &mut ctx.accounts.file_arena.data,
+            &mut ctx.accounts.dir_arena.data,
+        )?;
+        match xattrs.iter_mut().find(|kv| kv.key == key) {
+            Some(kv) => kv.value = value,
+            None => {
+                require!(xattrs.len() < MAX_XATTRS, WalrusFsError::TooManyXattrs);
+                xattrs.push(KeyValueStringString {
+                    key: key.clone(),
+                    value,
+                });
+            }
+        }
+
+        emit!(XattrUpdatedEvent {
+            path,
+            key,
+            removed: false,
+        });
+        Ok(())
+    }
+
+    pub fn remove_xattr(ctx: Context<SetXattr>, path: String, key: String) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+
+        let xattrs = internal_xattrs_mut(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &mut ctx.accounts.file_arena.data,
+            &mut ctx.accounts.dir_arena.data,
+        )?;
+        let index = xattrs
+            .iter()
+            .position(|kv| kv.key == key)
+            .ok_or(WalrusFsError::XattrNotFound)?;
+        xattrs.remove(index);
+
+        emit!(XattrUpdatedEvent {
+            path,
+            key,
+            removed: true,
+        });
+        Ok(())
+    }
+
+    pub fn init_description(
+        ctx: Context<InitDescription>,
+        path: String,
+        object_id: u64,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+
+        let resolved = internal_resolve_entry(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        require!(
+            resolved.object_id == object_id,
+            WalrusFsError::ObjectIdMismatch
+        );
+
+        let description = &mut ctx.accounts.description;
+        description.object_id = object_id;
+        description.description = String::new();
+        description.schema_version = SCHEMA_VERSION;
+        description.bump = ctx.bumps.description;
+        Ok(())
     }
+
+    /// Sets the description of a file or directory; an empty string clears it.
+    pub fn set_description(
+        ctx: Context<SetDescription>,
+        path: String,
+        description: String,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+        require!(
+            description.len() <= MAX_DESCRIPTION_LEN,
+            WalrusFsError::DescriptionTooLong
+        );
+
+        let resolved = internal_resolve_entry(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        require!(
+            resolved.object_id == ctx.accounts.description.object_id,
+            WalrusFsError::ObjectIdMismatch
+        );
+        let has_description = !description.is_empty();
+        if resolved.is_dir {
+            get_mut_from_dir_arena(&mut ctx.accounts.dir_arena.data, resolved.object_id)
+                .ok_or(WalrusFsError::ArenaMismatchError)?
+                .has_description = has_description;
+        } else {
+            let f = get_mut_from_file_arena(&mut ctx.accounts.file_arena.data, resolved.object_id)
+                .ok_or(WalrusFsError::ArenaMismatchError)?;
+            require!(!f.sealed, WalrusFsError::FileSealed);
+            f.has_description = has_description;
+        }
+        ctx.accounts.description.description = description;
+
+        emit!(DescriptionUpdatedEvent {
+            path,
+            has_description,
+        });
+        Ok(())
+    }
+
+    /// Seals a file against update, rename, move and delete. A `permanent` seal
+    /// can never be lifted.
+    pub fn seal_file(ctx: Context<UpdateFileMeta>, path: String, permanent: bool) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+
+        let file_id = internal_resolve_file_id(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        let f = get_mut_from_file_arena(&mut ctx.accounts.file_arena.data, file_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        require_file_writer(
+            &ctx.accounts.walrusfs_root,
+            &ctx.accounts.writer_grant,
+            &ctx.accounts.group,
+            &ctx.accounts.session_key,
+            f,
+            ctx.accounts.authority.key,
+            &path,
+        )?;
+        require!(!f.seal_permanent, WalrusFsError::SealIsPermanent);
+        f.sealed = true;
+        f.seal_permanent = permanent;
+
+        emit!(FileSealedEvent { path, permanent });
+        Ok(())
+    }
+
+    pub fn unseal_file(ctx: Context<UpdateFileMeta>, path: String) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+
+        let file_id = internal_resolve_file_id(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        let f = get_mut_from_file_arena(&mut ctx.accounts.file_arena.data, file_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        require_file_writer(
+            &ctx.accounts.walrusfs_root,
+            &ctx.accounts.writer_grant,
+            &ctx.accounts.group,
+            &ctx.accounts.session_key,
+            f,
+            ctx.accounts.authority.key,
+            &path,
+        )?;
+        require!(!f.seal_permanent, WalrusFsError::SealIsPermanent);
+        require!(f.sale.is_none(), WalrusFsError::FileListedForSale);
+        f.sealed = false;
+
+        emit!(FileUnsealedEvent { path });
+        Ok(())
+    }
+
+    /// Takes or renews an advisory lease on a file for `lease_slots` slots. Writers that
+    /// pass `expect_unlocked` fail while any unexpired lease is held.
+    pub fn lock_file(ctx: Context<UpdateFileMeta>, path: String, lease_slots: u64) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+        require!(lease_slots > 0, WalrusFsError::InvalidLease);
+
+        let holder = *acting_signer(&ctx.accounts.session_key, ctx.accounts.authority.key, &path)?;
+        let current_slot = Clock::get()?.slot;
+        let file_id = internal_resolve_file_id(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        let f = get_mut_from_file_arena(&mut ctx.accounts.file_arena.data, file_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        require_file_writer(
+            &ctx.accounts.walrusfs_root,
+            &ctx.accounts.writer_grant,
+            &ctx.accounts.group,
+            &ctx.accounts.session_key,
+            f,
+            ctx.accounts.authority.key,
+            &path,
+        )?;
+        if let Some(lock) = active_lock(f, current_slot) {
+            require_keys_eq!(lock.holder, holder, WalrusFsError::FileLocked);
+        }
+        let expires_slot = current_slot.saturating_add(lease_slots);
+        f.lock = Some(FileLock {
+            holder,
+            expires_slot,
+        });
+
+        emit!(FileLockedEvent {
+            path,
+            holder,
+            expires_slot,
+        });
+        Ok(())
+    }
+
+    pub fn unlock_file(ctx: Context<UpdateFileMeta>, path: String) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+
+        let holder = *acting_signer(&ctx.accounts.session_key, ctx.accounts.authority.key, &path)?;
+        let current_slot = Clock::get()?.slot;
+        let file_id = internal_resolve_file_id(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        let f = get_mut_from_file_arena(&mut ctx.accounts.file_arena.data, file_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        require_file_writer(
+            &ctx.accounts.walrusfs_root,
+            &ctx.accounts.writer_grant,
+            &ctx.accounts.group,
+            &ctx.accounts.session_key,
+            f,
+            ctx.accounts.authority.key,
+            &path,
+        )?;
+        let lock = active_lock(f, current_slot).ok_or(WalrusFsError::LockNotHeld)?;
+        require_keys_eq!(lock.holder, holder, WalrusFsError::LockNotHeld);
+        f.lock = None;
+
+        emit!(FileUnlockedEvent { path });
+        Ok(())
+    }
+
+    pub fn list_expiring(
+        ctx: Context<ReadUserFileSystem>,
+        before_epoch: u64,
+        limit: u32,
+    ) -> Result<Vec<FileObjectExAnchor>> {
+        let mut expiring: Vec<&KeyValueU64FileObject> = ctx
+            .accounts
+            .file_arena
+            .data
+            .iter()
+            .filter(|kv| {
+                matches!(
+                    kv.value.kind,
+                    ObjectKind::RegularFile | ObjectKind::ChunkedFile
+                )
+            })
+            .filter(|kv| kv.value.walrus_epoch_till < before_epoch)
+            .collect();
+        // Pinned files first, so a crank that runs out of funds renews them before the rest
+        expiring.sort_by_key(|kv| !kv.value.pinned);
+        Ok(expiring
+            .into_iter()
+            .take(limit as usize)
+            .map(|kv| FileObjectExAnchor {
+                id: kv.key,
+                obj: kv.value.clone(),
+            })
+            .collect())
+    }
+
+    pub fn initialize_blob_delete_queue(ctx: Context<InitializeBlobDeleteQueue>) -> Result<()> {
+        let queue = &mut ctx.accounts.pending_blob_deletes;
+        queue.blob_ids = Vec::new();
+        queue.pinned_blobs = Vec::new();
+        queue.schema_version = SCHEMA_VERSION;
+        queue.bump = ctx.bumps.pending_blob_deletes;
+        Ok(())
+    }
+
+    pub fn initialize_trash(ctx: Context<InitializeTrash>) -> Result<()> {
+        let trash = &mut ctx.accounts.trash;
+        trash.entries = Vec::new();
+        trash.schema_version = SCHEMA_VERSION;
+        trash.bump = ctx.bumps.trash;
+        Ok(())
+    }
+
+    pub fn initialize_deletion_cursor(ctx: Context<InitializeDeletionCursor>) -> Result<()> {
+        let cursor = &mut ctx.accounts.deletion_cursor;
+        cursor.path = String::new();
+        cursor.pending_dir_ids = Vec::new();
+        cursor.dirs_removed = 0;
+        cursor.files_removed = 0;
+        cursor.schema_version = SCHEMA_VERSION;
+        cursor.bump = ctx.bumps.deletion_cursor;
+        Ok(())
+    }
+
+    pub fn initialize_snapshot_index(ctx: Context<InitializeSnapshotIndex>) -> Result<()> {
+        let index = &mut ctx.accounts.snapshot_index;
+        index.snapshots = Vec::new();
+        index.schema_version = SCHEMA_VERSION;
+        index.bump = ctx.bumps.snapshot_index;
+        Ok(())
+    }
+
+    /// Captures the metadata of every entry under `path` into a new snapshot PDA and
+    /// pins the blobs it lists, so deleting the live files leaves those blobs out of
+    /// the delete queue until delete_snapshot.
+    pub fn create_snapshot(ctx: Context<CreateSnapshot>, path: String, name: String) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+        require!(
+            !name.is_empty() && name.len() <= MAX_SNAPSHOT_NAME_LEN,
+            WalrusFsError::InvalidSnapshotName
+        );
+
+        let entries = internal_capture_snapshot_entries(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.file_arena.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+
+        let now = Clock::get()?.unix_timestamp as u64 * 1000;
+        ctx.accounts.snapshot_index.snapshots.push(SnapshotSummary {
+            name: name.clone(),
+            path: path.clone(),
+            create_ts: now,
+            entry_count: entries.len() as u32,
+        });
+        internal_pin_snapshot_blobs(&entries, &mut ctx.accounts.pending_blob_deletes);
+        let payer = ctx.accounts.authority.to_account_info();
+        let system_program = ctx.accounts.system_program.to_account_info();
+        grow_to_fit(&ctx.accounts.snapshot_index, &payer, &system_program)?;
+        grow_to_fit(&ctx.accounts.pending_blob_deletes, &payer, &system_program)?;
+
+        let snapshot = &mut ctx.accounts.snapshot;
+        snapshot.name = name.clone();
+        snapshot.path = path.clone();
+        snapshot.create_ts = now;
+        snapshot.entries = entries;
+        snapshot.schema_version = SCHEMA_VERSION;
+        snapshot.bump = ctx.bumps.snapshot;
+
+        emit!(SnapshotCreatedEvent {
+            name,
+            path,
+            entry_count: snapshot.entries.len() as u32,
+        });
+        Ok(())
+    }
+
+    /// Removes a snapshot and releases its pins, queueing any deletable blob that
+    /// neither the live tree nor another snapshot still uses.
+    pub fn delete_snapshot(ctx: Context<DeleteSnapshot>, name: String) -> Result<()> {
+        let index = &mut ctx.accounts.snapshot_index.snapshots;
+        let position = index
+            .iter()
+            .position(|s| s.name == name)
+            .ok_or(WalrusFsError::SnapshotNotFound)?;
+        index.remove(position);
+
+        internal_unpin_snapshot_blobs(
+            &ctx.accounts.snapshot.entries,
+            &mut ctx.accounts.pending_blob_deletes,
+            &ctx.accounts.file_arena.data,
+        );
+
+        emit!(SnapshotDeletedEvent { name });
+        Ok(())
+    }
+
+    pub fn list_snapshots(ctx: Context<ListSnapshots>) -> Result<Vec<SnapshotSummary>> {
+        Ok(ctx.accounts.snapshot_index.snapshots.clone())
+    }
+
+    /// Returns a page of a snapshot's entries, sorted by relative path.
+    pub fn read_snapshot(
+        ctx: Context<ReadSnapshot>,
+        _name: String,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Vec<SnapshotEntry>> {
+        Ok(page_of(
+            ctx.accounts.snapshot.entries.clone(),
+            offset,
+            limit,
+        ))
+    }
+
+    /// Compares two snapshots by relative path; `modified` holds the entries from `other_name`.
+    pub fn diff_snapshots(
+        ctx: Context<DiffSnapshots>,
+        _base_name: String,
+        _other_name: String,
+    ) -> Result<SnapshotDiffAnchor> {
+        Ok(internal_diff_snapshot_entries(
+            &ctx.accounts.base_snapshot.entries,
+            &ctx.accounts.other_snapshot.entries,
+        ))
+    }
+
+    /// Compares a snapshot with the live tree at the snapshot's path, so sync tools
+    /// can tell which entries need uploading since the snapshot was taken.
+    pub fn diff_snapshot_live(
+        ctx: Context<DiffSnapshotLive>,
+        _name: String,
+    ) -> Result<SnapshotDiffAnchor> {
+        let snapshot = &ctx.accounts.snapshot;
+        let live_entries = internal_capture_snapshot_entries(
+            &snapshot.path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.file_arena.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        Ok(internal_diff_snapshot_entries(
+            &snapshot.entries,
+            &live_entries,
+        ))
+    }
+
+    /// Copies a snapshot into its own read-only PDA for publishing. The clone keeps
+    /// its own pins, so its blobs stay out of the delete queue even after the
+    /// snapshot is deleted; published clones are never changed or removed.
+    pub fn publish_clone(ctx: Context<PublishClone>, snapshot_name: String) -> Result<()> {
+        let snapshot = &ctx.accounts.snapshot;
+        let clone = &mut ctx.accounts.published_clone;
+        clone.source_root = ctx.accounts.walrusfs_root.key();
+        clone.snapshot_name = snapshot_name;
+        clone.path = snapshot.path.clone();
+        clone.snapshot_ts = snapshot.create_ts;
+        clone.publish_ts = Clock::get()?.unix_timestamp as u64 * 1000;
+        clone.entries = snapshot.entries.clone();
+        clone.schema_version = SCHEMA_VERSION;
+        clone.bump = ctx.bumps.published_clone;
+
+        let mut bytes = Vec::new();
+        clone.try_serialize(&mut bytes)?;
+        require!(
+            bytes.len() <= PUBLISHED_CLONE_PDA_SPACE,
+            WalrusFsError::SnapshotTooLargeToPublish
+        );
+
+        internal_pin_snapshot_blobs(&clone.entries, &mut ctx.accounts.pending_blob_deletes);
+        grow_to_fit(
+            &ctx.accounts.pending_blob_deletes,
+            &ctx.accounts.authority.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+        )?;
+
+        emit!(ClonePublishedEvent {
+            clone: clone.key(),
+            snapshot_name: clone.snapshot_name.clone(),
+            entry_count: clone.entries.len() as u32,
+        });
+        Ok(())
+    }
+
+    /// Returns a page of a published clone's entries, sorted by relative path.
+    pub fn read_published(
+        ctx: Context<ReadPublished>,
+        _snapshot_name: String,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Vec<SnapshotEntry>> {
+        Ok(page_of(
+            ctx.accounts.published_clone.entries.clone(),
+            offset,
+            limit,
+        ))
+    }
+
+    /// Looks up one entry of a published clone by its path relative to the clone.
+    pub fn resolve_published(
+        ctx: Context<ReadPublished>,
+        _snapshot_name: String,
+        path: String,
+    ) -> Result<SnapshotEntry> {
+        let path = canonicalize_path(&path)?;
+        let entry = ctx
+            .accounts
+            .published_clone
+            .entries
+            .iter()
+            .find(|e| e.path == path)
+            .ok_or(WalrusFsError::PathNotFound)?;
+        Ok(entry.clone())
+    }
+
+    pub fn confirm_blob_deleted(ctx: Context<ConfirmBlobDeleted>, blob_id: [u8; 32]) -> Result<()> {
+        let queue = &mut ctx.accounts.pending_blob_deletes;
+        let index = queue
+            .blob_ids
+            .iter()
+            .position(|b| *b == blob_id)
+            .ok_or(WalrusFsError::BlobNotPendingDelete)?;
+        queue.blob_ids.remove(index);
+
+        emit!(BlobDeleteConfirmedEvent { blob_id });
+        Ok(())
+    }
+
+    /// Creates the tag dictionary of a filesystem initialized before tags were
+    /// interned. Run before migrate_filesystem, which fills it.
+    pub fn initialize_tag_dictionary(ctx: Context<InitializeTagDictionary>) -> Result<()> {
+        let tag_dictionary = &mut ctx.accounts.tag_dictionary;
+        tag_dictionary.tags = Vec::new();
+        tag_dictionary.schema_version = SCHEMA_VERSION;
+        tag_dictionary.bump = ctx.bumps.tag_dictionary;
+        Ok(())
+    }
+
+    /// Upgrades the root and its four core PDAs from the layout filesystems had
+    /// before schema_version to the current one, decoding blob ids and interning
+    /// tags into the tag dictionary. Safe to rerun, as accounts that are already
+    /// current are skipped.
+    pub fn migrate_filesystem(ctx: Context<MigrateFilesystem>) -> Result<()> {
+        let payer = ctx.accounts.authority.to_account_info();
+        let system_program = ctx.accounts.system_program.to_account_info();
+        migrate_root_layout(&ctx.accounts.walrusfs_root, &payer, &system_program)?;
+        migrate_root_children_layout(
+            &ctx.accounts.root_children_files,
+            &ctx.accounts.root_children_directories,
+            &payer,
+            &system_program,
+        )?;
+        migrate_arena_layouts(
+            &ctx.accounts.file_arena,
+            &ctx.accounts.dir_arena,
+            &ctx.accounts.root_children_files,
+            &ctx.accounts.root_children_directories,
+            ctx.accounts.authority.key(),
+            &mut ctx.accounts.tag_dictionary.tags,
+            &payer,
+            &system_program,
+        )
+    }
+
+    /// Upgrades whichever of the filesystem's optional singleton PDAs are passed.
+    /// Run after migrate_filesystem.
+    pub fn migrate_sidecars(ctx: Context<MigrateSidecars>) -> Result<()> {
+        let payer = ctx.accounts.authority.to_account_info();
+        let system_program = ctx.accounts.system_program.to_account_info();
+        let accounts = &ctx.accounts;
+        if let Some(queue) = &accounts.pending_blob_deletes {
+            migrate_pda_layout::<PendingBlobDeletesPda>(queue, &payer, &system_program)?;
+        }
+        if let Some(trash) = &accounts.trash {
+            migrate_pda_layout::<TrashPda>(trash, &payer, &system_program)?;
+        }
+        if let Some(cursor) = &accounts.deletion_cursor {
+            migrate_pda_layout::<DeletionCursorPda>(cursor, &payer, &system_program)?;
+        }
+        if let Some(index) = &accounts.snapshot_index {
+            migrate_pda_layout::<SnapshotIndexPda>(index, &payer, &system_program)?;
+        }
+        if let Some(audit_log) = &accounts.audit_log {
+            migrate_pda_layout::<AuditLogPda>(audit_log, &payer, &system_program)?;
+        }
+        Ok(())
+    }
+
+    pub fn migrate_snapshot(ctx: Context<MigrateSnapshot>, _name: String) -> Result<()> {
+        migrate_pda_layout::<SnapshotPda>(
+            &ctx.accounts.snapshot,
+            &ctx.accounts.authority.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+        )
+    }
+
+    pub fn migrate_share_keys(ctx: Context<MigrateShareKeys>, _file_id: u64) -> Result<()> {
+        migrate_pda_layout::<ShareKeysPda>(
+            &ctx.accounts.share_keys,
+            &ctx.accounts.authority.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+        )
+    }
+
+    pub fn migrate_description(ctx: Context<MigrateDescription>, _object_id: u64) -> Result<()> {
+        migrate_pda_layout::<DescriptionPda>(
+            &ctx.accounts.description,
+            &ctx.accounts.authority.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+        )
+    }
+
+    pub fn initialize_audit_log(ctx: Context<InitializeAuditLog>) -> Result<()> {
+        let audit_log = &mut ctx.accounts.audit_log;
+        audit_log.sequence = 0;
+        audit_log.last_hash = [0u8; 32];
+        audit_log.last_log_blob_id = String::new();
+        audit_log.schema_version = SCHEMA_VERSION;
+        audit_log.bump = ctx.bumps.audit_log;
+        Ok(())
+    }
+
+    /// Appends a log segment. The new head is sha256(prev_hash || log_blob_id), so
+    /// each head commits to every segment before it; `prev_hash` guards against
+    /// racing another committer.
+    pub fn commit_audit_log(
+        ctx: Context<CommitAuditLog>,
+        log_blob_id: String,
+        prev_hash: [u8; 32],
+    ) -> Result<()> {
+        validate_string_len(&log_blob_id, "log_blob_id")?;
+
+        let audit_log = &mut ctx.accounts.audit_log;
+        require!(
+            audit_log.last_hash == prev_hash,
+            WalrusFsError::AuditLogChainMismatch
+        );
+        let log_hash = hashv(&[&prev_hash, log_blob_id.as_bytes()]).to_bytes();
+
+        audit_log.sequence += 1;
+        audit_log.last_hash = log_hash;
+        audit_log.last_log_blob_id = log_blob_id.clone();
+
+        emit!(AuditLogCommittedEvent {
+            sequence: audit_log.sequence,
+            log_blob_id,
+            prev_hash,
+            log_hash,
+        });
+        Ok(())
+    }
+
+    pub fn retag(
+        ctx: Context<BulkUpdateMeta>,
+        path_prefix: String,
+        from_tag: String,
+        to_tag: Option<String>,
+        limit: u32,
+        cursor: u64,
+    ) -> Result<RetagResultAnchor> {
+        let path_prefix = canonicalize_path(&path_prefix)?;
+        validate_string_len(&from_tag, "tag")?;
+        if let Some(t) = &to_tag {
+            validate_string_len(t, "tag")?;
+        }
+
+        let file_arena_data = &mut ctx.accounts.file_arena.data;
+        let dir_arena_data = &mut ctx.accounts.dir_arena.data;
+        let root_children_files_data = &ctx.accounts.root_children_files.data;
+        let root_children_dirs_data = &ctx.accounts.root_children_directories.data;
+        let tag_dictionary = &mut ctx.accounts.tag_dictionary.tags;
+        // A tag that was never interned is on no entry
+        let Some(from_id) = lookup_tag(tag_dictionary, &from_tag) else {
+            return Ok(RetagResultAnchor {
+                updated: 0,
+                next_cursor: None,
+            });
+        };
+        let to_id = to_tag
+            .as_deref()
+            .map(|t| intern_tag(tag_dictionary, t))
+            .transpose()?;
+
+        let prefix_dir_id =
+            internal_resolve_dir_id(&path_prefix, root_children_dirs_data, dir_arena_data)?;
+        let mut entries = internal_walk_subtree(
+            prefix_dir_id,
+            &path_prefix,
+            root_children_files_data,
+            root_children_dirs_data,
+            dir_arena_data,
+        )?;
+        entries.sort_by_key(|e| e.id);
+
+        let mut updated = 0u32;
+        let mut next_cursor = None;
+        for entry in entries.into_iter().filter(|e| e.id > cursor) {
+            if !entry.is_dir
+                && get_from_file_arena(file_arena_data, entry.id).is_some_and(|f| f.sealed)
+            {
+                continue;
+            }
+            let tags = if entry.is_dir {
+                &mut get_mut_from_dir_arena(dir_arena_data, entry.id)
+                    .ok_or(WalrusFsError::ArenaMismatchError)?
+                    .tags
+            } else {
+                &mut get_mut_from_file_arena(file_arena_data, entry.id)
+                    .ok_or(WalrusFsError::ArenaMismatchError)?
+                    .tags
+            };
+            let Some(index) = tags.iter().position(|t| *t == from_id) else {
+                continue;
+            };
+            if updated == limit {
+                next_cursor = Some(entry.id - 1);
+                break;
+            }
+
+            match to_id {
+                Some(new_id) if !tags.contains(&new_id) => tags[index] = new_id,
+                _ => {
+                    tags.remove(index);
+                }
+            }
+            updated += 1;
+
+            emit!(TagsUpdatedEvent {
+                path: entry.path,
+                tags: tag_names(tag_dictionary, tags),
+            });
+        }
+
+        Ok(RetagResultAnchor {
+            updated,
+            next_cursor,
+        })
+    }
+
+    /// Renames a tag on every file and directory, including directory default tags.
+    /// With `path_prefix` only that subtree is rewritten. Sealed files keep their tags.
+    pub fn rename_tag(
+        ctx: Context<BulkUpdateMeta>,
+        old_tag: String,
+        new_tag: String,
+        path_prefix: Option<String>,
+    ) -> Result<()> {
+        validate_string_len(&old_tag, "tag")?;
+        validate_string_len(&new_tag, "tag")?;
+        let path_prefix = path_prefix.map(|p| canonicalize_path(&p)).transpose()?;
+
+        let file_arena_data = &mut ctx.accounts.file_arena.data;
+        let dir_arena_data = &mut ctx.accounts.dir_arena.data;
+        let root_children_files_data = &ctx.accounts.root_children_files.data;
+        let root_children_dirs_data = &ctx.accounts.root_children_directories.data;
+
+        // None rewrites the whole arenas, which also covers detached (trashed) objects
+        let scope: Option<(BTreeSet<u64>, BTreeSet<u64>)> = match &path_prefix {
+            Some(prefix) => {
+                let prefix_dir_id =
+                    internal_resolve_dir_id(prefix, root_children_dirs_data, dir_arena_data)?;
+                let mut file_ids = BTreeSet::new();
+                let mut dir_ids: BTreeSet<u64> = prefix_dir_id.into_iter().collect();
+                for entry in internal_walk_subtree(
+                    prefix_dir_id,
+                    prefix,
+                    root_children_files_data,
+                    root_children_dirs_data,
+                    dir_arena_data,
+                )? {
+                    if entry.is_dir {
+                        dir_ids.insert(entry.id);
+                    } else {
+                        file_ids.insert(entry.id);
+                    }
+                }
+                Some((file_ids, dir_ids))
+            }
+            None => None,
+        };
+
+        let mut files_updated = 0u32;
+        let mut dirs_updated = 0u32;
+        let tag_dictionary = &mut ctx.accounts.tag_dictionary.tags;
+        // A tag that was never interned is on no entry
+        if let Some(old_id) = lookup_tag(tag_dictionary, &old_tag) {
+            let new_id = intern_tag(tag_dictionary, &new_tag)?;
+            for kv in file_arena_data.iter_mut() {
+                if !scope.iter().all(|(ids, _)| ids.contains(&kv.key)) || kv.value.sealed {
+                    continue;
+                }
+                if rename_tag_in(&mut kv.value.tags, old_id, new_id) {
+                    files_updated += 1;
+                }
+            }
+            for kv in dir_arena_data.iter_mut() {
+                if !scope.iter().all(|(_, ids)| ids.contains(&kv.key)) {
+                    continue;
+                }
+                let renamed = rename_tag_in(&mut kv.value.tags, old_id, new_id);
+                let renamed_default = rename_tag_in(&mut kv.value.default_tags, old_id, new_id);
+                if renamed || renamed_default {
+                    dirs_updated += 1;
+                }
+            }
+        }
+
+        emit!(TagRenamedEvent {
+            old_tag,
+            new_tag,
+            path_prefix,
+            files_updated,
+            dirs_updated,
+        });
+        Ok(())
+    }
+
+    /// Cheap presence check: only the children maps are consulted, never the file arena.
+    /// Extended attributes are kept out of stat and list_dir to keep listings small.
+    pub fn get_xattrs(
+        ctx: Context<ReadUserFileSystem>,
+        path: String,
+    ) -> Result<Vec<KeyValueStringString>> {
+        let path = canonicalize_path(&path)?;
+
+        let file_arena_data = &ctx.accounts.file_arena.data;
+        let dir_arena_data = &ctx.accounts.dir_arena.data;
+        let root_children_dirs_data = &ctx.accounts.root_children_directories.data;
+
+        let (parent_dir_id, name) =
+            internal_resolve_parent_id_and_name(&path, root_children_dirs_data, dir_arena_data)?;
+        let children_files = internal_children_files(
+            parent_dir_id,
+            &ctx.accounts.root_children_files.data,
+            dir_arena_data,
+        )?;
+        if let Some(file_id) = get_from_vec_str_key(children_files, &name) {
+            let f = get_from_file_arena(file_arena_data, *file_id)
+                .ok_or(WalrusFsError::ArenaMismatchError)?;
+            return Ok(f.xattrs.clone());
+        }
+        let children_dirs =
+            internal_children_dirs(parent_dir_id, root_children_dirs_data, dir_arena_data)?;
+        let dir_id =
+            get_from_vec_str_key(children_dirs, &name).ok_or(WalrusFsError::PathNotFound)?;
+        let d =
+            get_from_dir_arena(dir_arena_data, *dir_id).ok_or(WalrusFsError::ArenaMismatchError)?;
+        Ok(d.xattrs.clone())
+    }
+
+    pub fn exists(ctx: Context<ReadUserFileSystem>, path: String) -> Result<EntryKind> {
+        let path = canonicalize_path(&path)?;
+        if path == "/" {
+            return Ok(EntryKind::Dir);
+        }
+
+        let dir_arena_data = &ctx.accounts.dir_arena.data;
+        let root_children_files_data = &ctx.accounts.root_children_files.data;
+        let root_children_dirs_data = &ctx.accounts.root_children_directories.data;
+
+        // A missing intermediate component simply means the entry does not exist
+        let (parent_dir_id, name) = match internal_resolve_parent_id_and_name(
+            &path,
+            root_children_dirs_data,
+            dir_arena_data,
+        ) {
+            Ok(r) => r,
+            Err(_) => return Ok(EntryKind::None),
+        };
+
+        if contains_key_in_vec_str(
+            internal_children_files(parent_dir_id, root_children_files_data, dir_arena_data)?,
+            &name,
+        ) {
+            Ok(EntryKind::File)
+        } else if contains_key_in_vec_str(
+            internal_children_dirs(parent_dir_id, root_children_dirs_data, dir_arena_data)?,
+            &name,
+        ) {
+            Ok(EntryKind::Dir)
+        } else {
+            Ok(EntryKind::None)
+        }
+    }
+
+    /// Resolves a path to its stable object id. The root directory reports id 0,
+    /// which is never handed out to an entry.
+    pub fn resolve_path(
+        ctx: Context<ReadUserFileSystem>,
+        path: String,
+    ) -> Result<ResolvedPathAnchor> {
+        let path = canonicalize_path(&path)?;
+        if path == "/" {
+            return Ok(ResolvedPathAnchor {
+                is_dir: true,
+                object_id: ROOT_DIR_OBJECT_ID,
+            });
+        }
+
+        internal_resolve_entry(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )
+    }
+
+    /// Sums file sizes below a directory, like `du`. `dir_count` excludes the directory itself.
+    /// stat's cached subtree_bytes gives the same total without the walk, except that
+    /// it counts a hard-linked file once per entry.
+    pub fn dir_size(ctx: Context<ReadUserFileSystem>, path: String) -> Result<DirSizeAnchor> {
+        let path = canonicalize_path(&path)?;
+
+        let file_arena_data = &ctx.accounts.file_arena.data;
+        let dir_arena_data = &ctx.accounts.dir_arena.data;
+        let root_children_files_data = &ctx.accounts.root_children_files.data;
+        let root_children_dirs_data = &ctx.accounts.root_children_directories.data;
+
+        let (file_ids, dir_ids) =
+            match internal_resolve_dir_id(&path, root_children_dirs_data, dir_arena_data)? {
+                Some(dir_id) => internal_recursive_get_dir_obj_ids(dir_id, dir_arena_data)?,
+                None => internal_collect_reachable_ids(
+                    root_children_files_data,
+                    root_children_dirs_data,
+                    dir_arena_data,
+                )?,
+            };
+
+        let mut total_bytes: u64 = 0;
+        for file_id in file_ids.iter() {
+            let f = get_from_file_arena(file_arena_data, *file_id)
+                .ok_or(WalrusFsError::ArenaMismatchError)?;
+            total_bytes = total_bytes.saturating_add(f.size);
+        }
+
+        Ok(DirSizeAnchor {
+            total_bytes,
+            file_count: file_ids.len() as u64,
+            dir_count: dir_ids.len() as u64,
+        })
+    }
+
+    /// Whole-filesystem accounting. Totals cover entries reachable from the root;
+    /// arena counts include orphans, so a gap between the two means reap_orphans has work.
+    pub fn fs_stats(ctx: Context<ReadUserFileSystem>) -> Result<FsStatsAnchor> {
+        let file_arena_data = &ctx.accounts.file_arena.data;
+        let dir_arena_data = &ctx.accounts.dir_arena.data;
+
+        let (file_ids, dir_ids) = internal_collect_reachable_ids(
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            dir_arena_data,
+        )?;
+        let mut total_bytes: u64 = 0;
+        for file_id in file_ids.iter() {
+            if let Some(f) = get_from_file_arena(file_arena_data, *file_id) {
+                total_bytes = total_bytes.saturating_add(f.size);
+            }
+        }
+
+        Ok(FsStatsAnchor {
+            total_files: file_ids.len() as u64,
+            total_dirs: dir_ids.len() as u64,
+            total_bytes,
+            obj_id_counter: ctx.accounts.walrusfs_root.obj_id_counter,
+            file_arena_entries: file_arena_data.len() as u32,
+            dir_arena_entries: dir_arena_data.len() as u32,
+            file_arena_pct_used: space_used_pct(&ctx.accounts.file_arena)?,
+            dir_arena_pct_used: space_used_pct(&ctx.accounts.dir_arena)?,
+            root_files_pct_used: space_used_pct(&ctx.accounts.root_children_files)?,
+            root_dirs_pct_used: space_used_pct(&ctx.accounts.root_children_directories)?,
+        })
+    }
+
+    /// Files and directories carrying `tag`, with their ids and paths. `limit` of 0
+    /// returns every match from `offset` on.
+    pub fn find_by_tag(
+        ctx: Context<ReadUserFileSystem>,
+        tag: String,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<FoundEntryAnchor>> {
+        validate_string_len(&tag, "tag")?;
+        // A tag that was never interned is on no entry
+        let Some(tag_id) = lookup_tag(&ctx.accounts.tag_dictionary.tags, &tag) else {
+            return Ok(Vec::new());
+        };
+
+        let file_arena_data = &ctx.accounts.file_arena.data;
+        let dir_arena_data = &ctx.accounts.dir_arena.data;
+
+        let entries = internal_walk_subtree(
+            None,
+            "/",
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            dir_arena_data,
+        )?;
+
+        let mut matches = Vec::new();
+        for entry in entries {
+            let tags = if entry.is_dir {
+                &get_from_dir_arena(dir_arena_data, entry.id)
+                    .ok_or(WalrusFsError::ArenaMismatchError)?
+                    .tags
+            } else {
+                &get_from_file_arena(file_arena_data, entry.id)
+                    .ok_or(WalrusFsError::ArenaMismatchError)?
+                    .tags
+            };
+            if tags.contains(&tag_id) {
+                matches.push(FoundEntryAnchor {
+                    id: entry.id,
+                    is_dir: entry.is_dir,
+                    path: entry.path,
+                });
+            }
+        }
+        Ok(page_of(matches, offset, limit))
+    }
+
+    /// Every file entry pointing at `walrus_blob_id`, e.g. to find what an expiring blob backs.
+    pub fn find_by_blob_id(
+        ctx: Context<ReadUserFileSystem>,
+        walrus_blob_id: [u8; 32],
+    ) -> Result<Vec<FoundEntryAnchor>> {
+        let file_arena_data = &ctx.accounts.file_arena.data;
+        let entries = internal_walk_subtree(
+            None,
+            "/",
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+
+        let mut matches = Vec::new();
+        for entry in entries.into_iter().filter(|e| !e.is_dir) {
+            let f = get_from_file_arena(file_arena_data, entry.id)
+                .ok_or(WalrusFsError::ArenaMismatchError)?;
+            let in_segments = f
+                .journal_segments
+                .iter()
+                .flatten()
+                .any(|seg| seg.walrus_blob_id == walrus_blob_id);
+            let in_chunks = f
+                .chunks
+                .iter()
+                .flatten()
+                .any(|chunk| chunk.walrus_blob_id == walrus_blob_id);
+            if f.walrus_blob_id == walrus_blob_id || in_segments || in_chunks {
+                matches.push(FoundEntryAnchor {
+                    id: entry.id,
+                    is_dir: false,
+                    path: entry.path,
+                });
+            }
+        }
+        Ok(matches)
+    }
+
+    /// Type-ahead over one directory. The last component of `pattern` is matched
+    /// against entry names as a prefix or, when it contains `*`, as a glob; a
+    /// trailing slash matches everything in that directory.
+    pub fn search(
+        ctx: Context<ReadUserFileSystem>,
+        pattern: String,
+        limit: u32,
+    ) -> Result<Vec<FoundEntryAnchor>> {
+        let normalized: String = pattern.nfc().collect();
+        let split = normalized.rfind('/').ok_or(WalrusFsError::PathError)?;
+        let dir_path = canonicalize_path(&normalized[..=split])?;
+        let name_pattern = &normalized[split + 1..];
+        validate_string_len(name_pattern, "pattern")?;
+
+        let dir_arena_data = &ctx.accounts.dir_arena.data;
+        let root_children_files_data = &ctx.accounts.root_children_files.data;
+        let root_children_dirs_data = &ctx.accounts.root_children_directories.data;
+
+        let dir_id = internal_resolve_dir_id(&dir_path, root_children_dirs_data, dir_arena_data)?;
+        let children_files =
+            internal_children_files(dir_id, root_children_files_data, dir_arena_data)?;
+        let children_dirs =
+            internal_children_dirs(dir_id, root_children_dirs_data, dir_arena_data)?;
+
+        let is_glob = name_pattern.contains('*');
+        let mut results = Vec::new();
+        for (is_dir, children) in [(true, children_dirs), (false, children_files)] {
+            for kv_pair in children.iter() {
+                let matched = if is_glob {
+                    glob_match(name_pattern, &kv_pair.key)
+                } else {
+                    kv_pair.key.starts_with(name_pattern)
+                };
+                if matched {
+                    results.push(FoundEntryAnchor {
+                        id: kv_pair.value,
+                        is_dir,
+                        path: join_path(&dir_path, &kv_pair.key),
+                    });
+                }
+            }
+        }
+        results.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(page_of(results, 0, limit))
+    }
+
+    pub fn describe(ctx: Context<ReadUserFileSystem>) -> Result<DescribeAnchor> {
+        let root = &ctx.accounts.walrusfs_root;
+        Ok(DescribeAnchor {
+            program_version: env!("CARGO_PKG_VERSION").to_string(),
+            layout_version: LAYOUT_VERSION,
+            max_tags: MAX_TAGS as u32,
+            max_string_len: MAX_STRING_LEN as u32,
+            max_path_len: MAX_PATH_LEN as u32,
+            children_pda_space: CHILDREN_PDA_SPACE as u32,
+            arena_pda_space: ARENA_PDA_SPACE as u32,
+            collation: root.collation,
+            default_renewal_policy: root.default_renewal_policy,
+            space_warning_pcts: root.space_warning_pcts,
+            limits: root.limits,
+            features: FEATURES.iter().map(|f| f.to_string()).collect(),
+        })
+    }
+
+    /// Frees up to `limit` arena entries that neither the root, the trash nor a
+    /// delete in progress reaches. Deletable blobs that lose their last reference
+    /// are queued as delete_file does.
+    pub fn reap_orphans(ctx: Context<ReapOrphans>, limit: u32) -> Result<()> {
+        let file_arena_data = &mut ctx.accounts.file_arena.data;
+        let dir_arena_data = &mut ctx.accounts.dir_arena.data;
+        let root_children_files_data = &ctx.accounts.root_children_files.data;
+        let root_children_dirs_data = &ctx.accounts.root_children_directories.data;
+
+        let (mut reachable_files, mut reachable_dirs) = internal_collect_reachable_ids(
+            root_children_files_data,
+            root_children_dirs_data,
+            dir_arena_data,
+        )?;
+        // Trashed entries are detached but still restorable, and a delete in progress
+        // frees its own subtree through delete_dir_continue
+        let trash = load_initialized_pda::<TrashPda>(&ctx.accounts.trash)?;
+        let cursor = load_initialized_pda::<DeletionCursorPda>(&ctx.accounts.deletion_cursor)?;
+        let detached_dirs = trash
+            .iter()
+            .flat_map(|t| t.entries.iter())
+            .filter(|entry| entry.is_dir)
+            .map(|entry| entry.object_id)
+            .chain(
+                cursor
+                    .iter()
+                    .flat_map(|c| c.pending_dir_ids.iter().copied()),
+            );
+        for dir_id in detached_dirs {
+            // Ids on the cursor's stack may already be removed
+            if get_from_dir_arena(dir_arena_data, dir_id).is_none() {
+                continue;
+            }
+            reachable_dirs.insert(dir_id);
+            let (sub_file_ids, sub_dir_ids) =
+                internal_recursive_get_dir_obj_ids(dir_id, dir_arena_data)?;
+            reachable_files.extend(sub_file_ids);
+            reachable_dirs.extend(sub_dir_ids);
+        }
+        reachable_files.extend(
+            trash
+                .iter()
+                .flat_map(|t| t.entries.iter())
+                .filter(|entry| !entry.is_dir)
+                .map(|entry| entry.object_id),
+        );
+
+        let mut budget = limit as usize;
+        let mut reaped_file_ids = Vec::new();
+        let mut reaped_dir_ids = Vec::new();
+
+        // Pinned objects are left for the owner to remove explicitly
+        let orphan_dir_ids: Vec<u64> = dir_arena_data
+            .iter()
+            .filter(|kv| !reachable_dirs.contains(&kv.key) && !kv.value.pinned)
+            .map(|kv| kv.key)
+            .take(budget)
+            .collect();
+        for dir_id in orphan_dir_ids {
+            remove_from_dir_arena(dir_arena_data, &dir_id);
+            reaped_dir_ids.push(dir_id);
+        }
+        budget -= reaped_dir_ids.len();
+
+        let orphan_file_ids: Vec<u64> = file_arena_data
+            .iter()
+            .filter(|kv| !reachable_files.contains(&kv.key) && !kv.value.pinned)
+            .map(|kv| kv.key)
+            .take(budget)
+            .collect();
+        let mut removed_files = Vec::new();
+        for file_id in orphan_file_ids {
+            removed_files.extend(remove_from_file_arena(file_arena_data, &file_id));
+            reaped_file_ids.push(file_id);
+        }
+
+        internal_enqueue_unreferenced_blobs(
+            removed_files,
+            file_arena_data,
+            ctx.accounts
+                .pending_blob_deletes
+                .as_deref_mut()
+                .map(|q| &mut **q),
+        )?;
+
+        emit!(OrphansReapedEvent {
+            file_ids: reaped_file_ids,
+            dir_ids: reaped_dir_ids,
+        });
+        Ok(())
+    }
+
+    pub fn init_share_keys(ctx: Context<InitShareKeys>, path: String, file_id: u64) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+
+        let resolved_file_id = internal_resolve_file_id(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        require!(resolved_file_id == file_id, WalrusFsError::ObjectIdMismatch);
+
+        let share_keys = &mut ctx.accounts.share_keys;
+        share_keys.file_id = file_id;
+        share_keys.key_epoch = 0;
+        share_keys.wraps = Vec::new();
+        share_keys.schema_version = SCHEMA_VERSION;
+        share_keys.bump = ctx.bumps.share_keys;
+        Ok(())
+    }
+
+    pub fn put_share_key(
+        ctx: Context<UpdateShareKeys>,
+        path: String,
+        grantee: Pubkey,
+        wrapped_key: Vec<u8>,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+        require!(
+            !wrapped_key.is_empty() && wrapped_key.len() <= MAX_WRAPPED_KEY_LEN,
+            WalrusFsError::InvalidWrappedKey
+        );
+
+        let resolved_file_id = internal_resolve_file_id(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        let share_keys = &mut ctx.accounts.share_keys;
+        require!(
+            resolved_file_id == share_keys.file_id,
+            WalrusFsError::ObjectIdMismatch
+        );
+
+        let key_epoch = share_keys.key_epoch;
+        match share_keys.wraps.iter_mut().find(|w| w.grantee == grantee) {
+            Some(existing) => {
+                existing.wrapped_key = wrapped_key;
+                existing.key_epoch = key_epoch;
+            }
+            None => share_keys.wraps.push(WrappedKeyEntry {
+                grantee,
+                wrapped_key,
+                key_epoch,
+            }),
+        }
+
+        emit!(ShareKeyPutEvent { path, grantee });
+        Ok(())
+    }
+
+    pub fn remove_share_key(
+        ctx: Context<UpdateShareKeys>,
+        path: String,
+        grantee: Pubkey,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+
+        let resolved_file_id = internal_resolve_file_id(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        let share_keys = &mut ctx.accounts.share_keys;
+        require!(
+            resolved_file_id == share_keys.file_id,
+            WalrusFsError::ObjectIdMismatch
+        );
+
+        let index = share_keys
+            .wraps
+            .iter()
+            .position(|w| w.grantee == grantee)
+            .ok_or(WalrusFsError::ShareKeyNotFound)?;
+        share_keys.wraps.remove(index);
+
+        emit!(ShareKeyRemovedEvent { path, grantee });
+        Ok(())
+    }
+
+    pub fn rotate_share_keys(
+        ctx: Context<UpdateShareKeys>,
+        path: String,
+        revoked: Vec<Pubkey>,
+    ) -> Result<()> {
+        let path = canonicalize_path(&path)?;
+
+        let resolved_file_id = internal_resolve_file_id(
+            &path,
+            &ctx.accounts.root_children_files.data,
+            &ctx.accounts.root_children_directories.data,
+            &ctx.accounts.dir_arena.data,
+        )?;
+        let share_keys = &mut ctx.accounts.share_keys;
+        require!(
+            resolved_file_id == share_keys.file_id,
+            WalrusFsError::ObjectIdMismatch
+        );
+
+        // Remaining wraps are kept but marked stale by the epoch bump until the
+        // client re-wraps the new content key for each current grantee.
+        share_keys.key_epoch += 1;
+        share_keys.wraps.retain(|w| !revoked.contains(&w.grantee));
+
+        emit!(ShareKeysRotatedEvent {
+            path,
+            key_epoch: share_keys.key_epoch,
+            revoked,
+        });
+        Ok(())
+    }
+}
+
+// --- Internal Helper Functions (Modified parameters, core logic adapted) ---
+fn internal_resolve_parent_id_and_name<'a>(
+    full_path: &str,
+    root_children_dirs_data: &'a Vec<KeyValueStringU64>,
+    dir_arena_data: &'a [KeyValueU64DirObject],
+) -> Result<(Option<u64>, String)> {
+    let path = remove_trailing_slash(full_path);
+    if path == "/" {
+        return err!(WalrusFsError::InvalidPathOperationOnRoot);
+    }
+
+    let mut components = path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<&str>>();
+    if components.is_empty() {
+        return err!(WalrusFsError::PathError);
+    }
+
+    let name = components.pop().unwrap().to_string();
+
+    let mut current_parent_id: Option<u64> = None;
+    let mut current_children_dirs_vec: &Vec<KeyValueStringU64> = root_children_dirs_data;
+
+    for component_str in components {
+        let component = component_str.to_string();
+        let found_id_ref = get_from_vec_str_key(current_children_dirs_vec, &component)
+            .ok_or(WalrusFsError::PathNotFound)?;
+
+        current_parent_id = Some(*found_id_ref);
+        let dir_object = get_from_dir_arena(dir_arena_data, *found_id_ref)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        require!(dir_object.mount.is_none(), WalrusFsError::PathInMount);
+        current_children_dirs_vec = &dir_object.children_directories;
+    }
+    Ok((current_parent_id, name))
+}
+
+// Default tags of `dir_path` and each of its ancestors, nearest directory first.
+fn internal_inherited_default_tags(
+    dir_path: &str,
+    root_children_dirs_data: &Vec<KeyValueStringU64>,
+    dir_arena_data: &[KeyValueU64DirObject],
+) -> Result<Vec<u16>> {
+    let mut inherited = Vec::new();
+    let mut current_children_dirs_vec = root_children_dirs_data;
+    for component in dir_path.split('/').filter(|s| !s.is_empty()) {
+        let dir_id = get_from_vec_str_key(current_children_dirs_vec, component)
+            .ok_or(WalrusFsError::PathNotFound)?;
+        let dir_object =
+            get_from_dir_arena(dir_arena_data, *dir_id).ok_or(WalrusFsError::ArenaMismatchError)?;
+        inherited.splice(0..0, dir_object.default_tags.iter().cloned());
+        current_children_dirs_vec = &dir_object.children_directories;
+    }
+    Ok(inherited)
+}
+
+// Explicit tags come first; inherited ones fill the remaining slots up to the
+// filesystem's tag limit.
+fn merge_default_tags(mut tags: Vec<u16>, inherited: &[u16], limits: &FsLimits) -> Vec<u16> {
+    for tag in inherited {
+        if tags.len() >= limits.max_tags as usize {
+            break;
+        }
+        if !tags.contains(tag) {
+            tags.push(*tag);
+        }
+    }
+    tags
+}
+
+// Replaces `old_id` in place, or drops it when `new_id` is already present.
+fn rename_tag_in(tags: &mut Vec<u16>, old_id: u16, new_id: u16) -> bool {
+    match tags.iter().position(|t| *t == old_id) {
+        Some(index) => {
+            if tags.contains(&new_id) {
+                tags.remove(index);
+            } else {
+                tags[index] = new_id;
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+fn lookup_tag(tag_dictionary: &[String], name: &str) -> Option<u16> {
+    tag_dictionary
+        .iter()
+        .position(|t| t == name)
+        .map(|id| id as u16)
+}
+
+// Id of `name`, adding it to the dictionary first if it's new.
+fn intern_tag(tag_dictionary: &mut Vec<String>, name: &str) -> Result<u16> {
+    if let Some(id) = lookup_tag(tag_dictionary, name) {
+        return Ok(id);
+    }
+    require!(
+        tag_dictionary.len() < MAX_DICTIONARY_TAGS,
+        WalrusFsError::TagDictionaryFull
+    );
+    tag_dictionary.push(name.to_string());
+    Ok((tag_dictionary.len() - 1) as u16)
+}
+
+fn intern_tags(tag_dictionary: &mut Vec<String>, names: &[String]) -> Result<Vec<u16>> {
+    names
+        .iter()
+        .map(|name| intern_tag(tag_dictionary, name))
+        .collect()
+}
+
+fn tag_names(tag_dictionary: &[String], ids: &[u16]) -> Vec<String> {
+    ids.iter()
+        .filter_map(|id| tag_dictionary.get(*id as usize).cloned())
+        .collect()
+}
+
+fn internal_children_files<'a>(
+    parent_dir_id: Option<u64>,
+    root_children_files_data: &'a Vec<KeyValueStringU64>,
+    dir_arena_data: &'a [KeyValueU64DirObject],
+) -> Result<&'a Vec<KeyValueStringU64>> {
+    match parent_dir_id {
+        Some(id) => Ok(&get_from_dir_arena(dir_arena_data, id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?
+            .children_files),
+        None => Ok(root_children_files_data),
+    }
+}
+
+fn internal_children_files_mut<'a>(
+    parent_dir_id: Option<u64>,
+    root_children_files_data: &'a mut Vec<KeyValueStringU64>,
+    dir_arena_data: &'a mut [KeyValueU64DirObject],
+) -> Result<&'a mut Vec<KeyValueStringU64>> {
+    match parent_dir_id {
+        Some(id) => Ok(&mut get_mut_from_dir_arena(dir_arena_data, id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?
+            .children_files),
+        None => Ok(root_children_files_data),
+    }
+}
+
+fn internal_children_dirs<'a>(
+    parent_dir_id: Option<u64>,
+    root_children_dirs_data: &'a Vec<KeyValueStringU64>,
+    dir_arena_data: &'a [KeyValueU64DirObject],
+) -> Result<&'a Vec<KeyValueStringU64>> {
+    match parent_dir_id {
+        Some(id) => Ok(&get_from_dir_arena(dir_arena_data, id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?
+            .children_directories),
+        None => Ok(root_children_dirs_data),
+    }
+}
+
+fn internal_children_dirs_mut<'a>(
+    parent_dir_id: Option<u64>,
+    root_children_dirs_data: &'a mut Vec<KeyValueStringU64>,
+    dir_arena_data: &'a mut [KeyValueU64DirObject],
+) -> Result<&'a mut Vec<KeyValueStringU64>> {
+    match parent_dir_id {
+        Some(id) => Ok(&mut get_mut_from_dir_arena(dir_arena_data, id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?
+            .children_directories),
+        None => Ok(root_children_dirs_data),
+    }
+}
+
+// Resolves a non-root path to the file or directory it names.
+fn internal_resolve_entry(
+    clean_path: &str,
+    root_children_files_data: &Vec<KeyValueStringU64>,
+    root_children_dirs_data: &Vec<KeyValueStringU64>,
+    dir_arena_data: &[KeyValueU64DirObject],
+) -> Result<ResolvedPathAnchor> {
+    let (parent_dir_id, name) =
+        internal_resolve_parent_id_and_name(clean_path, root_children_dirs_data, dir_arena_data)?;
+
+    if let Some(file_id) = get_from_vec_str_key(
+        internal_children_files(parent_dir_id, root_children_files_data, dir_arena_data)?,
+        &name,
+    ) {
+        Ok(ResolvedPathAnchor {
+            is_dir: false,
+            object_id: *file_id,
+        })
+    } else if let Some(dir_id) = get_from_vec_str_key(
+        internal_children_dirs(parent_dir_id, root_children_dirs_data, dir_arena_data)?,
+        &name,
+    ) {
+        Ok(ResolvedPathAnchor {
+            is_dir: true,
+            object_id: *dir_id,
+        })
+    } else {
+        err!(WalrusFsError::PathNotFound)
+    }
+}
+
+// Body of batch_add_files, writing as `signer`: the transaction signer, or the
+// signer of a permit it relays.
+fn internal_batch_add_files(
+    accounts: &mut AddFile,
+    signer: &Pubkey,
+    parent_path: String,
+    entries: Vec<AddFileEntry>,
+) -> Result<()> {
+    let parent_path = canonicalize_path(&parent_path)?;
+    let limits = accounts.walrusfs_root.limits;
+    // Writer grants and session keys are seeded by the transaction signer, as
+    // in internal_batch_delete_files
+    let (no_grant, no_session) = (None, None);
+    let (writer_grant, session_key) = if accounts.authority.key == signer {
+        (&accounts.writer_grant, &accounts.session_key)
+    } else {
+        (&no_grant, &no_session)
+    };
+    require_path_depth(path_depth(&parent_path) + 1, &limits)?;
+    for entry in entries.iter() {
+        validate_tags(&entry.tags, &limits)?;
+    }
+
+    let thresholds = accounts.walrusfs_root.space_warning_pcts;
+    let file_arena_pct = space_used_pct(&accounts.file_arena)?;
+    let dir_arena_pct = space_used_pct(&accounts.dir_arena)?;
+    let root_files_pct = space_used_pct(&accounts.root_children_files)?;
+
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp as u64 * 1000;
+    let root = &mut accounts.walrusfs_root;
+    let file_arena_data = &mut accounts.file_arena.data;
+    let dir_arena_data = &mut accounts.dir_arena.data;
+    let root_children_files_data = &mut accounts.root_children_files.data;
+    let root_children_dirs_data_ro = &accounts.root_children_directories.data;
+
+    let parent_dir_id =
+        internal_resolve_dir_id(&parent_path, root_children_dirs_data_ro, dir_arena_data)?;
+    let (parent_mode, parent_group) = dir_access(parent_dir_id, dir_arena_data)?;
+    require_writable(
+        root,
+        writer_grant,
+        &accounts.group,
+        session_key,
+        signer,
+        &parent_path,
+        parent_mode,
+        parent_group,
+    )?;
+    let tag_dictionary = &mut accounts.tag_dictionary.tags;
+    let inherited_tags =
+        internal_inherited_default_tags(&parent_path, root_children_dirs_data_ro, dir_arena_data)?;
+    let children_files_map =
+        internal_children_files_mut(parent_dir_id, root_children_files_data, dir_arena_data)?;
+
+    let mut results = Vec::with_capacity(entries.len());
+    let mut bytes_added: u64 = 0;
+    for entry in entries {
+        let name = canonicalize_name(&entry.name)?;
+        if contains_key_in_vec_str(children_files_map, &name) {
+            results.push(BatchEntryResult { name, ok: false });
+            continue;
+        }
+
+        root.obj_id_counter += 1;
+        let new_file_id = root.obj_id_counter;
+        let new_file = FileObjectAnchor {
+            create_ts: now,
+            modify_ts: now,
+            tags: merge_default_tags(
+                intern_tags(tag_dictionary, &entry.tags)?,
+                &inherited_tags,
+                &limits,
+            ),
+            size: entry.size,
+            walrus_blob_id: entry.walrus_blob_id,
+            walrus_epoch_till: entry.end_epoch,
+            deletable: false,
+            renewal_policy: root.default_renewal_policy,
+            sort_key: compute_sort_key(&name, root.collation),
+            symlink_target: None,
+            journal_segments: None,
+            chunks: None,
+            link_count: 1,
+            sealed: false,
+            seal_permanent: false,
+            lock: None,
+            access_ts: None,
+            access_count: 0,
+            sha256: None,
+            content_type: String::new(),
+            preview_blob_id: None,
+            sui_object_id: None,
+            encryption: None,
+            storage_class: StorageClass::default(),
+            xattrs: Vec::new(),
+            has_description: false,
+            pinned: false,
+            kind: ObjectKind::RegularFile,
+            status: BlobStatus::PendingCertification,
+            owner: root.authority,
+            mode: file_mode_under(parent_mode),
+            group: parent_group,
+            access_policy: None,
+            sale: None,
+        };
+        insert_into_file_arena(file_arena_data, new_file_id, new_file);
+        insert_child(children_files_map, name.clone(), new_file_id, &limits)?;
+        bytes_added = bytes_added.saturating_add(entry.size);
+        results.push(BatchEntryResult { name, ok: true });
+    }
+    if results.iter().any(|r| r.ok) {
+        bump_dir_version(
+            parent_dir_id,
+            &mut accounts.root_children_files.version,
+            dir_arena_data,
+        )?;
+        adjust_subtree_bytes(
+            &parent_path,
+            bytes_added,
+            0,
+            root_children_dirs_data_ro,
+            dir_arena_data,
+        )?;
+    }
+
+    emit!(BatchFilesAddedEvent {
+        parent_path,
+        results
+    });
+
+    emit_space_low_if_crossed(&accounts.file_arena, file_arena_pct, &thresholds)?;
+    emit_space_low_if_crossed(&accounts.dir_arena, dir_arena_pct, &thresholds)?;
+    emit_space_low_if_crossed(&accounts.root_children_files, root_files_pct, &thresholds)?;
+    Ok(())
+}
+
+// Body of batch_delete_files, deleting as `signer` like internal_batch_add_files.
+fn internal_batch_delete_files(
+    accounts: &mut DeleteFile,
+    signer: &Pubkey,
+    paths: Vec<String>,
+    expect_unlocked: bool,
+) -> Result<()> {
+    // Writer grants and session keys are seeded by the transaction signer, so
+    // they only count when it is also the signer being authorized rather than
+    // a relayer
+    let (no_grant, no_session) = (None, None);
+    let (writer_grant, session_key) = if accounts.authority.key == signer {
+        (&accounts.writer_grant, &accounts.session_key)
+    } else {
+        (&no_grant, &no_session)
+    };
+    let file_arena_data = &mut accounts.file_arena.data;
+    let dir_arena_data = &mut accounts.dir_arena.data;
+    let root_children_files_data = &mut accounts.root_children_files.data;
+    let root_children_dirs_data_ro = &accounts.root_children_directories.data;
+
+    let mut results = Vec::with_capacity(paths.len());
+    let mut removed_files = Vec::new();
+    let mut touched_parents = Vec::new();
+    for raw_path in paths {
+        let resolved = canonicalize_path(&raw_path).and_then(|path| {
+            let (parent_dir_id, file_name) = internal_resolve_parent_id_and_name(
+                &path,
+                root_children_dirs_data_ro,
+                dir_arena_data,
+            )?;
+            let file_id = internal_resolve_file_id(
+                &path,
+                root_children_files_data,
+                root_children_dirs_data_ro,
+                dir_arena_data,
+            )?;
+            require_file_writer(
+                &accounts.walrusfs_root,
+                writer_grant,
+                &accounts.group,
+                session_key,
+                get_from_file_arena(file_arena_data, file_id)
+                    .ok_or(WalrusFsError::ArenaMismatchError)?,
+                signer,
+                &path,
+            )?;
+            let actor = *acting_signer(session_key, signer, &path)?;
+            Ok((path, parent_dir_id, file_name, actor))
+        });
+        let (path, parent_dir_id, file_name, actor) = match resolved {
+            Ok(r) => r,
+            Err(_) => {
+                results.push(BatchEntryResult {
+                    name: raw_path,
+                    ok: false,
+                });
+                continue;
+            }
+        };
+
+        let children_files_vec =
+            internal_children_files_mut(parent_dir_id, root_children_files_data, dir_arena_data)?;
+        let file_id = get_from_vec_str_key(children_files_vec, &file_name)
+            .copied()
+            .filter(|id| require_unsealed(file_arena_data, *id).is_ok())
+            .filter(|id| {
+                get_from_file_arena(file_arena_data, *id)
+                    .is_some_and(|f| require_unlocked(f, expect_unlocked, &actor).is_ok())
+            });
+        match file_id {
+            Some(file_id) => {
+                remove_from_vec_str_key(children_files_vec, &file_name);
+                touched_parents.push(parent_dir_id);
+                let removed_bytes = get_from_file_arena(file_arena_data, file_id)
+                    .ok_or(WalrusFsError::ArenaMismatchError)?
+                    .size;
+                adjust_subtree_bytes(
+                    parent_dir_path(&path),
+                    0,
+                    removed_bytes,
+                    root_children_dirs_data_ro,
+                    dir_arena_data,
+                )?;
+                removed_files.extend(internal_unlink_file(file_arena_data, file_id)?);
+                results.push(BatchEntryResult {
+                    name: path,
+                    ok: true,
+                });
+            }
+            None => results.push(BatchEntryResult {
+                name: path,
+                ok: false,
+            }),
+        }
+    }
+    for parent_id in touched_parents {
+        bump_dir_version(
+            parent_id,
+            &mut accounts.root_children_files.version,
+            dir_arena_data,
+        )?;
+    }
+
+    internal_enqueue_unreferenced_blobs(
+        removed_files,
+        file_arena_data,
+        accounts
+            .pending_blob_deletes
+            .as_deref_mut()
+            .map(|q| &mut **q),
+    )?;
+
+    emit!(BatchFilesDeletedEvent { results });
+    Ok(())
+}
+
+// What a permit's signer signs: the permit fields, bound to this program and
+// filesystem, and a hash of the data of the instruction the signer would have
+// sent directly.
+#[derive(AnchorSerialize)]
+struct PermitMessage {
+    program_id: Pubkey,
+    walrusfs_root: Pubkey,
+    nonce: u64,
+    expiry_slot: u64,
+    instruction_hash: [u8; 32],
+}
+
+// Accepts `permit` for the write described by `instruction_data` and consumes
+// its nonce. The instruction before this one must be an Ed25519 program
+// instruction verifying the permit signer's signature over the PermitMessage.
+fn use_permit(
+    permit: &Permit,
+    instruction_data: &[u8],
+    walrusfs_root: &Pubkey,
+    relayer: &Pubkey,
+    permit_nonce: &mut Option<Box<Account<PermitNoncePda>>>,
+    instructions: &Option<UncheckedAccount>,
+) -> Result<()> {
+    let permit_nonce = permit_nonce.as_mut().ok_or(WalrusFsError::InvalidPermit)?;
+    let instructions = instructions.as_ref().ok_or(WalrusFsError::InvalidPermit)?;
+    require!(
+        permit_nonce.signer == permit.signer,
+        WalrusFsError::InvalidPermit
+    );
+    require!(
+        permit.expiry_slot > Clock::get()?.slot,
+        WalrusFsError::PermitExpired
+    );
+    require!(
+        permit.nonce == permit_nonce.next_nonce,
+        WalrusFsError::PermitNonceMismatch
+    );
+
+    let message = PermitMessage {
+        program_id: crate::ID,
+        walrusfs_root: *walrusfs_root,
+        nonce: permit.nonce,
+        expiry_slot: permit.expiry_slot,
+        instruction_hash: hash(instruction_data).to_bytes(),
+    }
+    .try_to_vec()?;
+    require_ed25519_signature(instructions, &permit.signer, &message)?;
+    permit_nonce.next_nonce += 1;
+
+    emit!(PermitUsedEvent {
+        signer: permit.signer,
+        nonce: permit.nonce,
+        relayer: *relayer,
+    });
+    Ok(())
+}
+
+// Checks that the previous instruction had the Ed25519 program verify one
+// signature by `signer` over `message`, with the key and message in its own
+// data. The runtime fails the transaction if the signature itself is invalid.
+fn require_ed25519_signature(
+    instructions: &AccountInfo,
+    signer: &Pubkey,
+    message: &[u8],
+) -> Result<()> {
+    let current = load_current_index_checked(instructions)?;
+    require!(current > 0, WalrusFsError::InvalidPermit);
+    let ix = load_instruction_at_checked(current as usize - 1, instructions)?;
+    require!(
+        ix.program_id == ed25519_program::ID,
+        WalrusFsError::InvalidPermit
+    );
+
+    // One signature count byte and a padding byte, then seven u16 offsets:
+    // signature, its instruction, key, its instruction, message offset, message
+    // size and its instruction. u16::MAX as an instruction means this one.
+    let data = &ix.data;
+    require!(
+        data.len() >= 16 && data[0] == 1,
+        WalrusFsError::InvalidPermit
+    );
+    let field = |i: usize| u16::from_le_bytes([data[2 + 2 * i], data[3 + 2 * i]]);
+    require!(
+        field(1) == u16::MAX && field(3) == u16::MAX && field(6) == u16::MAX,
+        WalrusFsError::InvalidPermit
+    );
+    let key_at = field(2) as usize;
+    let message_at = field(4) as usize;
+    let message_len = field(5) as usize;
+    require!(
+        data.get(key_at..key_at + 32) == Some(signer.as_ref())
+            && data.get(message_at..message_at + message_len) == Some(message),
+        WalrusFsError::InvalidPermit
+    );
+    Ok(())
+}
+
+fn internal_set_pinned(clean_path: &str, pinned: bool, accounts: &mut PinEntry) -> Result<()> {
+    let resolved = internal_resolve_entry(
+        clean_path,
+        &accounts.root_children_files.data,
+        &accounts.root_children_directories.data,
+        &accounts.dir_arena.data,
+    )?;
+    if resolved.is_dir {
+        get_mut_from_dir_arena(&mut accounts.dir_arena.data, resolved.object_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?
+            .pinned = pinned;
+    } else {
+        get_mut_from_file_arena(&mut accounts.file_arena.data, resolved.object_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?
+            .pinned = pinned;
+    }
+
+    emit!(PinnedEvent {
+        path: clean_path.to_string(),
+        pinned,
+    });
+    Ok(())
+}
+
+// Resolves `path` to the xattrs of the file or directory it names. Sealed files
+// are rejected like any other metadata change.
+fn internal_xattrs_mut<'a>(
+    clean_path: &str,
+    root_children_files_data: &Vec<KeyValueStringU64>,
+    root_children_dirs_data: &Vec<KeyValueStringU64>,
+    file_arena_data: &'a mut [KeyValueU64FileObject],
+    dir_arena_data: &'a mut [KeyValueU64DirObject],
+) -> Result<&'a mut Vec<KeyValueStringString>> {
+    let (parent_dir_id, name) =
+        internal_resolve_parent_id_and_name(clean_path, root_children_dirs_data, dir_arena_data)?;
+
+    let file_id = get_from_vec_str_key(
+        internal_children_files(parent_dir_id, root_children_files_data, dir_arena_data)?,
+        &name,
+    )
+    .copied();
+    if let Some(file_id) = file_id {
+        let f = get_mut_from_file_arena(file_arena_data, file_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        require!(!f.sealed, WalrusFsError::FileSealed);
+        return Ok(&mut f.xattrs);
+    }
+
+    let dir_id = *get_from_vec_str_key(
+        internal_children_dirs(parent_dir_id, root_children_dirs_data, dir_arena_data)?,
+        &name,
+    )
+    .ok_or(WalrusFsError::PathNotFound)?;
+    Ok(&mut get_mut_from_dir_arena(dir_arena_data, dir_id)
+        .ok_or(WalrusFsError::ArenaMismatchError)?
+        .xattrs)
+}
+
+fn file_list_entry(
+    name: String,
+    f: &FileObjectAnchor,
+    tag_dictionary: &[String],
+) -> DirListObjectAnchor {
+    DirListObjectAnchor {
+        name,
+        create_ts: f.create_ts,
+        modify_ts: f.modify_ts,
+        access_ts: f.access_ts,
+        access_count: f.access_count,
+        is_dir: false,
+        tags: tag_names(tag_dictionary, &f.tags),
+        size: f.size,
+        walrus_blob_id: f.walrus_blob_id,
+        walrus_epoch_till: f.walrus_epoch_till,
+        sha256: f.sha256,
+        content_type: f.content_type.clone(),
+        preview_blob_id: f.preview_blob_id.clone(),
+        sui_object_id: f.sui_object_id,
+        encryption: f.encryption.clone(),
+        storage_class: f.storage_class,
+        renewal_policy: f.renewal_policy,
+        symlink_target: f.symlink_target.clone(),
+        journal_segments: f.journal_segments.clone(),
+        chunks: f.chunks.clone(),
+        link_count: f.link_count,
+        sealed: f.sealed,
+        lock: f.lock.clone(),
+        has_description: f.has_description,
+        description: None,
+        pinned: f.pinned,
+        kind: Some(f.kind),
+        status: Some(f.status),
+        owner: Some(f.owner),
+        child_file_count: 0,
+        child_dir_count: 0,
+        subtree_bytes: 0,
+        mode: f.mode,
+        group: f.group,
+        mount: None,
+        access_policy: f.access_policy.clone(),
+        sale_price_lamports: f.sale.as_ref().map(|s| s.price_lamports),
+    }
+}
+
+fn dir_list_entry(
+    name: String,
+    d: &DirObjectAnchor,
+    tag_dictionary: &[String],
+) -> DirListObjectAnchor {
+    DirListObjectAnchor {
+        name,
+        create_ts: d.create_ts,
+        modify_ts: d.modify_ts,
+        access_ts: None,
+        access_count: 0,
+        is_dir: true,
+        tags: tag_names(tag_dictionary, &d.tags),
+        size: 0,
+        walrus_blob_id: NO_BLOB_ID,
+        walrus_epoch_till: 0,
+        sha256: None,
+        content_type: String::new(),
+        preview_blob_id: None,
+        sui_object_id: None,
+        encryption: None,
+        storage_class: StorageClass::default(),
+        renewal_policy: RenewalPolicy::default(),
+        symlink_target: None,
+        journal_segments: None,
+        chunks: None,
+        link_count: 1,
+        sealed: false,
+        lock: None,
+        has_description: d.has_description,
+        description: None,
+        pinned: d.pinned,
+        kind: None,
+        status: None,
+        owner: None,
+        child_file_count: d.child_file_count,
+        child_dir_count: d.child_dir_count,
+        subtree_bytes: d.subtree_bytes,
+        mode: d.mode,
+        group: d.group,
+        mount: d.mount.clone(),
+        access_policy: d.access_policy.clone(),
+        sale_price_lamports: None,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn internal_list_children(
+    children_files: &[KeyValueStringU64],
+    children_dirs: &[KeyValueStringU64],
+    file_arena_data: &[KeyValueU64FileObject],
+    dir_arena_data: &[KeyValueU64DirObject],
+    sort_by: ListSortBy,
+    descending: bool,
+    filter: &ListFilter,
+    collation: Collation,
+    tag_dictionary: &[String],
+) -> Result<Vec<DirListObjectAnchor>> {
+    let mut results = Vec::new();
+    let tag_filter = filter.tag.as_deref().map(|t| lookup_tag(tag_dictionary, t));
+    let matches = |tags: &[u16], pinned: bool| {
+        tag_filter
+            .iter()
+            .all(|id| id.is_some_and(|id| tags.contains(&id)))
+            && (pinned || !filter.pinned_only)
+    };
+
+    // Filtered-out entries are skipped before any cloning
+    if filter.kind != ListKindFilter::FilesOnly {
+        for kv_pair in children_dirs.iter() {
+            let d = get_from_dir_arena(dir_arena_data, kv_pair.value)
+                .ok_or(WalrusFsError::ArenaMismatchError)?;
+            if matches(&d.tags, d.pinned) {
+                results.push((
+                    Cow::Borrowed(d.sort_key.as_slice()),
+                    dir_list_entry(kv_pair.key.clone(), d, tag_dictionary),
+                ));
+            }
+        }
+    }
+
+    if filter.kind != ListKindFilter::DirsOnly {
+        for kv_pair in children_files.iter() {
+            let f = get_from_file_arena(file_arena_data, kv_pair.value)
+                .ok_or(WalrusFsError::ArenaMismatchError)?;
+            if matches(&f.tags, f.pinned) {
+                // A hard-linked object stores the sort key of only one of its names
+                let sort_key = if f.link_count > 1 {
+                    Cow::Owned(compute_sort_key(&kv_pair.key, collation))
+                } else {
+                    Cow::Borrowed(f.sort_key.as_slice())
+                };
+                results.push((
+                    sort_key,
+                    file_list_entry(kv_pair.key.clone(), f, tag_dictionary),
+                ));
+            }
+        }
+    }
+
+    // Stable order for pagination: requested field, then precomputed sort key, then raw name
+    results.sort_by(|a, b| {
+        let ord = match sort_by {
+            ListSortBy::Name => std::cmp::Ordering::Equal,
+            ListSortBy::CreateTs => a.1.create_ts.cmp(&b.1.create_ts),
+            ListSortBy::Size => a.1.size.cmp(&b.1.size),
+            ListSortBy::DirsFirst => b.1.is_dir.cmp(&a.1.is_dir),
+        }
+        .then_with(|| a.0.cmp(&b.0))
+        .then_with(|| a.1.name.cmp(&b.1.name));
+        if descending {
+            ord.reverse()
+        } else {
+            ord
+        }
+    });
+    Ok(results.into_iter().map(|(_, entry)| entry).collect())
+}
+
+fn paginate_listing(
+    entries: Vec<DirListObjectAnchor>,
+    offset: u32,
+    limit: u32,
+    version: u64,
+) -> DirListPageAnchor {
+    let total_count = entries.len() as u32;
+    DirListPageAnchor {
+        entries: page_of(entries, offset, limit),
+        total_count,
+        version,
+    }
+}
+
+// `limit` of 0 means no limit, wherever an offset/limit pair is taken.
+fn page_of<T>(items: Vec<T>, offset: u32, limit: u32) -> Vec<T> {
+    let take = if limit == 0 {
+        usize::MAX
+    } else {
+        limit as usize
+    };
+    items.into_iter().skip(offset as usize).take(take).collect()
+}
+
+fn internal_get_dir_children_refs<'a>(
+    path_with_trailing_slash: &str,
+    root_children_files_data: &'a [KeyValueStringU64],
+    root_children_dirs_data: &'a Vec<KeyValueStringU64>,
+    dir_arena_data: &'a [KeyValueU64DirObject],
+) -> Result<(Vec<KeyValueStringU64>, Vec<KeyValueStringU64>)> {
+    if path_with_trailing_slash == "/" {
+        return Ok((
+            root_children_files_data.to_vec(),
+            root_children_dirs_data.clone(),
+        ));
+    }
+
+    let components: Vec<&str> = path_with_trailing_slash
+        .trim_matches('/')
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    let mut current_dir_id_opt: Option<u64> = None;
+    let mut current_children_dirs_vec_ref = root_children_dirs_data;
+
+    for component_str in components {
+        let component = component_str.to_string();
+        let dir_id_ref = get_from_vec_str_key(current_children_dirs_vec_ref, &component)
+            .ok_or(WalrusFsError::PathNotFound)?;
+
+        let dir_object = get_from_dir_arena(dir_arena_data, *dir_id_ref)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+
+        current_children_dirs_vec_ref = &dir_object.children_directories;
+        current_dir_id_opt = Some(*dir_id_ref);
+    }
+
+    let target_dir_id = current_dir_id_opt.ok_or(WalrusFsError::PathNotFound)?; // Should be Some if path is valid and not root
+    let target_dir_obj = get_from_dir_arena(dir_arena_data, target_dir_id)
+        .ok_or(WalrusFsError::ArenaMismatchError)?;
+
+    Ok((
+        target_dir_obj.children_files.clone(),
+        target_dir_obj.children_directories.clone(),
+    ))
+}
+
+// Follows symlinks at the final path component until a non-link (or missing)
+// entry is reached. Symlinks in intermediate components are not followed.
+fn internal_follow_symlinks(path: String, fs: &FsView) -> Result<String> {
+    let mut current = path;
+    for _ in 0..MAX_SYMLINK_HOPS {
+        let target = match internal_resolve_file_id(
+            &current,
+            &fs.root_children_files.data,
+            &fs.root_children_directories.data,
+            fs.dir_arena,
+        ) {
+            Ok(file_id) => get_from_file_arena(fs.file_arena, file_id)
+                .ok_or(WalrusFsError::ArenaMismatchError)?
+                .symlink_target
+                .clone(),
+            Err(_) => None, // A directory or a missing entry ends the chain
+        };
+        match target {
+            Some(next) => current = next,
+            None => return Ok(current),
+        }
+    }
+    err!(WalrusFsError::SymlinkLoop)
+}
+
+// Policies on the directories along `clean_path` and on the entry it names,
+// outermost first.
+fn internal_access_policies(
+    clean_path: &str,
+    root_children_files_data: &Vec<KeyValueStringU64>,
+    root_children_dirs_data: &Vec<KeyValueStringU64>,
+    file_arena_data: &[KeyValueU64FileObject],
+    dir_arena_data: &[KeyValueU64DirObject],
+) -> Result<Vec<AccessPolicy>> {
+    if clean_path == "/" {
+        return Ok(Vec::new());
+    }
+    let mut policies = Vec::new();
+    let (parent_dir_id, name) =
+        internal_resolve_parent_id_and_name(clean_path, root_children_dirs_data, dir_arena_data)?;
+    let mut current_children_dirs_vec = root_children_dirs_data;
+    for component in parent_dir_path(clean_path)
+        .split('/')
+        .filter(|s| !s.is_empty())
+    {
+        let dir_id = get_from_vec_str_key(current_children_dirs_vec, component)
+            .ok_or(WalrusFsError::PathNotFound)?;
+        let dir_object =
+            get_from_dir_arena(dir_arena_data, *dir_id).ok_or(WalrusFsError::ArenaMismatchError)?;
+        policies.extend(dir_object.access_policy.clone());
+        current_children_dirs_vec = &dir_object.children_directories;
+    }
+
+    if let Some(file_id) = get_from_vec_str_key(
+        internal_children_files(parent_dir_id, root_children_files_data, dir_arena_data)?,
+        &name,
+    ) {
+        let f = get_from_file_arena(file_arena_data, *file_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        policies.extend(f.access_policy.clone());
+    } else if let Some(dir_id) = get_from_vec_str_key(current_children_dirs_vec, &name) {
+        let d =
+            get_from_dir_arena(dir_arena_data, *dir_id).ok_or(WalrusFsError::ArenaMismatchError)?;
+        policies.extend(d.access_policy.clone());
+    } else {
+        return err!(WalrusFsError::PathNotFound);
+    }
+    Ok(policies)
+}
+
+// A token balance held by the wallet, with the collection its mint was verified
+// in when the Metaplex metadata was passed.
+struct Holding {
+    mint: Pubkey,
+    amount: u64,
+    collection: Option<Pubkey>,
+}
+
+// The leading fields of a Metaplex metadata account, up to the collection.
+#[derive(AnchorDeserialize)]
+struct MetadataPrefix {
+    key: u8,
+    _update_authority: Pubkey,
+    mint: Pubkey,
+    _name: String,
+    _symbol: String,
+    _uri: String,
+    _seller_fee_basis_points: u16,
+    _creators: Option<Vec<MetadataCreator>>,
+    _primary_sale_happened: bool,
+    _is_mutable: bool,
+    _edition_nonce: Option<u8>,
+    _token_standard: Option<u8>,
+    collection: Option<MetadataCollection>,
+}
+
+#[derive(AnchorDeserialize)]
+struct MetadataCreator {
+    _address: Pubkey,
+    _verified: bool,
+    _share: u8,
+}
+
+#[derive(AnchorDeserialize)]
+struct MetadataCollection {
+    verified: bool,
+    key: Pubkey,
+}
+
+// Token accounts owned by `wallet` among `accounts`, read from the SPL layout
+// shared by both token programs: mint, owner, then amount. Other accounts are
+// ignored, except Metaplex metadata, which vouches for a mint's collection.
+fn wallet_holdings(wallet: &Pubkey, accounts: &[AccountInfo]) -> Result<Vec<Holding>> {
+    let mut holdings = Vec::new();
+    let mut collections = Vec::new();
+    for (i, info) in accounts.iter().enumerate() {
+        if accounts[..i].iter().any(|a| a.key == info.key) {
+            continue; // Passing an account twice must not count its balance twice
+        }
+        let data = info.try_borrow_data()?;
+        if *info.owner == SPL_TOKEN_PROGRAM_ID || *info.owner == SPL_TOKEN_2022_PROGRAM_ID {
+            if !is_token_account(&data) || data[32..64] != wallet.to_bytes() {
+                continue;
+            }
+            holdings.push(Holding {
+                mint: Pubkey::try_from(&data[0..32]).unwrap(),
+                amount: u64::from_le_bytes(data[64..72].try_into().unwrap()),
+                collection: None,
+            });
+        } else if *info.owner == METAPLEX_METADATA_PROGRAM_ID {
+            if let Ok(metadata) = MetadataPrefix::deserialize(&mut &data[..]) {
+                if metadata.key != METAPLEX_METADATA_V1_KEY {
+                    continue;
+                }
+                if let Some(collection) = metadata.collection.filter(|c| c.verified) {
+                    collections.push((metadata.mint, collection.key));
+                }
+            }
+        }
+    }
+    for holding in holdings.iter_mut() {
+        holding.collection = collections
+            .iter()
+            .find(|(mint, _)| *mint == holding.mint)
+            .map(|(_, collection)| *collection);
+    }
+    Ok(holdings)
+}
+
+// Token accounts are 165 bytes. Token-2022 ones with extensions are longer and,
+// unlike mints, carry an account type of 2 right after that.
+fn is_token_account(data: &[u8]) -> bool {
+    data.len() == 165 || (data.len() > 165 && data[165] == 2)
+}
+
+fn holdings_satisfy(holdings: &[Holding], policy: &AccessPolicy) -> bool {
+    match policy {
+        AccessPolicy::MintHolder { mint, min_amount } => {
+            holdings
+                .iter()
+                .filter(|h| h.mint == *mint)
+                .fold(0u64, |total, h| total.saturating_add(h.amount))
+                >= *min_amount
+        }
+        AccessPolicy::CollectionMember { collection } => holdings
+            .iter()
+            .any(|h| h.amount > 0 && h.collection == Some(*collection)),
+    }
+}
+
+// The accounts reads resolve paths against: the instruction's own filesystem, or
+// one mounted into it and loaded by load_mounted_fs.
+struct FsView<'a> {
+    root_children_files: &'a ChildrenFilesPda,
+    root_children_directories: &'a ChildrenDirectoriesPda,
+    file_arena: &'a [KeyValueU64FileObject],
+    dir_arena: &'a [KeyValueU64DirObject],
+    tag_dictionary: &'a [String],
+    collation: Collation,
+}
+
+struct MountedFs {
+    root_children_files: ChildrenFilesPda,
+    root_children_directories: ChildrenDirectoriesPda,
+    file_arena: FileArenaPda,
+    dir_arena: DirArenaPda,
+    tag_dictionary: TagDictionaryPda,
+    collation: Collation,
+}
+
+impl MountedFs {
+    fn view(&self) -> FsView<'_> {
+        FsView {
+            root_children_files: &self.root_children_files,
+            root_children_directories: &self.root_children_directories,
+            file_arena: &self.file_arena.data,
+            dir_arena: &self.dir_arena.data,
+            tag_dictionary: &self.tag_dictionary.tags,
+            collation: self.collation,
+        }
+    }
+}
+
+// Splits a path that reaches into a mount point into the mount and the path it
+// names in the mounted filesystem. The mount dir itself counts only with
+// `include_mount_dir`, as stat reports it from this filesystem. None when the
+// path stays here, including when it doesn't resolve.
+fn internal_find_mount(
+    clean_path: &str,
+    include_mount_dir: bool,
+    root_children_dirs_data: &Vec<KeyValueStringU64>,
+    dir_arena_data: &[KeyValueU64DirObject],
+) -> Result<Option<(MountPoint, String)>> {
+    let components: Vec<&str> = clean_path.split('/').filter(|s| !s.is_empty()).collect();
+    let mut current_children_dirs_vec = root_children_dirs_data;
+    for (i, component) in components.iter().enumerate() {
+        let Some(dir_id) = get_from_vec_str_key(current_children_dirs_vec, component) else {
+            return Ok(None);
+        };
+        let dir_object =
+            get_from_dir_arena(dir_arena_data, *dir_id).ok_or(WalrusFsError::ArenaMismatchError)?;
+        if let Some(mount) = &dir_object.mount {
+            let rest = &components[i + 1..];
+            if rest.is_empty() && !include_mount_dir {
+                return Ok(None);
+            }
+            let mounted_path = rest
+                .iter()
+                .fold(mount.path.clone(), |p, name| join_path(&p, name));
+            return Ok(Some((mount.clone(), mounted_path)));
+        }
+        current_children_dirs_vec = &dir_object.children_directories;
+    }
+    Ok(None)
+}
+
+// Reads a mounted filesystem from `accounts`: its walrusfs_root,
+// root_children_files, root_children_directories, file_arena, dir_arena and
+// tag_dictionary, in that order. Mounts are followed one level deep.
+fn load_mounted_fs(mount: &MountPoint, accounts: &[AccountInfo]) -> Result<MountedFs> {
+    require!(accounts.len() >= 6, WalrusFsError::InvalidMountAccounts);
+    // The other PDAs are seeded by the root's address
+    let root: WalrusfsRootPda = load_mounted_pda(
+        &accounts[0],
+        &[
+            b"walrusfs_root",
+            mount.owner.as_ref(),
+            mount.fs_name.as_bytes(),
+        ],
+        |r: &WalrusfsRootPda| r.bump,
+    )?;
+    let root_key = accounts[0].key();
+    Ok(MountedFs {
+        root_children_files: load_mounted_pda(
+            &accounts[1],
+            &[b"root_children_files", root_key.as_ref()],
+            |p: &ChildrenFilesPda| p.bump,
+        )?,
+        root_children_directories: load_mounted_pda(
+            &accounts[2],
+            &[b"root_children_directories", root_key.as_ref()],
+            |p: &ChildrenDirectoriesPda| p.bump,
+        )?,
+        file_arena: load_mounted_pda(
+            &accounts[3],
+            &[b"file_arena", root_key.as_ref()],
+            |p: &FileArenaPda| p.bump,
+        )?,
+        dir_arena: load_mounted_pda(
+            &accounts[4],
+            &[b"dir_arena", root_key.as_ref()],
+            |p: &DirArenaPda| p.bump,
+        )?,
+        tag_dictionary: load_mounted_pda(
+            &accounts[5],
+            &[b"tag_dictionary", root_key.as_ref()],
+            |p: &TagDictionaryPda| p.bump,
+        )?,
+        collation: root.collation,
+    })
+}
+
+// Loads a PDA that may not have been created yet, returning None until it is.
+// The caller's seeds constraint already pins the address.
+fn load_initialized_pda<T: AccountDeserialize>(info: &AccountInfo) -> Result<Option<T>> {
+    if info.data_is_empty() {
+        return Ok(None);
+    }
+    require_keys_eq!(*info.owner, crate::ID, WalrusFsError::ArenaMismatchError);
+    Ok(Some(T::try_deserialize(&mut &info.try_borrow_data()?[..])?))
+}
+
+// Deserializes a PDA of this program, checking its address against `seeds` and
+// the bump it stores, as Anchor's seeds constraint would.
+fn load_mounted_pda<T: AccountDeserialize>(
+    info: &AccountInfo,
+    seeds: &[&[u8]],
+    bump: impl Fn(&T) -> u8,
+) -> Result<T> {
+    require_keys_eq!(*info.owner, crate::ID, WalrusFsError::InvalidMountAccounts);
+    let pda = T::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+    let bump = [bump(&pda)];
+    let mut seeds_with_bump = seeds.to_vec();
+    seeds_with_bump.push(&bump);
+    let expected = Pubkey::create_program_address(&seeds_with_bump, &crate::ID)
+        .map_err(|_| error!(WalrusFsError::InvalidMountAccounts))?;
+    require_keys_eq!(info.key(), expected, WalrusFsError::InvalidMountAccounts);
+    Ok(pda)
+}
+
+// Stats a non-root path, returning the object id along with its entry.
+fn internal_stat(clean_path: &str, fs: &FsView) -> Result<(u64, DirListObjectAnchor)> {
+    let (parent_dir_id, item_name) = internal_resolve_parent_id_and_name(
+        clean_path,
+        &fs.root_children_directories.data,
+        fs.dir_arena,
+    )?;
+    let (parent_files_vec, parent_dirs_vec) = match parent_dir_id {
+        Some(id) => {
+            let parent_dir =
+                get_from_dir_arena(fs.dir_arena, id).ok_or(WalrusFsError::ArenaMismatchError)?;
+            (&parent_dir.children_files, &parent_dir.children_directories)
+        }
+        None => (
+            &fs.root_children_files.data,
+            &fs.root_children_directories.data,
+        ),
+    };
+
+    if let Some(file_id_ref) = get_from_vec_str_key(parent_files_vec, &item_name) {
+        let f = get_from_file_arena(fs.file_arena, *file_id_ref)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        Ok((
+            *file_id_ref,
+            file_list_entry(item_name, f, fs.tag_dictionary),
+        ))
+    } else if let Some(dir_id_ref) = get_from_vec_str_key(parent_dirs_vec, &item_name) {
+        let d = get_from_dir_arena(fs.dir_arena, *dir_id_ref)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        Ok((*dir_id_ref, dir_list_entry(item_name, d, fs.tag_dictionary)))
+    } else {
+        err!(WalrusFsError::PathNotFound)
+    }
+}
+
+// Every entry of a directory, sorted and filtered, with the directory's version.
+fn internal_list_dir(
+    clean_path: &str,
+    fs: &FsView,
+    sort_by: ListSortBy,
+    descending: bool,
+    filter: &ListFilter,
+) -> Result<(Vec<DirListObjectAnchor>, u64)> {
+    let (target_dir_files_vec, target_dir_dirs_vec) = internal_get_dir_children_refs(
+        &ensure_trailing_slash(clean_path),
+        &fs.root_children_files.data,
+        &fs.root_children_directories.data,
+        fs.dir_arena,
+    )?;
+    let entries = internal_list_children(
+        &target_dir_files_vec,
+        &target_dir_dirs_vec,
+        fs.file_arena,
+        fs.dir_arena,
+        sort_by,
+        descending,
+        filter,
+        fs.collation,
+        fs.tag_dictionary,
+    )?;
+    let version = internal_dir_version(
+        internal_resolve_dir_id(clean_path, &fs.root_children_directories.data, fs.dir_arena)?,
+        root_dir_version(fs.root_children_files, fs.root_children_directories),
+        fs.dir_arena,
+    )?;
+    Ok((entries, version))
+}
+
+// Directory ids of a subtree in breadth-first order, starting with `dir_id` at depth 0.
+fn internal_bfs_dir_ids(
+    dir_id: u64,
+    max_depth: Option<u32>,
+    dir_arena_data: &[KeyValueU64DirObject],
+) -> Result<Vec<u64>> {
+    let mut ordered = vec![dir_id];
+    let mut depths = vec![0u32];
+    let mut next = 0;
+    while next < ordered.len() {
+        let (current_id, depth) = (ordered[next], depths[next]);
+        next += 1;
+        if max_depth.is_some_and(|max| depth >= max) {
+            continue;
+        }
+        let dir_object = get_from_dir_arena(dir_arena_data, current_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        for kv_pair in dir_object.children_directories.iter() {
+            ordered.push(kv_pair.value);
+            depths.push(depth + 1);
+        }
+    }
+    Ok(ordered)
+}
+
+fn internal_recursive_get_dir_obj_ids(
+    dir_id: u64,
+    dir_arena_data: &[KeyValueU64DirObject],
+) -> Result<(BTreeSet<u64>, BTreeSet<u64>)> {
+    let mut file_ids = BTreeSet::new();
+    let mut dir_ids_recursive = BTreeSet::new();
+
+    let mut dirs_to_process = vec![dir_id];
+    let mut visited_dirs = BTreeSet::new();
+
+    while let Some(current_dir_id) = dirs_to_process.pop() {
+        if visited_dirs.contains(&current_dir_id) {
+            continue;
+        }
+        visited_dirs.insert(current_dir_id);
+
+        let dir_object = get_from_dir_arena(dir_arena_data, current_dir_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+
+        for kv_pair in dir_object.children_files.iter() {
+            file_ids.insert(kv_pair.value);
+        }
+
+        for kv_pair in dir_object.children_directories.iter() {
+            let sub_dir_id = kv_pair.value;
+            if sub_dir_id != dir_id {
+                // Avoid self-reference cycles if they could exist (though unlikely here)
+                dir_ids_recursive.insert(sub_dir_id);
+            }
+            // Only add to process if not already visited (implicitly handled by visited_dirs check at loop start)
+            if !visited_dirs.contains(&sub_dir_id) {
+                // Explicit check here can be slightly more efficient
+                dirs_to_process.push(sub_dir_id);
+            }
+        }
+    }
+    Ok((file_ids, dir_ids_recursive))
+}
+fn internal_resolve_file_id(
+    clean_path: &str,
+    root_children_files_data: &Vec<KeyValueStringU64>,
+    root_children_dirs_data: &Vec<KeyValueStringU64>,
+    dir_arena_data: &[KeyValueU64DirObject],
+) -> Result<u64> {
+    let (parent_dir_id, file_name) =
+        internal_resolve_parent_id_and_name(clean_path, root_children_dirs_data, dir_arena_data)?;
+
+    let parent_files_vec = match parent_dir_id {
+        Some(id) => {
+            &get_from_dir_arena(dir_arena_data, id)
+                .ok_or(WalrusFsError::ArenaMismatchError)?
+                .children_files
+        }
+        None => root_children_files_data,
+    };
+    let file_id =
+        get_from_vec_str_key(parent_files_vec, &file_name).ok_or(WalrusFsError::PathNotFound)?;
+    Ok(*file_id)
+}
+
+fn require_unsealed(file_arena_data: &[KeyValueU64FileObject], file_id: u64) -> Result<()> {
+    let f =
+        get_from_file_arena(file_arena_data, file_id).ok_or(WalrusFsError::ArenaMismatchError)?;
+    require!(!f.sealed, WalrusFsError::FileSealed);
+    Ok(())
+}
+
+// A lease past its expiry slot no longer counts as held.
+fn active_lock(f: &FileObjectAnchor, current_slot: u64) -> Option<&FileLock> {
+    f.lock.as_ref().filter(|l| l.expires_slot > current_slot)
+}
+
+// The lock's own holder may always write through it.
+fn require_unlocked(f: &FileObjectAnchor, expect_unlocked: bool, actor: &Pubkey) -> Result<()> {
+    if expect_unlocked {
+        require!(
+            active_lock(f, Clock::get()?.slot)
+                .filter(|l| l.holder != *actor)
+                .is_none(),
+            WalrusFsError::FileLocked
+        );
+    }
+    Ok(())
+}
+
+// Compare-and-swap guard: fails when the entry was replaced or re-uploaded since
+// the client read it.
+fn require_expected(
+    file_id: u64,
+    f: &FileObjectAnchor,
+    expected_object_id: Option<u64>,
+    expected_blob_id: &Option<[u8; 32]>,
+) -> Result<()> {
+    require!(
+        expected_object_id.iter().all(|id| *id == file_id),
+        WalrusFsError::PreconditionFailed
+    );
+    require!(
+        expected_blob_id.iter().all(|b| *b == f.walrus_blob_id),
+        WalrusFsError::PreconditionFailed
+    );
+    Ok(())
+}
+
+// Brings one PDA written before schema_version existed up to SCHEMA_VERSION. That
+// layout is the current one minus the version, so the data shifts right by two
+// bytes and the account grows to fit, with `payer` covering the extra rent.
+// Accounts that already load at SCHEMA_VERSION are left as they are.
+fn migrate_pda_layout<'info, T: AccountDeserialize + Discriminator>(
+    account: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+) -> Result<()> {
+    {
+        let raw = account.try_borrow_data()?;
+        require!(
+            raw.len() >= 8 && raw[..8] == *T::DISCRIMINATOR,
+            anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch
+        );
+        if raw.len() >= 10
+            && raw[8..10] == SCHEMA_VERSION.to_le_bytes()
+            && T::try_deserialize(&mut &raw[..]).is_ok()
+        {
+            return Ok(());
+        }
+    }
+
+    let old_len = account.data_len();
+    grow_pda(account, payer, system_program, old_len + 2)?;
+
+    let mut raw = account.try_borrow_mut_data()?;
+    raw.copy_within(8..old_len, 10);
+    raw[8..10].copy_from_slice(&SCHEMA_VERSION.to_le_bytes());
+    T::try_deserialize(&mut &raw[..])?;
+
+    emit!(SchemaMigratedEvent {
+        account: account.key(),
+        schema_version: SCHEMA_VERSION,
+    });
+    Ok(())
+}
+
+// Returns None if `account` already loads as a current `T`, or else its contents
+// in `B`, the layout from before schema_version.
+fn decode_baseline<T: AccountDeserialize + Discriminator, B: AnchorDeserialize>(
+    account: &AccountInfo,
+) -> Result<Option<B>> {
+    let raw = account.try_borrow_data()?;
+    if has_current_layout::<T>(&raw, SCHEMA_VERSION)? {
+        return Ok(None);
+    }
+    B::deserialize(&mut &raw[8..])
+        .map(Some)
+        .map_err(|_| error!(anchor_lang::error::ErrorCode::AccountDidNotDeserialize))
+}
+
+// Every field the baseline root lacked starts at its default. No baseline root
+// could transfer authority, so its address still derives from the authority, and
+// an empty seed_name adds nothing to the derivation.
+fn migrate_root_layout<'info>(
+    account: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+) -> Result<()> {
+    let Some(legacy) = decode_baseline::<WalrusfsRootPda, BaselineRootPda>(account)? else {
+        return Ok(());
+    };
+    let root = WalrusfsRootPda {
+        schema_version: SCHEMA_VERSION,
+        current_epoch: legacy.current_epoch,
+        obj_id_counter: legacy.obj_id_counter,
+        authority: legacy.authority,
+        bump: legacy.bump,
+        default_renewal_policy: RenewalPolicy::default(),
+        space_warning_pcts: DEFAULT_SPACE_WARNING_PCTS,
+        collation: Collation::default(),
+        name: String::new(),
+        description: String::new(),
+        tags: Vec::new(),
+        limits: DEFAULT_FS_LIMITS,
+        seed_key: legacy.authority,
+        pending_authority: None,
+        co_authorities: Vec::new(),
+        frozen: false,
+        cpi_callers: None,
+        seed_name: String::new(),
+    };
+    // Root instructions never grow the account, so it gets its full size now
+    grow_pda(account, payer, system_program, WALRUSFS_ROOT_PDA_SPACE)?;
+    write_migrated_pda(account, payer, system_program, &root, SCHEMA_VERSION)
+}
+
+// Both lists keep their entries and start their version counters at zero.
+fn migrate_root_children_layout<'info>(
+    files: &AccountInfo<'info>,
+    dirs: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+) -> Result<()> {
+    if let Some(legacy) = decode_baseline::<ChildrenFilesPda, BaselineChildrenPda>(files)? {
+        let migrated = ChildrenFilesPda {
+            schema_version: SCHEMA_VERSION,
+            data: legacy.data,
+            version: 0,
+            bump: legacy.bump,
+        };
+        write_migrated_pda(files, payer, system_program, &migrated, SCHEMA_VERSION)?;
+    }
+    if let Some(legacy) = decode_baseline::<ChildrenDirectoriesPda, BaselineChildrenPda>(dirs)? {
+        let migrated = ChildrenDirectoriesPda {
+            schema_version: SCHEMA_VERSION,
+            data: legacy.data,
+            version: 0,
+            bump: legacy.bump,
+        };
+        write_migrated_pda(dirs, payer, system_program, &migrated, SCHEMA_VERSION)?;
+    }
+    Ok(())
+}
+
+// Checks the discriminator, then whether the account already loads as a `T` at
+// `schema_version`.
+fn has_current_layout<T: AccountDeserialize + Discriminator>(
+    raw: &[u8],
+    schema_version: u16,
+) -> Result<bool> {
+    require!(
+        raw.len() >= 8 && raw[..8] == *T::DISCRIMINATOR,
+        anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch
+    );
+    Ok(raw.len() >= 10
+        && raw[8..10] == schema_version.to_le_bytes()
+        && T::try_deserialize(&mut &raw[..]).is_ok())
+}
+
+// Overwrites a PDA with its migrated contents, growing it first if they don't fit.
+fn write_migrated_pda<'info, T: AccountSerialize>(
+    account: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    migrated: &T,
+    schema_version: u16,
+) -> Result<()> {
+    let mut bytes = Vec::new();
+    migrated.try_serialize(&mut bytes)?;
+    grow_pda(account, payer, system_program, bytes.len())?;
+    account.try_borrow_mut_data()?[..bytes.len()].copy_from_slice(&bytes);
+
+    emit!(SchemaMigratedEvent {
+        account: account.key(),
+        schema_version,
+    });
+    Ok(())
+}
+
+// Migrates both arenas together, since sort keys come from the names entries are
+// listed under and subtree_bytes from the files below. Runs after the root children
+// are migrated. Fields the baseline lacked are set as for an entry created today
+// by `fs_authority` in a default directory.
+#[allow(clippy::too_many_arguments)]
+fn migrate_arena_layouts<'info>(
+    file_arena: &AccountInfo<'info>,
+    dir_arena: &AccountInfo<'info>,
+    root_children_files: &AccountInfo<'info>,
+    root_children_directories: &AccountInfo<'info>,
+    fs_authority: Pubkey,
+    tag_dictionary: &mut Vec<String>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+) -> Result<()> {
+    let legacy_files = decode_baseline::<FileArenaPda, BaselineFileArenaPda>(file_arena)?;
+    let legacy_dirs = decode_baseline::<DirArenaPda, BaselineDirArenaPda>(dir_arena)?;
+    let (legacy_files, legacy_dirs) = match (legacy_files, legacy_dirs) {
+        (None, None) => return Ok(()),
+        (Some(files), Some(dirs)) => (files, dirs),
+        // Both are migrated by the same instruction, so only one being current
+        // means neither can be trusted
+        _ => return err!(WalrusFsError::ArenaMismatchError),
+    };
+    let root_files =
+        ChildrenFilesPda::try_deserialize(&mut &root_children_files.try_borrow_data()?[..])?;
+    let root_dirs = ChildrenDirectoriesPda::try_deserialize(
+        &mut &root_children_directories.try_borrow_data()?[..],
+    )?;
+    let file_names = || {
+        root_files.data.iter().chain(
+            legacy_dirs
+                .data
+                .iter()
+                .flat_map(|kv| kv.value.children_files.iter()),
+        )
+    };
+    let dir_names = || {
+        root_dirs.data.iter().chain(
+            legacy_dirs
+                .data
+                .iter()
+                .flat_map(|kv| kv.value.children_directories.iter()),
+        )
+    };
+
+    let mut files = Vec::with_capacity(legacy_files.data.len());
+    for kv in legacy_files.data {
+        let f = kv.value;
+        files.push(KeyValueU64FileObject {
+            key: kv.key,
+            value: FileObjectAnchor {
+                create_ts: f.create_ts,
+                modify_ts: f.create_ts,
+                tags: intern_tags(tag_dictionary, &f.tags)?,
+                size: f.size,
+                walrus_blob_id: decode_blob_id(&f.walrus_blob_id)?,
+                walrus_epoch_till: f.walrus_epoch_till,
+                deletable: false, // Not recorded, so never assumed
+                renewal_policy: RenewalPolicy::default(),
+                sort_key: baseline_sort_key(file_names(), kv.key),
+                symlink_target: None,
+                journal_segments: None,
+                chunks: None,
+                link_count: 1,
+                sealed: false,
+                seal_permanent: false,
+                lock: None,
+                access_ts: None,
+                access_count: 0,
+                sha256: None,
+                content_type: String::new(),
+                preview_blob_id: None,
+                sui_object_id: None,
+                encryption: None,
+                storage_class: StorageClass::default(),
+                xattrs: Vec::new(),
+                has_description: false,
+                pinned: false,
+                kind: ObjectKind::RegularFile,
+                status: BlobStatus::Certified, // Baseline blobs were already live
+                owner: fs_authority,
+                mode: file_mode_under(DEFAULT_DIR_MODE),
+                group: None,
+                access_policy: None,
+                sale: None,
+            },
+        });
+    }
+
+    let mut dirs = Vec::with_capacity(legacy_dirs.data.len());
+    for kv in legacy_dirs.data.iter() {
+        let d = &kv.value;
+        dirs.push(KeyValueU64DirObject {
+            key: kv.key,
+            value: DirObjectAnchor {
+                create_ts: d.create_ts,
+                modify_ts: d.create_ts,
+                tags: intern_tags(tag_dictionary, &d.tags)?,
+                default_tags: Vec::new(),
+                sort_key: baseline_sort_key(dir_names(), kv.key),
+                version: 0,
+                xattrs: Vec::new(),
+                has_description: false,
+                pinned: false,
+                children_files: d.children_files.clone(),
+                children_directories: d.children_directories.clone(),
+                child_file_count: d.children_files.len() as u32,
+                child_dir_count: d.children_directories.len() as u32,
+                subtree_bytes: 0, // Filled in below
+                mode: DEFAULT_DIR_MODE,
+                group: None,
+                mount: None,
+                access_policy: None,
+            },
+        });
+    }
+    rebuild_subtree_bytes(&mut dirs, &files)?;
+
+    let migrated_files = FileArenaPda {
+        schema_version: SCHEMA_VERSION,
+        data: files,
+        bump: legacy_files.bump,
+    };
+    write_migrated_pda(
+        file_arena,
+        payer,
+        system_program,
+        &migrated_files,
+        SCHEMA_VERSION,
+    )?;
+    let migrated_dirs = DirArenaPda {
+        schema_version: SCHEMA_VERSION,
+        data: dirs,
+        bump: legacy_dirs.bump,
+    };
+    write_migrated_pda(
+        dir_arena,
+        payer,
+        system_program,
+        &migrated_dirs,
+        SCHEMA_VERSION,
+    )
+}
+
+// The sort key for the name `id` is listed under in `children`, using the default
+// collation every baseline filesystem has. Baseline entries have exactly one name.
+fn baseline_sort_key<'a>(
+    mut children: impl Iterator<Item = &'a KeyValueStringU64>,
+    id: u64,
+) -> Vec<u8> {
+    children
+        .find(|child| child.value == id)
+        .map(|child| compute_sort_key(&child.key, Collation::default()))
+        .unwrap_or_default()
+}
+
+// Sums subtree_bytes for every directory from scratch. Only migration needs this;
+// instructions keep the totals current through adjust_subtree_bytes.
+fn rebuild_subtree_bytes(
+    dir_arena_data: &mut [KeyValueU64DirObject],
+    file_arena_data: &[KeyValueU64FileObject],
+) -> Result<()> {
+    let mut totals = Vec::with_capacity(dir_arena_data.len());
+    for kv in dir_arena_data.iter() {
+        let mut total: u64 = 0;
+        let mut dirs_to_process = vec![kv.key];
+        while let Some(dir_id) = dirs_to_process.pop() {
+            let d = get_from_dir_arena(dir_arena_data, dir_id)
+                .ok_or(WalrusFsError::ArenaMismatchError)?;
+            for child in d.children_files.iter() {
+                if let Some(f) = get_from_file_arena(file_arena_data, child.value) {
+                    total = total.saturating_add(f.size);
+                }
+            }
+            dirs_to_process.extend(d.children_directories.iter().map(|child| child.value));
+        }
+        totals.push(total);
+    }
+    for (kv, total) in dir_arena_data.iter_mut().zip(totals) {
+        kv.value.subtree_bytes = total;
+    }
+    Ok(())
+}
+
+fn grow_pda<'info>(
+    account: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    new_len: usize,
+) -> Result<()> {
+    if account.data_len() >= new_len {
+        return Ok(());
+    }
+    let rent_due = Rent::get()?
+        .minimum_balance(new_len)
+        .saturating_sub(account.lamports());
+    if rent_due > 0 {
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                system_program.clone(),
+                anchor_lang::system_program::Transfer {
+                    from: payer.clone(),
+                    to: account.clone(),
+                },
+            ),
+            rent_due,
+        )?;
+    }
+    account.realloc(new_len, true)?;
+    Ok(())
+}
+
+// Grows an account so its current contents fit when Anchor writes them back.
+fn grow_to_fit<'info, T>(
+    account: &Account<'info, T>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+) -> Result<()>
+where
+    T: AccountSerialize + AccountDeserialize + Owner + Clone,
+{
+    let mut bytes = Vec::new();
+    account.try_serialize(&mut bytes)?;
+    grow_pda(
+        &account.to_account_info(),
+        payer,
+        system_program,
+        bytes.len(),
+    )
+}
+
+fn validate_encryption(encryption: &Option<EncryptionInfo>) -> Result<()> {
+    require!(
+        encryption.iter().all(|e| {
+            !e.scheme.is_empty()
+                && e.scheme.len() <= MAX_ENCRYPTION_SCHEME_LEN
+                && e.nonce.len() <= MAX_ENCRYPTION_NONCE_LEN
+                && !e.wrapped_key.is_empty()
+                && e.wrapped_key.len() <= MAX_WRAPPED_KEY_LEN
+        }),
+        WalrusFsError::InvalidEncryption
+    );
+    Ok(())
+}
+
+// Decodes a blob id in Walrus's text form: 32 bytes as unpadded base64url. The
+// empty string, used by entries without a blob, maps to NO_BLOB_ID.
+fn decode_blob_id(text: &str) -> Result<[u8; 32]> {
+    if text.is_empty() {
+        return Ok(NO_BLOB_ID);
+    }
+    require!(text.len() == 43, WalrusFsError::InvalidBlobId);
+    let mut blob_id = [0u8; 32];
+    let mut acc: u32 = 0;
+    let mut bits = 0;
+    let mut out = 0;
+    for c in text.bytes() {
+        let sextet = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'-' => 62,
+            b'_' => 63,
+            _ => return err!(WalrusFsError::InvalidBlobId),
+        };
+        acc = (acc << 6) | sextet as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            blob_id[out] = (acc >> bits) as u8;
+            acc &= (1 << bits) - 1;
+            out += 1;
+        }
+    }
+    // 43 characters carry 258 bits; the last two must be zero padding
+    require!(acc == 0, WalrusFsError::InvalidBlobId);
+    Ok(blob_id)
+}
+
+// The root has no arena entry, so its version is split across the two root children
+// PDAs. Each is bumped only when its own list changes, so the sum still only grows.
+fn root_dir_version(files: &ChildrenFilesPda, dirs: &ChildrenDirectoriesPda) -> u64 {
+    files.version + dirs.version
+}
+
+fn internal_dir_version(
+    dir_id: Option<u64>,
+    root_version: u64,
+    dir_arena_data: &[KeyValueU64DirObject],
+) -> Result<u64> {
+    match dir_id {
+        Some(id) => Ok(get_from_dir_arena(dir_arena_data, id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?
+            .version),
+        None => Ok(root_version),
+    }
+}
+
+// Optimistic concurrency guard: fails when the directory's children changed since
+// the client listed it.
+fn require_dir_version(
+    dir_id: Option<u64>,
+    root_version: u64,
+    dir_arena_data: &[KeyValueU64DirObject],
+    expected_version: Option<u64>,
+) -> Result<()> {
+    let version = internal_dir_version(dir_id, root_version, dir_arena_data)?;
+    require!(
+        expected_version.iter().all(|v| *v == version),
+        WalrusFsError::VersionMismatch
+    );
+    Ok(())
+}
+
+// The deployment's program config, or None while its address is still empty.
+fn load_program_config(info: &AccountInfo) -> Result<Option<ProgramConfigPda>> {
+    if info.data_is_empty() {
+        return Ok(None);
+    }
+    require_keys_eq!(
+        *info.owner,
+        crate::ID,
+        ErrorCode::AccountOwnedByWrongProgram
+    );
+    let data = info.try_borrow_data()?;
+    Ok(Some(ProgramConfigPda::try_deserialize(&mut &data[..])?))
+}
+
+// A config that exists but cannot be read counts as paused.
+fn program_paused(info: &AccountInfo) -> bool {
+    load_program_config(info).map_or(true, |config| config.is_some_and(|c| c.paused))
+}
+
+// Whether a write may proceed given the root's CPI caller whitelist. Under a
+// CPI, the transaction's top-level instruction names the calling program; the
+// instructions sysvar must be passed to read it. The sysvar does not record
+// inner instructions, so only a direct CPI from that top-level program passes:
+// deeper nesting could hide any program between it and this one.
+fn cpi_caller_allowed(root: &WalrusfsRootPda, instructions: &Option<UncheckedAccount>) -> bool {
+    let Some(allowed) = &root.cpi_callers else {
+        return true;
+    };
+    let stack_height = get_stack_height();
+    if stack_height <= TRANSACTION_LEVEL_STACK_HEIGHT {
+        return true;
+    }
+    if stack_height > TRANSACTION_LEVEL_STACK_HEIGHT + 1 {
+        return false;
+    }
+    let Some(instructions) = instructions else {
+        return false;
+    };
+    load_current_index_checked(instructions)
+        .and_then(|index| load_instruction_at_checked(index as usize, instructions))
+        .is_ok_and(|ix| allowed.contains(&ix.program_id))
+}
+
+// The fs authority and its co-authorities may sign any write to the filesystem.
+fn is_fs_writer(root: &WalrusfsRootPda, signer: &Pubkey) -> bool {
+    *signer == root.authority || root.co_authorities.contains(signer)
+}
+
+// Other signers need an unexpired writer grant whose prefix covers `path`. The
+// grant's seeds already tie it to the signer.
+fn require_path_writer(
+    root: &WalrusfsRootPda,
+    grant: &Option<Box<Account<WriterGrantPda>>>,
+    signer: &Pubkey,
+    path: &str,
+) -> Result<()> {
+    if is_fs_writer(root, signer) {
+        return Ok(());
+    }
+    let grant = grant.as_ref().ok_or(WalrusFsError::Unauthorized)?;
+    require!(
+        grant.expiry_slot > Clock::get()?.slot,
+        WalrusFsError::WriterGrantExpired
+    );
+    require!(
+        path_within(path, &grant.path_prefix),
+        WalrusFsError::OutsideWriterGrant
+    );
+    Ok(())
+}
+
+// Mode and group of a directory about to gain or lose an entry, where None is
+// the root. Mount points take no entries of their own.
+fn dir_access(
+    dir_id: Option<u64>,
+    dir_arena_data: &[KeyValueU64DirObject],
+) -> Result<(u16, Option<Pubkey>)> {
+    match dir_id {
+        Some(id) => {
+            let d =
+                get_from_dir_arena(dir_arena_data, id).ok_or(WalrusFsError::ArenaMismatchError)?;
+            require!(d.mount.is_none(), WalrusFsError::PathInMount);
+            Ok((d.mode, d.group))
+        }
+        None => Ok((DEFAULT_DIR_MODE, None)),
+    }
+}
+
+// New files take their directory's mode without the execute bits.
+fn file_mode_under(dir_mode: u16) -> u16 {
+    dir_mode & 0o666
+}
+
+// The key a write is authorized as: the owner of the signer's session key when
+// one is passed, provided it is unexpired and `path` is within its scope, and
+// otherwise the signer.
+fn acting_signer<'a>(
+    session: &'a Option<Box<Account<SessionKeyPda>>>,
+    signer: &'a Pubkey,
+    path: &str,
+) -> Result<&'a Pubkey> {
+    let Some(session) = session else {
+        return Ok(signer);
+    };
+    require!(
+        session.expiry_slot > Clock::get()?.slot,
+        WalrusFsError::SessionKeyExpired
+    );
+    require!(
+        path_within(path, &session.path_prefix),
+        WalrusFsError::OutsideSessionScope
+    );
+    Ok(&session.owner)
+}
+
+// Whether a signer other than the authorities may write an object with `mode`
+// and `object_group`. Members of the object's group, proven by passing its
+// GroupPda, get the group-write bit; anyone else needs a writer grant covering
+// `path` and the other-write bit.
+#[allow(clippy::too_many_arguments)]
+fn require_writable(
+    root: &WalrusfsRootPda,
+    grant: &Option<Box<Account<WriterGrantPda>>>,
+    group: &Option<Box<Account<GroupPda>>>,
+    session: &Option<Box<Account<SessionKeyPda>>>,
+    signer: &Pubkey,
+    path: &str,
+    mode: u16,
+    object_group: Option<Pubkey>,
+) -> Result<()> {
+    let signer = acting_signer(session, signer, path)?;
+    if is_fs_writer(root, signer) {
+        return Ok(());
+    }
+    if let Some(group) = group {
+        if object_group == Some(group.key()) && group.members.contains(signer) {
+            require!(
+                mode & MODE_GROUP_WRITE != 0,
+                WalrusFsError::PermissionDenied
+            );
+            return Ok(());
+        }
+    }
+    require_path_writer(root, grant, signer, path)?;
+    require!(
+        mode & MODE_OTHER_WRITE != 0,
+        WalrusFsError::PermissionDenied
+    );
+    Ok(())
+}
+
+// Changes to a file may also be signed by the file's owner.
+fn require_file_writer(
+    root: &WalrusfsRootPda,
+    grant: &Option<Box<Account<WriterGrantPda>>>,
+    group: &Option<Box<Account<GroupPda>>>,
+    session: &Option<Box<Account<SessionKeyPda>>>,
+    f: &FileObjectAnchor,
+    signer: &Pubkey,
+    path: &str,
+) -> Result<()> {
+    let signer = acting_signer(session, signer, path)?;
+    if *signer == f.owner {
+        return Ok(());
+    }
+    require_writable(root, grant, group, &None, signer, path, f.mode, f.group)
+}
+
+// Records a change to a directory's children, also refreshing modify_ts and the
+// cached child counts for arena dirs. `root_list_version` is the version field of
+// whichever root children PDA holds the list that changed.
+fn bump_dir_version(
+    dir_id: Option<u64>,
+    root_list_version: &mut u64,
+    dir_arena_data: &mut [KeyValueU64DirObject],
+) -> Result<()> {
+    match dir_id {
+        Some(id) => {
+            let d = get_mut_from_dir_arena(dir_arena_data, id)
+                .ok_or(WalrusFsError::ArenaMismatchError)?;
+            d.version += 1;
+            d.modify_ts = Clock::get()?.unix_timestamp as u64 * 1000;
+            d.child_file_count = d.children_files.len() as u32;
+            d.child_dir_count = d.children_directories.len() as u32;
+        }
+        None => *root_list_version += 1,
+    }
+    Ok(())
+}
+
+// Carries a change in file bytes below `dir_path` into the subtree_bytes of that
+// directory and each of its ancestors. The root keeps no aggregates; fs_stats
+// covers it.
+fn adjust_subtree_bytes(
+    dir_path: &str,
+    bytes_added: u64,
+    bytes_removed: u64,
+    root_children_dirs_data: &Vec<KeyValueStringU64>,
+    dir_arena_data: &mut [KeyValueU64DirObject],
+) -> Result<()> {
+    if bytes_added == bytes_removed {
+        return Ok(());
+    }
+    let mut dir_ids = Vec::new();
+    let mut current_children_dirs_vec = root_children_dirs_data;
+    for component in dir_path.split('/').filter(|s| !s.is_empty()) {
+        let dir_id = *get_from_vec_str_key(current_children_dirs_vec, component)
+            .ok_or(WalrusFsError::PathNotFound)?;
+        current_children_dirs_vec = &get_from_dir_arena(dir_arena_data, dir_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?
+            .children_directories;
+        dir_ids.push(dir_id);
+    }
+    for dir_id in dir_ids {
+        let d = get_mut_from_dir_arena(dir_arena_data, dir_id)
+            .ok_or(WalrusFsError::ArenaMismatchError)?;
+        d.subtree_bytes = d
+            .subtree_bytes
+            .saturating_add(bytes_added)
+            .saturating_sub(bytes_removed);
+    }
+    Ok(())
+}
+
+// Drops one directory entry's reference to a file object. The object leaves the
+// arena with its last link and is returned so its blob can be released.
+fn internal_unlink_file(
+    file_arena_data: &mut Vec<KeyValueU64FileObject>,
+    file_id: u64,
+) -> Result<Option<FileObjectAnchor>> {
+    let f = get_mut_from_file_arena(file_arena_data, file_id)
+        .ok_or(WalrusFsError::ArenaMismatchError)?;
+    if f.link_count > 1 {
+        f.link_count -= 1;
+        return Ok(None);
+    }
+    Ok(remove_from_file_arena(file_arena_data, &file_id))
+}
+
+// Removes a directory and everything below it from the arenas, returning the file
+// objects that lost their last link.
+fn internal_remove_dir_subtree(
+    dir_id: u64,
+    file_arena_data: &mut Vec<KeyValueU64FileObject>,
+    dir_arena_data: &mut Vec<KeyValueU64DirObject>,
+) -> Result<Vec<FileObjectAnchor>> {
+    let (_, sub_dir_ids) = internal_recursive_get_dir_obj_ids(dir_id, dir_arena_data)?;
+
+    // Unlink once per directory entry, since a hard-linked file can appear several times
+    let mut removed_files = Vec::new();
+    for id in sub_dir_ids.iter().chain(std::iter::once(&dir_id)) {
+        let linked_file_ids: Vec<u64> = get_from_dir_arena(dir_arena_data, *id)
+            .map(|d| d.children_files.iter().map(|kv| kv.value).collect())
+            .unwrap_or_default();
+        for file_id in linked_file_ids {
+            // Err means the object is already gone from the arena; nothing to free
+            if let Ok(Some(f)) = internal_unlink_file(file_arena_data, file_id) {
+                removed_files.push(f);
+            }
+        }
+    }
+    for id in sub_dir_ids {
+        remove_from_dir_arena(dir_arena_data, &id);
+    }
+    remove_from_dir_arena(dir_arena_data, &dir_id);
+    Ok(removed_files)
+}
+
+// Queues the blobs of removed deletable files once no remaining file in the
+// arena references them (i.e. their reference count dropped to zero). Blobs a
+// snapshot still lists are marked for release instead.
+fn internal_enqueue_unreferenced_blobs(
+    removed_files: Vec<FileObjectAnchor>,
+    file_arena_data: &[KeyValueU64FileObject],
+    pending_blob_deletes: Option<&mut PendingBlobDeletesPda>,
+) -> Result<()> {
+    let unreferenced: Vec<[u8; 32]> = removed_files
+        .into_iter()
+        .filter(|f| f.deletable)
+        .map(|f| f.walrus_blob_id)
+        .filter(|blob_id| {
+            !file_arena_data
+                .iter()
+                .any(|kv| kv.value.walrus_blob_id == *blob_id)
+        })
+        .collect();
+    if unreferenced.is_empty() {
+        return Ok(());
+    }
+
+    let queue = pending_blob_deletes.ok_or(WalrusFsError::BlobDeleteQueueRequired)?;
+    for blob_id in unreferenced {
+        match queue.pinned_blobs.iter_mut().find(|p| p.blob_id == blob_id) {
+            Some(pin) => pin.release_on_unpin = true,
+            None => internal_queue_blob_delete(&mut queue.blob_ids, blob_id),
+        }
+    }
+    Ok(())
+}
+
+fn internal_queue_blob_delete(blob_ids: &mut Vec<[u8; 32]>, blob_id: [u8; 32]) {
+    if !blob_ids.contains(&blob_id) {
+        emit!(BlobDeleteQueuedEvent { blob_id });
+        blob_ids.push(blob_id);
+    }
+}
+
+// Distinct file blobs listed by snapshot entries.
+fn snapshot_blob_ids(entries: &[SnapshotEntry]) -> Vec<[u8; 32]> {
+    let mut blob_ids: Vec<[u8; 32]> = entries
+        .iter()
+        .filter(|e| !e.is_dir && e.walrus_blob_id != NO_BLOB_ID)
+        .map(|e| e.walrus_blob_id)
+        .collect();
+    blob_ids.sort();
+    blob_ids.dedup();
+    blob_ids
+}
+
+fn internal_pin_snapshot_blobs(entries: &[SnapshotEntry], queue: &mut PendingBlobDeletesPda) {
+    for blob_id in snapshot_blob_ids(entries) {
+        match queue.pinned_blobs.iter_mut().find(|p| p.blob_id == blob_id) {
+            Some(pin) => pin.holders += 1,
+            None => queue.pinned_blobs.push(PinnedBlob {
+                blob_id,
+                holders: 1,
+                release_on_unpin: false,
+            }),
+        }
+    }
+}
+
+// Drops one hold on each blob the entries list. A blob whose last hold goes is
+// queued if the last live file using it was deletable and none uses it now.
+fn internal_unpin_snapshot_blobs(
+    entries: &[SnapshotEntry],
+    queue: &mut PendingBlobDeletesPda,
+    file_arena_data: &[KeyValueU64FileObject],
+) {
+    for blob_id in snapshot_blob_ids(entries) {
+        let Some(index) = queue.pinned_blobs.iter().position(|p| p.blob_id == blob_id) else {
+            continue;
+        };
+        let pin = &mut queue.pinned_blobs[index];
+        pin.holders = pin.holders.saturating_sub(1);
+        if pin.holders > 0 {
+            continue;
+        }
+        let release = pin.release_on_unpin;
+        queue.pinned_blobs.remove(index);
+        if release
+            && !file_arena_data
+                .iter()
+                .any(|kv| kv.value.walrus_blob_id == blob_id)
+        {
+            internal_queue_blob_delete(&mut queue.blob_ids, blob_id);
+        }
+    }
+}
+
+// Resolves a directory path to its id; None means the root directory.
+fn internal_resolve_dir_id(
+    clean_path: &str,
+    root_children_dirs_data: &Vec<KeyValueStringU64>,
+    dir_arena_data: &[KeyValueU64DirObject],
+) -> Result<Option<u64>> {
+    if clean_path == "/" {
+        return Ok(None);
+    }
+    let (parent_dir_id, dir_name) =
+        internal_resolve_parent_id_and_name(clean_path, root_children_dirs_data, dir_arena_data)?;
+
+    let parent_dirs_vec = match parent_dir_id {
+        Some(id) => {
+            &get_from_dir_arena(dir_arena_data, id)
+                .ok_or(WalrusFsError::ArenaMismatchError)?
+                .children_directories
+        }
+        None => root_children_dirs_data,
+    };
+    let dir_id =
+        get_from_vec_str_key(parent_dirs_vec, &dir_name).ok_or(WalrusFsError::PathNotFound)?;
+    Ok(Some(*dir_id))
+}
+
+fn compute_sort_key(name: &str, collation: Collation) -> Vec<u8> {
+    match collation {
+        Collation::Binary => name.as_bytes().to_vec(),
+        Collation::CaseFolded => name.to_lowercase().into_bytes(),
+        Collation::NumericAware => {
+            // Each digit run becomes '0', its length (leading zeros stripped),
+            // then the digits, so shorter numbers sort before longer ones.
+            let folded = name.to_lowercase();
+            let bytes = folded.as_bytes();
+            let mut key = Vec::with_capacity(bytes.len() + 2);
+            let mut i = 0;
+            while i < bytes.len() {
+                if !bytes[i].is_ascii_digit() {
+                    key.push(bytes[i]);
+                    i += 1;
+                    continue;
+                }
+                let start = i;
+                while i < bytes.len() && bytes[i].is_ascii_digit() {
+                    i += 1;
+                }
+                let digits = &bytes[start..i];
+                let first_significant = digits
+                    .iter()
+                    .position(|b| *b != b'0')
+                    .unwrap_or(digits.len() - 1);
+                let significant = &digits[first_significant..];
+                key.push(b'0');
+                key.push(significant.len() as u8);
+                key.extend_from_slice(significant);
+            }
+            key
+        }
+    }
+}
+
+fn parent_dir_path(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(0) | None => "/",
+        Some(i) => &path[..i],
+    }
+}
+
+fn join_path(dir_path: &str, name: &str) -> String {
+    if dir_path == "/" {
+        format!("/{}", name)
+    } else {
+        format!("{}/{}", dir_path, name)
+    }
+}
+
+struct SubtreeEntry {
+    id: u64,
+    is_dir: bool,
+    path: String,
+}
+
+// Every file and directory below dir_id (None = root), with canonical paths.
+fn internal_walk_subtree(
+    dir_id: Option<u64>,
+    dir_path: &str,
+    root_children_files_data: &Vec<KeyValueStringU64>,
+    root_children_dirs_data: &Vec<KeyValueStringU64>,
+    dir_arena_data: &[KeyValueU64DirObject],
+) -> Result<Vec<SubtreeEntry>> {
+    let mut entries = Vec::new();
+    let mut dirs_to_process = vec![(dir_id, dir_path.to_string())];
+
+    while let Some((current_dir_id, current_path)) = dirs_to_process.pop() {
+        let (files_vec, dirs_vec) = match current_dir_id {
+            Some(id) => {
+                let dir_object = get_from_dir_arena(dir_arena_data, id)
+                    .ok_or(WalrusFsError::ArenaMismatchError)?;
+                (&dir_object.children_files, &dir_object.children_directories)
+            }
+            None => (root_children_files_data, root_children_dirs_data),
+        };
+
+        for kv_pair in files_vec.iter() {
+            entries.push(SubtreeEntry {
+                id: kv_pair.value,
+                is_dir: false,
+                path: join_path(&current_path, &kv_pair.key),
+            });
+        }
+        for kv_pair in dirs_vec.iter() {
+            let sub_path = join_path(&current_path, &kv_pair.key);
+            entries.push(SubtreeEntry {
+                id: kv_pair.value,
+                is_dir: true,
+                path: sub_path.clone(),
+            });
+            dirs_to_process.push((Some(kv_pair.value), sub_path));
+        }
+    }
+    Ok(entries)
+}
+
+// Snapshot entries for everything under `dir_path`, sorted by relative path.
+fn internal_capture_snapshot_entries(
+    dir_path: &str,
+    root_children_files_data: &Vec<KeyValueStringU64>,
+    root_children_dirs_data: &Vec<KeyValueStringU64>,
+    file_arena_data: &[KeyValueU64FileObject],
+    dir_arena_data: &[KeyValueU64DirObject],
+) -> Result<Vec<SnapshotEntry>> {
+    let dir_id = internal_resolve_dir_id(dir_path, root_children_dirs_data, dir_arena_data)?;
+    let subtree = internal_walk_subtree(
+        dir_id,
+        dir_path,
+        root_children_files_data,
+        root_children_dirs_data,
+        dir_arena_data,
+    )?;
+
+    let mut entries = Vec::with_capacity(subtree.len());
+    for entry in subtree {
+        let relative_path = if dir_path == "/" {
+            entry.path
+        } else {
+            entry.path[dir_path.len()..].to_string()
+        };
+        entries.push(if entry.is_dir {
+            let d = get_from_dir_arena(dir_arena_data, entry.id)
+                .ok_or(WalrusFsError::ArenaMismatchError)?;
+            SnapshotEntry {
+                path: relative_path,
+                object_id: entry.id,
+                is_dir: true,
+                walrus_blob_id: NO_BLOB_ID,
+                size: 0,
+                modify_ts: d.create_ts,
+            }
+        } else {
+            let f = get_from_file_arena(file_arena_data, entry.id)
+                .ok_or(WalrusFsError::ArenaMismatchError)?;
+            SnapshotEntry {
+                path: relative_path,
+                object_id: entry.id,
+                is_dir: false,
+                walrus_blob_id: f.walrus_blob_id,
+                size: f.size,
+                modify_ts: f.modify_ts,
+            }
+        });
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+// Merges two path-sorted entry lists. An entry whose kind changed counts as
+// removed and re-added.
+fn internal_diff_snapshot_entries(
+    base: &[SnapshotEntry],
+    other: &[SnapshotEntry],
+) -> SnapshotDiffAnchor {
+    let mut diff = SnapshotDiffAnchor {
+        added: Vec::new(),
+        removed: Vec::new(),
+        modified: Vec::new(),
+    };
+    let (mut i, mut j) = (0, 0);
+    while i < base.len() || j < other.len() {
+        match (base.get(i), other.get(j)) {
+            (Some(b), Some(o)) if b.path == o.path => {
+                if b.is_dir != o.is_dir {
+                    diff.removed.push(b.clone());
+                    diff.added.push(o.clone());
+                } else if b != o {
+                    diff.modified.push(o.clone());
+                }
+                i += 1;
+                j += 1;
+            }
+            (Some(b), Some(o)) if b.path < o.path => {
+                diff.removed.push(b.clone());
+                i += 1;
+            }
+            (Some(b), None) => {
+                diff.removed.push(b.clone());
+                i += 1;
+            }
+            (_, Some(o)) => {
+                diff.added.push(o.clone());
+                j += 1;
+            }
+            (None, None) => break,
+        }
+    }
+    diff
+}
+
+// Every file and dir id reachable from the root children lists. Arena entries
+// outside these sets are orphans left behind by partial failures.
+fn internal_collect_reachable_ids(
+    root_children_files_data: &[KeyValueStringU64],
+    root_children_dirs_data: &[KeyValueStringU64],
+    dir_arena_data: &[KeyValueU64DirObject],
+) -> Result<(BTreeSet<u64>, BTreeSet<u64>)> {
+    let mut file_ids: BTreeSet<u64> = root_children_files_data.iter().map(|kv| kv.value).collect();
+    let mut dir_ids = BTreeSet::new();
+
+    for kv_pair in root_children_dirs_data.iter() {
+        dir_ids.insert(kv_pair.value);
+        let (sub_file_ids, sub_dir_ids) =
+            internal_recursive_get_dir_obj_ids(kv_pair.value, dir_arena_data)?;
+        file_ids.extend(sub_file_ids);
+        dir_ids.extend(sub_dir_ids);
+    }
+    Ok((file_ids, dir_ids))
+}
+
+// --- Account Space Monitoring ---
+fn space_used_pct<'info, T>(account: &Account<'info, T>) -> Result<u8>
+where
+    T: AccountSerialize + AccountDeserialize + Owner + Clone,
+{
+    let mut serialized = Vec::new();
+    (**account).try_serialize(&mut serialized)?;
+    let capacity = account.to_account_info().data_len().max(1);
+    Ok((serialized.len() * 100 / capacity).min(100) as u8)
+}
+
+// Emits SpaceLowEvent for the highest threshold crossed by the latest write.
+fn emit_space_low_if_crossed<'info, T>(
+    account: &Account<'info, T>,
+    pct_before: u8,
+    thresholds: &[u8; 3],
+) -> Result<()>
+where
+    T: AccountSerialize + AccountDeserialize + Owner + Clone,
+{
+    let pct_used = space_used_pct(account)?;
+    if let Some(threshold) = thresholds
+        .iter()
+        .rev()
+        .find(|t| pct_before < **t && pct_used >= **t)
+    {
+        emit!(SpaceLowEvent {
+            account: account.key(),
+            pct_used,
+            threshold: *threshold,
+        });
+    }
+    Ok(())
+}
+
+// --- Path Validation and String Utils (Unchanged) ---
+// Canonical form used for every stored name, event and return value:
+// NFC, leading slash, no trailing slash (except for the root itself).
+fn canonicalize_path(path: &str) -> Result<String> {
+    let normalized: String = path.nfc().collect();
+    let canonical = remove_trailing_slash(&normalized);
+    validate_path(&canonical)?;
+    Ok(canonical)
+}
+
+// A single path component, normalized the same way as full paths.
+fn canonicalize_name(name: &str) -> Result<String> {
+    let normalized: String = name.nfc().collect();
+    if normalized.is_empty() || normalized.contains('/') {
+        return err!(WalrusFsError::PathError);
+    }
+    validate_string_len(&normalized, "name")?;
+    Ok(normalized)
+}
+
+// `*` matches any run of characters, including none; everything else is literal.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let n: Vec<char> = name.chars().collect();
+    let (mut pi, mut ni) = (0, 0);
+    let mut last_star: Option<(usize, usize)> = None;
+    while ni < n.len() {
+        if pi < p.len() && p[pi] == '*' {
+            last_star = Some((pi, ni));
+            pi += 1;
+        } else if pi < p.len() && p[pi] == n[ni] {
+            pi += 1;
+            ni += 1;
+        } else if let Some((star_pi, star_ni)) = last_star {
+            // Let the last star swallow one more character and retry
+            pi = star_pi + 1;
+            ni = star_ni + 1;
+            last_star = Some((star_pi, star_ni + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+fn validate_path(path: &str) -> Result<()> {
+    if path.is_empty() || path.len() > MAX_PATH_LEN {
+        // Path can be multiple components
+        return err!(WalrusFsError::PathError);
+    }
+    if !path.starts_with('/') && !path.is_empty() {
+        return err!(WalrusFsError::PathError);
+    }
+    if path.contains("//") {
+        return err!(WalrusFsError::PathError);
+    }
+    Ok(())
+}
+
+fn validate_tags(tags: &[String], limits: &FsLimits) -> Result<()> {
+    if tags.len() > limits.max_tags as usize {
+        return err!(WalrusFsError::TooManyTags);
+    }
+    for tag in tags {
+        validate_string_len(tag, "tag")?;
+        require!(
+            tag.len() <= limits.max_string_len as usize,
+            WalrusFsError::StringTooLong
+        );
+    }
+    Ok(())
+}
+
+// Every limit must be usable and no higher than its counterpart in `ceiling`.
+fn validate_fs_limits(limits: &FsLimits, ceiling: &FsLimits) -> Result<()> {
+    require!(
+        limits.max_string_len > 0
+            && limits.max_path_depth > 0
+            && limits.max_children_per_dir > 0
+            && limits.max_tags <= ceiling.max_tags
+            && limits.max_string_len <= ceiling.max_string_len
+            && limits.max_path_depth <= ceiling.max_path_depth
+            && limits.max_children_per_dir <= ceiling.max_children_per_dir,
+        WalrusFsError::InvalidLimits
+    );
+    Ok(())
+}
+
+fn path_depth(clean_path: &str) -> usize {
+    clean_path.split('/').filter(|c| !c.is_empty()).count()
+}
+
+fn require_path_depth(depth: usize, limits: &FsLimits) -> Result<()> {
+    require!(
+        depth <= limits.max_path_depth as usize,
+        WalrusFsError::PathTooDeep
+    );
+    Ok(())
+}
+
+fn validate_string_len(s: &str, field_name: &str) -> Result<()> {
+    if s.len() > MAX_STRING_LEN {
+        msg!(
+            "String too long for field: {}. Max: {}, Found: {}",
+            field_name,
+            MAX_STRING_LEN,
+            s.len()
+        );
+        return err!(WalrusFsError::StringTooLong);
+    }
+    Ok(())
+}
+
+fn remove_trailing_slash(path: &str) -> String {
+    if path != "/" && path.ends_with('/') {
+        path[..path.len() - 1].to_string()
+    } else {
+        path.to_string()
+    }
+}
+
+// Both paths canonical; a path is within itself.
+fn path_within(path: &str, prefix: &str) -> bool {
+    prefix == "/" || path == prefix || path.starts_with(&ensure_trailing_slash(prefix))
+}
+
+fn ensure_trailing_slash(path: &str) -> String {
+    if path == "/" {
+        return "/".to_string();
+    } // Keep root as is
+    if !path.ends_with('/') {
+        format!("{}/", path)
+    } else {
+        path.to_string()
+    }
+}
+
+// --- Accounts Structs for Instructions (Unchanged Structurally, but types inside accounts are modified) ---
+// --- (All `#[derive(Accounts)]` structs remain as they were, definitions are not repeated for brevity) ---
+// Example:
+#[derive(Accounts)]
+#[instruction(name: String)]
+pub struct InitializeWalrusfs<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = WALRUSFS_ROOT_PDA_SPACE,
+        seeds = [b"walrusfs_root".as_ref(), payer.key().as_ref(), name.as_bytes()],
+        bump
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        init,
+        payer = payer,
+        space = CHILDREN_PDA_SPACE,
+        seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()],
+        bump
+    )]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>, // Type inside uses Vec now
+    #[account(
+        init,
+        payer = payer,
+        space = CHILDREN_PDA_SPACE,
+        seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()],
+        bump
+    )]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>, // Type inside uses Vec
+    #[account(
+        init,
+        payer = payer,
+        space = ARENA_PDA_SPACE,
+        seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump
+    )]
+    pub file_arena: Box<Account<'info, FileArenaPda>>, // Type inside uses Vec
+    #[account(
+        init,
+        payer = payer,
+        space = ARENA_PDA_SPACE,
+        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump
+    )]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>, // Type inside uses Vec
+    #[account(
+        init,
+        payer = payer,
+        space = TAG_DICTIONARY_PDA_SPACE,
+        seeds = [b"tag_dictionary".as_ref(), walrusfs_root.key().as_ref()],
+        bump
+    )]
+    pub tag_dictionary: Box<Account<'info, TagDictionaryPda>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+    /// CHECK: Receives the creation fee; checked against program_config.
+    #[account(mut)]
+    pub fee_recipient: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeProgramConfig<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>, // The program's upgrade authority
+    #[account(
+        init,
+        payer = authority,
+        space = PROGRAM_CONFIG_PDA_SPACE,
+        seeds = [b"program_config".as_ref()],
+        bump
+    )]
+    pub program_config: Box<Account<'info, ProgramConfigPda>>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()) @ WalrusFsError::Unauthorized)]
+    pub program: Program<'info, crate::program::WalrusfsAnchor>,
+    #[account(constraint = program_data.upgrade_authority_address == Some(authority.key()) @ WalrusFsError::Unauthorized)]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateProgramConfig<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"program_config".as_ref()],
+        bump = program_config.bump,
+        has_one = admin @ WalrusFsError::Unauthorized
+    )]
+    pub program_config: Box<Account<'info, ProgramConfigPda>>,
+}
+
+#[derive(Accounts)]
+pub struct AddDir<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) || writer_grant.is_some() || group.is_some() || session_key.is_some() @ WalrusFsError::Unauthorized,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account( // Read-only; only needed to compute the root's version
+        seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()],
+        bump = root_children_files.bump
+    )]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
+    #[account( 
+        mut, // Mutable if adding to root
+        seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()],
+        bump = root_children_directories.bump
+    )]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account(
+        mut,
+        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = dir_arena.bump
+    )]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    #[account(
+        mut,
+        seeds = [b"tag_dictionary".as_ref(), walrusfs_root.key().as_ref()],
+        bump = tag_dictionary.bump
+    )]
+    pub tag_dictionary: Box<Account<'info, TagDictionaryPda>>,
+    #[account( // Passed by delegates writing under a writer grant
+        seeds = [b"writer_grant".as_ref(), walrusfs_root.key().as_ref(), authority.key().as_ref()],
+        bump = writer_grant.bump
+    )]
+    pub writer_grant: Option<Box<Account<'info, WriterGrantPda>>>,
+    #[account( // Passed by members writing through an object's group
+        seeds = [b"group".as_ref(), walrusfs_root.key().as_ref(), group.name.as_bytes()],
+        bump = group.bump
+    )]
+    pub group: Option<Box<Account<'info, GroupPda>>>,
+    #[account( // Passed when signing with a session key
+        seeds = [b"session_key".as_ref(), walrusfs_root.key().as_ref(), authority.key().as_ref()],
+        bump = session_key.bump
+    )]
+    pub session_key: Option<Box<Account<'info, SessionKeyPda>>>,
+    /// CHECK: The instructions sysvar, needed when writing through CPI to a
+    /// filesystem that limits its CPI callers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RenameDir<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account( // Read-only; only needed to compute the root's version
+        seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()],
+        bump = root_children_files.bump
+    )]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
+    #[account( 
+        mut, // Could be renaming a dir in root, or a dir in a subdir (affecting dir_arena)
+        seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()],
+        bump = root_children_directories.bump
+    )]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account( 
+        mut,
+        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = dir_arena.bump
+    )]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    /// CHECK: The instructions sysvar, needed when writing through CPI to a
+    /// filesystem that limits its CPI callers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetDefaultTags<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_directories.bump)]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account(
+        mut,
+        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = dir_arena.bump
+    )]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    #[account(
+        mut,
+        seeds = [b"tag_dictionary".as_ref(), walrusfs_root.key().as_ref()],
+        bump = tag_dictionary.bump
+    )]
+    pub tag_dictionary: Box<Account<'info, TagDictionaryPda>>,
+    /// CHECK: The instructions sysvar, needed when writing through CPI to a
+    /// filesystem that limits its CPI callers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetXattr<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_files.bump)]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
+    #[account(seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_directories.bump)]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account(
+        mut, // When the path names a file
+        seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = file_arena.bump
+    )]
+    pub file_arena: Box<Account<'info, FileArenaPda>>,
+    #[account(
+        mut, // When the path names a directory
+        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = dir_arena.bump
+    )]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    /// CHECK: The instructions sysvar, needed when writing through CPI to a
+    /// filesystem that limits its CPI callers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(path: String, object_id: u64)]
+pub struct InitDescription<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_files.bump)]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
+    #[account(seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_directories.bump)]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account(seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()], bump = dir_arena.bump)]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    #[account(
+        init,
+        payer = authority,
+        space = DESCRIPTION_PDA_SPACE,
+        seeds = [b"description".as_ref(), walrusfs_root.key().as_ref(), object_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub description: Box<Account<'info, DescriptionPda>>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: The instructions sysvar, needed when writing through CPI to a
+    /// filesystem that limits its CPI callers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetDescription<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_files.bump)]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
+    #[account(seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_directories.bump)]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account(
+        mut, // has_description flag when the path names a file
+        seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = file_arena.bump
+    )]
+    pub file_arena: Box<Account<'info, FileArenaPda>>,
+    #[account(
+        mut, // has_description flag when the path names a directory
+        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = dir_arena.bump
+    )]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    #[account(
+        mut,
+        seeds = [b"description".as_ref(), walrusfs_root.key().as_ref(), description.object_id.to_le_bytes().as_ref()],
+        bump = description.bump
+    )]
+    pub description: Box<Account<'info, DescriptionPda>>,
+    /// CHECK: The instructions sysvar, needed when writing through CPI to a
+    /// filesystem that limits its CPI callers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct PinEntry<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_files.bump)]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
+    #[account(seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_directories.bump)]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account(
+        mut, // When the path names a file
+        seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = file_arena.bump
+    )]
+    pub file_arena: Box<Account<'info, FileArenaPda>>,
+    #[account(
+        mut, // When the path names a directory
+        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = dir_arena.bump
+    )]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    /// CHECK: The instructions sysvar, needed when writing through CPI to a
+    /// filesystem that limits its CPI callers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Chmod<'info> {
+    pub authority: Signer<'info>, // An fs authority, or the owner of the file being changed
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_files.bump)]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
+    #[account(seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_directories.bump)]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account(
+        mut, // When the path names a file
+        seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = file_arena.bump
+    )]
+    pub file_arena: Box<Account<'info, FileArenaPda>>,
+    #[account(
+        mut, // When the path names a directory
+        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = dir_arena.bump
+    )]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    /// CHECK: The instructions sysvar, needed when writing through CPI to a
+    /// filesystem that limits its CPI callers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Chgrp<'info> {
+    pub authority: Signer<'info>, // As for chmod
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_files.bump)]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
+    #[account(seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_directories.bump)]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account(
+        mut, // When the path names a file
+        seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = file_arena.bump
+    )]
+    pub file_arena: Box<Account<'info, FileArenaPda>>,
+    #[account(
+        mut, // When the path names a directory
+        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = dir_arena.bump
+    )]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    #[account( // The new group; omitted to clear it
+        seeds = [b"group".as_ref(), walrusfs_root.key().as_ref(), group.name.as_bytes()],
+        bump = group.bump
+    )]
+    pub group: Option<Box<Account<'info, GroupPda>>>,
+    /// CHECK: The instructions sysvar, needed when writing through CPI to a
+    /// filesystem that limits its CPI callers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetMount<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_directories.bump)]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account(
+        mut,
+        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = dir_arena.bump
+    )]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    /// CHECK: The instructions sysvar, needed when writing through CPI to a
+    /// filesystem that limits its CPI callers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MoveDir<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        mut, // Source or destination parent may be the root
+        seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()],
+        bump = root_children_directories.bump
+    )]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account(
+        mut,
+        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = dir_arena.bump
+    )]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    /// CHECK: The instructions sysvar, needed when writing through CPI to a
+    /// filesystem that limits its CPI callers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CopyDir<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        mut, // Mutable if copying into root
+        seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()],
+        bump = root_children_directories.bump
+    )]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account(
+        mut,
+        seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = file_arena.bump
+    )]
+    pub file_arena: Box<Account<'info, FileArenaPda>>,
+    #[account(
+        mut,
+        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = dir_arena.bump
+    )]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    /// CHECK: The instructions sysvar, needed when writing through CPI to a
+    /// filesystem that limits its CPI callers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+// Specific read operations will use the ReadUserFileSystem context
+#[derive(Accounts)]
+pub struct ListDir<'info> {
+    // Inherits structure from ReadUserFileSystem
+    /// CHECK: Owner of the filesystem.
+    pub owner: AccountInfo<'info>,
+    #[account(seeds = [b"walrusfs_root".as_ref(), owner.key().as_ref(), walrusfs_root.seed_name.as_bytes()], bump = walrusfs_root.bump)]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_files.bump)]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
+    #[account(seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_directories.bump)]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account(seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()], bump = file_arena.bump)]
+    pub file_arena: Box<Account<'info, FileArenaPda>>,
+    #[account(seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()], bump = dir_arena.bump)]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    #[account(seeds = [b"tag_dictionary".as_ref(), walrusfs_root.key().as_ref()], bump = tag_dictionary.bump)]
+    pub tag_dictionary: Box<Account<'info, TagDictionaryPda>>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateEpoch<'info> {
+    pub authority: Signer<'info>, // The owner of this filesystem instance
+    #[account(
+        mut,
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthorityTransfer<'info> {
+    pub new_authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateRootSettings<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(delegate: Pubkey)]
+pub struct GrantWriter<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        init,
+        payer = authority,
+        space = WRITER_GRANT_PDA_SPACE,
+        seeds = [b"writer_grant".as_ref(), walrusfs_root.key().as_ref(), delegate.as_ref()],
+        bump
+    )]
+    pub writer_grant: Box<Account<'info, WriterGrantPda>>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(delegate: Pubkey)]
+pub struct RevokeWriter<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"writer_grant".as_ref(), walrusfs_root.key().as_ref(), delegate.as_ref()],
+        bump = writer_grant.bump
+    )]
+    pub writer_grant: Box<Account<'info, WriterGrantPda>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(grantee: Pubkey)]
+pub struct GrantReader<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        init,
+        payer = authority,
+        space = READ_GRANT_PDA_SPACE,
+        seeds = [b"read_grant".as_ref(), walrusfs_root.key().as_ref(), grantee.as_ref()],
+        bump
+    )]
+    pub read_grant: Box<Account<'info, ReadGrantPda>>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(grantee: Pubkey)]
+pub struct RevokeReader<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"read_grant".as_ref(), walrusfs_root.key().as_ref(), grantee.as_ref()],
+        bump = read_grant.bump
+    )]
+    pub read_grant: Box<Account<'info, ReadGrantPda>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(grantee: Pubkey)]
+pub struct CanRead<'info> {
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        seeds = [b"read_grant".as_ref(), walrusfs_root.key().as_ref(), grantee.as_ref()],
+        bump = read_grant.bump
+    )]
+    pub read_grant: Option<Box<Account<'info, ReadGrantPda>>>,
+}
+
+#[derive(Accounts)]
+#[instruction(share_key: Pubkey)]
+pub struct CreateShare<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        init,
+        payer = authority,
+        space = SHARE_GRANT_PDA_SPACE,
+        seeds = [b"share".as_ref(), walrusfs_root.key().as_ref(), share_key.as_ref()],
+        bump
+    )]
+    pub share_grant: Box<Account<'info, ShareGrantPda>>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(capability_key: Pubkey)]
+pub struct MintCapability<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        init,
+        payer = authority,
+        space = READ_CAPABILITY_PDA_SPACE,
+        seeds = [b"capability".as_ref(), walrusfs_root.key().as_ref(), capability_key.as_ref()],
+        bump
+    )]
+    pub capability: Box<Account<'info, ReadCapabilityPda>>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(capability_key: Pubkey)]
+pub struct ConsumeCapability<'info> {
+    pub consumer: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        mut,
+        seeds = [b"capability".as_ref(), walrusfs_root.key().as_ref(), capability_key.as_ref()],
+        bump = capability.bump
+    )]
+    pub capability: Box<Account<'info, ReadCapabilityPda>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(capability_key: Pubkey)]
+pub struct RevokeCapability<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"capability".as_ref(), walrusfs_root.key().as_ref(), capability_key.as_ref()],
+        bump = capability.bump
+    )]
+    pub capability: Box<Account<'info, ReadCapabilityPda>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(session_pubkey: Pubkey)]
+pub struct CreateSessionKey<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        init,
+        payer = owner,
+        space = SESSION_KEY_PDA_SPACE,
+        seeds = [b"session_key".as_ref(), walrusfs_root.key().as_ref(), session_pubkey.as_ref()],
+        bump
+    )]
+    pub session_key: Box<Account<'info, SessionKeyPda>>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(session_pubkey: Pubkey)]
+pub struct RevokeSessionKey<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"session_key".as_ref(), walrusfs_root.key().as_ref(), session_pubkey.as_ref()],
+        bump = session_key.bump,
+        has_one = owner @ WalrusFsError::Unauthorized
+    )]
+    pub session_key: Box<Account<'info, SessionKeyPda>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(signer: Pubkey)]
+pub struct InitPermitNonce<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        init,
+        payer = payer,
+        space = PERMIT_NONCE_PDA_SPACE,
+        seeds = [b"permit_nonce".as_ref(), walrusfs_root.key().as_ref(), signer.as_ref()],
+        bump
+    )]
+    pub permit_nonce: Box<Account<'info, PermitNoncePda>>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(share_key: Pubkey)]
+pub struct RevokeShare<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"share".as_ref(), walrusfs_root.key().as_ref(), share_key.as_ref()],
+        bump = share_grant.bump
+    )]
+    pub share_grant: Box<Account<'info, ShareGrantPda>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(share_key: Pubkey)]
+pub struct CheckShare<'info> {
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        seeds = [b"share".as_ref(), walrusfs_root.key().as_ref(), share_key.as_ref()],
+        bump = share_grant.bump
+    )]
+    pub share_grant: Box<Account<'info, ShareGrantPda>>,
+}
+
+#[derive(Accounts)]
+#[instruction(name: String)]
+pub struct CreateGroup<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        init,
+        payer = authority,
+        space = GROUP_PDA_SPACE,
+        seeds = [b"group".as_ref(), walrusfs_root.key().as_ref(), name.as_bytes()],
+        bump
+    )]
+    pub group: Box<Account<'info, GroupPda>>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ManageGroup<'info> {
+    pub authority: Signer<'info>, // An fs authority or the group's admin
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        mut,
+        seeds = [b"group".as_ref(), walrusfs_root.key().as_ref(), group.name.as_bytes()],
+        bump = group.bump,
+        constraint = group.admin == authority.key() || is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized
+    )]
+    pub group: Box<Account<'info, GroupPda>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetCollation<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_files.bump)]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
+    #[account(seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_directories.bump)]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account(
+        mut,
+        seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = file_arena.bump
+    )]
+    pub file_arena: Box<Account<'info, FileArenaPda>>,
+    #[account(
+        mut,
+        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = dir_arena.bump
+    )]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    /// CHECK: The instructions sysvar, needed when writing through CPI to a
+    /// filesystem that limits its CPI callers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AddFile<'info> {
+    pub authority: Signer<'info>, // Checked per path: file owners may replace or remove their files
+    #[account(
+        mut,
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        mut,
+        seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()],
+        bump = root_children_files.bump
+    )]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
+    #[account( // Read-only for traversal, but derived from user-specific root
+        seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()],
+        bump = root_children_directories.bump
+    )]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account(
+        mut,
+        seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = file_arena.bump
+    )]
+    pub file_arena: Box<Account<'info, FileArenaPda>>,
+    #[account( // Mutable because a parent DirObject's children_files list might be updated
+        mut, 
+        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = dir_arena.bump
+    )]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    #[account(
+        mut,
+        seeds = [b"tag_dictionary".as_ref(), walrusfs_root.key().as_ref()],
+        bump = tag_dictionary.bump
+    )]
+    pub tag_dictionary: Box<Account<'info, TagDictionaryPda>>,
+    #[account( // Required only when overwriting drops a deletable blob's last reference
+        mut,
+        seeds = [b"pending_blob_deletes".as_ref(), walrusfs_root.key().as_ref()],
+        bump = pending_blob_deletes.bump
+    )]
+    pub pending_blob_deletes: Option<Box<Account<'info, PendingBlobDeletesPda>>>,
+    #[account( // Passed by delegates writing under a writer grant
+        seeds = [b"writer_grant".as_ref(), walrusfs_root.key().as_ref(), authority.key().as_ref()],
+        bump = writer_grant.bump
+    )]
+    pub writer_grant: Option<Box<Account<'info, WriterGrantPda>>>,
+    #[account( // Passed by members writing through an object's group
+        seeds = [b"group".as_ref(), walrusfs_root.key().as_ref(), group.name.as_bytes()],
+        bump = group.bump
+    )]
+    pub group: Option<Box<Account<'info, GroupPda>>>,
+    #[account( // Passed when signing with a session key
+        seeds = [b"session_key".as_ref(), walrusfs_root.key().as_ref(), authority.key().as_ref()],
+        bump = session_key.bump
+    )]
+    pub session_key: Option<Box<Account<'info, SessionKeyPda>>>,
+    #[account( // Passed with instructions by relayers of a permit
+        mut,
+        seeds = [b"permit_nonce".as_ref(), walrusfs_root.key().as_ref(), permit_nonce.signer.as_ref()],
+        bump = permit_nonce.bump
+    )]
+    pub permit_nonce: Option<Box<Account<'info, PermitNoncePda>>>,
+    /// CHECK: The instructions sysvar, holding a permit's Ed25519 verification or
+    /// naming the CPI caller.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Stat<'info> {
+    // Inherits structure from ReadUserFileSystem
+    /// CHECK: Owner of the filesystem.
+    pub owner: AccountInfo<'info>,
+    #[account(seeds = [b"walrusfs_root".as_ref(), owner.key().as_ref(), walrusfs_root.seed_name.as_bytes()], bump = walrusfs_root.bump)]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_files.bump)]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
+    #[account(seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_directories.bump)]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account(seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()], bump = file_arena.bump)]
+    pub file_arena: Box<Account<'info, FileArenaPda>>,
+    #[account(seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()], bump = dir_arena.bump)]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    #[account( // Optional; when it belongs to the entry, stat returns its text
+        seeds = [b"description".as_ref(), walrusfs_root.key().as_ref(), description.object_id.to_le_bytes().as_ref()],
+        bump = description.bump
+    )]
+    pub description: Option<Box<Account<'info, DescriptionPda>>>,
+    #[account(seeds = [b"tag_dictionary".as_ref(), walrusfs_root.key().as_ref()], bump = tag_dictionary.bump)]
+    pub tag_dictionary: Box<Account<'info, TagDictionaryPda>>,
+}
+
+#[derive(Accounts)]
+pub struct RenameFile<'info> {
+    pub authority: Signer<'info>, // An fs authority, the file's owner, a group member, a delegate with a writer grant, or a session key
+    #[account(
+        // Not mutable itself, but needed for deriving other PDA keys
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        mut, // Children list at root could change
+        seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()],
+        bump = root_children_files.bump
+    )]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
+    #[account( // For path traversal only
+        seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()],
+        bump = root_children_directories.bump
+    )]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account( // File arena is mutable as the renamed file's sort key changes
+        mut,
+        seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = file_arena.bump
+    )]
+    pub file_arena: Box<Account<'info, FileArenaPda>>,
+    #[account( // Dir arena is mutable as children_files within a DirObject might change
+        mut,
+        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = dir_arena.bump
+    )]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    #[account( // Passed by delegates writing under a writer grant
+        seeds = [b"writer_grant".as_ref(), walrusfs_root.key().as_ref(), authority.key().as_ref()],
+        bump = writer_grant.bump
+    )]
+    pub writer_grant: Option<Box<Account<'info, WriterGrantPda>>>,
+    #[account( // Passed by members writing through an object's group
+        seeds = [b"group".as_ref(), walrusfs_root.key().as_ref(), group.name.as_bytes()],
+        bump = group.bump
+    )]
+    pub group: Option<Box<Account<'info, GroupPda>>>,
+    #[account( // Passed when signing with a session key
+        seeds = [b"session_key".as_ref(), walrusfs_root.key().as_ref(), authority.key().as_ref()],
+        bump = session_key.bump
+    )]
+    pub session_key: Option<Box<Account<'info, SessionKeyPda>>>,
+    /// CHECK: The instructions sysvar, needed when writing through CPI to a
+    /// filesystem that limits its CPI callers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct LinkFile<'info> {
+    pub authority: Signer<'info>, // An fs authority, the file's owner, a group member, a delegate with a writer grant, or a session key
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        mut, // Mutable if linking into root
+        seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()],
+        bump = root_children_files.bump
+    )]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
+    #[account( // For path traversal only
+        seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()],
+        bump = root_children_directories.bump
+    )]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account( // File arena is mutable as the link count changes
+        mut,
+        seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = file_arena.bump
+    )]
+    pub file_arena: Box<Account<'info, FileArenaPda>>,
+    #[account( // Mutable if linking into a subdirectory
+        mut,
+        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = dir_arena.bump
+    )]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    #[account( // Passed by delegates writing under a writer grant
+        seeds = [b"writer_grant".as_ref(), walrusfs_root.key().as_ref(), authority.key().as_ref()],
+        bump = writer_grant.bump
+    )]
+    pub writer_grant: Option<Box<Account<'info, WriterGrantPda>>>,
+    #[account( // Passed by members writing through an object's group
+        seeds = [b"group".as_ref(), walrusfs_root.key().as_ref(), group.name.as_bytes()],
+        bump = group.bump
+    )]
+    pub group: Option<Box<Account<'info, GroupPda>>>,
+    #[account( // Passed when signing with a session key
+        seeds = [b"session_key".as_ref(), walrusfs_root.key().as_ref(), authority.key().as_ref()],
+        bump = session_key.bump
+    )]
+    pub session_key: Option<Box<Account<'info, SessionKeyPda>>>,
+    /// CHECK: The instructions sysvar, needed when writing through CPI to a
+    /// filesystem that limits its CPI callers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MoveFile<'info> {
+    pub authority: Signer<'info>, // An fs authority, the file's owner, a group member, a delegate with a writer grant, or a session key
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        mut, // Source or destination may be the root
+        seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()],
+        bump = root_children_files.bump
+    )]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
+    #[account( // For path traversal only
+        seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()],
+        bump = root_children_directories.bump
+    )]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account( // File arena is mutable as the moved file's sort key changes
+        mut,
+        seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = file_arena.bump
+    )]
+    pub file_arena: Box<Account<'info, FileArenaPda>>,
+    #[account( // Both parents' children_files may live here
+        mut,
+        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = dir_arena.bump
+    )]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    #[account( // Passed by delegates writing under a writer grant
+        seeds = [b"writer_grant".as_ref(), walrusfs_root.key().as_ref(), authority.key().as_ref()],
+        bump = writer_grant.bump
+    )]
+    pub writer_grant: Option<Box<Account<'info, WriterGrantPda>>>,
+    #[account( // Passed by members writing through an object's group
+        seeds = [b"group".as_ref(), walrusfs_root.key().as_ref(), group.name.as_bytes()],
+        bump = group.bump
+    )]
+    pub group: Option<Box<Account<'info, GroupPda>>>,
+    #[account( // Passed when signing with a session key
+        seeds = [b"session_key".as_ref(), walrusfs_root.key().as_ref(), authority.key().as_ref()],
+        bump = session_key.bump
+    )]
+    pub session_key: Option<Box<Account<'info, SessionKeyPda>>>,
+    /// CHECK: The instructions sysvar, needed when writing through CPI to a
+    /// filesystem that limits its CPI callers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DeleteFile<'info> {
+    pub authority: Signer<'info>, // Checked per path: file owners may replace or remove their files
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        mut,
+        seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()],
+        bump = root_children_files.bump
+    )]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
+    #[account( // For path traversal
+        seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()],
+        bump = root_children_directories.bump
+    )]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account(
+        mut,
+        seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = file_arena.bump
+    )]
+    pub file_arena: Box<Account<'info, FileArenaPda>>,
+    #[account( // Dir arena is mutable as children_files within a DirObject might change
+        mut,
+        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = dir_arena.bump
+    )]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    #[account( // Required only when a deletable blob loses its last reference
+        mut,
+        seeds = [b"pending_blob_deletes".as_ref(), walrusfs_root.key().as_ref()],
+        bump = pending_blob_deletes.bump
+    )]
+    pub pending_blob_deletes: Option<Box<Account<'info, PendingBlobDeletesPda>>>,
+    #[account( // Required only when to_trash is set
+        mut,
+        seeds = [b"trash".as_ref(), walrusfs_root.key().as_ref()],
+        bump = trash.bump
+    )]
+    pub trash: Option<Box<Account<'info, TrashPda>>>,
+    #[account( // Passed by delegates writing under a writer grant
+        seeds = [b"writer_grant".as_ref(), walrusfs_root.key().as_ref(), authority.key().as_ref()],
+        bump = writer_grant.bump
+    )]
+    pub writer_grant: Option<Box<Account<'info, WriterGrantPda>>>,
+    #[account( // Passed by members writing through an object's group
+        seeds = [b"group".as_ref(), walrusfs_root.key().as_ref(), group.name.as_bytes()],
+        bump = group.bump
+    )]
+    pub group: Option<Box<Account<'info, GroupPda>>>,
+    #[account( // Passed when signing with a session key
+        seeds = [b"session_key".as_ref(), walrusfs_root.key().as_ref(), authority.key().as_ref()],
+        bump = session_key.bump
+    )]
+    pub session_key: Option<Box<Account<'info, SessionKeyPda>>>,
+    #[account( // Passed with instructions by relayers of a permit
+        mut,
+        seeds = [b"permit_nonce".as_ref(), walrusfs_root.key().as_ref(), permit_nonce.signer.as_ref()],
+        bump = permit_nonce.bump
+    )]
+    pub permit_nonce: Option<Box<Account<'info, PermitNoncePda>>>,
+    /// CHECK: The instructions sysvar, holding a permit's Ed25519 verification or
+    /// naming the CPI caller.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DeleteDir<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account( // Read-only; only needed to compute the root's version
+        seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()],
+        bump = root_children_files.bump
+    )]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
+    #[account(
+        mut, // For deleting dir at root or for path traversal if parent is root
+        seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()],
+        bump = root_children_directories.bump
+    )]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account(
+        mut,
+        seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = file_arena.bump
+    )]
+    pub file_arena: Box<Account<'info, FileArenaPda>>,
+    #[account(
+        mut,
+        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = dir_arena.bump
+    )]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    #[account( // Required only when a deletable blob loses its last reference
+        mut,
+        seeds = [b"pending_blob_deletes".as_ref(), walrusfs_root.key().as_ref()],
+        bump = pending_blob_deletes.bump
+    )]
+    pub pending_blob_deletes: Option<Box<Account<'info, PendingBlobDeletesPda>>>,
+    #[account( // Required only when to_trash is set
+        mut,
+        seeds = [b"trash".as_ref(), walrusfs_root.key().as_ref()],
+        bump = trash.bump
+    )]
+    pub trash: Option<Box<Account<'info, TrashPda>>>,
+    /// CHECK: The instructions sysvar, needed when writing through CPI to a
+    /// filesystem that limits its CPI callers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DeleteDirBegin<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account( // Read-only; only needed to compute the root's version
+        seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()],
+        bump = root_children_files.bump
+    )]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
+    #[account(
+        mut, // For detaching a dir at root
+        seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()],
+        bump = root_children_directories.bump
+    )]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account( // Read-only; checked for sealed files before anything is detached
+        seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = file_arena.bump
+    )]
+    pub file_arena: Box<Account<'info, FileArenaPda>>,
+    #[account(
+        mut,
+        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = dir_arena.bump
+    )]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    #[account(
+        mut,
+        seeds = [b"deletion_cursor".as_ref(), walrusfs_root.key().as_ref()],
+        bump = deletion_cursor.bump
+    )]
+    pub deletion_cursor: Box<Account<'info, DeletionCursorPda>>,
+    /// CHECK: The instructions sysvar, needed when writing through CPI to a
+    /// filesystem that limits its CPI callers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DeleteDirContinue<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        mut,
+        seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = file_arena.bump
+    )]
+    pub file_arena: Box<Account<'info, FileArenaPda>>,
+    #[account(
+        mut,
+        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = dir_arena.bump
+    )]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    #[account(
+        mut,
+        seeds = [b"deletion_cursor".as_ref(), walrusfs_root.key().as_ref()],
+        bump = deletion_cursor.bump
+    )]
+    pub deletion_cursor: Box<Account<'info, DeletionCursorPda>>,
+    #[account( // Required only when a deletable blob loses its last reference
+        mut,
+        seeds = [b"pending_blob_deletes".as_ref(), walrusfs_root.key().as_ref()],
+        bump = pending_blob_deletes.bump
+    )]
+    pub pending_blob_deletes: Option<Box<Account<'info, PendingBlobDeletesPda>>>,
+    /// CHECK: The instructions sysvar, needed when writing through CPI to a
+    /// filesystem that limits its CPI callers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Restore<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        mut,
+        seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()],
+        bump = root_children_files.bump
+    )]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
+    #[account(
+        mut,
+        seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()],
+        bump = root_children_directories.bump
+    )]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account(seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()], bump = file_arena.bump)]
+    pub file_arena: Box<Account<'info, FileArenaPda>>, // For restored file sizes
+    #[account(
+        mut,
+        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = dir_arena.bump
+    )]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    #[account(
+        mut,
+        seeds = [b"trash".as_ref(), walrusfs_root.key().as_ref()],
+        bump = trash.bump
+    )]
+    pub trash: Box<Account<'info, TrashPda>>,
+    /// CHECK: The instructions sysvar, needed when writing through CPI to a
+    /// filesystem that limits its CPI callers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct EmptyTrash<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        mut,
+        seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = file_arena.bump
+    )]
+    pub file_arena: Box<Account<'info, FileArenaPda>>,
+    #[account(
+        mut,
+        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = dir_arena.bump
+    )]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    #[account(
+        mut,
+        seeds = [b"trash".as_ref(), walrusfs_root.key().as_ref()],
+        bump = trash.bump
+    )]
+    pub trash: Box<Account<'info, TrashPda>>,
+    #[account( // Required only when a deletable blob loses its last reference
+        mut,
+        seeds = [b"pending_blob_deletes".as_ref(), walrusfs_root.key().as_ref()],
+        bump = pending_blob_deletes.bump
+    )]
+    pub pending_blob_deletes: Option<Box<Account<'info, PendingBlobDeletesPda>>>,
+    /// CHECK: The instructions sysvar, needed when writing through CPI to a
+    /// filesystem that limits its CPI callers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct GetDirAll<'info> {
+    // Inherits structure from ReadUserFileSystem
+    /// CHECK: Owner of the filesystem.
+    pub owner: AccountInfo<'info>,
+    #[account(seeds = [b"walrusfs_root".as_ref(), owner.key().as_ref(), walrusfs_root.seed_name.as_bytes()], bump = walrusfs_root.bump)]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_files.bump)]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
+    #[account(seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_directories.bump)]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account(seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()], bump = file_arena.bump)]
+    pub file_arena: Box<Account<'info, FileArenaPda>>,
+    #[account(seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()], bump = dir_arena.bump)]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    #[account(seeds = [b"tag_dictionary".as_ref(), walrusfs_root.key().as_ref()], bump = tag_dictionary.bump)]
+    pub tag_dictionary: Box<Account<'info, TagDictionaryPda>>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateFileMeta<'info> {
+    pub authority: Signer<'info>, // An fs authority, the file's owner, a group member, a delegate with a writer grant, or a session key
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_files.bump)]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
+    #[account(seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_directories.bump)]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account(
+        mut,
+        seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = file_arena.bump
+    )]
+    pub file_arena: Box<Account<'info, FileArenaPda>>,
+    #[account(
+        mut, // Size changes update subtree_bytes
+        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = dir_arena.bump
+    )]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    #[account(
+        mut,
+        seeds = [b"tag_dictionary".as_ref(), walrusfs_root.key().as_ref()],
+        bump = tag_dictionary.bump
+    )]
+    pub tag_dictionary: Box<Account<'info, TagDictionaryPda>>,
+    #[account( // Passed by delegates writing under a writer grant
+        seeds = [b"writer_grant".as_ref(), walrusfs_root.key().as_ref(), authority.key().as_ref()],
+        bump = writer_grant.bump
+    )]
+    pub writer_grant: Option<Box<Account<'info, WriterGrantPda>>>,
+    #[account( // Passed by members writing through an object's group
+        seeds = [b"group".as_ref(), walrusfs_root.key().as_ref(), group.name.as_bytes()],
+        bump = group.bump
+    )]
+    pub group: Option<Box<Account<'info, GroupPda>>>,
+    #[account( // Passed when signing with a session key
+        seeds = [b"session_key".as_ref(), walrusfs_root.key().as_ref(), authority.key().as_ref()],
+        bump = session_key.bump
+    )]
+    pub session_key: Option<Box<Account<'info, SessionKeyPda>>>,
+    /// CHECK: The instructions sysvar, needed when writing through CPI to a
+    /// filesystem that limits its CPI callers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct BuyFile<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    /// CHECK: The file's current owner, who is paid; checked against the file.
+    #[account(mut)]
+    pub seller: AccountInfo<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_files.bump)]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
+    #[account(seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_directories.bump)]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account(
+        mut,
+        seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = file_arena.bump
+    )]
+    pub file_arena: Box<Account<'info, FileArenaPda>>,
+    #[account(seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()], bump = dir_arena.bump)]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    /// CHECK: The instructions sysvar, needed when writing through CPI to a
+    /// filesystem that limits its CPI callers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateFile<'info> {
+    pub authority: Signer<'info>, // An fs authority, the file's owner, a group member, a delegate with a writer grant, or a session key
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_files.bump)]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
+    #[account(seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_directories.bump)]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account(
+        mut,
+        seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = file_arena.bump
+    )]
+    pub file_arena: Box<Account<'info, FileArenaPda>>,
+    #[account(
+        mut, // Size changes update subtree_bytes
+        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = dir_arena.bump
+    )]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    #[account( // Required only when a deletable blob loses its last reference
+        mut,
+        seeds = [b"pending_blob_deletes".as_ref(), walrusfs_root.key().as_ref()],
+        bump = pending_blob_deletes.bump
+    )]
+    pub pending_blob_deletes: Option<Box<Account<'info, PendingBlobDeletesPda>>>,
+    #[account( // Passed by delegates writing under a writer grant
+        seeds = [b"writer_grant".as_ref(), walrusfs_root.key().as_ref(), authority.key().as_ref()],
+        bump = writer_grant.bump
+    )]
+    pub writer_grant: Option<Box<Account<'info, WriterGrantPda>>>,
+    #[account( // Passed by members writing through an object's group
+        seeds = [b"group".as_ref(), walrusfs_root.key().as_ref(), group.name.as_bytes()],
+        bump = group.bump
+    )]
+    pub group: Option<Box<Account<'info, GroupPda>>>,
+    #[account( // Passed when signing with a session key
+        seeds = [b"session_key".as_ref(), walrusfs_root.key().as_ref(), authority.key().as_ref()],
+        bump = session_key.bump
+    )]
+    pub session_key: Option<Box<Account<'info, SessionKeyPda>>>,
+    /// CHECK: The instructions sysvar, needed when writing through CPI to a
+    /// filesystem that limits its CPI callers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeBlobDeleteQueue<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        init,
+        payer = authority,
+        space = PENDING_BLOB_DELETES_PDA_SPACE,
+        seeds = [b"pending_blob_deletes".as_ref(), walrusfs_root.key().as_ref()],
+        bump
+    )]
+    pub pending_blob_deletes: Box<Account<'info, PendingBlobDeletesPda>>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeSnapshotIndex<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        init,
+        payer = authority,
+        space = SNAPSHOT_INDEX_PDA_SPACE,
+        seeds = [b"snapshot_index".as_ref(), walrusfs_root.key().as_ref()],
+        bump
+    )]
+    pub snapshot_index: Box<Account<'info, SnapshotIndexPda>>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(path: String, name: String)]
+pub struct CreateSnapshot<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_files.bump)]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
+    #[account(seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_directories.bump)]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account(seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()], bump = file_arena.bump)]
+    pub file_arena: Box<Account<'info, FileArenaPda>>,
+    #[account(seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()], bump = dir_arena.bump)]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    #[account(
+        mut,
+        seeds = [b"snapshot_index".as_ref(), walrusfs_root.key().as_ref()],
+        bump = snapshot_index.bump
+    )]
+    pub snapshot_index: Box<Account<'info, SnapshotIndexPda>>,
+    #[account(
+        init,
+        payer = authority,
+        space = SNAPSHOT_PDA_SPACE,
+        seeds = [b"snapshot".as_ref(), walrusfs_root.key().as_ref(), name.as_bytes()],
+        bump
+    )]
+    pub snapshot: Box<Account<'info, SnapshotPda>>,
+    // Holds the snapshot's blob pins
+    #[account(
+        mut,
+        seeds = [b"pending_blob_deletes".as_ref(), walrusfs_root.key().as_ref()],
+        bump = pending_blob_deletes.bump
+    )]
+    pub pending_blob_deletes: Box<Account<'info, PendingBlobDeletesPda>>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(name: String)]
+pub struct DeleteSnapshot<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()], bump = file_arena.bump)]
+    pub file_arena: Box<Account<'info, FileArenaPda>>,
+    #[account(
+        mut,
+        seeds = [b"snapshot_index".as_ref(), walrusfs_root.key().as_ref()],
+        bump = snapshot_index.bump
+    )]
+    pub snapshot_index: Box<Account<'info, SnapshotIndexPda>>,
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"snapshot".as_ref(), walrusfs_root.key().as_ref(), name.as_bytes()],
+        bump = snapshot.bump
+    )]
+    pub snapshot: Box<Account<'info, SnapshotPda>>,
+    #[account(
+        mut,
+        seeds = [b"pending_blob_deletes".as_ref(), walrusfs_root.key().as_ref()],
+        bump = pending_blob_deletes.bump
+    )]
+    pub pending_blob_deletes: Box<Account<'info, PendingBlobDeletesPda>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ListSnapshots<'info> {
+    /// CHECK: Owner of the filesystem.
+    pub owner: AccountInfo<'info>,
+    #[account(seeds = [b"walrusfs_root".as_ref(), owner.key().as_ref(), walrusfs_root.seed_name.as_bytes()], bump = walrusfs_root.bump)]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(seeds = [b"snapshot_index".as_ref(), walrusfs_root.key().as_ref()], bump = snapshot_index.bump)]
+    pub snapshot_index: Box<Account<'info, SnapshotIndexPda>>,
+}
+
+#[derive(Accounts)]
+#[instruction(name: String)]
+pub struct ReadSnapshot<'info> {
+    /// CHECK: Owner of the filesystem.
+    pub owner: AccountInfo<'info>,
+    #[account(seeds = [b"walrusfs_root".as_ref(), owner.key().as_ref(), walrusfs_root.seed_name.as_bytes()], bump = walrusfs_root.bump)]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(seeds = [b"snapshot".as_ref(), walrusfs_root.key().as_ref(), name.as_bytes()], bump = snapshot.bump)]
+    pub snapshot: Box<Account<'info, SnapshotPda>>,
+}
+
+#[derive(Accounts)]
+#[instruction(base_name: String, other_name: String)]
+pub struct DiffSnapshots<'info> {
+    /// CHECK: Owner of the filesystem.
+    pub owner: AccountInfo<'info>,
+    #[account(seeds = [b"walrusfs_root".as_ref(), owner.key().as_ref(), walrusfs_root.seed_name.as_bytes()], bump = walrusfs_root.bump)]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(seeds = [b"snapshot".as_ref(), walrusfs_root.key().as_ref(), base_name.as_bytes()], bump = base_snapshot.bump)]
+    pub base_snapshot: Box<Account<'info, SnapshotPda>>,
+    #[account(seeds = [b"snapshot".as_ref(), walrusfs_root.key().as_ref(), other_name.as_bytes()], bump = other_snapshot.bump)]
+    pub other_snapshot: Box<Account<'info, SnapshotPda>>,
+}
+
+#[derive(Accounts)]
+#[instruction(name: String)]
+pub struct DiffSnapshotLive<'info> {
+    /// CHECK: Owner of the filesystem.
+    pub owner: AccountInfo<'info>,
+    #[account(seeds = [b"walrusfs_root".as_ref(), owner.key().as_ref(), walrusfs_root.seed_name.as_bytes()], bump = walrusfs_root.bump)]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_files.bump)]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
+    #[account(seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_directories.bump)]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account(seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()], bump = file_arena.bump)]
+    pub file_arena: Box<Account<'info, FileArenaPda>>,
+    #[account(seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()], bump = dir_arena.bump)]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    #[account(seeds = [b"snapshot".as_ref(), walrusfs_root.key().as_ref(), name.as_bytes()], bump = snapshot.bump)]
+    pub snapshot: Box<Account<'info, SnapshotPda>>,
+}
+
+#[derive(Accounts)]
+#[instruction(snapshot_name: String)]
+pub struct PublishClone<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(seeds = [b"snapshot".as_ref(), walrusfs_root.key().as_ref(), snapshot_name.as_bytes()], bump = snapshot.bump)]
+    pub snapshot: Box<Account<'info, SnapshotPda>>,
+    #[account(
+        init,
+        payer = authority,
+        space = PUBLISHED_CLONE_PDA_SPACE,
+        seeds = [b"published".as_ref(), walrusfs_root.key().as_ref(), snapshot_name.as_bytes()],
+        bump
+    )]
+    pub published_clone: Box<Account<'info, PublishedClonePda>>,
+    // Holds the clone's blob pins
+    #[account(
+        mut,
+        seeds = [b"pending_blob_deletes".as_ref(), walrusfs_root.key().as_ref()],
+        bump = pending_blob_deletes.bump
+    )]
+    pub pending_blob_deletes: Box<Account<'info, PendingBlobDeletesPda>>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(snapshot_name: String)]
+pub struct ReadPublished<'info> {
+    /// CHECK: Owner of the filesystem.
+    pub owner: AccountInfo<'info>,
+    #[account(seeds = [b"walrusfs_root".as_ref(), owner.key().as_ref(), walrusfs_root.seed_name.as_bytes()], bump = walrusfs_root.bump)]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(seeds = [b"published".as_ref(), walrusfs_root.key().as_ref(), snapshot_name.as_bytes()], bump = published_clone.bump)]
+    pub published_clone: Box<Account<'info, PublishedClonePda>>,
 }
 
-// --- Accounts Structs for Instructions (Unchanged Structurally, but types inside accounts are modified) ---
-// --- (All `#[derive(Accounts)]` structs remain as they were, definitions are not repeated for brevity) ---
-// Example:
 #[derive(Accounts)]
-pub struct InitializeWalrusfs<'info> {
+pub struct InitializeTrash<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
     #[account(
-        init,
-        payer = payer,
-        space = WALRUSFS_ROOT_PDA_SPACE,
-        seeds = [b"walrusfs_root".as_ref(), payer.key().as_ref()],
-        bump
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized
     )]
     pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
     #[account(
         init,
-        payer = payer,
-        space = CHILDREN_PDA_SPACE,
-        seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()],
+        payer = authority,
+        space = TRASH_PDA_SPACE,
+        seeds = [b"trash".as_ref(), walrusfs_root.key().as_ref()],
         bump
     )]
-    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>, // Type inside uses Vec now
+    pub trash: Box<Account<'info, TrashPda>>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
     #[account(
-        init,
-        payer = payer,
-        space = CHILDREN_PDA_SPACE,
-        seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()],
-        bump
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
     )]
-    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>, // Type inside uses Vec
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeDeletionCursor<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
     #[account(
-        init,
-        payer = payer,
-        space = ARENA_PDA_SPACE,
-        seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()],
-        bump
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized
     )]
-    pub file_arena: Box<Account<'info, FileArenaPda>>, // Type inside uses Vec
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
     #[account(
         init,
-        payer = payer,
-        space = ARENA_PDA_SPACE,
-        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
+        payer = authority,
+        space = DELETION_CURSOR_PDA_SPACE,
+        seeds = [b"deletion_cursor".as_ref(), walrusfs_root.key().as_ref()],
         bump
     )]
-    pub dir_arena: Box<Account<'info, DirArenaPda>>, // Type inside uses Vec
-    #[account(mut)]
-    pub payer: Signer<'info>,
+    pub deletion_cursor: Box<Account<'info, DeletionCursorPda>>,
     pub system_program: Program<'info, System>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
 }
 
 #[derive(Accounts)]
-pub struct AddDir<'info> {
-    pub authority: Signer<'info>,
+pub struct MigrateFilesystem<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>, // Pays rent for the grown accounts
+    /// CHECK: May predate schema_version, so none of these load as typed accounts;
+    /// the seeds tie each one to the signer's filesystem.
+    #[account(mut, seeds = [b"walrusfs_root".as_ref(), authority.key().as_ref()], bump)]
+    pub walrusfs_root: UncheckedAccount<'info>,
+    /// CHECK: See walrusfs_root.
+    #[account(mut, seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()], bump)]
+    pub root_children_files: UncheckedAccount<'info>,
+    /// CHECK: See walrusfs_root.
+    #[account(mut, seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()], bump)]
+    pub root_children_directories: UncheckedAccount<'info>,
+    /// CHECK: See walrusfs_root.
+    #[account(mut, seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()], bump)]
+    pub file_arena: UncheckedAccount<'info>,
+    /// CHECK: See walrusfs_root.
+    #[account(mut, seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()], bump)]
+    pub dir_arena: UncheckedAccount<'info>,
     #[account(
         mut,
-        seeds = [b"walrusfs_root".as_ref(), authority.key().as_ref()],
-        bump = walrusfs_root.bump
-    )]
-    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
-    #[account( 
-        mut, // Mutable if adding to root
-        seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()],
-        bump = root_children_directories.bump
+        seeds = [b"tag_dictionary".as_ref(), walrusfs_root.key().as_ref()],
+        bump = tag_dictionary.bump
     )]
-    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    pub tag_dictionary: Box<Account<'info, TagDictionaryPda>>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
     #[account(
-        mut,
-        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
-        bump = dir_arena.bump
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
     )]
-    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    pub program_config: UncheckedAccount<'info>,
 }
 
 #[derive(Accounts)]
-pub struct RenameDir<'info> {
+pub struct InitializeTagDictionary<'info> {
+    #[account(mut)]
     pub authority: Signer<'info>,
+    /// CHECK: May predate schema_version; the seeds tie it to the signer.
+    #[account(seeds = [b"walrusfs_root".as_ref(), authority.key().as_ref()], bump)]
+    pub walrusfs_root: UncheckedAccount<'info>,
     #[account(
-        seeds = [b"walrusfs_root".as_ref(), authority.key().as_ref()],
-        bump = walrusfs_root.bump
+        init,
+        payer = authority,
+        space = TAG_DICTIONARY_PDA_SPACE,
+        seeds = [b"tag_dictionary".as_ref(), walrusfs_root.key().as_ref()],
+        bump
+    )]
+    pub tag_dictionary: Box<Account<'info, TagDictionaryPda>>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateSidecars<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>, // Pays rent for the grown accounts
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized
     )]
     pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
-    #[account( 
-        mut, // Could be renaming a dir in root, or a dir in a subdir (affecting dir_arena)
-        seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()],
-        bump = root_children_directories.bump
+    /// CHECK: May predate schema_version; the seeds tie it to this filesystem.
+    #[account(mut, seeds = [b"pending_blob_deletes".as_ref(), walrusfs_root.key().as_ref()], bump)]
+    pub pending_blob_deletes: Option<UncheckedAccount<'info>>,
+    /// CHECK: See pending_blob_deletes.
+    #[account(mut, seeds = [b"trash".as_ref(), walrusfs_root.key().as_ref()], bump)]
+    pub trash: Option<UncheckedAccount<'info>>,
+    /// CHECK: See pending_blob_deletes.
+    #[account(mut, seeds = [b"deletion_cursor".as_ref(), walrusfs_root.key().as_ref()], bump)]
+    pub deletion_cursor: Option<UncheckedAccount<'info>>,
+    /// CHECK: See pending_blob_deletes.
+    #[account(mut, seeds = [b"snapshot_index".as_ref(), walrusfs_root.key().as_ref()], bump)]
+    pub snapshot_index: Option<UncheckedAccount<'info>>,
+    /// CHECK: See pending_blob_deletes.
+    #[account(mut, seeds = [b"audit_log".as_ref(), walrusfs_root.key().as_ref()], bump)]
+    pub audit_log: Option<UncheckedAccount<'info>>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
     )]
-    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
-    #[account( 
-        mut,
-        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
-        bump = dir_arena.bump
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(name: String)]
+pub struct MigrateSnapshot<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>, // Pays rent for the grown accounts
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized
     )]
-    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    /// CHECK: May predate schema_version; the seeds tie it to this filesystem.
+    #[account(mut, seeds = [b"snapshot".as_ref(), walrusfs_root.key().as_ref(), name.as_bytes()], bump)]
+    pub snapshot: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
 }
 
-// Specific read operations will use the ReadUserFileSystem context
 #[derive(Accounts)]
-pub struct ListDir<'info> {
-    // Inherits structure from ReadUserFileSystem
-    /// CHECK: Owner of the filesystem.
-    pub owner: AccountInfo<'info>,
-    #[account(seeds = [b"walrusfs_root".as_ref(), owner.key().as_ref()], bump = walrusfs_root.bump)]
+#[instruction(file_id: u64)]
+pub struct MigrateShareKeys<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>, // Pays rent for the grown accounts
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized
+    )]
     pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
-    #[account(seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_files.bump)]
-    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
-    #[account(seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_directories.bump)]
-    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
-    #[account(seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()], bump = file_arena.bump)]
-    pub file_arena: Box<Account<'info, FileArenaPda>>,
-    #[account(seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()], bump = dir_arena.bump)]
-    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    /// CHECK: May predate schema_version; the seeds tie it to this filesystem.
+    #[account(mut, seeds = [b"share_keys".as_ref(), walrusfs_root.key().as_ref(), file_id.to_le_bytes().as_ref()], bump)]
+    pub share_keys: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
 }
 
 #[derive(Accounts)]
-pub struct UpdateEpoch<'info> {
-    pub authority: Signer<'info>, // The owner of this filesystem instance
+#[instruction(object_id: u64)]
+pub struct MigrateDescription<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>, // Pays rent for the grown accounts
     #[account(
-        mut,
-        seeds = [b"walrusfs_root".as_ref(), authority.key().as_ref()],
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
         bump = walrusfs_root.bump,
-        // constraint = walrusfs_root.authority == authority.key() @ WalrusFsError::Unauthorized // Redundant due to seed but can be explicit
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized
     )]
     pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    /// CHECK: May predate schema_version; the seeds tie it to this filesystem.
+    #[account(mut, seeds = [b"description".as_ref(), walrusfs_root.key().as_ref(), object_id.to_le_bytes().as_ref()], bump)]
+    pub description: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
 }
 
 #[derive(Accounts)]
-pub struct AddFile<'info> {
+pub struct ConfirmBlobDeleted<'info> {
     pub authority: Signer<'info>,
     #[account(
-        mut,
-        seeds = [b"walrusfs_root".as_ref(), authority.key().as_ref()],
-        bump = walrusfs_root.bump
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized
     )]
     pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
     #[account(
         mut,
-        seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()],
-        bump = root_children_files.bump
+        seeds = [b"pending_blob_deletes".as_ref(), walrusfs_root.key().as_ref()],
+        bump = pending_blob_deletes.bump
     )]
-    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
-    #[account( // Read-only for traversal, but derived from user-specific root
-        seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()],
-        bump = root_children_directories.bump
+    pub pending_blob_deletes: Box<Account<'info, PendingBlobDeletesPda>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
     )]
-    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeAuditLog<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(
+        init,
+        payer = authority,
+        space = AUDIT_LOG_PDA_SPACE,
+        seeds = [b"audit_log".as_ref(), walrusfs_root.key().as_ref()],
+        bump
+    )]
+    pub audit_log: Box<Account<'info, AuditLogPda>>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CommitAuditLog<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized
+    )]
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
     #[account(
         mut,
-        seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()],
-        bump = file_arena.bump
+        seeds = [b"audit_log".as_ref(), walrusfs_root.key().as_ref()],
+        bump = audit_log.bump
     )]
-    pub file_arena: Box<Account<'info, FileArenaPda>>,
-    #[account( // Mutable because a parent DirObject's children_files list might be updated
-        mut, 
-        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
-        bump = dir_arena.bump
+    pub audit_log: Box<Account<'info, AuditLogPda>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
     )]
-    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    pub program_config: UncheckedAccount<'info>,
 }
 
 #[derive(Accounts)]
-pub struct Stat<'info> {
-    // Inherits structure from ReadUserFileSystem
-    /// CHECK: Owner of the filesystem.
-    pub owner: AccountInfo<'info>,
-    #[account(seeds = [b"walrusfs_root".as_ref(), owner.key().as_ref()], bump = walrusfs_root.bump)]
+pub struct BulkUpdateMeta<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
+    )]
     pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
     #[account(seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_files.bump)]
     pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
     #[account(seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_directories.bump)]
     pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
-    #[account(seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()], bump = file_arena.bump)]
+    #[account(
+        mut,
+        seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = file_arena.bump
+    )]
     pub file_arena: Box<Account<'info, FileArenaPda>>,
-    #[account(seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()], bump = dir_arena.bump)]
+    #[account(
+        mut,
+        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = dir_arena.bump
+    )]
     pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    #[account(
+        mut,
+        seeds = [b"tag_dictionary".as_ref(), walrusfs_root.key().as_ref()],
+        bump = tag_dictionary.bump
+    )]
+    pub tag_dictionary: Box<Account<'info, TagDictionaryPda>>,
+    /// CHECK: The instructions sysvar, needed when writing through CPI to a
+    /// filesystem that limits its CPI callers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
 }
 
 #[derive(Accounts)]
-pub struct RenameFile<'info> {
+pub struct ReapOrphans<'info> {
     pub authority: Signer<'info>,
     #[account(
-        // Not mutable itself, but needed for deriving other PDA keys
-        seeds = [b"walrusfs_root".as_ref(), authority.key().as_ref()],
-        bump = walrusfs_root.bump
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
     )]
     pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
-    #[account(
-        mut, // Children list at root could change
+    #[account( // Read-only, only used to compute reachability
         seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()],
         bump = root_children_files.bump
     )]
     pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
-    #[account( // For path traversal only
+    #[account(
         seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()],
         bump = root_children_directories.bump
     )]
     pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
-    #[account( // Dir arena is mutable as children_files within a DirObject might change
+    #[account(
+        mut,
+        seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()],
+        bump = file_arena.bump
+    )]
+    pub file_arena: Box<Account<'info, FileArenaPda>>,
+    #[account(
         mut,
         seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
         bump = dir_arena.bump
     )]
     pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    /// CHECK: May not be initialized yet; when it is, its entries count as reachable.
+    #[account(seeds = [b"trash".as_ref(), walrusfs_root.key().as_ref()], bump)]
+    pub trash: UncheckedAccount<'info>,
+    /// CHECK: Likewise, for the subtree of a delete in progress.
+    #[account(seeds = [b"deletion_cursor".as_ref(), walrusfs_root.key().as_ref()], bump)]
+    pub deletion_cursor: UncheckedAccount<'info>,
+    #[account( // Required only when a deletable blob loses its last reference
+        mut,
+        seeds = [b"pending_blob_deletes".as_ref(), walrusfs_root.key().as_ref()],
+        bump = pending_blob_deletes.bump
+    )]
+    pub pending_blob_deletes: Option<Box<Account<'info, PendingBlobDeletesPda>>>,
+    /// CHECK: The instructions sysvar, needed when writing through CPI to a
+    /// filesystem that limits its CPI callers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
 }
 
 #[derive(Accounts)]
-pub struct DeleteFile<'info> {
+#[instruction(path: String, file_id: u64)]
+pub struct InitShareKeys<'info> {
+    #[account(mut)]
     pub authority: Signer<'info>,
     #[account(
-        seeds = [b"walrusfs_root".as_ref(), authority.key().as_ref()],
-        bump = walrusfs_root.bump
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized
     )]
     pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_files.bump)]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
+    #[account(seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_directories.bump)]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account(seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()], bump = dir_arena.bump)]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
     #[account(
-        mut,
-        seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()],
-        bump = root_children_files.bump
+        init,
+        payer = authority,
+        space = SHARE_KEYS_PDA_SPACE,
+        seeds = [b"share_keys".as_ref(), walrusfs_root.key().as_ref(), file_id.to_le_bytes().as_ref()],
+        bump
     )]
-    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
-    #[account( // For path traversal
-        seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()],
-        bump = root_children_directories.bump
+    pub share_keys: Box<Account<'info, ShareKeysPda>>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
     )]
-    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    pub program_config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateShareKeys<'info> {
+    pub authority: Signer<'info>,
     #[account(
-        mut,
-        seeds = [b"file_arena".as_ref(), walrusfs_root.key().as_ref()],
-        bump = file_arena.bump
+        seeds = [b"walrusfs_root".as_ref(), walrusfs_root.seed_key.as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = is_fs_writer(&walrusfs_root, authority.key) @ WalrusFsError::Unauthorized
     )]
-    pub file_arena: Box<Account<'info, FileArenaPda>>,
-    #[account( // Dir arena is mutable as children_files within a DirObject might change
+    pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
+    #[account(seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_files.bump)]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
+    #[account(seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_directories.bump)]
+    pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
+    #[account(seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()], bump = dir_arena.bump)]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    #[account(
         mut,
-        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
-        bump = dir_arena.bump
+        seeds = [b"share_keys".as_ref(), walrusfs_root.key().as_ref(), share_keys.file_id.to_le_bytes().as_ref()],
+        bump = share_keys.bump
     )]
-    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    pub share_keys: Box<Account<'info, ShareKeysPda>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
+    #[account(
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
+    )]
+    pub program_config: UncheckedAccount<'info>,
 }
 
 #[derive(Accounts)]
-pub struct DeleteDir<'info> {
-    pub authority: Signer<'info>,
+pub struct RecordAccess<'info> {
+    /// CHECK: Owner of the filesystem. No signer is needed to record an access.
+    pub owner: AccountInfo<'info>,
     #[account(
-        seeds = [b"walrusfs_root".as_ref(), authority.key().as_ref()],
-        bump = walrusfs_root.bump
+        seeds = [b"walrusfs_root".as_ref(), owner.key().as_ref(), walrusfs_root.seed_name.as_bytes()],
+        bump = walrusfs_root.bump,
+        constraint = !walrusfs_root.frozen @ WalrusFsError::FilesystemFrozen,
+        constraint = cpi_caller_allowed(&walrusfs_root, &instructions) @ WalrusFsError::CpiCallerNotAllowed
     )]
     pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
-    #[account(
-        mut, // For deleting dir at root or for path traversal if parent is root
-        seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()],
-        bump = root_children_directories.bump
-    )]
+    #[account(seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_files.bump)]
+    pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
+    #[account(seeds = [b"root_children_directories".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_directories.bump)]
     pub root_children_directories: Box<Account<'info, ChildrenDirectoriesPda>>,
     #[account(
         mut,
@@ -1189,20 +11247,27 @@ pub struct DeleteDir<'info> {
         bump = file_arena.bump
     )]
     pub file_arena: Box<Account<'info, FileArenaPda>>,
+    #[account(seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()], bump = dir_arena.bump)]
+    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    /// CHECK: The instructions sysvar, needed when writing through CPI to a
+    /// filesystem that limits its CPI callers.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+    /// CHECK: The program config PDA, read by program_paused; empty until created.
     #[account(
-        mut,
-        seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()],
-        bump = dir_arena.bump
+        seeds = [b"program_config".as_ref()],
+        bump,
+        constraint = !program_paused(&program_config) @ WalrusFsError::ProgramPaused
     )]
-    pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    pub program_config: UncheckedAccount<'info>,
 }
 
+// Shared read-only context for queries over an owner's filesystem
 #[derive(Accounts)]
-pub struct GetDirAll<'info> {
-    // Inherits structure from ReadUserFileSystem
+pub struct ReadUserFileSystem<'info> {
     /// CHECK: Owner of the filesystem.
     pub owner: AccountInfo<'info>,
-    #[account(seeds = [b"walrusfs_root".as_ref(), owner.key().as_ref()], bump = walrusfs_root.bump)]
+    #[account(seeds = [b"walrusfs_root".as_ref(), owner.key().as_ref(), walrusfs_root.seed_name.as_bytes()], bump = walrusfs_root.bump)]
     pub walrusfs_root: Box<Account<'info, WalrusfsRootPda>>,
     #[account(seeds = [b"root_children_files".as_ref(), walrusfs_root.key().as_ref()], bump = root_children_files.bump)]
     pub root_children_files: Box<Account<'info, ChildrenFilesPda>>,
@@ -1212,6 +11277,8 @@ pub struct GetDirAll<'info> {
     pub file_arena: Box<Account<'info, FileArenaPda>>,
     #[account(seeds = [b"dir_arena".as_ref(), walrusfs_root.key().as_ref()], bump = dir_arena.bump)]
     pub dir_arena: Box<Account<'info, DirArenaPda>>,
+    #[account(seeds = [b"tag_dictionary".as_ref(), walrusfs_root.key().as_ref()], bump = tag_dictionary.bump)]
+    pub tag_dictionary: Box<Account<'info, TagDictionaryPda>>,
 }
 
 // ... All other `#[derive(Accounts)]` structs from your original code (UpdateEpoch, AddFile, AddDir, ListDir, Stat, RenameFile, RenameDir, DeleteFile, DeleteDir, GetDirAll)
@@ -1224,11 +11291,41 @@ pub struct GetDirAll<'info> {
 pub struct DirListObjectAnchor {
     pub name: String,
     pub create_ts: u64,
+    pub modify_ts: u64,
+    pub access_ts: Option<u64>,
+    pub access_count: u64,
     pub is_dir: bool,
     pub tags: Vec<String>,
     pub size: u64,
-    pub walrus_blob_id: String,
+    pub walrus_blob_id: [u8; 32],
     pub walrus_epoch_till: u64,
+    pub sha256: Option<[u8; 32]>,
+    pub content_type: String,
+    pub preview_blob_id: Option<String>,
+    pub sui_object_id: Option<[u8; 32]>,
+    pub encryption: Option<EncryptionInfo>,
+    pub storage_class: StorageClass,
+    pub renewal_policy: RenewalPolicy,
+    pub symlink_target: Option<String>,
+    pub journal_segments: Option<Vec<JournalSegment>>,
+    pub chunks: Option<Vec<FileChunk>>,
+    pub link_count: u32,
+    pub sealed: bool,
+    pub lock: Option<FileLock>,
+    pub has_description: bool,
+    pub description: Option<String>, // Filled only by stat, when the sidecar is passed
+    pub pinned: bool,
+    pub kind: Option<ObjectKind>,   // None for directories
+    pub status: Option<BlobStatus>, // None for directories
+    pub owner: Option<Pubkey>,      // None for directories
+    pub child_file_count: u32,      // Zero for files, like the two below
+    pub child_dir_count: u32,
+    pub subtree_bytes: u64,
+    pub mode: u16,
+    pub group: Option<Pubkey>,
+    pub mount: Option<MountPoint>, // None for files
+    pub access_policy: Option<AccessPolicy>,
+    pub sale_price_lamports: Option<u64>, // Some for files listed for sale
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
@@ -1241,6 +11338,7 @@ pub struct FileObjectExAnchor {
 pub struct DirObjectExAnchor {
     pub id: u64,
     pub create_ts: u64,
+    pub modify_ts: u64,
     pub tags: Vec<String>,
     pub children_file_names: Vec<String>,
     pub children_file_ids: Vec<u64>,
@@ -1253,6 +11351,99 @@ pub struct RecursiveDirListAnchor {
     pub dirobj: u64,
     pub files: Vec<FileObjectExAnchor>,
     pub dirs: Vec<DirObjectExAnchor>,
+    pub next_cursor: Option<u64>, // Dir id to pass back as `cursor`; None when done
+}
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct DescribeAnchor {
+    pub program_version: String,
+    pub layout_version: u16,
+    pub max_tags: u32,
+    pub max_string_len: u32,
+    pub max_path_len: u32,
+    pub children_pda_space: u32,
+    pub arena_pda_space: u32,
+    pub collation: Collation,
+    pub default_renewal_policy: RenewalPolicy,
+    pub space_warning_pcts: [u8; 3],
+    pub limits: FsLimits, // This filesystem's own limits, at most the max_* fields above
+    pub features: Vec<String>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct DirListPageAnchor {
+    pub entries: Vec<DirListObjectAnchor>,
+    pub total_count: u32, // Entries matching the filter, not just this page
+    pub version: u64,     // Pass back as expected_version to guard a write against this listing
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct DirSizeAnchor {
+    pub total_bytes: u64,
+    pub file_count: u64,
+    pub dir_count: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct FsStatsAnchor {
+    pub total_files: u64,
+    pub total_dirs: u64,
+    pub total_bytes: u64,
+    pub obj_id_counter: u64,
+    pub file_arena_entries: u32,
+    pub dir_arena_entries: u32,
+    pub file_arena_pct_used: u8,
+    pub dir_arena_pct_used: u8,
+    pub root_files_pct_used: u8,
+    pub root_dirs_pct_used: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct FoundEntryAnchor {
+    pub id: u64,
+    pub is_dir: bool,
+    pub path: String,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct ResolvedPathAnchor {
+    pub is_dir: bool,
+    pub object_id: u64,
+}
+
+// Authorizes a relayed write as `signer`, who signed it off chain. See use_permit.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct Permit {
+    pub signer: Pubkey,
+    pub nonce: u64,
+    pub expiry_slot: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct AddFileEntry {
+    pub name: String, // Single component under the batch's parent path
+    pub tags: Vec<String>,
+    pub size: u64,
+    pub walrus_blob_id: [u8; 32],
+    pub end_epoch: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct BatchEntryResult {
+    pub name: String,
+    pub ok: bool, // false when the entry was skipped
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct SnapshotDiffAnchor {
+    pub added: Vec<SnapshotEntry>,
+    pub removed: Vec<SnapshotEntry>,
+    pub modified: Vec<SnapshotEntry>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct RetagResultAnchor {
+    pub updated: u32,
+    pub next_cursor: Option<u64>, // Pass back as `cursor` to continue; None when done
 }
 // --- Events (Unchanged, not repeated for brevity) ---
 #[event]
@@ -1261,7 +11452,7 @@ pub struct FileAlreadyExistsEvent {
     create_ts: u64,
     tags: Vec<String>,
     size: u64,
-    walrus_blob_id: String,
+    walrus_blob_id: [u8; 32],
     walrus_epoch_till: u64,
 }
 #[event]
@@ -1270,8 +11461,9 @@ pub struct FileAddedEvent {
     create_ts: u64,
     tags: Vec<String>,
     size: u64,
-    walrus_blob_id: String,
+    walrus_blob_id: [u8; 32],
     walrus_epoch_till: u64,
+    encrypted: bool,
 }
 #[event]
 pub struct DirAlreadyExistsEvent {
@@ -1286,9 +11478,410 @@ pub struct DirAddedEvent {
     tags: Vec<String>,
 }
 #[event]
+pub struct DefaultTagsUpdatedEvent {
+    path: String,
+    default_tags: Vec<String>,
+}
+#[event]
+pub struct TagsUpdatedEvent {
+    path: String,
+    tags: Vec<String>,
+}
+#[event]
+pub struct LinkEvent {
+    existing_path: String,
+    new_path: String,
+    link_count: u32,
+}
+#[event]
+pub struct MoveEvent {
+    from_path: String,
+    to_path: String,
+    is_dir: bool,
+}
+#[event]
+pub struct SymlinkAddedEvent {
+    path: String,
+    target: String,
+}
+#[event]
+pub struct JournalAddedEvent {
+    path: String,
+}
+#[event]
+pub struct SegmentAppendedEvent {
+    path: String,
+    segment_index: u32,
+    walrus_blob_id: [u8; 32],
+    size: u64,
+}
+#[event]
+pub struct ChunkAddedEvent {
+    path: String,
+    walrus_blob_id: [u8; 32],
+    offset: u64,
+    length: u64,
+}
+#[event]
+pub struct FileUpdatedEvent {
+    path: String,
+    size: u64,
+    walrus_blob_id: [u8; 32],
+    walrus_epoch_till: u64,
+    encrypted: bool,
+}
+#[event]
+pub struct FileEpochExtendedEvent {
+    path: String,
+    walrus_epoch_till: u64,
+}
+#[event]
+pub struct FileSealedEvent {
+    path: String,
+    permanent: bool,
+}
+#[event]
+pub struct FileUnsealedEvent {
+    path: String,
+}
+#[event]
+pub struct FileLockedEvent {
+    path: String,
+    holder: Pubkey,
+    expires_slot: u64,
+}
+#[event]
+pub struct FileUnlockedEvent {
+    path: String,
+}
+#[event]
+pub struct FileTouchedEvent {
+    path: String,
+    modify_ts: u64,
+}
+#[event]
+pub struct FileAccessedEvent {
+    path: String,
+    access_ts: u64,
+    access_count: u64,
+}
+#[event]
+pub struct BatchFilesAddedEvent {
+    parent_path: String,
+    results: Vec<BatchEntryResult>,
+}
+#[event]
+pub struct BatchFilesDeletedEvent {
+    results: Vec<BatchEntryResult>, // `name` holds the requested path
+}
+#[event]
+pub struct DirCopiedEvent {
+    from_path: String,
+    to_path: String,
+    dirs_copied: u64,
+    files_copied: u64,
+}
+#[event]
+pub struct TrashedEvent {
+    path: String,
+    is_dir: bool,
+}
+#[event]
+pub struct RestoredEvent {
+    path: String,
+}
+#[event]
+pub struct TrashEmptiedEvent {
+    entry_count: u64,
+}
+#[event]
+pub struct SnapshotCreatedEvent {
+    name: String,
+    path: String,
+    entry_count: u32,
+}
+#[event]
+pub struct SnapshotDeletedEvent {
+    name: String,
+}
+#[event]
+pub struct ClonePublishedEvent {
+    clone: Pubkey,
+    snapshot_name: String,
+    entry_count: u32,
+}
+#[event]
+pub struct ModeChangedEvent {
+    path: String,
+    mode: u16,
+}
+#[event]
+pub struct GroupChangedEvent {
+    path: String,
+    group: Option<Pubkey>,
+}
+#[event]
+pub struct AccessPolicyChangedEvent {
+    path: String,
+    policy: Option<AccessPolicy>,
+}
+#[event]
+pub struct MountChangedEvent {
+    path: String,
+    target: Option<MountPoint>,
+}
+#[event]
+pub struct PausedChangedEvent {
+    paused: bool,
+    admin: Pubkey,
+}
+#[event]
+pub struct FeesUpdatedEvent {
+    fs_creation_fee_lamports: u64,
+    fee_recipient: Pubkey,
+}
+#[event]
+pub struct CpiCallersChangedEvent {
+    callers: Option<Vec<Pubkey>>,
+}
+#[event]
+pub struct FrozenChangedEvent {
+    frozen: bool,
+    changed_by: Pubkey,
+}
+#[event]
+pub struct SessionKeyCreatedEvent {
+    session_key: Pubkey,
+    owner: Pubkey,
+    path_prefix: String,
+    expiry_slot: u64,
+}
+#[event]
+pub struct SessionKeyRevokedEvent {
+    session_key: Pubkey,
+    owner: Pubkey,
+}
+#[event]
+pub struct PermitUsedEvent {
+    signer: Pubkey,
+    nonce: u64,
+    relayer: Pubkey,
+}
+#[event]
+pub struct PinnedEvent {
+    path: String,
+    pinned: bool,
+}
+#[event]
+pub struct DescriptionUpdatedEvent {
+    path: String,
+    has_description: bool,
+}
+#[event]
+pub struct XattrUpdatedEvent {
+    path: String,
+    key: String,
+    removed: bool,
+}
+#[event]
+pub struct DeleteProgressEvent {
+    path: String,
+    dirs_removed: u64,
+    files_removed: u64,
+    dirs_remaining: u32,
+}
+#[event]
 pub struct DeleteEvent {
     path: String,
 }
+#[event]
+pub struct SpaceLowEvent {
+    account: Pubkey,
+    pct_used: u8,
+    threshold: u8,
+}
+#[event]
+pub struct ShareKeyPutEvent {
+    path: String,
+    grantee: Pubkey,
+}
+#[event]
+pub struct ShareKeyRemovedEvent {
+    path: String,
+    grantee: Pubkey,
+}
+#[event]
+pub struct ShareKeysRotatedEvent {
+    path: String,
+    key_epoch: u32,
+    revoked: Vec<Pubkey>,
+}
+#[event]
+pub struct BlobDeleteQueuedEvent {
+    blob_id: [u8; 32],
+}
+#[event]
+pub struct BlobDeleteConfirmedEvent {
+    blob_id: [u8; 32],
+}
+#[event]
+pub struct BlobStatusChangedEvent {
+    path: String,
+    previous: BlobStatus,
+    status: BlobStatus,
+}
+#[event]
+pub struct FileListedForSaleEvent {
+    path: String,
+    seller: Pubkey,
+    price_lamports: u64,
+}
+#[event]
+pub struct SaleCancelledEvent {
+    path: String,
+}
+#[event]
+pub struct FileSoldEvent {
+    path: String,
+    seller: Pubkey,
+    buyer: Pubkey,
+    price_lamports: u64,
+}
+#[event]
+pub struct FileOwnerChangedEvent {
+    path: String,
+    previous: Pubkey,
+    owner: Pubkey,
+}
+#[event]
+pub struct FsLimitsUpdatedEvent {
+    limits: FsLimits,
+}
+#[event]
+pub struct AuthorityTransferProposedEvent {
+    authority: Pubkey,
+    pending_authority: Option<Pubkey>,
+}
+#[event]
+pub struct AuthorityTransferredEvent {
+    previous: Pubkey,
+    authority: Pubkey,
+}
+#[event]
+pub struct CoAuthorityAddedEvent {
+    co_authority: Pubkey,
+    added_by: Pubkey,
+}
+#[event]
+pub struct CoAuthorityRemovedEvent {
+    co_authority: Pubkey,
+    removed_by: Pubkey,
+}
+#[event]
+pub struct WriterGrantedEvent {
+    delegate: Pubkey,
+    path_prefix: String,
+    expiry_slot: u64,
+    granted_by: Pubkey,
+}
+#[event]
+pub struct WriterRevokedEvent {
+    delegate: Pubkey,
+    revoked_by: Pubkey,
+}
+#[event]
+pub struct ReaderGrantedEvent {
+    grantee: Pubkey,
+    path_prefix: String,
+    expiry_slot: u64,
+    granted_by: Pubkey,
+}
+#[event]
+pub struct ReaderRevokedEvent {
+    grantee: Pubkey,
+    revoked_by: Pubkey,
+}
+#[event]
+pub struct ShareCreatedEvent {
+    share_key: Pubkey,
+    path: String,
+    grantee: Option<Pubkey>,
+    expiry_slot: u64,
+    created_by: Pubkey,
+}
+#[event]
+pub struct CapabilityMintedEvent {
+    capability_key: Pubkey,
+    path: String,
+    gateway: Pubkey,
+    expiry_slot: u64,
+    max_uses: Option<u32>,
+}
+#[event]
+pub struct CapabilityConsumedEvent {
+    capability_key: Pubkey,
+    path: String,
+    consumer: Pubkey,
+    uses: u32,
+}
+#[event]
+pub struct CapabilityRevokedEvent {
+    capability_key: Pubkey,
+    revoked_by: Pubkey,
+}
+#[event]
+pub struct ShareRevokedEvent {
+    share_key: Pubkey,
+    revoked_by: Pubkey,
+}
+#[event]
+pub struct GroupCreatedEvent {
+    name: String,
+    admin: Pubkey,
+    created_by: Pubkey,
+}
+#[event]
+pub struct GroupMemberAddedEvent {
+    name: String,
+    member: Pubkey,
+    added_by: Pubkey,
+}
+#[event]
+pub struct GroupMemberRemovedEvent {
+    name: String,
+    member: Pubkey,
+    removed_by: Pubkey,
+}
+#[event]
+pub struct FsMetadataUpdatedEvent {
+    name: String,
+    tags: Vec<String>,
+}
+#[event]
+pub struct SchemaMigratedEvent {
+    account: Pubkey,
+    schema_version: u16,
+}
+#[event]
+pub struct AuditLogCommittedEvent {
+    sequence: u64,
+    log_blob_id: String,
+    prev_hash: [u8; 32],
+    log_hash: [u8; 32],
+}
+#[event]
+pub struct TagRenamedEvent {
+    old_tag: String,
+    new_tag: String,
+    path_prefix: Option<String>,
+    files_updated: u32,
+    dirs_updated: u32,
+}
+#[event]
+pub struct OrphansReapedEvent {
+    file_ids: Vec<u64>,
+    dir_ids: Vec<u64>,
+}
 // --- Errors (Unchanged, not repeated for brevity) ---
 #[error_code]
 pub enum WalrusFsError {
@@ -1314,5 +11907,152 @@ pub enum WalrusFsError {
     InvalidPathOperationOnRoot,
     #[msg("Bump seed not found.")] // Not explicitly used in this code, but good to have
     BumpError,
+    #[msg("Object id does not match the entry at the specified path.")]
+    ObjectIdMismatch,
+    #[msg("Wrapped key is empty or exceeds the maximum length.")]
+    InvalidWrappedKey,
+    #[msg("No wrapped key found for the grantee.")]
+    ShareKeyNotFound,
+    #[msg("Previous hash does not match the head of the audit log.")]
+    AuditLogChainMismatch,
+    #[msg("A deletable blob lost its last reference; pass the pending blob delete queue.")]
+    BlobDeleteQueueRequired,
+    #[msg("Blob id is not in the pending delete queue.")]
+    BlobNotPendingDelete,
+    #[msg("Thresholds must be ascending percentages no greater than 100.")]
+    InvalidThresholds,
+    #[msg("Cannot move a directory into itself or one of its subdirectories.")]
+    MoveIntoOwnSubtree,
+    #[msg("New end epoch must not be earlier than the current one.")]
+    EpochRegression,
+    #[msg("Directory is not empty; pass recursive to delete its contents.")]
+    DirectoryNotEmpty,
+    #[msg("Cursor does not name a directory in this listing.")]
+    InvalidCursor,
+    #[msg("Too many levels of symbolic links.")]
+    SymlinkLoop,
+    #[msg("Trash account is required to move entries to the trash.")]
+    TrashRequired,
+    #[msg("A resumable delete is already in progress; finish it with delete_dir_continue.")]
+    DeletionInProgress,
+    #[msg("File is sealed and cannot be modified or deleted.")]
+    FileSealed,
+    #[msg("File is permanently sealed.")]
+    SealIsPermanent,
+    #[msg("File is locked by an unexpired lease.")]
+    FileLocked,
+    #[msg("No unexpired lock on this file is held by the signer.")]
+    LockNotHeld,
+    #[msg("Lease must be at least one slot.")]
+    InvalidLease,
+    #[msg("Entry no longer matches the expected object id or blob id.")]
+    PreconditionFailed,
+    #[msg("Directory changed since it was read.")]
+    VersionMismatch,
+    #[msg("Snapshot name must be 1 to 32 bytes.")]
+    InvalidSnapshotName,
+    #[msg("Entry is not a journal.")]
+    NotAJournal,
+    #[msg("Journals can only be extended with append_segment.")]
+    JournalIsAppendOnly,
+    #[msg("Entry is not a chunked file.")]
+    NotAChunkedFile,
+    #[msg("Chunked files are extended with add_chunk, not update_file.")]
+    ChunkedFileUpdate,
+    #[msg("Chunk must be non-empty and start at or after the end of the previous chunk.")]
+    InvalidChunk,
+    #[msg("Xattr key must be 1 to 64 bytes and its value at most 256 bytes.")]
+    InvalidXattr,
+    #[msg("Too many xattrs on this entry.")]
+    TooManyXattrs,
+    #[msg("No xattr with this key.")]
+    XattrNotFound,
+    #[msg("Description must be at most 256 bytes.")]
+    DescriptionTooLong,
+    #[msg("Blob id must be 32 bytes in unpadded base64url.")]
+    InvalidBlobId,
+    #[msg("Encryption scheme must be 1 to 32 bytes, nonce at most 32 bytes and wrapped key 1 to 128 bytes.")]
+    InvalidEncryption,
+    #[msg("Limits must be non-zero and may only be lowered.")]
+    InvalidLimits,
+    #[msg("Path has more components than this filesystem allows.")]
+    PathTooDeep,
+    #[msg("Directory has as many children as this filesystem allows.")]
+    DirectoryFull,
+    #[msg("The tag dictionary has no room for another tag name.")]
+    TagDictionaryFull,
+    #[msg("Blob status can only move forward from its current value.")]
+    InvalidStatusTransition,
+    #[msg("Only the proposed new authority can accept the transfer.")]
+    NotPendingAuthority,
+    #[msg("This filesystem already has as many co-authorities as allowed.")]
+    TooManyCoAuthorities,
+    #[msg("Key is already an authority of this filesystem.")]
+    AlreadyCoAuthority,
+    #[msg("Key is not a co-authority of this filesystem.")]
+    NotCoAuthority,
+    #[msg("The writer grant has expired.")]
+    WriterGrantExpired,
+    #[msg("Path is outside the prefix of the signer's writer grant.")]
+    OutsideWriterGrant,
+    #[msg("The read grant's expiry slot has already passed.")]
+    ReadGrantExpired,
+    #[msg("The share's expiry slot has already passed.")]
+    ShareExpired,
+    #[msg("Mode may only set the owner, group and other rwx bits.")]
+    InvalidMode,
+    #[msg("The mode does not let this signer write here.")]
+    PermissionDenied,
+    #[msg("Group names must be 1 to 32 bytes.")]
+    InvalidGroupName,
+    #[msg("This group already has as many members as allowed.")]
+    TooManyGroupMembers,
+    #[msg("Key is already a member of this group.")]
+    AlreadyGroupMember,
+    #[msg("Key is not a member of this group.")]
+    NotGroupMember,
+    #[msg("Entries below a mount point belong to the mounted filesystem.")]
+    PathInMount,
+    #[msg("Pass the mounted filesystem's PDAs as remaining accounts.")]
+    InvalidMountAccounts,
+    #[msg("The permit is not signed by its signer in the preceding Ed25519 instruction.")]
+    InvalidPermit,
+    #[msg("The permit's expiry slot has passed.")]
+    PermitExpired,
+    #[msg("The permit's nonce is not the signer's next one.")]
+    PermitNonceMismatch,
+    #[msg("The session key's expiry slot has passed.")]
+    SessionKeyExpired,
+    #[msg("Path is outside the scope of the signer's session key.")]
+    OutsideSessionScope,
+    #[msg("The filesystem is frozen; unfreeze it to change entries.")]
+    FilesystemFrozen,
+    #[msg("The program is paused.")]
+    ProgramPaused,
+    #[msg("Fees go to the recipient in the program config.")]
+    InvalidFeeRecipient,
+    #[msg("This filesystem allows at most 8 CPI callers.")]
+    TooManyCpiCallers,
+    #[msg("The calling program may not write to this filesystem through CPI.")]
+    CpiCallerNotAllowed,
+    #[msg("The file is listed for sale; cancel the sale first.")]
+    FileListedForSale,
+    #[msg("The file is not listed for sale.")]
+    FileNotForSale,
+    #[msg("The seller account is not the file's owner.")]
+    SellerMismatch,
+    #[msg("The capability's expiry slot has passed.")]
+    CapabilityExpired,
+    #[msg("The capability has no uses left.")]
+    CapabilityExhausted,
+    #[msg("Path is outside the capability's path.")]
+    OutsideCapability,
+    #[msg("Snapshot not found.")]
+    SnapshotNotFound,
+    #[msg("The snapshot is too large to publish as a clone.")]
+    SnapshotTooLargeToPublish,
+    #[msg("The sale price is above the buyer's maximum.")]
+    PriceAboveMaximum,
+    #[msg("Filesystem names are at most 32 bytes.")]
+    FsNameTooLong,
 }
-
@@ -2,7 +2,12 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program, BN, web3, ProgramError } from "@coral-xyz/anchor";
 import { WalrusfsAnchor, IDL } from "../target/types/walrusfs_anchor"; // Adjust path if needed
+import { CpiRelay } from "../target/types/cpi_relay";
 import { expect } from "chai";
+import { createHash } from "crypto";
+
+// Walrus blob ids are 32 bytes; tests derive them from readable labels.
+const blob = (label: string): number[] => Array.from(createHash("sha256").update(label).digest());
 
 // --- Helper Functions ---
 async function expectError(promise: Promise<any>, expectedErrorName: string) {
@@ -46,10 +51,19 @@ describe("walrusfs-anchor", () => {
   let rootChildrenDirectoriesPda: web3.PublicKey;
   let fileArenaPda: web3.PublicKey;
   let dirArenaPda: web3.PublicKey;
+  let tagDictionaryPda: web3.PublicKey;
+  // The core filesystem PDAs signed for by the authority; spread in extra accounts or another signer as needed
+  let adminAccounts: { walrusfsRoot: web3.PublicKey; rootChildrenFiles: web3.PublicKey; rootChildrenDirectories: web3.PublicKey; fileArena: web3.PublicKey; dirArena: web3.PublicKey; authority: web3.PublicKey };
 
   const MAX_TAGS = 5;
   const MAX_STRING_LEN = 64;
 
+  // Arena objects hold tag ids; resolve them through the tag dictionary.
+  const tagNames = async (ids: number[]): Promise<string[]> => {
+    const dictionary = await program.account.tagDictionaryPda.fetch(tagDictionaryPda);
+    return ids.map((id) => dictionary.tags[id]);
+  };
+
 
   before(async () => {
     // Derive PDA addresses based on the payer's public key
@@ -73,18 +87,37 @@ describe("walrusfs-anchor", () => {
       [Buffer.from("dir_arena"), walrusfsRootPda.toBuffer()],
       program.programId
     );
+    [tagDictionaryPda] = await web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("tag_dictionary"), walrusfsRootPda.toBuffer()],
+      program.programId
+    );
+    adminAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+
+    // Every write checks the program config, so it must exist first. The
+    // deployer is the program's upgrade authority.
+    const [programConfigPda] = web3.PublicKey.findProgramAddressSync([Buffer.from("program_config")], program.programId);
+    if (!(await provider.connection.getAccountInfo(programConfigPda))) {
+      const [programData] = web3.PublicKey.findProgramAddressSync(
+        [program.programId.toBuffer()],
+        new web3.PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+      );
+      await program.methods.initializeProgramConfig(payer.publicKey)
+        .accounts({ authority: payer.publicKey, program: program.programId, programData, systemProgram: web3.SystemProgram.programId })
+        .rpc();
+    }
   });
 
   it("Is initialized!", async () => {
     try {
         await program.methods
-        .initializeWalrusfs()
+        .initializeWalrusfs("", null)
         .accounts({
           walrusfsRoot: walrusfsRootPda,
           rootChildrenFiles: rootChildrenFilesPda,
           rootChildrenDirectories: rootChildrenDirectoriesPda,
           fileArena: fileArenaPda,
           dirArena: dirArenaPda,
+          tagDictionary: tagDictionaryPda,
           payer: payer.publicKey,
           systemProgram: web3.SystemProgram.programId,
         })
@@ -157,12 +190,12 @@ describe("walrusfs-anchor", () => {
     const path = "/file1.txt";
     const tags = ["doc", "important"];
     const size = new BN(1024);
-    const walrusBlobId = "blob_id_1";
+    const walrusBlobId = blob("blob_id_1");
     const endEpoch = new BN(200);
     const overwrite = false;
 
     await program.methods
-      .addFile(path, tags, size, walrusBlobId, endEpoch, overwrite)
+      .addFile(path, tags, size, walrusBlobId, endEpoch, null, "", null, { standard: {} }, null, null, overwrite, false, null)
       .accounts({
         walrusfsRoot: walrusfsRootPda,
         rootChildrenFiles: rootChildrenFilesPda,
@@ -184,9 +217,9 @@ describe("walrusfs-anchor", () => {
     const fileArena = await program.account.fileArenaPda.fetch(fileArenaPda);
     expect(fileArena.data.length).to.equal(1);
     expect(fileArena.data[0].key.toNumber()).to.equal(1);
-    expect(fileArena.data[0].value.tags).to.deep.equal(tags);
+    expect(await tagNames(fileArena.data[0].value.tags)).to.deep.equal(tags);
     expect(fileArena.data[0].value.size.eq(size)).to.be.true;
-    expect(fileArena.data[0].value.walrusBlobId).to.equal(walrusBlobId);
+    expect(fileArena.data[0].value.walrusBlobId).to.deep.equal(walrusBlobId);
   });
 
   it("Fails to add an existing file without overwrite flag", async () => {
@@ -194,7 +227,7 @@ describe("walrusfs-anchor", () => {
     // ... other params
     await expectError(
       program.methods
-        .addFile(path, [], new BN(0), "", new BN(0), false)
+        .addFile(path, [], new BN(0), blob(""), new BN(0), null, "", null, { standard: {} }, null, null, false, false, null)
         .accounts({
           walrusfsRoot: walrusfsRootPda,
           rootChildrenFiles: rootChildrenFilesPda,
@@ -212,11 +245,11 @@ describe("walrusfs-anchor", () => {
     const path = "/file1.txt";
     const newTags = ["updated"];
     const newSize = new BN(2048);
-    const newWalrusBlobId = "blob_id_1_updated";
+    const newWalrusBlobId = blob("blob_id_1_updated");
     const newEndEpoch = new BN(250);
 
     await program.methods
-      .addFile(path, newTags, newSize, newWalrusBlobId, newEndEpoch, true)
+      .addFile(path, newTags, newSize, newWalrusBlobId, newEndEpoch, null, "", null, { standard: {} }, null, null, true, false, null)
       .accounts({
         walrusfsRoot: walrusfsRootPda,
         rootChildrenFiles: rootChildrenFilesPda,
@@ -241,7 +274,7 @@ describe("walrusfs-anchor", () => {
     expect(fileArena.data.length).to.equal(1); // Only the new version remains
     const newFileInArena = fileArena.data.find(f => f.key.toNumber() === 2);
     expect(newFileInArena).to.exist;
-    expect(newFileInArena.value.tags).to.deep.equal(newTags);
+    expect(await tagNames(newFileInArena.value.tags)).to.deep.equal(newTags);
     expect(newFileInArena.value.size.eq(newSize)).to.be.true;
   });
 
@@ -252,7 +285,7 @@ describe("walrusfs-anchor", () => {
     const tags = ["folder"];
 
     await program.methods
-      .addDir(path, tags)
+      .addDir(path, tags, false, null)
       .accounts({
         walrusfsRoot: walrusfsRootPda,
         rootChildrenDirectories: rootChildrenDirectoriesPda,
@@ -272,7 +305,7 @@ describe("walrusfs-anchor", () => {
     const dirArena = await program.account.dirArenaPda.fetch(dirArenaPda);
     expect(dirArena.data.length).to.equal(1);
     expect(dirArena.data[0].key.toNumber()).to.equal(3);
-    expect(dirArena.data[0].value.tags).to.deep.equal(tags);
+    expect(await tagNames(dirArena.data[0].value.tags)).to.deep.equal(tags);
     expect(dirArena.data[0].value.childrenFiles).to.be.an('array').that.is.empty;
     expect(dirArena.data[0].value.childrenDirectories).to.be.an('array').that.is.empty;
   });
@@ -281,7 +314,7 @@ describe("walrusfs-anchor", () => {
     const path = "/dir1"; // Same as before
     await expectError(
       program.methods
-        .addDir(path, [])
+        .addDir(path, [], false, null)
         .accounts({
             walrusfsRoot: walrusfsRootPda,
             rootChildrenDirectories: rootChildrenDirectoriesPda,
@@ -293,15 +326,30 @@ describe("walrusfs-anchor", () => {
     );
   });
 
+  it("Succeeds on an existing directory with if_not_exists", async () => {
+    await program.methods
+      .addDir("/dir1", ["ignored"], true, null)
+      .accounts({
+          walrusfsRoot: walrusfsRootPda,
+          rootChildrenDirectories: rootChildrenDirectoriesPda,
+          dirArena: dirArenaPda,
+          authority: payer.publicKey,
+      })
+      .rpc();
+
+    const statResult = await program.methods.stat("/dir1", false).accounts({ owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda }).view();
+    expect(statResult.tags).to.not.include("ignored");
+  });
+
   it("Adds a file to a subdirectory", async () => {
     const path = "/dir1/subfile.txt";
     const tags = ["sub"];
     const size = new BN(512);
-    const walrusBlobId = "blob_sub_1";
+    const walrusBlobId = blob("blob_sub_1");
     const endEpoch = new BN(300);
 
     await program.methods
-      .addFile(path, tags, size, walrusBlobId, endEpoch, false)
+      .addFile(path, tags, size, walrusBlobId, endEpoch, null, "", null, { standard: {} }, null, null, false, false, null)
       .accounts({
         walrusfsRoot: walrusfsRootPda,
         rootChildrenFiles: rootChildrenFilesPda, // Not directly used for subdirs
@@ -325,7 +373,7 @@ describe("walrusfs-anchor", () => {
     const fileArena = await program.account.fileArenaPda.fetch(fileArenaPda);
     const subFileInArena = fileArena.data.find(f => f.key.toNumber() === 4);
     expect(subFileInArena).to.exist;
-    expect(subFileInArena.value.tags).to.deep.equal(tags);
+    expect(await tagNames(subFileInArena.value.tags)).to.deep.equal(tags);
   });
 
   it("Adds a subdirectory", async () => {
@@ -333,7 +381,7 @@ describe("walrusfs-anchor", () => {
     const tags = ["nested"];
 
     await program.methods
-      .addDir(path, tags)
+      .addDir(path, tags, false, null)
       .accounts({
         walrusfsRoot: walrusfsRootPda,
         rootChildrenDirectories: rootChildrenDirectoriesPda,
@@ -354,13 +402,13 @@ describe("walrusfs-anchor", () => {
 
     const subDirObject = dirArena.data.find(d => d.key.toNumber() === 5); // subdir1's ID
     expect(subDirObject).to.exist;
-    expect(subDirObject.value.tags).to.deep.equal(tags);
+    expect(await tagNames(subDirObject.value.tags)).to.deep.equal(tags);
   });
 
   // --- Listing and Stat Operations ---
   it("Lists the root directory", async () => {
-    const results = await program.methods
-      .listDir("/")
+    const { entries: results } = await program.methods
+      .listDir("/", 0, 0, { name: {} }, false, { kind: { both: {} }, tag: null, pinnedOnly: false }, false)
       .accounts({
         owner: payer.publicKey, // For read operations, owner is used to derive PDAs
         walrusfsRoot: walrusfsRootPda,
@@ -384,8 +432,8 @@ describe("walrusfs-anchor", () => {
   });
 
   it("Lists a subdirectory '/dir1/'", async () => {
-    const results = await program.methods
-      .listDir("/dir1/") // or "/dir1"
+    const { entries: results } = await program.methods
+      .listDir("/dir1/", 0, 0, { name: {} }, false, { kind: { both: {} }, tag: null, pinnedOnly: false }, false) // or "/dir1"
       .accounts({
         owner: payer.publicKey,
         walrusfsRoot: walrusfsRootPda,
@@ -408,7 +456,7 @@ describe("walrusfs-anchor", () => {
 
   it("Gets stat for a file", async () => {
     const statResult = await program.methods
-      .stat("/file1.txt")
+      .stat("/file1.txt", false)
       .accounts({
         owner: payer.publicKey,
         walrusfsRoot: walrusfsRootPda,
@@ -427,7 +475,7 @@ describe("walrusfs-anchor", () => {
 
   it("Gets stat for a directory", async () => {
     const statResult = await program.methods
-      .stat("/dir1") // or "/dir1/"
+      .stat("/dir1", false) // or "/dir1/"
       .accounts({
         owner: payer.publicKey,
         walrusfsRoot: walrusfsRootPda,
@@ -446,7 +494,7 @@ describe("walrusfs-anchor", () => {
   it("Fails to get stat for a non-existent path", async () => {
     await expectError(
       program.methods
-      .stat("/nonexistent.txt")
+      .stat("/nonexistent.txt", false)
       .accounts({
         owner: payer.publicKey,
         walrusfsRoot: walrusfsRootPda,
@@ -466,11 +514,12 @@ describe("walrusfs-anchor", () => {
     const toPath = "/renamed_file1.txt";
 
     await program.methods
-      .renameFile(fromPath, toPath)
+      .renameFile(fromPath, toPath, false, null, null, null)
       .accounts({
         walrusfsRoot: walrusfsRootPda,
         rootChildrenFiles: rootChildrenFilesPda,
         rootChildrenDirectories: rootChildrenDirectoriesPda,
+        fileArena: fileArenaPda,
         dirArena: dirArenaPda,
         authority: payer.publicKey,
       })
@@ -484,7 +533,7 @@ describe("walrusfs-anchor", () => {
     expect(oldFile).to.not.exist;
 
     // Check stat of new name
-    const statResult = await program.methods.stat(toPath).accounts({ owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda }).view();
+    const statResult = await program.methods.stat(toPath, false).accounts({ owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda }).view();
     expect(statResult.name).to.equal("renamed_file1.txt");
   });
 
@@ -493,7 +542,7 @@ describe("walrusfs-anchor", () => {
     const toPath = "/dir1/renamed_subdir1";
 
     await program.methods
-      .renameDir(fromPath, toPath)
+      .renameDir(fromPath, toPath, null)
       .accounts({
         walrusfsRoot: walrusfsRootPda,
         rootChildrenDirectories: rootChildrenDirectoriesPda,
@@ -514,17 +563,17 @@ describe("walrusfs-anchor", () => {
 
   it("Fails to rename file if 'to_path' already exists", async () => {
     // Add a temporary file that will cause conflict
-    await program.methods.addFile("/temp_file.txt", [], new BN(10), "temp_blob", new BN(400), false)
+    await program.methods.addFile("/temp_file.txt", [], new BN(10), blob("temp_blob"), new BN(400), null, "", null, { standard: {} }, null, null, false, false, null)
         .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc();
 
     await expectError(
         program.methods
-        .renameFile("/renamed_file1.txt", "/temp_file.txt")
-        .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc(),
+        .renameFile("/renamed_file1.txt", "/temp_file.txt", false, null, null, null)
+        .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc(),
         "FileAlreadyExists"
     );
      // cleanup
-    await program.methods.deleteFile("/temp_file.txt")
+    await program.methods.deleteFile("/temp_file.txt", false, false, null, null, null)
         .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc();
   });
 
@@ -533,7 +582,7 @@ describe("walrusfs-anchor", () => {
     const path = "/renamed_file1.txt";
     // file ID was 2
     await program.methods
-      .deleteFile(path)
+      .deleteFile(path, false, false, null, null, null)
       .accounts({
         walrusfsRoot: walrusfsRootPda,
         rootChildrenFiles: rootChildrenFilesPda,
@@ -556,12 +605,12 @@ describe("walrusfs-anchor", () => {
   it("Deletes an empty directory", async () => {
     // First add an empty dir
     const emptyDirPath = "/dir_empty";
-    await program.methods.addDir(emptyDirPath, []).accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc();
+    await program.methods.addDir(emptyDirPath, [], false, null).accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc();
     const rootAccountBefore = await program.account.walrusfsRootPda.fetch(walrusfsRootPda);
     const emptyDirId = rootAccountBefore.objIdCounter; // ID of /dir_empty
 
     await program.methods
-      .deleteDir(emptyDirPath)
+      .deleteDir(emptyDirPath, false, false, null)
       .accounts({
         walrusfsRoot: walrusfsRootPda,
         rootChildrenDirectories: rootChildrenDirectoriesPda,
@@ -590,9 +639,14 @@ describe("walrusfs-anchor", () => {
     let fileArenaState = await program.account.fileArenaPda.fetch(fileArenaPda);
     expect(fileArenaState.data.find(f => f.key.toNumber() === 4 /* /dir1/subfile.txt */)).to.exist;
 
+    await expectError(
+        program.methods.deleteDir(pathToDelete, false, false, null)
+        .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc(),
+        "DirectoryNotEmpty"
+    );
 
     await program.methods
-      .deleteDir(pathToDelete)
+      .deleteDir(pathToDelete, true, false, null)
       .accounts({
         walrusfsRoot: walrusfsRootPda,
         rootChildrenDirectories: rootChildrenDirectoriesPda,
@@ -618,12 +672,12 @@ describe("walrusfs-anchor", () => {
 
   it("Fails to delete non-existent file/dir", async () => {
     await expectError(
-        program.methods.deleteFile("/non_existent_file.txt")
+        program.methods.deleteFile("/non_existent_file.txt", false, false, null, null, null)
         .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc(),
         "PathNotFound"
     );
     await expectError(
-        program.methods.deleteDir("/non_existent_dir")
+        program.methods.deleteDir("/non_existent_dir", false, false, null)
         .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc(),
         "PathNotFound"
     );
@@ -632,13 +686,13 @@ describe("walrusfs-anchor", () => {
   // --- GetDirAll ---
   it("Gets all directory contents recursively (after re-populating)", async () => {
     // Re-populate for this test
-    await program.methods.addDir("/level1", ["l1_tag"]).accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc(); // ID 6
-    await program.methods.addFile("/level1/fileA.txt", ["file_a"], new BN(100), "blobA", new BN(500), false).accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc(); // ID 7
-    await program.methods.addDir("/level1/level2", ["l2_tag"]).accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc(); // ID 8
-    await program.methods.addFile("/level1/level2/fileB.txt", ["file_b"], new BN(200), "blobB", new BN(600), false).accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc(); // ID 9
+    await program.methods.addDir("/level1", ["l1_tag"], false, null).accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc(); // ID 6
+    await program.methods.addFile("/level1/fileA.txt", ["file_a"], new BN(100), blob("blobA"), new BN(500), null, "", null, { standard: {} }, null, null, false, false, null).accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc(); // ID 7
+    await program.methods.addDir("/level1/level2", ["l2_tag"], false, null).accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc(); // ID 8
+    await program.methods.addFile("/level1/level2/fileB.txt", ["file_b"], new BN(200), blob("blobB"), new BN(600), null, "", null, { standard: {} }, null, null, false, false, null).accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc(); // ID 9
 
     const result = await program.methods
-      .getDirAll("/level1")
+      .getDirAll("/level1", null, new BN(0), 0)
       .accounts({
         owner: payer.publicKey,
         walrusfsRoot: walrusfsRootPda,
@@ -655,9 +709,9 @@ describe("walrusfs-anchor", () => {
     const fileA = result.files.find(f => f.id.toNumber() === 9);
     const fileB = result.files.find(f => f.id.toNumber() === 11);
     expect(fileA).to.exist;
-    expect(fileA.obj.walrusBlobId).to.equal("blobA");
+    expect(fileA.obj.walrusBlobId).to.deep.equal(blob("blobA"));
     expect(fileB).to.exist;
-    expect(fileB.obj.walrusBlobId).to.equal("blobB");
+    expect(fileB.obj.walrusBlobId).to.deep.equal(blob("blobB"));
 
     expect(result.dirs.length).to.equal(2); // /level1 and /level1/level2
     const dirLevel1 = result.dirs.find(d => d.id.toNumber() === 8);
@@ -675,13 +729,13 @@ describe("walrusfs-anchor", () => {
   it("Fails operations with invalid paths", async () => {
     const invalidPaths = ["", "no_slash", "/path//double_slash", `/${"a".repeat(MAX_STRING_LEN * 6)}`];
     for (const p of invalidPaths) {
-        await expectError(program.methods.addFile(p, [], new BN(0), "", new BN(0), false)
+        await expectError(program.methods.addFile(p, [], new BN(0), blob(""), new BN(0), null, "", null, { standard: {} }, null, null, false, false, null)
             .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc(), "PathError");
-        await expectError(program.methods.addDir(p, [])
+        await expectError(program.methods.addDir(p, [], false, null)
             .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc(), "PathError");
     }
     // Operation on root
-    await expectError(program.methods.addFile("/", [], new BN(0), "", new BN(0), false)
+    await expectError(program.methods.addFile("/", [], new BN(0), blob(""), new BN(0), null, "", null, { standard: {} }, null, null, false, false, null)
         .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc(), "InvalidPathOperationOnRoot");
   });
 
@@ -689,21 +743,18 @@ describe("walrusfs-anchor", () => {
     const tooManyTags = Array(MAX_TAGS + 1).fill("tag");
     const longString = "a".repeat(MAX_STRING_LEN + 1);
 
-    await expectError(program.methods.addFile("/tags_test.txt", tooManyTags, new BN(0), "blob", new BN(0), false)
+    await expectError(program.methods.addFile("/tags_test.txt", tooManyTags, new BN(0), blob("blob"), new BN(0), null, "", null, { standard: {} }, null, null, false, false, null)
         .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc(), "TooManyTags");
 
-    await expectError(program.methods.addFile("/long_blob.txt", [], new BN(0), longString, new BN(0), false)
-        .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc(), "StringTooLong");
-
-    await expectError(program.methods.addFile("/long_tag.txt", [longString], new BN(0), "blob", new BN(0), false)
+    await expectError(program.methods.addFile("/long_tag.txt", [longString], new BN(0), blob("blob"), new BN(0), null, "", null, { standard: {} }, null, null, false, false, null)
         .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc(), "StringTooLong");
   });
 
   // Add more tests for edge cases for rename, delete involving paths like "/" or non-existent parents
   it("Fails rename if 'from_path' does not exist", async () => {
     await expectError(
-        program.methods.renameFile("/non_existent_from.txt", "/some_to.txt")
-        .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc(),
+        program.methods.renameFile("/non_existent_from.txt", "/some_to.txt", false, null, null, null)
+        .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc(),
         "PathNotFound" // Or could be ConstraintSeeds if path resolution fails earlier for PDA derivation
     );
   });
@@ -711,24 +762,2031 @@ describe("walrusfs-anchor", () => {
   it("Fails rename if paths are in different directories", async () => {
     // Ensure /level4 exists for this test
     if (!((await program.account.dirArenaPda.fetch(dirArenaPda)).data.find(d => d.value.childrenDirectories.find(k => k.key == "level4") ))) {
-      await program.methods.addDir("/level4", []).accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc();
+      await program.methods.addDir("/level4", [], false, null).accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc();
     }
      // Ensure /level4/fileA.txt exists from previous test or add it
     if (!(await program.account.dirArenaPda.fetch(dirArenaPda)).data.find(d => d.value.childrenFiles.find(k => k.key == "fileC.txt")  )) {
-       await program.methods.addFile("/level4/fileC.txt", [], new BN(100), "blobA", new BN(500), false).accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc();
+       await program.methods.addFile("/level4/fileC.txt", [], new BN(100), blob("blobA"), new BN(500), null, "", null, { standard: {} }, null, null, false, false, null).accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc();
     }
     // Add a root file to attempt renaming into subdir
-    await program.methods.addFile("/root_file_for_rename.txt", [], new BN(10), "root_blob", new BN(1000), false)
+    await program.methods.addFile("/root_file_for_rename.txt", [], new BN(10), blob("root_blob"), new BN(1000), null, "", null, { standard: {} }, null, null, false, false, null)
         .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc();
    
     await expectError(
-        program.methods.renameFile("/root_file_for_rename.txt", "/level1/new_name.txt")
-        .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc(),
+        program.methods.renameFile("/root_file_for_rename.txt", "/level1/new_name.txt", false, null, null, null)
+        .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc(),
         "RenamePathMismatch"
     );
     // cleanup
-    await program.methods.deleteFile("/root_file_for_rename.txt")
+    await program.methods.deleteFile("/root_file_for_rename.txt", false, false, null, null, null)
+      .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc();
+
+  });
+
+  // --- Maintenance ---
+  it("Reaps nothing when every arena entry is reachable", async () => {
+    const fileArenaBefore = await program.account.fileArenaPda.fetch(fileArenaPda);
+    const dirArenaBefore = await program.account.dirArenaPda.fetch(dirArenaPda);
+
+    await program.methods
+      .reapOrphans(100)
+      .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey })
+      .rpc();
+
+    const fileArenaAfter = await program.account.fileArenaPda.fetch(fileArenaPda);
+    const dirArenaAfter = await program.account.dirArenaPda.fetch(dirArenaPda);
+    expect(fileArenaAfter.data.length).to.equal(fileArenaBefore.data.length);
+    expect(dirArenaAfter.data.length).to.equal(dirArenaBefore.data.length);
+  });
+
+  // --- Share keys ---
+  it("Stores and removes wrapped content keys for a file", async () => {
+    await program.methods.addFile("/shared.bin", [], new BN(10), blob("shared_blob"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null)
+      .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc();
+    const fileId = (await program.account.walrusfsRootPda.fetch(walrusfsRootPda)).objIdCounter;
+
+    const [shareKeysPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("share_keys"), walrusfsRootPda.toBuffer(), fileId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const grantee = web3.Keypair.generate().publicKey;
+
+    await program.methods.initShareKeys("/shared.bin", fileId)
+      .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, shareKeys: shareKeysPda, authority: payer.publicKey, systemProgram: web3.SystemProgram.programId }).rpc();
+    await program.methods.putShareKey("/shared.bin", grantee, Buffer.from([1, 2, 3]))
+      .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, shareKeys: shareKeysPda, authority: payer.publicKey }).rpc();
+
+    let shareKeys = await program.account.shareKeysPda.fetch(shareKeysPda);
+    expect(shareKeys.wraps.length).to.equal(1);
+    expect(shareKeys.wraps[0].grantee.equals(grantee)).to.be.true;
+
+    await program.methods.removeShareKey("/shared.bin", grantee)
+      .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, shareKeys: shareKeysPda, authority: payer.publicKey }).rpc();
+    shareKeys = await program.account.shareKeysPda.fetch(shareKeysPda);
+    expect(shareKeys.wraps).to.be.empty;
+  });
+
+  it("Rotates share keys and drops revoked grantees", async () => {
+    const fileId = (await program.account.childrenFilesPda.fetch(rootChildrenFilesPda)).data.find(f => f.key === "shared.bin").value;
+    const [shareKeysPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("share_keys"), walrusfsRootPda.toBuffer(), fileId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const kept = web3.Keypair.generate().publicKey;
+    const revoked = web3.Keypair.generate().publicKey;
+    const accounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, shareKeys: shareKeysPda, authority: payer.publicKey };
+
+    await program.methods.putShareKey("/shared.bin", kept, Buffer.from([1])).accounts(accounts).rpc();
+    await program.methods.putShareKey("/shared.bin", revoked, Buffer.from([2])).accounts(accounts).rpc();
+    await program.methods.rotateShareKeys("/shared.bin", [revoked]).accounts(accounts).rpc();
+
+    const shareKeys = await program.account.shareKeysPda.fetch(shareKeysPda);
+    expect(shareKeys.keyEpoch).to.equal(1);
+    expect(shareKeys.wraps.length).to.equal(1);
+    expect(shareKeys.wraps[0].grantee.equals(kept)).to.be.true;
+    expect(shareKeys.wraps[0].keyEpoch).to.equal(0); // stale until re-wrapped
+  });
+
+  // --- Audit log ---
+  it("Commits hash-chained audit log segments", async () => {
+    const [auditLogPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("audit_log"), walrusfsRootPda.toBuffer()],
+      program.programId
+    );
+    await program.methods.initializeAuditLog()
+      .accounts({ walrusfsRoot: walrusfsRootPda, auditLog: auditLogPda, authority: payer.publicKey, systemProgram: web3.SystemProgram.programId }).rpc();
+
+    const zero = Array(32).fill(0);
+    await program.methods.commitAuditLog("audit_blob_1", zero)
+      .accounts({ walrusfsRoot: walrusfsRootPda, auditLog: auditLogPda, authority: payer.publicKey }).rpc();
+
+    const auditLog = await program.account.auditLogPda.fetch(auditLogPda);
+    expect(auditLog.sequence.toNumber()).to.equal(1);
+    expect(auditLog.lastLogBlobId).to.equal("audit_blob_1");
+    const expected = createHash("sha256").update(Buffer.from(zero)).update("audit_blob_1").digest();
+    expect(Buffer.from(auditLog.lastHash).equals(expected)).to.be.true;
+
+    await expectError(
+      program.methods.commitAuditLog("audit_blob_2", zero)
+        .accounts({ walrusfsRoot: walrusfsRootPda, auditLog: auditLogPda, authority: payer.publicKey }).rpc(),
+      "AuditLogChainMismatch"
+    );
+  });
+
+  // --- Deletable blob coordination ---
+  it("Queues unreferenced deletable blobs and confirms their deletion", async () => {
+    const [pendingBlobDeletesPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("pending_blob_deletes"), walrusfsRootPda.toBuffer()],
+      program.programId
+    );
+    await program.methods.initializeBlobDeleteQueue()
+      .accounts({ walrusfsRoot: walrusfsRootPda, pendingBlobDeletes: pendingBlobDeletesPda, authority: payer.publicKey, systemProgram: web3.SystemProgram.programId }).rpc();
+
+    await program.methods.addFile("/scratch.tmp", [], new BN(10), blob("scratch_blob"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null)
+      .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc();
+    await program.methods.setBlobDeletable("/scratch.tmp", true)
+      .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc();
+    await program.methods.deleteFile("/scratch.tmp", false, false, null, null, null)
+      .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, pendingBlobDeletes: pendingBlobDeletesPda, authority: payer.publicKey }).rpc();
+
+    let queue = await program.account.pendingBlobDeletesPda.fetch(pendingBlobDeletesPda);
+    expect(queue.blobIds).to.deep.equal([blob("scratch_blob")]);
+
+    await program.methods.confirmBlobDeleted(blob("scratch_blob"))
+      .accounts({ walrusfsRoot: walrusfsRootPda, pendingBlobDeletes: pendingBlobDeletesPda, authority: payer.publicKey }).rpc();
+    queue = await program.account.pendingBlobDeletesPda.fetch(pendingBlobDeletesPda);
+    expect(queue.blobIds).to.be.empty;
+
+    // Overwriting drops the old blob's last reference just like deleting does
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, pendingBlobDeletes: pendingBlobDeletesPda, authority: payer.publicKey };
+    await program.methods.addFile("/draft.tmp", [], new BN(10), blob("draft_v1"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null)
+      .accounts(writeAccounts).rpc();
+    await program.methods.setBlobDeletable("/draft.tmp", true).accounts(writeAccounts).rpc();
+    await program.methods.addFile("/draft.tmp", [], new BN(10), blob("draft_v2"), new BN(700), null, "", null, { standard: {} }, null, null, true, false, null)
+      .accounts(writeAccounts).rpc();
+    queue = await program.account.pendingBlobDeletesPda.fetch(pendingBlobDeletesPda);
+    expect(queue.blobIds).to.deep.equal([blob("draft_v1")]);
+  });
+
+  // --- Renewal policy ---
+  it("Applies the default renewal policy and lets files override it", async () => {
+    await program.methods.setDefaultRenewalPolicy({ autoWhileFunded: {} })
+      .accounts({ walrusfsRoot: walrusfsRootPda, authority: payer.publicKey }).rpc();
+    await program.methods.addFile("/precious.bin", [], new BN(10), blob("precious_blob"), new BN(5), null, "", null, { standard: {} }, null, null, false, false, null)
+      .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc();
+
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    let statResult = await program.methods.stat("/precious.bin", false).accounts(readAccounts).view();
+    expect(statResult.renewalPolicy).to.deep.equal({ autoWhileFunded: {} });
+
+    await program.methods.setRenewalPolicy("/precious.bin", { never: {} })
+      .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc();
+    statResult = await program.methods.stat("/precious.bin", false).accounts(readAccounts).view();
+    expect(statResult.renewalPolicy).to.deep.equal({ never: {} });
+
+    const expiring = await program.methods.listExpiring(new BN(6), 10).accounts(readAccounts).view();
+    expect(expiring.map(e => e.obj.walrusBlobId)).to.deep.include(blob("precious_blob"));
+  });
+
+  // --- Space warnings ---
+  it("Validates space warning thresholds", async () => {
+    await program.methods.setSpaceWarningThresholds([70, 85, 95])
+      .accounts({ walrusfsRoot: walrusfsRootPda, authority: payer.publicKey }).rpc();
+    const rootAccount = await program.account.walrusfsRootPda.fetch(walrusfsRootPda);
+    expect(rootAccount.spaceWarningPcts).to.deep.equal([70, 85, 95]);
+
+    await expectError(
+      program.methods.setSpaceWarningThresholds([90, 80, 95])
+        .accounts({ walrusfsRoot: walrusfsRootPda, authority: payer.publicKey }).rpc(),
+      "InvalidThresholds"
+    );
+  });
+
+  // --- Canonical paths ---
+  it("Canonicalizes paths to NFC without trailing slash", async () => {
+    const decomposed = "/cafe\u0301.txt"; // 'e' + combining acute accent
+    const composed = "/caf\u00e9.txt";
+    await program.methods.addFile(decomposed, [], new BN(1), blob("nfc_blob"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null)
+      .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc();
+
+    const statResult = await program.methods.stat(composed, false)
+      .accounts({ owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda }).view();
+    expect(statResult.name).to.equal("caf\u00e9.txt");
+  });
+
+  // --- Bulk retag ---
+  it("Retags entries under a prefix in bounded batches", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    await program.methods.addDir("/retag", ["old"], false, null).accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc();
+    await program.methods.addFile("/retag/a.txt", ["old"], new BN(1), blob("retag_a"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    await program.methods.addFile("/retag/b.txt", ["old", "keep"], new BN(1), blob("retag_b"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+
+    // Limit 1 per call; already-retagged entries no longer match, so restarting is safe
+    await program.methods.retag("/retag", "old", "new", 1, new BN(0)).accounts(writeAccounts).rpc();
+    await program.methods.retag("/retag", "old", "new", 1, new BN(0)).accounts(writeAccounts).rpc();
+
+    const { entries: listing } = await program.methods.listDir("/retag", 0, 0, { name: {} }, false, { kind: { both: {} }, tag: null, pinnedOnly: false }, false)
+      .accounts({ owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda }).view();
+    for (const entry of listing) {
+      expect(entry.tags).to.not.include("old");
+      expect(entry.tags).to.include("new");
+    }
+  });
+
+  // --- Collation ---
+  it("Sorts listings by the configured collation", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    await program.methods.addDir("/sorted", [], false, null).accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc();
+    for (const name of ["file10.txt", "File2.txt", "file1.txt"]) {
+      await program.methods.addFile(`/sorted/${name}`, [], new BN(1), blob(`sorted_${name}`), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    }
+    await program.methods.setCollation({ numericAware: {} }).accounts(writeAccounts).rpc();
+
+    const { entries: listing } = await program.methods.listDir("/sorted", 0, 0, { name: {} }, false, { kind: { both: {} }, tag: null, pinnedOnly: false }, false)
+      .accounts({ owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda }).view();
+    expect(listing.map(e => e.name)).to.deep.equal(["file1.txt", "File2.txt", "file10.txt"]);
+  });
+
+  // --- Self-description ---
+  it("Describes the deployment's limits and features", async () => {
+    const description = await program.methods.describe()
+      .accounts({ owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda }).view();
+    expect(description.maxTags).to.equal(MAX_TAGS);
+    expect(description.maxStringLen).to.equal(MAX_STRING_LEN);
+    expect(description.features).to.include("canonical_paths");
+  });
+
+
+  // --- Move ---
+  it("Moves a file between directories", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    await program.methods.addDir("/move_src", [], false, null).accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc();
+    await program.methods.addDir("/move_dst", [], false, null).accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc();
+    await program.methods.addFile("/move_src/m.txt", [], new BN(1), blob("move_blob"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+
+    await program.methods.moveFile("/move_src/m.txt", "/move_dst/moved.txt", false, null, null).accounts(writeAccounts).rpc();
+
+    expect((await program.methods.listDir("/move_src", 0, 0, { name: {} }, false, { kind: { both: {} }, tag: null, pinnedOnly: false }, false).accounts(readAccounts).view()).entries).to.be.empty;
+    const statResult = await program.methods.stat("/move_dst/moved.txt", false).accounts(readAccounts).view();
+    expect(statResult.walrusBlobId).to.deep.equal(blob("move_blob"));
+
+    await expectError(
+      program.methods.moveFile("/move_src/m.txt", "/move_dst/again.txt", false, null, null).accounts(writeAccounts).rpc(),
+      "PathNotFound"
+    );
+  });
+
+
+  it("Moves a directory and refuses to move it into its own subtree", async () => {
+    const dirAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    await program.methods.addDir("/mv_a", [], false, null).accounts(dirAccounts).rpc();
+    await program.methods.addDir("/mv_a/inner", [], false, null).accounts(dirAccounts).rpc();
+    await program.methods.addDir("/mv_b", [], false, null).accounts(dirAccounts).rpc();
+
+    await expectError(
+      program.methods.moveDir("/mv_a", "/mv_a/inner/mv_a").accounts(dirAccounts).rpc(),
+      "MoveIntoOwnSubtree"
+    );
+
+    await program.methods.moveDir("/mv_a", "/mv_b/mv_a").accounts(dirAccounts).rpc();
+    const { entries: listing } = await program.methods.listDir("/mv_b/mv_a", 0, 0, { name: {} }, false, { kind: { both: {} }, tag: null, pinnedOnly: false }, false).accounts(readAccounts).view();
+    expect(listing.map(e => e.name)).to.deep.equal(["inner"]);
+  });
+
+
+  // --- Copy ---
+  it("Deep-copies a directory subtree sharing the same blobs", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    const dirAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    await program.methods.addDir("/cp_src", ["t"], false, null).accounts(dirAccounts).rpc();
+    await program.methods.addDir("/cp_src/sub", [], false, null).accounts(dirAccounts).rpc();
+    await program.methods.addFile("/cp_src/sub/f.txt", [], new BN(1), blob("cp_blob"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+
+    await program.methods.copyDir("/cp_src", "/cp_dst")
+      .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc();
+
+    const original = await program.methods.stat("/cp_src/sub/f.txt", false).accounts(readAccounts).view();
+    const copy = await program.methods.stat("/cp_dst/sub/f.txt", false).accounts(readAccounts).view();
+    expect(copy.walrusBlobId).to.deep.equal(original.walrusBlobId);
+
+    // The copy is independent of the source
+    await program.methods.deleteFile("/cp_src/sub/f.txt", false, false, null, null, null).accounts(writeAccounts).rpc();
+    await program.methods.stat("/cp_dst/sub/f.txt", false).accounts(readAccounts).view();
+  });
+
+
+  it("Creates missing intermediate directories in one instruction", async () => {
+    const dirAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+
+    const created = await program.methods.addDirRecursive("/mkp/a/b", ["leaf"]).accounts(dirAccounts).view();
+    expect(created).to.have.lengthOf(3);
+    await program.methods.addDirRecursive("/mkp/a/b", ["leaf"]).accounts(dirAccounts).rpc();
+
+    const leaf = await program.methods.stat("/mkp/a/b", false).accounts(readAccounts).view();
+    expect(leaf.tags).to.deep.equal(["leaf"]);
+
+    // Re-running over existing components creates nothing
+    const again = await program.methods.addDirRecursive("/mkp/a/b", []).accounts(dirAccounts).view();
+    expect(again).to.be.empty;
+  });
+
+
+  // --- Tag updates ---
+  it("Updates a file's tags in place", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    await program.methods.addFile("/tagged.txt", ["a"], new BN(1), blob("tag_blob"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    const before = await program.methods.stat("/tagged.txt", false).accounts(readAccounts).view();
+
+    await program.methods.updateFileTags("/tagged.txt", ["b", "c"], false)
       .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc();
 
+    const after = await program.methods.stat("/tagged.txt", false).accounts(readAccounts).view();
+    expect(after.tags).to.deep.equal(["b", "c"]);
+    expect(after.createTs.toString()).to.equal(before.createTs.toString());
+
+    await expectError(
+      program.methods.updateFileTags("/tagged.txt", new Array(MAX_TAGS + 1).fill("t"), false).accounts(writeAccounts).rpc(),
+      "TooManyTags"
+    );
+  });
+
+
+  it("Updates a file's blob in place, keeping id and create_ts", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    await program.methods.addFile("/reupload.txt", [], new BN(1), blob("old_blob"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    const before = await program.methods.stat("/reupload.txt", false).accounts(readAccounts).view();
+    const idBefore = (await program.account.childrenFilesPda.fetch(rootChildrenFilesPda)).data.find(f => f.key === "reupload.txt").value;
+
+    await program.methods.updateFile("/reupload.txt", blob("new_blob"), new BN(42), new BN(900), null, null, null, null, null, false, null, null).accounts(writeAccounts).rpc();
+
+    const after = await program.methods.stat("/reupload.txt", false).accounts(readAccounts).view();
+    expect(after.walrusBlobId).to.deep.equal(blob("new_blob"));
+    expect(after.size.toNumber()).to.equal(42);
+    expect(after.walrusEpochTill.toNumber()).to.equal(900);
+    expect(after.createTs.toString()).to.equal(before.createTs.toString());
+    const idAfter = (await program.account.childrenFilesPda.fetch(rootChildrenFilesPda)).data.find(f => f.key === "reupload.txt").value;
+    expect(idAfter.toNumber()).to.equal(idBefore.toNumber());
+  });
+
+
+  it("Extends a file's end epoch but never shortens it", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    await program.methods.addFile("/renew.txt", [], new BN(1), blob("renew_blob"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+
+    await program.methods.extendFileEpoch("/renew.txt", new BN(800)).accounts(writeAccounts).rpc();
+    const statResult = await program.methods.stat("/renew.txt", false).accounts(readAccounts).view();
+    expect(statResult.walrusEpochTill.toNumber()).to.equal(800);
+
+    await expectError(
+      program.methods.extendFileEpoch("/renew.txt", new BN(750)).accounts(writeAccounts).rpc(),
+      "EpochRegression"
+    );
+  });
+
+
+  // --- Modification time ---
+  it("Bumps modify_ts on touch and updates but keeps create_ts", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    await program.methods.addFile("/touched.txt", [], new BN(1), blob("touch_blob"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    const before = await program.methods.stat("/touched.txt", false).accounts(readAccounts).view();
+    expect(before.modifyTs.toString()).to.equal(before.createTs.toString());
+
+    await new Promise(resolve => setTimeout(resolve, 1500));
+    await program.methods.touch("/touched.txt").accounts(writeAccounts).rpc();
+
+    const after = await program.methods.stat("/touched.txt", false).accounts(readAccounts).view();
+    expect(after.createTs.toString()).to.equal(before.createTs.toString());
+    expect(after.modifyTs.gte(before.modifyTs)).to.be.true;
+  });
+
+
+  // --- Batches ---
+  it("Adds several files in one instruction, skipping existing names", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    await program.methods.addDir("/batch", [], false, null).accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc();
+    await program.methods.addFile("/batch/b.txt", [], new BN(1), blob("batch_b_orig"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+
+    const entry = (name: string) => ({ name, tags: [], size: new BN(1), walrusBlobId: blob(`batch_${name}`), endEpoch: new BN(700) });
+    await program.methods.batchAddFiles("/batch", [entry("a.txt"), entry("b.txt"), entry("c.txt")]).accounts(writeAccounts).rpc();
+
+    const { entries: listing } = await program.methods.listDir("/batch", 0, 0, { name: {} }, false, { kind: { both: {} }, tag: null, pinnedOnly: false }, false).accounts(readAccounts).view();
+    expect(listing.map(e => e.name)).to.deep.equal(["a.txt", "b.txt", "c.txt"]);
+    const skipped = await program.methods.stat("/batch/b.txt", false).accounts(readAccounts).view();
+    expect(skipped.walrusBlobId).to.deep.equal(blob("batch_b_orig"));
+  });
+
+
+  it("Deletes several files in one instruction, reporting missing paths", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+
+    await program.methods.batchDeleteFiles(["/batch/a.txt", "/batch/missing.txt", "/batch/c.txt"], false).accounts(writeAccounts).rpc();
+
+    const { entries: listing } = await program.methods.listDir("/batch", 0, 0, { name: {} }, false, { kind: { both: {} }, tag: null, pinnedOnly: false }, false).accounts(readAccounts).view();
+    expect(listing.map(e => e.name)).to.deep.equal(["b.txt"]);
+  });
+
+
+  // --- Lookups ---
+  it("Reports whether a path is a file, a directory or absent", async () => {
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    expect(await program.methods.exists("/batch/b.txt").accounts(readAccounts).view()).to.deep.equal({ file: {} });
+    expect(await program.methods.exists("/batch").accounts(readAccounts).view()).to.deep.equal({ dir: {} });
+    expect(await program.methods.exists("/batch/nope.txt").accounts(readAccounts).view()).to.deep.equal({ none: {} });
+    expect(await program.methods.exists("/no_parent/nope.txt").accounts(readAccounts).view()).to.deep.equal({ none: {} });
+  });
+
+
+  it("Resolves paths to stable object ids", async () => {
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    const resolved = await program.methods.resolvePath("/batch/b.txt").accounts(readAccounts).view();
+    expect(resolved.isDir).to.be.false;
+
+    const fileArena = await program.account.fileArenaPda.fetch(fileArenaPda);
+    const entry = fileArena.data.find(f => f.key.eq(resolved.objectId));
+    expect(entry.value.walrusBlobId).to.deep.equal(blob("batch_b_orig"));
+
+    const root = await program.methods.resolvePath("/").accounts(readAccounts).view();
+    expect(root.isDir).to.be.true;
+    expect(root.objectId.toNumber()).to.equal(0);
+  });
+
+
+  it("Stats and lists entries by object id", async () => {
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    const dir = await program.methods.resolvePath("/batch").accounts(readAccounts).view();
+    const file = await program.methods.resolvePath("/batch/b.txt").accounts(readAccounts).view();
+
+    const byId = await program.methods.statById(file.objectId, false).accounts(readAccounts).view();
+    expect(byId.walrusBlobId).to.deep.equal(blob("batch_b_orig"));
+
+    const { entries: byIdListing } = await program.methods.listDirById(dir.objectId, 0, 0, { name: {} }, false, { kind: { both: {} }, tag: null, pinnedOnly: false }).accounts(readAccounts).view();
+    const { entries: byPathListing } = await program.methods.listDir("/batch", 0, 0, { name: {} }, false, { kind: { both: {} }, tag: null, pinnedOnly: false }, false).accounts(readAccounts).view();
+    expect(byIdListing.map(e => e.name)).to.deep.equal(byPathListing.map(e => e.name));
+
+    await expectError(program.methods.statById(new BN(999999), true).accounts(readAccounts).view(), "PathNotFound");
+  });
+
+
+  // --- Listing options ---
+  it("Pages through a directory listing", async () => {
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    const first = await program.methods.listDir("/sorted", 0, 2, { name: {} }, false, { kind: { both: {} }, tag: null, pinnedOnly: false }, false).accounts(readAccounts).view();
+    expect(first.totalCount).to.equal(3);
+    expect(first.entries.map(e => e.name)).to.deep.equal(["file1.txt", "File2.txt"]);
+
+    const second = await program.methods.listDir("/sorted", 2, 2, { name: {} }, false, { kind: { both: {} }, tag: null, pinnedOnly: false }, false).accounts(readAccounts).view();
+    expect(second.entries.map(e => e.name)).to.deep.equal(["file10.txt"]);
+  });
+
+
+  it("Sorts listings by the requested field and direction", async () => {
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    const { entries: descending } = await program.methods.listDir("/sorted", 0, 0, { name: {} }, true, { kind: { both: {} }, tag: null, pinnedOnly: false }, false).accounts(readAccounts).view();
+    expect(descending.map(e => e.name)).to.deep.equal(["file10.txt", "File2.txt", "file1.txt"]);
+
+    const { entries: dirsFirst } = await program.methods.listDir("/cp_dst", 0, 0, { dirsFirst: {} }, false, { kind: { both: {} }, tag: null, pinnedOnly: false }, false).accounts(readAccounts).view();
+    expect(dirsFirst[0].isDir).to.be.true;
+
+    const { entries: bySize } = await program.methods.listDir("/", 0, 0, { size: {} }, true, { kind: { both: {} }, tag: null, pinnedOnly: false }, false).accounts(readAccounts).view();
+    for (let i = 1; i < bySize.length; i++) {
+      expect(bySize[i - 1].size.gte(bySize[i].size)).to.be.true;
+    }
+  });
+
+
+  it("Filters listings by entry kind and tag", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    await program.methods.addDir("/gallery", [], false, null).accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc();
+    await program.methods.addDir("/gallery/albums", ["image"], false, null).accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc();
+    await program.methods.addFile("/gallery/cat.png", ["image"], new BN(1), blob("gallery_cat"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    await program.methods.addFile("/gallery/notes.txt", ["text"], new BN(1), blob("gallery_notes"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+
+    const images = await program.methods.listDir("/gallery", 0, 0, { name: {} }, false, { kind: { filesOnly: {} }, tag: "image", pinnedOnly: false }, false).accounts(readAccounts).view();
+    expect(images.totalCount).to.equal(1);
+    expect(images.entries.map(e => e.name)).to.deep.equal(["cat.png"]);
+
+    const dirs = await program.methods.listDir("/gallery", 0, 0, { name: {} }, false, { kind: { dirsOnly: {} }, tag: null, pinnedOnly: false }, false).accounts(readAccounts).view();
+    expect(dirs.entries.map(e => e.name)).to.deep.equal(["albums"]);
+  });
+
+
+  it("Exports a subtree with a depth limit and dir-id cursor", async () => {
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+
+    // /mkp/a/b: depth 1 stops after /mkp/a
+    const shallow = await program.methods.getDirAll("/mkp", 1, new BN(0), 0).accounts(readAccounts).view();
+    expect(shallow.dirs).to.have.lengthOf(2);
+    expect(shallow.nextCursor).to.be.null;
+
+    const firstPage = await program.methods.getDirAll("/mkp", null, new BN(0), 2).accounts(readAccounts).view();
+    expect(firstPage.dirs).to.have.lengthOf(2);
+    expect(firstPage.nextCursor).to.not.be.null;
+
+    const secondPage = await program.methods.getDirAll("/mkp", null, firstPage.nextCursor, 2).accounts(readAccounts).view();
+    expect(secondPage.dirs).to.have.lengthOf(1);
+    expect(secondPage.dirs[0].id.eq(firstPage.nextCursor)).to.be.true;
+    expect(secondPage.nextCursor).to.be.null;
+  });
+
+
+  // --- Accounting ---
+  it("Reports recursive size and counts for a directory", async () => {
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    // /level1: fileA.txt (100) and level2/fileB.txt (200)
+    const usage = await program.methods.dirSize("/level1").accounts(readAccounts).view();
+    expect(usage.totalBytes.toNumber()).to.equal(300);
+    expect(usage.fileCount.toNumber()).to.equal(2);
+    expect(usage.dirCount.toNumber()).to.equal(1);
+  });
+
+
+  it("Reports whole-filesystem statistics", async () => {
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    const stats = await program.methods.fsStats().accounts(readAccounts).view();
+    const root = await program.account.walrusfsRootPda.fetch(walrusfsRootPda);
+    const fileArena = await program.account.fileArenaPda.fetch(fileArenaPda);
+
+    expect(stats.objIdCounter.toString()).to.equal(root.objIdCounter.toString());
+    expect(stats.fileArenaEntries).to.equal(fileArena.data.length);
+    expect(stats.totalFiles.toNumber()).to.be.at.most(stats.fileArenaEntries);
+    expect(stats.fileArenaPctUsed).to.be.within(1, 100);
+  });
+
+
+  // --- Queries ---
+  it("Finds entries by tag", async () => {
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    const found = await program.methods.findByTag("image", 0, 0).accounts(readAccounts).view();
+    expect(found.map(e => e.path).sort()).to.deep.equal(["/gallery/albums", "/gallery/cat.png"]);
+
+    const page = await program.methods.findByTag("image", 1, 1).accounts(readAccounts).view();
+    expect(page).to.have.lengthOf(1);
+  });
+
+
+  it("Searches a directory by name prefix and glob", async () => {
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    const byPrefix = await program.methods.search("/sorted/file1", 0).accounts(readAccounts).view();
+    expect(byPrefix.map(e => e.path)).to.deep.equal(["/sorted/file1.txt", "/sorted/file10.txt"]);
+
+    const byGlob = await program.methods.search("/gallery/*.png", 0).accounts(readAccounts).view();
+    expect(byGlob.map(e => e.path)).to.deep.equal(["/gallery/cat.png"]);
+
+    const everything = await program.methods.search("/gallery/", 2).accounts(readAccounts).view();
+    expect(everything).to.have.lengthOf(2);
+  });
+
+
+  it("Finds every path referencing a blob id", async () => {
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    // copy_dir left /cp_dst/sub/f.txt pointing at cp_blob
+    const found = await program.methods.findByBlobId(blob("cp_blob")).accounts(readAccounts).view();
+    expect(found.map(e => e.path)).to.deep.equal(["/cp_dst/sub/f.txt"]);
+  });
+
+
+  // --- Symlinks ---
+  it("Adds symlinks and dereferences them on request", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    await program.methods.addSymlink("/latest", "/gallery", []).accounts(writeAccounts).rpc();
+
+    const link = await program.methods.stat("/latest", false).accounts(readAccounts).view();
+    expect(link.symlinkTarget).to.equal("/gallery");
+    const target = await program.methods.stat("/latest", true).accounts(readAccounts).view();
+    expect(target.isDir).to.be.true;
+
+    const { entries } = await program.methods.listDir("/latest", 0, 0, { name: {} }, false, { kind: { both: {} }, tag: null, pinnedOnly: false }, true).accounts(readAccounts).view();
+    expect(entries.map(e => e.name)).to.include("cat.png");
+
+    // Two links pointing at each other never resolve
+    await program.methods.addSymlink("/loop_a", "/loop_b", []).accounts(writeAccounts).rpc();
+    await program.methods.addSymlink("/loop_b", "/loop_a", []).accounts(writeAccounts).rpc();
+    await expectError(program.methods.stat("/loop_a", true).accounts(readAccounts).view(), "SymlinkLoop");
+  });
+
+
+  // --- Hard links ---
+  it("Hard-links a file and frees it only with the last link", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    await program.methods.addFile("/linked.txt", [], new BN(1), blob("linked_blob"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    await program.methods.link("/linked.txt", "/gallery/linked.txt").accounts(writeAccounts).rpc();
+
+    const original = await program.methods.resolvePath("/linked.txt").accounts(readAccounts).view();
+    const alias = await program.methods.resolvePath("/gallery/linked.txt").accounts(readAccounts).view();
+    expect(alias.objectId.eq(original.objectId)).to.be.true;
+    expect((await program.methods.stat("/linked.txt", false).accounts(readAccounts).view()).linkCount).to.equal(2);
+
+    await program.methods.deleteFile("/linked.txt", false, false, null, null, null).accounts(writeAccounts).rpc();
+    const survivor = await program.methods.stat("/gallery/linked.txt", false).accounts(readAccounts).view();
+    expect(survivor.linkCount).to.equal(1);
+
+    await program.methods.deleteFile("/gallery/linked.txt", false, false, null, null, null).accounts(writeAccounts).rpc();
+    const fileArena = await program.account.fileArenaPda.fetch(fileArenaPda);
+    expect(fileArena.data.find(f => f.key.eq(original.objectId))).to.not.exist;
+
+    // Each name sorts by itself, not by the name the object was created under
+    await program.methods.addDir("/links", [], false, null).accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc();
+    await program.methods.addFile("/links/zeta.txt", [], new BN(1), blob("zeta_blob"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    await program.methods.addFile("/links/mid.txt", [], new BN(1), blob("mid_blob"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    await program.methods.link("/links/zeta.txt", "/links/alpha.txt").accounts(writeAccounts).rpc();
+    const { entries } = await program.methods.listDir("/links", 0, 0, { name: {} }, false, { kind: { both: {} }, tag: null, pinnedOnly: false }, false)
+      .accounts(readAccounts).view();
+    expect(entries.map(e => e.name)).to.deep.equal(["alpha.txt", "mid.txt", "zeta.txt"]);
+  });
+
+
+
+  // --- Trash ---
+  it("Moves entries to the trash and restores them", async () => {
+    const [trashPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("trash"), walrusfsRootPda.toBuffer()],
+      program.programId
+    );
+    await program.methods.initializeTrash()
+      .accounts({ walrusfsRoot: walrusfsRootPda, trash: trashPda, authority: payer.publicKey, systemProgram: web3.SystemProgram.programId }).rpc();
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, trash: trashPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+
+    await program.methods.addFile("/gallery/trashed.txt", [], new BN(1), blob("trash_blob"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    await program.methods.deleteFile("/gallery/trashed.txt", true, false, null, null, null).accounts(writeAccounts).rpc();
+    expect(await program.methods.exists("/gallery/trashed.txt").accounts(readAccounts).view()).to.deep.equal({ none: {} });
+
+    let trash = await program.account.trashPda.fetch(trashPda);
+    expect(trash.entries.map(e => e.originalPath)).to.deep.equal(["/gallery/trashed.txt"]);
+    // reap_orphans always reads the trash, so trashed entries survive it
+    await program.methods.reapOrphans(100).accounts(writeAccounts).rpc();
+
+    await program.methods.restore("/gallery/trashed.txt").accounts(writeAccounts).rpc();
+    expect(await program.methods.exists("/gallery/trashed.txt").accounts(readAccounts).view()).to.deep.equal({ file: {} });
+
+    await program.methods.deleteDir("/mkp", true, true, null).accounts(writeAccounts).rpc();
+    await program.methods.emptyTrash().accounts(writeAccounts).rpc();
+    trash = await program.account.trashPda.fetch(trashPda);
+    expect(trash.entries).to.be.empty;
+    await expectError(program.methods.restore("/mkp").accounts(writeAccounts).rpc(), "PathNotFound");
+  });
+
+
+
+  // --- Sealed files ---
+  it("Rejects changes to sealed files until unsealed", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    await program.methods.addFile("/archive.txt", [], new BN(1), blob("archive_blob"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    await program.methods.sealFile("/archive.txt", false).accounts(writeAccounts).rpc();
+
+    await expectError(program.methods.deleteFile("/archive.txt", false, false, null, null, null).accounts(writeAccounts).rpc(), "FileSealed");
+    await expectError(program.methods.renameFile("/archive.txt", "/archive2.txt", false, null, null, null).accounts(writeAccounts).rpc(), "FileSealed");
+    await expectError(program.methods.updateFileTags("/archive.txt", ["x"], false).accounts(writeAccounts).rpc(), "FileSealed");
+    await expectError(program.methods.link("/archive.txt", "/archive-link.txt").accounts(writeAccounts).rpc(), "FileSealed");
+
+    await program.methods.unsealFile("/archive.txt").accounts(writeAccounts).rpc();
+    await program.methods.updateFileTags("/archive.txt", ["x"], false).accounts(writeAccounts).rpc();
+
+    await program.methods.sealFile("/archive.txt", true).accounts(writeAccounts).rpc();
+    await expectError(program.methods.unsealFile("/archive.txt").accounts(writeAccounts).rpc(), "SealIsPermanent");
+  });
+
+
+
+  // --- Locks ---
+  it("Holds an advisory lock until unlocked", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    const colleague = web3.Keypair.generate();
+    const colleagueAccounts = { ...writeAccounts, authority: colleague.publicKey };
+    await program.methods.addCoAuthority(colleague.publicKey).accounts({ walrusfsRoot: walrusfsRootPda, authority: payer.publicKey }).rpc();
+    await program.methods.addFile("/locked.txt", [], new BN(1), blob("locked_blob"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    await program.methods.lockFile("/locked.txt", new BN(1000)).accounts(colleagueAccounts).signers([colleague]).rpc();
+
+    const info = await program.methods.stat("/locked.txt", false).accounts(readAccounts).view();
+    expect(info.lock.holder.equals(colleague.publicKey)).to.be.true;
+    await expectError(program.methods.updateFileTags("/locked.txt", ["w"], true).accounts(writeAccounts).rpc(), "FileLocked");
+    await expectError(
+      program.methods.addFile("/locked.txt", [], new BN(2), blob("locked_v2"), new BN(700), null, "", null, { standard: {} }, null, null, true, true, null).accounts(writeAccounts).rpc(),
+      "FileLocked"
+    );
+    await program.methods.batchDeleteFiles(["/locked.txt"], true).accounts(writeAccounts).rpc();
+    expect((await program.methods.stat("/locked.txt", false).accounts(readAccounts).view()).size.toNumber()).to.equal(1);
+
+    // The holder writes through its own lock
+    await program.methods.updateFileTags("/locked.txt", ["held"], true).accounts(colleagueAccounts).signers([colleague]).rpc();
+    await expectError(program.methods.unlockFile("/locked.txt").accounts(writeAccounts).rpc(), "LockNotHeld");
+    await program.methods.unlockFile("/locked.txt").accounts(colleagueAccounts).signers([colleague]).rpc();
+    await program.methods.updateFileTags("/locked.txt", ["w"], true).accounts(writeAccounts).rpc();
+    await program.methods.removeCoAuthority(colleague.publicKey).accounts({ walrusfsRoot: walrusfsRootPda, authority: payer.publicKey }).rpc();
+  });
+
+
+
+  // --- Snapshots ---
+  it("Captures a directory snapshot that survives later changes", async () => {
+    const [snapshotIndexPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("snapshot_index"), walrusfsRootPda.toBuffer()],
+      program.programId
+    );
+    const [snapshotPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("snapshot"), walrusfsRootPda.toBuffer(), Buffer.from("gallery-v1")],
+      program.programId
+    );
+    const [pendingBlobDeletesPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("pending_blob_deletes"), walrusfsRootPda.toBuffer()],
+      program.programId
+    );
+    await program.methods.initializeSnapshotIndex()
+      .accounts({ walrusfsRoot: walrusfsRootPda, snapshotIndex: snapshotIndexPda, authority: payer.publicKey, systemProgram: web3.SystemProgram.programId }).rpc();
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+
+    await program.methods.createSnapshot("/gallery", "gallery-v1")
+      .accounts({ ...writeAccounts, snapshotIndex: snapshotIndexPda, snapshot: snapshotPda, pendingBlobDeletes: pendingBlobDeletesPda, systemProgram: web3.SystemProgram.programId }).rpc();
+    await program.methods.updateFile("/gallery/notes.txt", blob("notes_v2"), new BN(2), new BN(900), null, null, null, null, null, false, null, null).accounts(writeAccounts).rpc();
+
+    const summaries = await program.methods.listSnapshots()
+      .accounts({ owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, snapshotIndex: snapshotIndexPda }).view();
+    expect(summaries.map(s => s.name)).to.deep.equal(["gallery-v1"]);
+
+    const entries = await program.methods.readSnapshot("gallery-v1", 0, 0)
+      .accounts({ owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, snapshot: snapshotPda }).view();
+    const notes = entries.find(e => e.path === "/notes.txt");
+    expect(notes.walrusBlobId).to.deep.equal(blob("gallery_notes"));
+  });
+
+
+
+  it("Diffs a snapshot against the live tree and another snapshot", async () => {
+    const snapshotPdaFor = (name: string) => web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("snapshot"), walrusfsRootPda.toBuffer(), Buffer.from(name)],
+      program.programId
+    )[0];
+    const [snapshotIndexPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("snapshot_index"), walrusfsRootPda.toBuffer()],
+      program.programId
+    );
+    const [pendingBlobDeletesPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("pending_blob_deletes"), walrusfsRootPda.toBuffer()],
+      program.programId
+    );
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+
+    // notes.txt was re-uploaded after gallery-v1 was taken
+    const live = await program.methods.diffSnapshotLive("gallery-v1")
+      .accounts({ ...readAccounts, snapshot: snapshotPdaFor("gallery-v1") }).view();
+    expect(live.modified.map(e => e.path)).to.deep.equal(["/notes.txt"]);
+    expect(live.added).to.be.empty;
+
+    await program.methods.addFile("/gallery/new.png", [], new BN(3), blob("new_png"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    await program.methods.createSnapshot("/gallery", "gallery-v2")
+      .accounts({ ...writeAccounts, snapshotIndex: snapshotIndexPda, snapshot: snapshotPdaFor("gallery-v2"), pendingBlobDeletes: pendingBlobDeletesPda, systemProgram: web3.SystemProgram.programId }).rpc();
+
+    const diff = await program.methods.diffSnapshots("gallery-v1", "gallery-v2")
+      .accounts({ owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, baseSnapshot: snapshotPdaFor("gallery-v1"), otherSnapshot: snapshotPdaFor("gallery-v2") }).view();
+    expect(diff.added.map(e => e.path)).to.deep.equal(["/new.png"]);
+    expect(diff.modified.map(e => e.path)).to.deep.equal(["/notes.txt"]);
+    expect(diff.removed).to.be.empty;
+  });
+
+  it("Holds snapshot blobs out of the delete queue until the snapshot is deleted", async () => {
+    const [snapshotIndexPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("snapshot_index"), walrusfsRootPda.toBuffer()],
+      program.programId
+    );
+    const [snapshotPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("snapshot"), walrusfsRootPda.toBuffer(), Buffer.from("keep-v1")],
+      program.programId
+    );
+    const [pendingBlobDeletesPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("pending_blob_deletes"), walrusfsRootPda.toBuffer()],
+      program.programId
+    );
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, pendingBlobDeletes: pendingBlobDeletesPda, authority: payer.publicKey };
+    await program.methods.addDir("/keep", [], false, null).accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, authority: payer.publicKey }).rpc();
+    await program.methods.addFile("/keep/old.bin", [], new BN(4), blob("keep_old"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    await program.methods.setBlobDeletable("/keep/old.bin", true).accounts(writeAccounts).rpc();
+    await program.methods.createSnapshot("/keep", "keep-v1")
+      .accounts({ ...writeAccounts, snapshotIndex: snapshotIndexPda, snapshot: snapshotPda, systemProgram: web3.SystemProgram.programId }).rpc();
+
+    await program.methods.deleteFile("/keep/old.bin", false, false, null, null, null).accounts(writeAccounts).rpc();
+    let queue = await program.account.pendingBlobDeletesPda.fetch(pendingBlobDeletesPda);
+    expect(queue.blobIds).to.not.deep.include(blob("keep_old"));
+
+    await program.methods.deleteSnapshot("keep-v1")
+      .accounts({ walrusfsRoot: walrusfsRootPda, fileArena: fileArenaPda, snapshotIndex: snapshotIndexPda, snapshot: snapshotPda, pendingBlobDeletes: pendingBlobDeletesPda, authority: payer.publicKey }).rpc();
+    queue = await program.account.pendingBlobDeletesPda.fetch(pendingBlobDeletesPda);
+    expect(queue.blobIds).to.deep.include(blob("keep_old"));
+    expect(queue.pinnedBlobs.map(p => p.blobId)).to.not.deep.include(blob("keep_old"));
+    const summaries = await program.methods.listSnapshots()
+      .accounts({ owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, snapshotIndex: snapshotIndexPda }).view();
+    expect(summaries.map(s => s.name)).to.not.include("keep-v1");
+  });
+
+  it("Publishes a snapshot as a read-only clone that pins its blobs", async () => {
+    const [snapshotPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("snapshot"), walrusfsRootPda.toBuffer(), Buffer.from("gallery-v1")],
+      program.programId
+    );
+    const [publishedClonePda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("published"), walrusfsRootPda.toBuffer(), Buffer.from("gallery-v1")],
+      program.programId
+    );
+    const [pendingBlobDeletesPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("pending_blob_deletes"), walrusfsRootPda.toBuffer()],
+      program.programId
+    );
+    await program.methods.publishClone("gallery-v1")
+      .accounts({ walrusfsRoot: walrusfsRootPda, snapshot: snapshotPda, publishedClone: publishedClonePda, pendingBlobDeletes: pendingBlobDeletesPda, authority: payer.publicKey, systemProgram: web3.SystemProgram.programId }).rpc();
+
+    // The live file moved on to notes_v2; the clone still serves the snapshot's blob
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, publishedClone: publishedClonePda };
+    const notes = await program.methods.resolvePublished("gallery-v1", "/notes.txt").accounts(readAccounts).view();
+    expect(notes.walrusBlobId).to.deep.equal(blob("gallery_notes"));
+    const entries = await program.methods.readPublished("gallery-v1", 0, 0).accounts(readAccounts).view();
+    expect(entries.map(e => e.path)).to.include("/notes.txt");
+
+    const queue = await program.account.pendingBlobDeletesPda.fetch(pendingBlobDeletesPda);
+    const pin = queue.pinnedBlobs.find(p => Buffer.from(p.blobId).equals(Buffer.from(blob("gallery_notes"))));
+    expect(pin.holders).to.equal(2);
+    await expectError(program.methods.resolvePublished("gallery-v1", "/missing.txt").accounts(readAccounts).view(), "PathNotFound");
+  });
+
+
+
+  // --- Journals ---
+  it("Appends segments to a journal", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    await program.methods.addJournal("/app.log", []).accounts(writeAccounts).rpc();
+    await program.methods.appendSegment("/app.log", blob("log_seg_0"), new BN(100)).accounts(writeAccounts).rpc();
+    await program.methods.appendSegment("/app.log", blob("log_seg_1"), new BN(50)).accounts(writeAccounts).rpc();
+
+    const info = await program.methods.stat("/app.log", false).accounts(readAccounts).view();
+    expect(info.size.toNumber()).to.equal(150);
+    expect(info.journalSegments.map(s => s.walrusBlobId)).to.deep.equal([blob("log_seg_0"), blob("log_seg_1")]);
+
+    const found = await program.methods.findByBlobId(blob("log_seg_1")).accounts(readAccounts).view();
+    expect(found.map(e => e.path)).to.deep.equal(["/app.log"]);
+
+    await expectError(program.methods.updateFile("/app.log", blob("other"), new BN(1), new BN(900), null, null, null, null, null, false, null, null).accounts(writeAccounts).rpc(), "JournalIsAppendOnly");
+    await expectError(program.methods.appendSegment("/gallery/notes.txt", blob("seg"), new BN(1)).accounts(writeAccounts).rpc(), "NotAJournal");
+  });
+
+
+
+  // --- Chunked files ---
+  it("Builds a chunked file from ordered chunks", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    await program.methods.addChunkedFile("/video.mp4", [], new BN(700)).accounts(writeAccounts).rpc();
+    await program.methods.addChunk("/video.mp4", blob("video_c0"), new BN(0), new BN(1000)).accounts(writeAccounts).rpc();
+    await program.methods.addChunk("/video.mp4", blob("video_c1"), new BN(1000), new BN(500)).accounts(writeAccounts).rpc();
+
+    const info = await program.methods.stat("/video.mp4", false).accounts(readAccounts).view();
+    expect(info.size.toNumber()).to.equal(1500);
+    expect(info.chunks.map(c => c.walrusBlobId)).to.deep.equal([blob("video_c0"), blob("video_c1")]);
+
+    await expectError(program.methods.addChunk("/video.mp4", blob("video_c2"), new BN(1200), new BN(10)).accounts(writeAccounts).rpc(), "InvalidChunk");
+  });
+
+
+
+  // --- Default tags ---
+  it("Merges inherited default tags into new files", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    await program.methods.addDirRecursive("/projx/reports", []).accounts(writeAccounts).rpc();
+    await program.methods.setDefaultTags("/projx", ["project-x"]).accounts(writeAccounts).rpc();
+    await program.methods.setDefaultTags("/projx/reports", ["report"]).accounts(writeAccounts).rpc();
+
+    await program.methods.addFile("/projx/reports/q1.pdf", ["final"], new BN(1), blob("q1_blob"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    const info = await program.methods.stat("/projx/reports/q1.pdf", false).accounts(readAccounts).view();
+    expect(info.tags).to.deep.equal(["final", "report", "project-x"]);
+  });
+
+
+
+  it("Renames a tag across the filesystem", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    await program.methods.renameTag("project-x", "project-y", "/projx").accounts(writeAccounts).rpc();
+
+    const info = await program.methods.stat("/projx/reports/q1.pdf", false).accounts(readAccounts).view();
+    expect(info.tags).to.deep.equal(["final", "report", "project-y"]);
+
+    // The directory default was renamed too, so new files pick up the new tag
+    await program.methods.addFile("/projx/q2.pdf", [], new BN(1), blob("q2_blob"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    const next = await program.methods.stat("/projx/q2.pdf", false).accounts(readAccounts).view();
+    expect(next.tags).to.deep.equal(["project-y"]);
+  });
+
+
+
+  // --- Compare-and-swap guards ---
+  it("Rejects writes whose expected object or blob is stale", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    await program.methods.addFile("/cas.txt", [], new BN(1), blob("cas_v1"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    const { objectId } = await program.methods.resolvePath("/cas.txt").accounts(readAccounts).view();
+
+    await program.methods.updateFile("/cas.txt", blob("cas_v2"), new BN(2), new BN(700), null, null, null, null, null, false, objectId, blob("cas_v1")).accounts(writeAccounts).rpc();
+    // A second writer that read cas_v1 loses the race
+    await expectError(
+      program.methods.updateFile("/cas.txt", blob("cas_v3"), new BN(3), new BN(700), null, null, null, null, null, false, null, blob("cas_v1")).accounts(writeAccounts).rpc(),
+      "PreconditionFailed"
+    );
+    await expectError(
+      program.methods.deleteFile("/cas.txt", false, false, objectId.addn(1), null, null).accounts(writeAccounts).rpc(),
+      "PreconditionFailed"
+    );
+    await program.methods.deleteFile("/cas.txt", false, false, objectId, blob("cas_v2"), null).accounts(writeAccounts).rpc();
+  });
+
+  // --- Directory versions ---
+  it("Rejects writes against a stale directory listing", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    const listGallery = () => program.methods.listDir("/gallery", 0, 0, { name: {} }, false, { kind: { both: {} }, tag: null, pinnedOnly: false }, false).accounts(readAccounts).view();
+
+    const { version } = await listGallery();
+    await program.methods.addFile("/gallery/v1.txt", [], new BN(1), blob("ver_blob"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, version).accounts(writeAccounts).rpc();
+    const after = await listGallery();
+    expect(after.version.toNumber()).to.equal(version.toNumber() + 1);
+
+    // A writer still holding the first listing is rejected
+    await expectError(
+      program.methods.addFile("/gallery/v2.txt", [], new BN(1), blob("ver_blob"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, version).accounts(writeAccounts).rpc(),
+      "VersionMismatch"
+    );
+    await program.methods.deleteFile("/gallery/v1.txt", false, false, null, null, after.version).accounts(writeAccounts).rpc();
+  });
+
+  // --- Resumable delete ---
+  it("Deletes a large subtree over several transactions", async () => {
+    const [deletionCursorPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("deletion_cursor"), walrusfsRootPda.toBuffer()],
+      program.programId
+    );
+    await program.methods.initializeDeletionCursor()
+      .accounts({ walrusfsRoot: walrusfsRootPda, deletionCursor: deletionCursorPda, authority: payer.publicKey, systemProgram: web3.SystemProgram.programId }).rpc();
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, deletionCursor: deletionCursorPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+
+    await program.methods.addDirRecursive("/bulk/x/y", []).accounts(writeAccounts).rpc();
+    await program.methods.addFile("/bulk/x/one.txt", [], new BN(1), blob("bulk_one"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    await program.methods.addFile("/bulk/x/y/two.txt", [], new BN(1), blob("bulk_two"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+
+    await program.methods.deleteDirBegin("/bulk", null).accounts(writeAccounts).rpc();
+    expect(await program.methods.exists("/bulk").accounts(readAccounts).view()).to.deep.equal({ none: {} });
+    await expectError(program.methods.deleteDirBegin("/gallery", null).accounts(writeAccounts).rpc(), "DeletionInProgress");
+
+    let rounds = 0;
+    let done = false;
+    while (!done) {
+      done = await program.methods.deleteDirContinue(1).accounts(writeAccounts).view();
+      await program.methods.deleteDirContinue(1).accounts(writeAccounts).rpc();
+      rounds++;
+    }
+    expect(rounds).to.equal(3);
+    const cursor = await program.account.deletionCursorPda.fetch(deletionCursorPda);
+    expect(cursor.dirsRemoved.toNumber()).to.equal(3);
+    expect(cursor.filesRemoved.toNumber()).to.equal(2);
+  });
+
+  // --- Modify and access times ---
+  it("Tracks directory modify_ts and explicit access_ts", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    const listRoot = () => program.methods.listDir("/", 0, 0, { name: {} }, false, { kind: { both: {} }, tag: null, pinnedOnly: false }, false).accounts(readAccounts).view();
+
+    await program.methods.addDir("/times", [], false, null).accounts(writeAccounts).rpc();
+    const before = (await listRoot()).entries.find((e) => e.name === "times");
+    expect(before.modifyTs.toNumber()).to.equal(before.createTs.toNumber());
+    expect(before.accessTs).to.be.null;
+
+    await new Promise((resolve) => setTimeout(resolve, 1100));
+    await program.methods.addFile("/times/t.txt", [], new BN(1), blob("times_blob"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    const after = (await listRoot()).entries.find((e) => e.name === "times");
+    expect(after.modifyTs.toNumber()).to.be.greaterThan(before.modifyTs.toNumber());
+
+    await program.methods.recordAccess("/times/t.txt").accounts(readAccounts).rpc();
+    const { entries } = await program.methods.listDir("/times", 0, 0, { name: {} }, false, { kind: { both: {} }, tag: null, pinnedOnly: false }, false).accounts(readAccounts).view();
+    expect(entries[0].accessTs).to.not.be.null;
+  });
+
+  // --- Checksums ---
+  it("Stores the uploader's SHA-256 digest and clears it on re-upload", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const statAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    const digest = Array.from(createHash("sha256").update("checksum contents").digest());
+
+    await program.methods.addFile("/summed.txt", [], new BN(17), blob("summed_blob"), new BN(700), digest, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    let stat = await program.methods.stat("/summed.txt", false).accounts(statAccounts).view();
+    expect(stat.sha256).to.deep.equal(digest);
+
+    await program.methods.updateFile("/summed.txt", blob("summed_v2"), new BN(3), new BN(700), null, null, null, null, null, false, null, null).accounts(writeAccounts).rpc();
+    stat = await program.methods.stat("/summed.txt", false).accounts(statAccounts).view();
+    expect(stat.sha256).to.be.null;
+  });
+
+  // --- Content types ---
+  it("Records a content type that survives re-uploads unless replaced", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const statAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+
+    await program.methods.addFile("/page.html", [], new BN(1), blob("page_v1"), new BN(700), null, "text/html", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    await program.methods.updateFile("/page.html", blob("page_v2"), new BN(2), new BN(700), null, null, null, null, null, false, null, null).accounts(writeAccounts).rpc();
+    let stat = await program.methods.stat("/page.html", false).accounts(statAccounts).view();
+    expect(stat.contentType).to.equal("text/html");
+
+    await program.methods.updateFile("/page.html", blob("page_v3"), new BN(2), new BN(700), null, "application/xhtml+xml", null, null, null, false, null, null).accounts(writeAccounts).rpc();
+    stat = await program.methods.stat("/page.html", false).accounts(statAccounts).view();
+    expect(stat.contentType).to.equal("application/xhtml+xml");
+
+    await expectError(
+      program.methods.addFile("/bad.bin", [], new BN(1), blob("bad_blob"), new BN(700), null, "x".repeat(65), null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc(),
+      "StringTooLong"
+    );
+  });
+
+  // --- Extended attributes ---
+  it("Sets and removes extended attributes on files and directories", async () => {
+    const xattrAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+
+    await program.methods.setXattr("/gallery/cat.png", "exif.camera", "X100V").accounts(xattrAccounts).rpc();
+    await program.methods.setXattr("/gallery/cat.png", "exif.camera", "X100VI").accounts(xattrAccounts).rpc();
+    await program.methods.setXattr("/gallery", "schema", "sha256:abc").accounts(xattrAccounts).rpc();
+    expect(await program.methods.getXattrs("/gallery/cat.png").accounts(readAccounts).view())
+      .to.deep.equal([{ key: "exif.camera", value: "X100VI" }]);
+    expect(await program.methods.getXattrs("/gallery").accounts(readAccounts).view())
+      .to.deep.equal([{ key: "schema", value: "sha256:abc" }]);
+
+    await program.methods.removeXattr("/gallery", "schema").accounts(xattrAccounts).rpc();
+    expect(await program.methods.getXattrs("/gallery").accounts(readAccounts).view()).to.be.empty;
+    await expectError(program.methods.removeXattr("/gallery", "schema").accounts(xattrAccounts).rpc(), "XattrNotFound");
+    await expectError(program.methods.setXattr("/archive.txt", "k", "v").accounts(xattrAccounts).rpc(), "FileSealed");
+  });
+
+  // --- Descriptions ---
+  it("Stores descriptions in sidecar accounts and surfaces them in stat", async () => {
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    const { objectId } = await program.methods.resolvePath("/gallery").accounts(readAccounts).view();
+    const [descriptionPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("description"), walrusfsRootPda.toBuffer(), objectId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, description: descriptionPda, authority: payer.publicKey };
+
+    await program.methods.initDescription("/gallery", objectId)
+      .accounts({ ...writeAccounts, systemProgram: web3.SystemProgram.programId }).rpc();
+    await program.methods.setDescription("/gallery", "Holiday photos, 2024").accounts(writeAccounts).rpc();
+
+    const { entries } = await program.methods.listDir("/", 0, 0, { name: {} }, false, { kind: { both: {} }, tag: null, pinnedOnly: false }, false).accounts(readAccounts).view();
+    const listed = entries.find((e) => e.name === "gallery");
+    expect(listed.hasDescription).to.be.true;
+    expect(listed.description).to.be.null;
+
+    const stat = await program.methods.stat("/gallery", false).accounts({ ...readAccounts, description: descriptionPda }).view();
+    expect(stat.description).to.equal("Holiday photos, 2024");
+
+    await expectError(program.methods.setDescription("/gallery", "x".repeat(257)).accounts(writeAccounts).rpc(), "DescriptionTooLong");
+    await expectError(program.methods.setDescription("/gallery/cat.png", "wrong object").accounts(writeAccounts).rpc(), "ObjectIdMismatch");
+  });
+
+  // --- Preview blobs ---
+  it("Carries a preview blob that is replaced with each upload", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+
+    await program.methods.addFile("/gallery/dog.jpg", [], new BN(4096), blob("dog_full"), new BN(700), null, "image/jpeg", "dog_thumb", { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    const { entries } = await program.methods.listDir("/gallery", 0, 0, { name: {} }, false, { kind: { filesOnly: {} }, tag: null, pinnedOnly: false }, false).accounts(readAccounts).view();
+    expect(entries.find((e) => e.name === "dog.jpg").previewBlobId).to.equal("dog_thumb");
+
+    await program.methods.updateFile("/gallery/dog.jpg", blob("dog_full_v2"), new BN(4096), new BN(700), null, null, null, null, null, false, null, null).accounts(writeAccounts).rpc();
+    const stat = await program.methods.stat("/gallery/dog.jpg", false).accounts(readAccounts).view();
+    expect(stat.previewBlobId).to.be.null;
+  });
+
+  // --- Storage classes ---
+  it("Sets a storage class at creation and changes it later", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+
+    await program.methods.addFile("/scratch.bin", [], new BN(1), blob("scratch_tier"), new BN(700), null, "", null, { ephemeral: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    let stat = await program.methods.stat("/scratch.bin", false).accounts(readAccounts).view();
+    expect(stat.storageClass).to.deep.equal({ ephemeral: {} });
+
+    await program.methods.setStorageClass("/scratch.bin", { archive: {} }).accounts(writeAccounts).rpc();
+    stat = await program.methods.stat("/scratch.bin", false).accounts(readAccounts).view();
+    expect(stat.storageClass).to.deep.equal({ archive: {} });
+  });
+
+
+  // --- Pinning ---
+  it("Pins entries and lists only pinned ones on request", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    const pinnedOnly = { kind: { both: {} }, tag: null, pinnedOnly: true };
+
+    await program.methods.pin("/gallery/dog.jpg").accounts(writeAccounts).rpc();
+    let { entries } = await program.methods.listDir("/gallery", 0, 0, { name: {} }, false, pinnedOnly, false).accounts(readAccounts).view();
+    expect(entries.map((e) => e.name)).to.deep.equal(["dog.jpg"]);
+    expect(entries[0].pinned).to.be.true;
+
+    await program.methods.unpin("/gallery/dog.jpg").accounts(writeAccounts).rpc();
+    ({ entries } = await program.methods.listDir("/gallery", 0, 0, { name: {} }, false, pinnedOnly, false).accounts(readAccounts).view());
+    expect(entries).to.be.empty;
+  });
+
+
+  // --- Object kinds ---
+  it("Reports the kind of every listed entry", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+
+    await program.methods.addDir("/kinds", [], false, null).accounts(writeAccounts).rpc();
+    await program.methods.addFile("/kinds/plain.txt", [], new BN(1), blob("kinds_plain"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    await program.methods.addSymlink("/kinds/link", "/kinds/plain.txt", []).accounts(writeAccounts).rpc();
+    await program.methods.addJournal("/kinds/events.log", []).accounts(writeAccounts).rpc();
+    await program.methods.addDir("/kinds/sub", [], false, null).accounts(writeAccounts).rpc();
+
+    const { entries } = await program.methods.listDir("/kinds", 0, 0, { name: {} }, false, { kind: { both: {} }, tag: null, pinnedOnly: false }, false).accounts(readAccounts).view();
+    const kinds = Object.fromEntries(entries.map((e) => [e.name, e.kind]));
+    expect(kinds["plain.txt"]).to.deep.equal({ regularFile: {} });
+    expect(kinds["link"]).to.deep.equal({ symlink: {} });
+    expect(kinds["events.log"]).to.deep.equal({ journal: {} });
+    expect(kinds["sub"]).to.be.null;
+  });
+
+
+  // --- Sui object ids ---
+  it("Records the Sui object id of each uploaded blob", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+
+    await program.methods.addFile("/renewable.bin", [], new BN(1), blob("renewable_v1"), new BN(700), null, "", null, { standard: {} }, blob("sui_obj_v1"), null, false, false, null).accounts(writeAccounts).rpc();
+    let stat = await program.methods.stat("/renewable.bin", false).accounts(readAccounts).view();
+    expect(stat.suiObjectId).to.deep.equal(blob("sui_obj_v1"));
+
+    await program.methods.updateFile("/renewable.bin", blob("renewable_v2"), new BN(1), new BN(700), null, null, null, blob("sui_obj_v2"), null, false, null, null).accounts(writeAccounts).rpc();
+    stat = await program.methods.stat("/renewable.bin", false).accounts(readAccounts).view();
+    expect(stat.suiObjectId).to.deep.equal(blob("sui_obj_v2"));
+  });
+
+
+  // --- Encryption metadata ---
+  it("Round-trips encryption metadata and rejects malformed parameters", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    const encryption = { scheme: "aes-256-gcm", nonce: Buffer.alloc(12, 7), wrappedKey: Buffer.alloc(48, 9) };
+
+    await program.methods.addFile("/secret.bin", [], new BN(64), blob("secret_v1"), new BN(700), null, "", null, { standard: {} }, null, encryption, false, false, null).accounts(writeAccounts).rpc();
+    let stat = await program.methods.stat("/secret.bin", false).accounts(readAccounts).view();
+    expect(stat.encryption.scheme).to.equal("aes-256-gcm");
+    expect(Buffer.from(stat.encryption.nonce).equals(encryption.nonce)).to.be.true;
+    expect(Buffer.from(stat.encryption.wrappedKey).equals(encryption.wrappedKey)).to.be.true;
+
+    await program.methods.updateFile("/secret.bin", blob("secret_plain"), new BN(64), new BN(700), null, null, null, null, null, false, null, null).accounts(writeAccounts).rpc();
+    stat = await program.methods.stat("/secret.bin", false).accounts(readAccounts).view();
+    expect(stat.encryption).to.be.null;
+
+    await expectError(
+      program.methods.addFile("/bad_secret.bin", [], new BN(1), blob("bad_secret"), new BN(700), null, "", null, { standard: {} }, null, { ...encryption, scheme: "" }, false, false, null).accounts(writeAccounts).rpc(),
+      "InvalidEncryption"
+    );
+  });
+
+
+  // --- Schema versions ---
+  it("Stamps every PDA with the schema version and skips current ones on migration", async () => {
+    const root = await program.account.walrusfsRootPda.fetch(walrusfsRootPda);
+    expect(root.schemaVersion).to.equal(1);
+    const fileArena = await program.account.fileArenaPda.fetch(fileArenaPda);
+    expect(fileArena.schemaVersion).to.equal(1);
+    const dirArena = await program.account.dirArenaPda.fetch(dirArenaPda);
+    expect(dirArena.schemaVersion).to.equal(1);
+
+    const before = (await provider.connection.getAccountInfo(fileArenaPda)).data.length;
+    await program.methods.migrateFilesystem()
+      .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey })
+      .rpc();
+    expect((await provider.connection.getAccountInfo(fileArenaPda)).data.length).to.equal(before);
+  });
+
+
+  // --- Filesystem metadata ---
+  it("Sets a name, description and tags on the filesystem", async () => {
+    await program.methods.setFsMetadata("Team photos", "Shared album for the design team.", ["photos", "team"])
+      .accounts({ walrusfsRoot: walrusfsRootPda, authority: payer.publicKey }).rpc();
+    const root = await program.account.walrusfsRootPda.fetch(walrusfsRootPda);
+    expect(root.name).to.equal("Team photos");
+    expect(root.description).to.equal("Shared album for the design team.");
+    expect(root.tags).to.deep.equal(["photos", "team"]);
+
+    await expectError(
+      program.methods.setFsMetadata("x".repeat(65), "", []).accounts({ walrusfsRoot: walrusfsRootPda, authority: payer.publicKey }).rpc(),
+      "StringTooLong"
+    );
+  });
+
+
+  // --- Filesystem limits ---
+  it("Lowers the filesystem limits but refuses to raise them", async () => {
+    let root = await program.account.walrusfsRootPda.fetch(walrusfsRootPda);
+    expect(root.limits.maxTags).to.equal(5);
+    expect(root.limits.maxPathDepth).to.equal(160);
+
+    const lowered = { ...root.limits, maxPathDepth: 64, maxChildrenPerDir: 100000 };
+    await program.methods.setFsLimits(lowered).accounts({ walrusfsRoot: walrusfsRootPda, authority: payer.publicKey }).rpc();
+    root = await program.account.walrusfsRootPda.fetch(walrusfsRootPda);
+    expect(root.limits.maxPathDepth).to.equal(64);
+    expect(root.limits.maxChildrenPerDir).to.equal(100000);
+
+    await expectError(
+      program.methods.setFsLimits({ ...lowered, maxPathDepth: 65 }).accounts({ walrusfsRoot: walrusfsRootPda, authority: payer.publicKey }).rpc(),
+      "InvalidLimits"
+    );
+    await expectError(
+      program.methods.setFsLimits({ ...lowered, maxStringLen: 0 }).accounts({ walrusfsRoot: walrusfsRootPda, authority: payer.publicKey }).rpc(),
+      "InvalidLimits"
+    );
+  });
+
+
+  // --- Tag dictionary ---
+  it("Stores each tag name once and refers to it by id", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, tagDictionary: tagDictionaryPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, tagDictionary: tagDictionaryPda };
+    for (const name of ["/interned_a.txt", "/interned_b.txt"]) {
+      await program.methods.addFile(name, ["interned"], new BN(1), blob(name), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    }
+
+    const dictionary = await program.account.tagDictionaryPda.fetch(tagDictionaryPda);
+    expect(dictionary.tags.filter((t) => t === "interned")).to.have.length(1);
+    const id = dictionary.tags.indexOf("interned");
+    const fileArena = await program.account.fileArenaPda.fetch(fileArenaPda);
+    expect(fileArena.data.filter((kv) => kv.value.tags.includes(id))).to.have.length(2);
+
+    const stat = await program.methods.stat("/interned_a.txt", false).accounts(readAccounts).view();
+    expect(stat.tags).to.deep.equal(["interned"]);
+    const found = await program.methods.findByTag("interned", 0, 0).accounts(readAccounts).view();
+    expect(found.map((e) => e.path).sort()).to.deep.equal(["/interned_a.txt", "/interned_b.txt"]);
+  });
+
+
+  // --- Blob status ---
+  it("Moves a file's blob status forward only", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    await program.methods.addFile("/status.bin", [], new BN(1), blob("status"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    let stat = await program.methods.stat("/status.bin", false).accounts(readAccounts).view();
+    expect(stat.status).to.deep.equal({ pendingCertification: {} });
+
+    await program.methods.setBlobStatus("/status.bin", { certified: {} }).accounts(writeAccounts).rpc();
+    stat = await program.methods.stat("/status.bin", false).accounts(readAccounts).view();
+    expect(stat.status).to.deep.equal({ certified: {} });
+    await expectError(
+      program.methods.setBlobStatus("/status.bin", { pendingCertification: {} }).accounts(writeAccounts).rpc(),
+      "InvalidStatusTransition"
+    );
+
+    // A new blob starts over
+    await program.methods.updateFile("/status.bin", blob("status_v2"), new BN(2), new BN(700), null, null, null, null, null, false, null, null).accounts(writeAccounts).rpc();
+    stat = await program.methods.stat("/status.bin", false).accounts(readAccounts).view();
+    expect(stat.status).to.deep.equal({ pendingCertification: {} });
+
+    await program.methods.setBlobStatus("/status.bin", { superseded: {} }).accounts(writeAccounts).rpc();
+    await expectError(
+      program.methods.setBlobStatus("/status.bin", { certified: {} }).accounts(writeAccounts).rpc(),
+      "InvalidStatusTransition"
+    );
+  });
+
+
+  // --- Access counts ---
+  it("Counts downloads with record_access", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    await program.methods.addFile("/counted.bin", [], new BN(1), blob("counted"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    let stat = await program.methods.stat("/counted.bin", false).accounts(readAccounts).view();
+    expect(stat.accessCount.toNumber()).to.equal(0);
+
+    await program.methods.recordAccess("/counted.bin").accounts(readAccounts).rpc();
+    await program.methods.recordAccess("/counted.bin").accounts(readAccounts).rpc();
+    stat = await program.methods.stat("/counted.bin", false).accounts(readAccounts).view();
+    expect(stat.accessCount.toNumber()).to.equal(2);
+    expect(stat.accessTs).to.not.be.null;
+  });
+
+
+  // --- Directory aggregates ---
+  it("Keeps each directory's child counts and subtree bytes current", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    const statDir = (path: string) => program.methods.stat(path, false).accounts(readAccounts).view();
+
+    const dirAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    await program.methods.addDirRecursive("/agg/inner", []).accounts(dirAccounts).rpc();
+    await program.methods.addFile("/agg/a.bin", [], new BN(10), blob("agg_a"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    await program.methods.addFile("/agg/inner/b.bin", [], new BN(32), blob("agg_b"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    let agg = await statDir("/agg");
+    expect(agg.childFileCount).to.equal(1);
+    expect(agg.childDirCount).to.equal(1);
+    expect(agg.subtreeBytes.toNumber()).to.equal(42);
+    const usage = await program.methods.dirSize("/agg").accounts(readAccounts).view();
+    expect(usage.totalBytes.toNumber()).to.equal(42);
+
+    await program.methods.updateFile("/agg/inner/b.bin", blob("agg_b2"), new BN(50), new BN(700), null, null, null, null, null, false, null, null).accounts(writeAccounts).rpc();
+    expect((await statDir("/agg")).subtreeBytes.toNumber()).to.equal(60);
+
+    await program.methods.moveFile("/agg/inner/b.bin", "/agg_b.bin", false, null, null).accounts(writeAccounts).rpc();
+    agg = await statDir("/agg");
+    expect(agg.subtreeBytes.toNumber()).to.equal(10);
+    expect((await statDir("/agg/inner")).childFileCount).to.equal(0);
+
+    await program.methods.deleteFile("/agg/a.bin", false, false, null, null, null).accounts(writeAccounts).rpc();
+    agg = await statDir("/agg");
+    expect(agg.childFileCount).to.equal(0);
+    expect(agg.subtreeBytes.toNumber()).to.equal(0);
+  });
+
+
+  // --- File owners ---
+  it("Lets a file's owner change it alongside the fs authority", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+    const teammate = web3.Keypair.generate();
+    const stranger = web3.Keypair.generate();
+    await program.methods.addFile("/team.txt", [], new BN(1), blob("team"), new BN(700), null, "", null, { standard: {} }, null, null, false, false, null).accounts(writeAccounts).rpc();
+    let stat = await program.methods.stat("/team.txt", false).accounts(readAccounts).view();
+    expect(stat.owner.toBase58()).to.equal(payer.publicKey.toBase58());
+
+    await program.methods.chownFile("/team.txt", teammate.publicKey).accounts(writeAccounts).rpc();
+    await program.methods.touch("/team.txt")
+      .accounts({ ...writeAccounts, authority: teammate.publicKey })
+      .signers([teammate])
+      .rpc();
+    stat = await program.methods.stat("/team.txt", false).accounts(readAccounts).view();
+    expect(stat.owner.toBase58()).to.equal(teammate.publicKey.toBase58());
+
+    await expectError(
+      program.methods.touch("/team.txt").accounts({ ...writeAccounts, authority: stranger.publicKey }).signers([stranger]).rpc(),
+      "Unauthorized"
+    );
+
+    // The owner also controls the file's directory entries
+    await program.methods.renameFile("/team.txt", "/team-notes.txt", false, null, null, null)
+      .accounts({ ...writeAccounts, authority: teammate.publicKey })
+      .signers([teammate])
+      .rpc();
+    await program.methods.addFile("/team-notes.txt", [], new BN(2), blob("team2"), new BN(700), null, "", null, { standard: {} }, null, null, true, false, null)
+      .accounts({ ...writeAccounts, authority: teammate.publicKey })
+      .signers([teammate])
+      .rpc();
+    stat = await program.methods.stat("/team-notes.txt", false).accounts(readAccounts).view();
+    expect(stat.owner.toBase58()).to.equal(teammate.publicKey.toBase58());
+    await expectError(
+      program.methods.deleteFile("/team-notes.txt", false, false, null, null, null).accounts({ ...writeAccounts, authority: stranger.publicKey }).signers([stranger]).rpc(),
+      "Unauthorized"
+    );
+    await program.methods.deleteFile("/team-notes.txt", false, false, null, null, null)
+      .accounts({ ...writeAccounts, authority: teammate.publicKey })
+      .signers([teammate])
+      .rpc();
+  });
+
+
+  // --- Authority transfer ---
+  it("Hands the filesystem to a new authority in two steps", async () => {
+    const successor = web3.Keypair.generate();
+    const stranger = web3.Keypair.generate();
+    const settings = (authority: web3.PublicKey) => ({ walrusfsRoot: walrusfsRootPda, authority });
+
+    await program.methods.proposeAuthorityTransfer(successor.publicKey).accounts(settings(payer.publicKey)).rpc();
+    let root = await program.account.walrusfsRootPda.fetch(walrusfsRootPda);
+    expect(root.pendingAuthority.toBase58()).to.equal(successor.publicKey.toBase58());
+    await expectError(
+      program.methods.acceptAuthorityTransfer().accounts({ walrusfsRoot: walrusfsRootPda, newAuthority: stranger.publicKey }).signers([stranger]).rpc(),
+      "NotPendingAuthority"
+    );
+
+    await program.methods.acceptAuthorityTransfer().accounts({ walrusfsRoot: walrusfsRootPda, newAuthority: successor.publicKey }).signers([successor]).rpc();
+    root = await program.account.walrusfsRootPda.fetch(walrusfsRootPda);
+    expect(root.authority.toBase58()).to.equal(successor.publicKey.toBase58());
+    expect(root.seedKey.toBase58()).to.equal(payer.publicKey.toBase58());
+    expect(root.pendingAuthority).to.be.null;
+    await expectError(
+      program.methods.proposeAuthorityTransfer(payer.publicKey).accounts(settings(payer.publicKey)).rpc(),
+      "Unauthorized"
+    );
+
+    // Hand it back so later tests keep signing as the payer
+    await program.methods.proposeAuthorityTransfer(payer.publicKey).accounts(settings(successor.publicKey)).signers([successor]).rpc();
+    await program.methods.acceptAuthorityTransfer().accounts({ walrusfsRoot: walrusfsRootPda, newAuthority: payer.publicKey }).rpc();
+    root = await program.account.walrusfsRootPda.fetch(walrusfsRootPda);
+    expect(root.authority.toBase58()).to.equal(payer.publicKey.toBase58());
+  });
+
+
+  // --- Co-authorities ---
+  it("Lets co-authorities sign writes until they are removed", async () => {
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, authority: payer.publicKey };
+    const settings = (authority: web3.PublicKey) => ({ walrusfsRoot: walrusfsRootPda, authority });
+    const colleague = web3.Keypair.generate();
+    const newcomer = web3.Keypair.generate();
+
+    await program.methods.addCoAuthority(colleague.publicKey).accounts(settings(payer.publicKey)).rpc();
+    await expectError(
+      program.methods.addCoAuthority(colleague.publicKey).accounts(settings(payer.publicKey)).rpc(),
+      "AlreadyCoAuthority"
+    );
+    await program.methods.addFile("/shared.txt", [], new BN(1), blob("shared"), new BN(10), null, "", null, { standard: {} }, null, null, false, false, null)
+      .accounts({ ...writeAccounts, authority: colleague.publicKey })
+      .signers([colleague])
+      .rpc();
+    // Co-authorities write but can't administer the filesystem
+    for (const admin of [
+      program.methods.addCoAuthority(newcomer.publicKey),
+      program.methods.removeCoAuthority(colleague.publicKey),
+      program.methods.setFrozen(true),
+      program.methods.setCpiCallers([]),
+      program.methods.proposeAuthorityTransfer(colleague.publicKey),
+    ]) {
+      await expectError(admin.accounts(settings(colleague.publicKey)).signers([colleague]).rpc(), "Unauthorized");
+    }
+
+    await program.methods.addCoAuthority(newcomer.publicKey).accounts(settings(payer.publicKey)).rpc();
+    await program.methods.removeCoAuthority(colleague.publicKey).accounts(settings(payer.publicKey)).rpc();
+    await program.methods.removeCoAuthority(newcomer.publicKey).accounts(settings(payer.publicKey)).rpc();
+    const root = await program.account.walrusfsRootPda.fetch(walrusfsRootPda);
+    expect(root.coAuthorities).to.be.empty;
+    await expectError(
+      program.methods.touch("/shared.txt").accounts({ ...writeAccounts, authority: colleague.publicKey }).signers([colleague]).rpc(),
+      "Unauthorized"
+    );
+  });
+
+  // --- Writer grants ---
+  it("Lets a delegate write under its grant's prefix until revoked", async () => {
+    const bot = web3.Keypair.generate();
+    const [writerGrantPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("writer_grant"), walrusfsRootPda.toBuffer(), bot.publicKey.toBuffer()],
+      program.programId
+    );
+    const grantAccounts = { walrusfsRoot: walrusfsRootPda, writerGrant: writerGrantPda, authority: payer.publicKey };
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, writerGrant: writerGrantPda, authority: bot.publicKey };
+    const expirySlot = new BN((await provider.connection.getSlot()) + 10_000);
+
+    await program.methods.grantWriter(bot.publicKey, "/ci", expirySlot)
+      .accounts({ ...grantAccounts, systemProgram: web3.SystemProgram.programId })
+      .rpc();
+    const grant = await program.account.writerGrantPda.fetch(writerGrantPda);
+    expect(grant.pathPrefix).to.equal("/ci");
+
+    // Delegates need the other-write bit on the directories they write into
+    await program.methods.addDir("/ci", [], false, null).accounts({ ...adminAccounts, writerGrant: null }).rpc();
+    await program.methods.chmod("/ci", 0o777).accounts(adminAccounts).rpc();
+    await program.methods.addFile("/ci/build.log", [], new BN(1), blob("build"), new BN(10), null, "", null, { standard: {} }, null, null, false, false, null)
+      .accounts(writeAccounts)
+      .signers([bot])
+      .rpc();
+    await program.methods.touch("/ci/build.log").accounts(writeAccounts).signers([bot]).rpc();
+    await expectError(
+      program.methods.addFile("/cinema.txt", [], new BN(1), blob("cinema"), new BN(10), null, "", null, { standard: {} }, null, null, false, false, null)
+        .accounts(writeAccounts)
+        .signers([bot])
+        .rpc(),
+      "OutsideWriterGrant"
+    );
+
+    await program.methods.revokeWriter(bot.publicKey).accounts(grantAccounts).rpc();
+    await expectError(
+      program.methods.deleteFile("/ci/build.log", false, false, null, null, null)
+        .accounts({ ...writeAccounts, writerGrant: null })
+        .signers([bot])
+        .rpc(),
+      "Unauthorized"
+    );
+  });
+
+  // --- Read grants ---
+  it("Records read grants for gateways to check", async () => {
+    const reader = web3.Keypair.generate();
+    const [readGrantPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("read_grant"), walrusfsRootPda.toBuffer(), reader.publicKey.toBuffer()],
+      program.programId
+    );
+    const grantAccounts = { walrusfsRoot: walrusfsRootPda, readGrant: readGrantPda, authority: payer.publicKey };
+    const checkAccounts = { walrusfsRoot: walrusfsRootPda, readGrant: readGrantPda };
+    const expirySlot = new BN((await provider.connection.getSlot()) + 10_000);
+
+    await program.methods.grantReader(reader.publicKey, "/reports", expirySlot)
+      .accounts({ ...grantAccounts, systemProgram: web3.SystemProgram.programId })
+      .rpc();
+    expect(await program.methods.canRead(reader.publicKey, "/reports/q1.pdf").accounts(checkAccounts).view()).to.be.true;
+    expect(await program.methods.canRead(reader.publicKey, "/reports-old").accounts(checkAccounts).view()).to.be.false;
+    expect(await program.methods.canRead(payer.publicKey, "/anything").accounts({ ...checkAccounts, readGrant: null }).view()).to.be.true;
+
+    await program.methods.revokeReader(reader.publicKey).accounts(grantAccounts).rpc();
+    expect(await program.methods.canRead(reader.publicKey, "/reports/q1.pdf").accounts({ ...checkAccounts, readGrant: null }).view()).to.be.false;
+  });
+
+  // --- Shares ---
+  it("Checks share links until they expire or are revoked", async () => {
+    const shareKey = web3.Keypair.generate().publicKey;
+    const friend = web3.Keypair.generate().publicKey;
+    const [shareGrantPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("share"), walrusfsRootPda.toBuffer(), shareKey.toBuffer()],
+      program.programId
+    );
+    const shareAccounts = { walrusfsRoot: walrusfsRootPda, shareGrant: shareGrantPda, authority: payer.publicKey };
+    const checkAccounts = { walrusfsRoot: walrusfsRootPda, shareGrant: shareGrantPda };
+
+    await expectError(
+      program.methods.createShare(shareKey, "/albums/2024", friend, new BN(0))
+        .accounts({ ...shareAccounts, systemProgram: web3.SystemProgram.programId })
+        .rpc(),
+      "ShareExpired"
+    );
+    await program.methods.createShare(shareKey, "/albums/2024", friend, new BN((await provider.connection.getSlot()) + 10_000))
+      .accounts({ ...shareAccounts, systemProgram: web3.SystemProgram.programId })
+      .rpc();
+    expect(await program.methods.checkShare(shareKey, friend, "/albums/2024/beach.jpg").accounts(checkAccounts).view()).to.be.true;
+    expect(await program.methods.checkShare(shareKey, payer.publicKey, "/albums/2024/beach.jpg").accounts(checkAccounts).view()).to.be.false;
+    expect(await program.methods.checkShare(shareKey, friend, "/albums/2023").accounts(checkAccounts).view()).to.be.false;
+
+    await program.methods.revokeShare(shareKey).accounts(shareAccounts).rpc();
+    expect(await provider.connection.getAccountInfo(shareGrantPda)).to.be.null;
+  });
+
+  // --- Mode bits ---
+  it("Surfaces mode bits and enforces them for delegates", async () => {
+    const bot = web3.Keypair.generate();
+    const [writerGrantPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("writer_grant"), walrusfsRootPda.toBuffer(), bot.publicKey.toBuffer()],
+      program.programId
+    );
+    const botAccounts = { ...adminAccounts, writerGrant: writerGrantPda, authority: bot.publicKey };
+    const statAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+
+    await program.methods.addDir("/modes", [], false, null).accounts(adminAccounts).rpc();
+    await program.methods.addFile("/modes/plain.txt", [], new BN(1), blob("plain"), new BN(10), null, "", null, { standard: {} }, null, null, false, false, null)
+      .accounts(adminAccounts)
+      .rpc();
+    let stat = await program.methods.stat("/modes/plain.txt", false).accounts(statAccounts).view();
+    expect(stat.mode).to.equal(0o644);
+    await expectError(
+      program.methods.chmod("/modes", 0o1777).accounts(adminAccounts).rpc(),
+      "InvalidMode"
+    );
+
+    await program.methods.grantWriter(bot.publicKey, "/modes", new BN((await provider.connection.getSlot()) + 10_000))
+      .accounts({ walrusfsRoot: walrusfsRootPda, writerGrant: writerGrantPda, authority: payer.publicKey, systemProgram: web3.SystemProgram.programId })
+      .rpc();
+    await expectError(
+      program.methods.touch("/modes/plain.txt").accounts(botAccounts).signers([bot]).rpc(),
+      "PermissionDenied"
+    );
+    await program.methods.chmod("/modes/plain.txt", 0o666).accounts(adminAccounts).rpc();
+    await program.methods.touch("/modes/plain.txt").accounts(botAccounts).signers([bot]).rpc();
+    await expectError(
+      program.methods.chmod("/modes/plain.txt", 0o600).accounts({ ...adminAccounts, authority: bot.publicKey }).signers([bot]).rpc(),
+      "Unauthorized"
+    );
+
+    await program.methods.chmod("/modes", 0o775).accounts(adminAccounts).rpc();
+    stat = await program.methods.stat("/modes", false).accounts(statAccounts).view();
+    expect(stat.mode).to.equal(0o775);
+    await expectError(
+      program.methods.deleteFile("/modes/plain.txt", false, false, null, null, null).accounts(botAccounts).signers([bot]).rpc(),
+      "PermissionDenied"
+    );
+  });
+
+  // --- Groups ---
+  it("Lets group members write entries assigned to their group", async () => {
+    const member = web3.Keypair.generate();
+    const outsider = web3.Keypair.generate();
+    const [groupPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("group"), walrusfsRootPda.toBuffer(), Buffer.from("editors")],
+      program.programId
+    );
+    const groupAccounts = { walrusfsRoot: walrusfsRootPda, group: groupPda, authority: payer.publicKey };
+    const memberAccounts = { ...adminAccounts, group: groupPda, authority: member.publicKey };
+    const statAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda };
+
+    await program.methods.createGroup("editors", payer.publicKey)
+      .accounts({ ...groupAccounts, systemProgram: web3.SystemProgram.programId })
+      .rpc();
+    await program.methods.addGroupMember(member.publicKey).accounts(groupAccounts).rpc();
+    await expectError(
+      program.methods.addGroupMember(outsider.publicKey)
+        .accounts({ ...groupAccounts, authority: member.publicKey })
+        .signers([member])
+        .rpc(),
+      "Unauthorized"
+    );
+
+    // Members get the group bits of entries chgrp'd to their group, and new
+    // children inherit the group
+    await program.methods.addDir("/team", [], false, null).accounts(adminAccounts).rpc();
+    await program.methods.chgrp("/team").accounts({ ...adminAccounts, group: groupPda }).rpc();
+    await expectError(
+      program.methods.addDir("/team/drafts", [], false, null).accounts(memberAccounts).signers([member]).rpc(),
+      "PermissionDenied"
+    );
+    await program.methods.chmod("/team", 0o775).accounts(adminAccounts).rpc();
+    await program.methods.addDir("/team/drafts", [], false, null).accounts(memberAccounts).signers([member]).rpc();
+    await program.methods.addFile("/team/drafts/plan.md", [], new BN(1), blob("plan"), new BN(10), null, "", null, { standard: {} }, null, null, false, false, null)
+      .accounts(memberAccounts)
+      .signers([member])
+      .rpc();
+    const stat = await program.methods.stat("/team/drafts/plan.md", false).accounts(statAccounts).view();
+    expect(stat.group.toBase58()).to.equal(groupPda.toBase58());
+    expect(stat.mode).to.equal(0o664);
+
+    await program.methods.removeGroupMember(member.publicKey).accounts(groupAccounts).rpc();
+    await expectError(
+      program.methods.touch("/team/drafts/plan.md").accounts(memberAccounts).signers([member]).rpc(),
+      "Unauthorized"
+    );
+  });
+
+  // --- Mount points ---
+  it("Reads another filesystem through a mount point", async () => {
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, tagDictionary: tagDictionaryPda };
+    // The mounted filesystem's PDAs; mounting our own keeps the test to one wallet
+    const mountedAccounts = [walrusfsRootPda, rootChildrenFilesPda, rootChildrenDirectoriesPda, fileArenaPda, dirArenaPda, tagDictionaryPda]
+      .map((pubkey) => ({ pubkey, isWritable: false, isSigner: false }));
+    const filter = { kind: { both: {} }, tag: null, pinnedOnly: false };
+
+    await program.methods.addDir("/shared", [], false, null).accounts(adminAccounts).rpc();
+    await program.methods.setMount("/shared", { owner: payer.publicKey, fsName: "", path: "/team" })
+      .accounts({ walrusfsRoot: walrusfsRootPda, rootChildrenDirectories: rootChildrenDirectoriesPda, dirArena: dirArenaPda, authority: payer.publicKey })
+      .rpc();
+
+    const mountEntry = await program.methods.stat("/shared", false).accounts(readAccounts).view();
+    expect(mountEntry.mount.path).to.equal("/team");
+    const { entries } = await program.methods.listDir("/shared", 0, 0, { name: {} }, false, filter, false)
+      .accounts(readAccounts)
+      .remainingAccounts(mountedAccounts)
+      .view();
+    expect(entries.map((e) => e.name)).to.deep.equal(["drafts"]);
+    const plan = await program.methods.stat("/shared/drafts/plan.md", false)
+      .accounts(readAccounts)
+      .remainingAccounts(mountedAccounts)
+      .view();
+    expect(plan.name).to.equal("plan.md");
+
+    await expectError(
+      program.methods.stat("/shared/drafts/plan.md", false).accounts(readAccounts).view(),
+      "InvalidMountAccounts"
+    );
+    await expectError(
+      program.methods.addDir("/shared/local", [], false, null).accounts(adminAccounts).rpc(),
+      "PathInMount"
+    );
+  });
+
+  // --- Access policies ---
+  it("Gates reads on the policies along a path", async () => {
+    const reader = web3.Keypair.generate();
+    const mint = web3.Keypair.generate().publicKey;
+    const readAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, tagDictionary: tagDictionaryPda };
+
+    await program.methods.addDir("/gated", [], false, null).accounts(adminAccounts).rpc();
+    await program.methods.addFile("/gated/song.mp3", [], new BN(1), blob("song"), new BN(10), null, "", null, { standard: {} }, null, null, false, false, null)
+      .accounts(adminAccounts)
+      .rpc();
+    await program.methods.setAccessPolicy("/gated", { mintHolder: { mint, minAmount: new BN(1) } }).accounts(adminAccounts).rpc();
+    await expectError(
+      program.methods.setAccessPolicy("/gated", null).accounts({ ...adminAccounts, authority: reader.publicKey }).signers([reader]).rpc(),
+      "Unauthorized"
+    );
+
+    const stat = await program.methods.stat("/gated", false).accounts(readAccounts).view();
+    expect(stat.accessPolicy.mintHolder.mint.toBase58()).to.equal(mint.toBase58());
+    // Without token accounts for the mint, the reader fails the directory's policy
+    expect(await program.methods.checkAccess("/gated/song.mp3", reader.publicKey).accounts(readAccounts).view()).to.be.false;
+    expect(await program.methods.checkAccess("/gated/song.mp3", payer.publicKey).accounts(readAccounts).view()).to.be.true;
+    expect(await program.methods.checkAccess("/team", reader.publicKey).accounts(readAccounts).view()).to.be.true;
+
+    await program.methods.setAccessPolicy("/gated", null).accounts(adminAccounts).rpc();
+    expect(await program.methods.checkAccess("/gated/song.mp3", reader.publicKey).accounts(readAccounts).view()).to.be.true;
+
+    // Re-uploading a gated file keeps its gate
+    await program.methods.setAccessPolicy("/gated/song.mp3", { mintHolder: { mint, minAmount: new BN(1) } }).accounts(adminAccounts).rpc();
+    await program.methods.addFile("/gated/song.mp3", [], new BN(2), blob("song2"), new BN(10), null, "", null, { standard: {} }, null, null, true, false, null)
+      .accounts(adminAccounts)
+      .rpc();
+    expect(await program.methods.checkAccess("/gated/song.mp3", reader.publicKey).accounts(readAccounts).view()).to.be.false;
+    await program.methods.setAccessPolicy("/gated/song.mp3", null).accounts(adminAccounts).rpc();
+  });
+
+  // --- Permits ---
+  it("Applies writes an owner signed off-chain and someone else relayed", async () => {
+    const owner = web3.Keypair.generate();
+    const [permitNoncePda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("permit_nonce"), walrusfsRootPda.toBuffer(), owner.publicKey.toBuffer()],
+      program.programId
+    );
+    const writeAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, tagDictionary: tagDictionaryPda, authority: payer.publicKey };
+    const relayAccounts = { ...writeAccounts, permitNonce: permitNoncePda, instructions: web3.SYSVAR_INSTRUCTIONS_PUBKEY };
+    const expirySlot = new BN((await provider.connection.getSlot()) + 10_000);
+    const entries = [{ name: "relayed.txt", tags: [], size: new BN(1), walrusBlobId: blob("relayed"), endEpoch: new BN(10) }];
+
+    // The owner signs the data of the instruction they would have sent themselves
+    const signPermit = (nonce: number, direct: web3.TransactionInstruction) =>
+      web3.Ed25519Program.createInstructionWithPrivateKey({
+        privateKey: owner.secretKey,
+        message: Buffer.concat([
+          program.programId.toBuffer(),
+          walrusfsRootPda.toBuffer(),
+          new BN(nonce).toArrayLike(Buffer, "le", 8),
+          expirySlot.toArrayLike(Buffer, "le", 8),
+          createHash("sha256").update(direct.data).digest(),
+        ]),
+      });
+    const permit = (nonce: number) => ({ signer: owner.publicKey, nonce: new BN(nonce), expirySlot });
+    const directAdd = await program.methods.batchAddFiles("/", entries).accounts(writeAccounts).instruction();
+
+    await program.methods.addCoAuthority(owner.publicKey).accounts({ walrusfsRoot: walrusfsRootPda, authority: payer.publicKey }).rpc();
+    await program.methods.initPermitNonce(owner.publicKey)
+      .accounts({ payer: payer.publicKey, walrusfsRoot: walrusfsRootPda, permitNonce: permitNoncePda, systemProgram: web3.SystemProgram.programId })
+      .rpc();
+    await expectError(
+      program.methods.batchAddFilesWithPermit(permit(0), "/", entries).accounts(relayAccounts).rpc(),
+      "InvalidPermit"
+    );
+    await program.methods.batchAddFilesWithPermit(permit(0), "/", entries)
+      .accounts(relayAccounts)
+      .preInstructions([signPermit(0, directAdd)])
+      .rpc();
+    const stat = await program.methods.stat("/relayed.txt", false).accounts({ ...writeAccounts, owner: payer.publicKey }).view();
+    expect(stat.size.toNumber()).to.equal(1);
+
+    // Each nonce is good for one write, and a permit covers only what was signed
+    await expectError(
+      program.methods.batchAddFilesWithPermit(permit(0), "/", entries)
+        .accounts(relayAccounts)
+        .preInstructions([signPermit(0, directAdd)])
+        .rpc(),
+      "PermitNonceMismatch"
+    );
+    const directDelete = await program.methods.batchDeleteFiles(["/relayed.txt"], false).accounts(writeAccounts).instruction();
+    await expectError(
+      program.methods.batchDeleteFilesWithPermit(permit(1), ["/relayed.txt"], false)
+        .accounts(relayAccounts)
+        .preInstructions([signPermit(1, directAdd)])
+        .rpc(),
+      "InvalidPermit"
+    );
+    await program.methods.batchDeleteFilesWithPermit(permit(1), ["/relayed.txt"], false)
+      .accounts(relayAccounts)
+      .preInstructions([signPermit(1, directDelete)])
+      .rpc();
+    const nonce = await program.account.permitNoncePda.fetch(permitNoncePda);
+    expect(nonce.nextNonce.toNumber()).to.equal(2);
+
+    await program.methods.removeCoAuthority(owner.publicKey).accounts({ walrusfsRoot: walrusfsRootPda, authority: payer.publicKey }).rpc();
+  });
+
+  // --- Session keys ---
+  it("Lets a session key write as its owner within its scope", async () => {
+    const session = web3.Keypair.generate();
+    const [sessionKeyPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("session_key"), walrusfsRootPda.toBuffer(), session.publicKey.toBuffer()],
+      program.programId
+    );
+    const sessionAccounts = { owner: payer.publicKey, walrusfsRoot: walrusfsRootPda, sessionKey: sessionKeyPda };
+    const writeAccounts = { ...adminAccounts, sessionKey: sessionKeyPda, authority: session.publicKey };
+    const expirySlot = new BN((await provider.connection.getSlot()) + 10_000);
+
+    await program.methods.addDir("/saves", [], false, null).accounts(adminAccounts).rpc();
+    await program.methods.createSessionKey(session.publicKey, "/saves", expirySlot)
+      .accounts({ ...sessionAccounts, systemProgram: web3.SystemProgram.programId })
+      .rpc();
+    await program.methods.addFile("/saves/slot1.sav", [], new BN(1), blob("slot1"), new BN(10), null, "", null, { standard: {} }, null, null, false, false, null)
+      .accounts(writeAccounts)
+      .signers([session])
+      .rpc();
+    await expectError(
+      program.methods.addFile("/slot2.sav", [], new BN(1), blob("slot2"), new BN(10), null, "", null, { standard: {} }, null, null, false, false, null)
+        .accounts(writeAccounts)
+        .signers([session])
+        .rpc(),
+      "OutsideSessionScope"
+    );
+
+    await program.methods.revokeSessionKey(session.publicKey).accounts(sessionAccounts).rpc();
+    await expectError(
+      program.methods.touch("/saves/slot1.sav").accounts({ ...writeAccounts, sessionKey: null }).signers([session]).rpc(),
+      "Unauthorized"
+    );
+  });
+
+  // --- Freezing ---
+  it("Rejects changes to entries while the filesystem is frozen", async () => {
+    const settings = { walrusfsRoot: walrusfsRootPda, authority: payer.publicKey };
+    const statAccounts = { ...adminAccounts, owner: payer.publicKey };
+
+    await program.methods.addDir("/archive", [], false, null).accounts(adminAccounts).rpc();
+    await program.methods.setFrozen(true).accounts(settings).rpc();
+    let root = await program.account.walrusfsRootPda.fetch(walrusfsRootPda);
+    expect(root.frozen).to.be.true;
+
+    await expectError(
+      program.methods.addDir("/archive/2024", [], false, null).accounts(adminAccounts).rpc(),
+      "FilesystemFrozen"
+    );
+    await expectError(
+      program.methods.chmod("/archive", 0o700).accounts(adminAccounts).rpc(),
+      "FilesystemFrozen"
+    );
+    // Reads are unaffected
+    const stat = await program.methods.stat("/archive", false).accounts(statAccounts).view();
+    expect(stat.mode).to.equal(0o755);
+
+    await program.methods.setFrozen(false).accounts(settings).rpc();
+    await program.methods.addDir("/archive/2024", [], false, null).accounts(adminAccounts).rpc();
+    root = await program.account.walrusfsRootPda.fetch(walrusfsRootPda);
+    expect(root.frozen).to.be.false;
+  });
+
+  // --- Program config ---
+  it("Rejects every write while the program is paused", async () => {
+    const [programConfigPda] = web3.PublicKey.findProgramAddressSync([Buffer.from("program_config")], program.programId);
+    const stranger = web3.Keypair.generate();
+
+    await expectError(
+      program.methods.setPaused(true).accounts({ admin: stranger.publicKey }).signers([stranger]).rpc(),
+      "Unauthorized"
+    );
+    await program.methods.setPaused(true).accounts({ admin: payer.publicKey }).rpc();
+    await expectError(
+      program.methods.addDir("/paused", [], false, null).accounts(adminAccounts).rpc(),
+      "ProgramPaused"
+    );
+    await expectError(
+      program.methods.addDir("/paused", [], false, null).accounts({ ...adminAccounts, programConfig: stranger.publicKey }).rpc(),
+      "ConstraintSeeds"
+    );
+    await program.methods.setPaused(false).accounts({ admin: payer.publicKey }).rpc();
+    await program.methods.addDir("/paused", [], false, null).accounts(adminAccounts).rpc();
+
+    await program.methods.setFees(new BN(1000), stranger.publicKey).accounts({ admin: payer.publicKey }).rpc();
+    let config = await program.account.programConfigPda.fetch(programConfigPda);
+    expect(config.feeRecipient.toBase58()).to.equal(stranger.publicKey.toBase58());
+    await program.methods.setFees(new BN(0), payer.publicKey).accounts({ admin: payer.publicKey }).rpc();
+    config = await program.account.programConfigPda.fetch(programConfigPda);
+    expect(config.paused).to.be.false;
+    expect(config.fsCreationFeeLamports.toNumber()).to.equal(0);
+  });
+
+  // --- CPI callers ---
+  it("Keeps a whitelist of programs allowed to write through CPI", async () => {
+    const settings = { walrusfsRoot: walrusfsRootPda, authority: payer.publicKey };
+    const integration = web3.Keypair.generate().publicKey;
+
+    await expectError(
+      program.methods.setCpiCallers(Array.from({ length: 9 }, () => web3.Keypair.generate().publicKey)).accounts(settings).rpc(),
+      "TooManyCpiCallers"
+    );
+    await program.methods.setCpiCallers([integration]).accounts(settings).rpc();
+    let root = await program.account.walrusfsRootPda.fetch(walrusfsRootPda);
+    expect(root.cpiCallers.map((k) => k.toBase58())).to.deep.equal([integration.toBase58()]);
+
+    // Transactions that call the program directly are not CPIs
+    await program.methods.addDir("/integrations", [], false, null).accounts(adminAccounts).rpc();
+
+    // Through CPI, only a listed program calling in directly may write
+    const relay = anchor.workspace.CpiRelay as Program<CpiRelay>;
+    const relayAddDir = async (path: string, depth: number) => {
+      const ix = await program.methods.addDir(path, [], false, null)
+        .accounts({ ...adminAccounts, instructions: web3.SYSVAR_INSTRUCTIONS_PUBKEY })
+        .instruction();
+      return relay.methods.forward(depth, ix.data)
+        .remainingAccounts([{ pubkey: program.programId, isSigner: false, isWritable: false }, ...ix.keys])
+        .rpc();
+    };
+    await expectError(relayAddDir("/integrations/relayed", 0), "CpiCallerNotAllowed");
+    await program.methods.setCpiCallers([relay.programId]).accounts(settings).rpc();
+    await relayAddDir("/integrations/relayed", 0);
+    await expectError(relayAddDir("/integrations/nested", 1), "CpiCallerNotAllowed");
+
+    await program.methods.setCpiCallers(null).accounts(settings).rpc();
+    root = await program.account.walrusfsRootPda.fetch(walrusfsRootPda);
+    expect(root.cpiCallers).to.be.null;
+  });
+
+  // --- File sales ---
+  it("Sells a listed file to a buyer who pays its owner", async () => {
+    const seller = web3.Keypair.generate();
+    const buyer = web3.Keypair.generate();
+    const sellerAccounts = { ...adminAccounts, authority: seller.publicKey };
+    const statAccounts = { ...adminAccounts, owner: payer.publicKey };
+    const buyAccounts = { walrusfsRoot: walrusfsRootPda, rootChildrenFiles: rootChildrenFilesPda, rootChildrenDirectories: rootChildrenDirectoriesPda, fileArena: fileArenaPda, dirArena: dirArenaPda, buyer: buyer.publicKey, seller: seller.publicKey, systemProgram: web3.SystemProgram.programId };
+    const price = new BN(web3.LAMPORTS_PER_SOL / 10);
+    await provider.connection.confirmTransaction(
+      await provider.connection.requestAirdrop(buyer.publicKey, web3.LAMPORTS_PER_SOL),
+      "confirmed"
+    );
+
+    await program.methods.addFile("/dataset.parquet", [], new BN(1), blob("dataset"), new BN(10), null, "", null, { standard: {} }, null, null, false, false, null)
+      .accounts(adminAccounts)
+      .rpc();
+    await program.methods.chownFile("/dataset.parquet", seller.publicKey).accounts(adminAccounts).rpc();
+    await expectError(
+      program.methods.listFileForSale("/dataset.parquet", price).accounts(adminAccounts).rpc(),
+      "Unauthorized"
+    );
+    await program.methods.listFileForSale("/dataset.parquet", price).accounts(sellerAccounts).signers([seller]).rpc();
+    let stat = await program.methods.stat("/dataset.parquet", false).accounts(statAccounts).view();
+    expect(stat.salePriceLamports.toString()).to.equal(price.toString());
+    expect(stat.sealed).to.be.true;
+    await expectError(
+      program.methods.chownFile("/dataset.parquet", payer.publicKey).accounts(adminAccounts).rpc(),
+      "FileListedForSale"
+    );
+
+    await expectError(
+      program.methods.cancelSale("/dataset.parquet").accounts(adminAccounts).rpc(),
+      "Unauthorized"
+    );
+    await expectError(
+      program.methods.buyFile("/dataset.parquet", price).accounts({ ...buyAccounts, seller: payer.publicKey }).signers([buyer]).rpc(),
+      "SellerMismatch"
+    );
+    await expectError(
+      program.methods.buyFile("/dataset.parquet", price.subn(1)).accounts(buyAccounts).signers([buyer]).rpc(),
+      "PriceAboveMaximum"
+    );
+    await program.methods.buyFile("/dataset.parquet", price).accounts(buyAccounts).signers([buyer]).rpc();
+    expect(await provider.connection.getBalance(seller.publicKey)).to.equal(price.toNumber());
+    stat = await program.methods.stat("/dataset.parquet", false).accounts(statAccounts).view();
+    expect(stat.owner.toBase58()).to.equal(buyer.publicKey.toBase58());
+    expect(stat.salePriceLamports).to.be.null;
+    expect(stat.sealed).to.be.false;
+    await expectError(
+      program.methods.buyFile("/dataset.parquet", price).accounts({ ...buyAccounts, seller: buyer.publicKey }).signers([buyer]).rpc(),
+      "FileNotForSale"
+    );
+  });
+
+  // --- Read capabilities ---
+  it("Counts reads under a capability until its uses run out", async () => {
+    const capabilityKey = web3.Keypair.generate().publicKey;
+    const [capabilityPda] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("capability"), walrusfsRootPda.toBuffer(), capabilityKey.toBuffer()],
+      program.programId
+    );
+    const accounts = { walrusfsRoot: walrusfsRootPda, capability: capabilityPda };
+    const expirySlot = new BN((await provider.connection.getSlot()) + 10_000);
+
+    await program.methods.mintCapability(capabilityKey, "/dataset.parquet", payer.publicKey, expirySlot, 2)
+      .accounts({ ...accounts, authority: payer.publicKey, systemProgram: web3.SystemProgram.programId })
+      .rpc();
+    await expectError(
+      program.methods.consumeCapability(capabilityKey, "/team").accounts({ ...accounts, consumer: payer.publicKey }).rpc(),
+      "OutsideCapability"
+    );
+    const otherGateway = web3.Keypair.generate();
+    await expectError(
+      program.methods.consumeCapability(capabilityKey, "/dataset.parquet").accounts({ ...accounts, consumer: otherGateway.publicKey }).signers([otherGateway]).rpc(),
+      "Unauthorized"
+    );
+    await program.methods.consumeCapability(capabilityKey, "/dataset.parquet").accounts({ ...accounts, consumer: payer.publicKey }).rpc();
+    await program.methods.consumeCapability(capabilityKey, "/dataset.parquet").accounts({ ...accounts, consumer: payer.publicKey }).rpc();
+    const capability = await program.account.readCapabilityPda.fetch(capabilityPda);
+    expect(capability.uses).to.equal(2);
+    await expectError(
+      program.methods.consumeCapability(capabilityKey, "/dataset.parquet").accounts({ ...accounts, consumer: payer.publicKey }).rpc(),
+      "CapabilityExhausted"
+    );
+
+    await program.methods.revokeCapability(capabilityKey).accounts({ ...accounts, authority: payer.publicKey }).rpc();
+    expect(await provider.connection.getAccountInfo(capabilityPda)).to.be.null;
+  });
+
+  // --- Named filesystems ---
+  it("Keeps a wallet's named filesystems apart from its default one", async () => {
+    const [photosRoot] = web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("walrusfs_root"), payer.publicKey.toBuffer(), Buffer.from("photos")],
+      program.programId
+    );
+    const seeded = (seed: string) =>
+      web3.PublicKey.findProgramAddressSync([Buffer.from(seed), photosRoot.toBuffer()], program.programId)[0];
+    const photos = {
+      walrusfsRoot: photosRoot,
+      rootChildrenFiles: seeded("root_children_files"),
+      rootChildrenDirectories: seeded("root_children_directories"),
+      fileArena: seeded("file_arena"),
+      dirArena: seeded("dir_arena"),
+    };
+
+    await program.methods.initializeWalrusfs("photos", null)
+      .accounts({ ...photos, tagDictionary: seeded("tag_dictionary"), payer: payer.publicKey, systemProgram: web3.SystemProgram.programId })
+      .rpc();
+    const root = await program.account.walrusfsRootPda.fetch(photosRoot);
+    expect(root.seedName).to.equal("photos");
+    expect(root.name).to.equal("");
+    expect(root.seedKey.toBase58()).to.equal(payer.publicKey.toBase58());
+
+    await program.methods.addDir("/albums", [], false, null).accounts({ ...photos, authority: payer.publicKey }).rpc();
+    const dirArena = await program.account.dirArenaPda.fetch(photos.dirArena);
+    expect(dirArena.data.length).to.equal(1);
+    const defaultRoot = await program.account.walrusfsRootPda.fetch(walrusfsRootPda);
+    expect(defaultRoot.seedName).to.equal("");
   });
 });
\ No newline at end of file